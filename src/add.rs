@@ -0,0 +1,196 @@
+use std::collections::HashSet;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::create_worktree_blob;
+use crate::hash_worktree_blob;
+use crate::ignore::IgnoreRules;
+use crate::index::Index;
+use crate::index::IndexEntry;
+use crate::index::StatData;
+use crate::odb::ObjectStore;
+use crate::open_object_store_at;
+use crate::pathspec::Pathspec;
+use crate::worktree::has_conflict_stages;
+use crate::worktree::index_mtime;
+use crate::worktree::is_stat_clean;
+use crate::worktree::list_files;
+use crate::worktree::WorktreeFile;
+use crate::worktree::GITLINK_MODE;
+use crate::EntryMode;
+use crate::GitError;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AddOptions {
+    // `-u`: only files the index already tracks.
+    pub update: bool,
+    pub dry_run: bool,
+    // `-f`: ignored files too.
+    pub force: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct AddSummary {
+    // `add '<path>'` and `remove '<path>'` lines, as `--dry-run` shows.
+    pub changes: Vec<String>,
+    // Pathspecs only naming ignored files, which need `-f`.
+    pub ignored: Vec<String>,
+}
+
+// Stages what the pathspecs select in the worktree: new and modified
+// files are hashed into blobs and recorded with their mode and stat data,
+// tracked files that disappeared are removed. Files whose stat data did
+// not change are not read again.
+pub fn add(git_dir: &Path, worktree: &Path, pathspec: &Pathspec, options: AddOptions) -> Result<AddSummary, GitError> {
+    let index_path: PathBuf = git_dir.join("index");
+    let mut index: Index = Index::load(&index_path)?;
+    let index_mtime: Option<(u32, u32)> = index_mtime(&index_path);
+    let object_store: Box<dyn ObjectStore> = open_object_store_at(&git_dir.join("objects"));
+
+    let mut ignore: IgnoreRules = IgnoreRules::new(worktree, git_dir);
+    let ignore_rules: Option<&mut IgnoreRules> = if options.force { None } else { Some(&mut ignore) };
+    let files: Vec<WorktreeFile> = list_files(worktree, &index, ignore_rules, pathspec)?;
+
+    let mut summary: AddSummary = AddSummary::default();
+    let mut matched: Vec<bool> = vec![false; pathspec.specs().len()];
+    let mut present: HashSet<&str> = HashSet::new();
+    let mut staged: Vec<IndexEntry> = Vec::new();
+
+    for file in &files {
+        let path: &str = &file.path;
+        present.insert(path);
+        if let Some(spec) = pathspec.matching_spec(path) {
+            matched[spec] = true;
+        }
+
+        let tracked: Option<&IndexEntry> = index.find(path, 0);
+        let conflicted: bool = tracked.is_none() && has_conflict_stages(&index, path);
+        if options.update && tracked.is_none() && !conflicted {
+            continue;
+        }
+        if tracked.is_some_and(|entry| !entry.intent_to_add && is_stat_clean(entry, &file.metadata, index_mtime)) {
+            continue;
+        }
+
+        let full_path: PathBuf = worktree.join(path);
+        let (mode, sha1_hash): (EntryMode, String) = if options.dry_run {
+            hash_worktree_blob(&full_path, &file.metadata)?
+        } else {
+            create_worktree_blob(object_store.as_ref(), &full_path, &file.metadata)?
+        };
+        let entry: IndexEntry = IndexEntry::new(path, mode.as_index_mode(), &sha1_hash, StatData::from_metadata(&file.metadata));
+
+        // Same content, only the stat data needed refreshing.
+        let unchanged: bool = tracked.is_some_and(|tracked| {
+            tracked.sha1_hash.eq(&entry.sha1_hash) && tracked.mode == entry.mode && !tracked.intent_to_add
+        });
+        if !unchanged {
+            summary.changes.push(format!("add '{path}'"));
+        }
+        staged.push(entry);
+    }
+    if !options.dry_run {
+        index.add_all(staged);
+    }
+
+    // Tracked paths gone from the worktree, submodules apart.
+    let mut removed: Vec<String> = index
+        .entries
+        .iter()
+        .filter(|entry| !present.contains(entry.path.as_str()) && pathspec.matches(&entry.path))
+        .filter(|entry| entry.mode != GITLINK_MODE || !worktree.join(&entry.path).is_dir())
+        .map(|entry| entry.path.clone())
+        .collect();
+    removed.dedup();
+    for path in removed {
+        if let Some(spec) = pathspec.matching_spec(&path) {
+            matched[spec] = true;
+        }
+        summary.changes.push(format!("remove '{path}'"));
+        if !options.dry_run {
+            index.remove(&path);
+        }
+    }
+
+    for (spec, matched) in pathspec.specs().iter().zip(matched) {
+        if matched || spec.is_empty() {
+            continue;
+        }
+        if worktree.join(spec).symlink_metadata().is_ok() && ignore.is_ignored(spec, worktree.join(spec).is_dir()) {
+            summary.ignored.push(spec.clone());
+            continue;
+        }
+        return Err(GitError::InvalidPathspec(format!("pathspec '{spec}' did not match any files")));
+    }
+
+    if !options.dry_run {
+        index.write(&index_path)?;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::test_support::git;
+    use crate::test_support::TempRepo;
+
+    fn add_paths(repo: &Path, paths: &[&str], options: AddOptions) -> AddSummary {
+        let paths: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+        add(&repo.join(".git"), repo, &Pathspec::new(&paths), options).unwrap()
+    }
+
+    #[test]
+    fn test_add_stages_worktree() {
+        let repo: TempRepo = TempRepo::new("add");
+        fs::create_dir_all(repo.join("src/deep")).unwrap();
+        fs::create_dir_all(repo.join("build")).unwrap();
+        git(&repo, &["init", "-q"]);
+        fs::write(repo.join(".gitignore"), "build/\n*.log\n").unwrap();
+        fs::write(repo.join("README"), "readme\n").unwrap();
+        fs::write(repo.join("src/lib.rs"), "lib\n").unwrap();
+        fs::write(repo.join("src/deep/mod.rs"), "mod\n").unwrap();
+        fs::write(repo.join("run.sh"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(repo.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        std::os::unix::fs::symlink("README", repo.join("link")).unwrap();
+        fs::write(repo.join("build/out"), "out\n").unwrap();
+        fs::write(repo.join("debug.log"), "log\n").unwrap();
+
+        let summary: AddSummary = add_paths(&repo, &["src"], AddOptions { dry_run: true, ..AddOptions::default() });
+        assert_eq!(vec!["add 'src/deep/mod.rs'", "add 'src/lib.rs'"], summary.changes);
+        assert!(!repo.join(".git/index").exists());
+
+        let summary: AddSummary = add_paths(&repo, &["."], AddOptions::default());
+        assert_eq!(6, summary.changes.len());
+        assert_eq!(
+            "100644 .gitignore\n100644 README\n120000 link\n100755 run.sh\n100644 src/deep/mod.rs\n100644 src/lib.rs",
+            git(&repo, &["ls-files", "--format=%(objectmode) %(path)"])
+        );
+        // Stat data is right: git sees nothing to refresh.
+        assert_eq!("", git(&repo, &["diff-files", "--name-only"]));
+        assert_eq!(git(&repo, &["hash-object", "run.sh"]), git(&repo, &["rev-parse", ":run.sh"]));
+
+        // Nothing changed, nothing to report.
+        assert!(add_paths(&repo, &["."], AddOptions::default()).changes.is_empty());
+
+        let summary: AddSummary = add_paths(&repo, &["debug.log"], AddOptions::default());
+        assert_eq!(vec!["debug.log"], summary.ignored);
+        let summary: AddSummary = add_paths(&repo, &["debug.log"], AddOptions { force: true, ..AddOptions::default() });
+        assert_eq!(vec!["add 'debug.log'"], summary.changes);
+
+        // `-u` stages modifications and deletions, not new files.
+        fs::remove_file(repo.join("src/lib.rs")).unwrap();
+        fs::write(repo.join("README"), "changed readme\n").unwrap();
+        fs::write(repo.join("new.txt"), "new\n").unwrap();
+        let summary: AddSummary = add_paths(&repo, &[], AddOptions { update: true, ..AddOptions::default() });
+        assert_eq!(vec!["add 'README'", "remove 'src/lib.rs'"], summary.changes);
+        assert_eq!("?? new.txt", git(&repo, &["status", "--porcelain", "--untracked-files=all"]).lines().last().unwrap());
+
+        assert!(add(&repo.join(".git"), &repo, &Pathspec::new(&["missing".to_string()]), AddOptions::default()).is_err());
+    }
+}
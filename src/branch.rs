@@ -0,0 +1,393 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::fetch::branch_upstream;
+use crate::fetch::shorten_ref;
+use crate::fetch::ABBREV;
+use crate::is_ancestor;
+use crate::log::subject;
+use crate::odb::abbreviate;
+use crate::odb::ObjectStore;
+use crate::read_git_object;
+use crate::refs::check_ref_format;
+use crate::refs::delete_ref;
+use crate::refs::list_refs;
+use crate::refs::read_ref;
+use crate::refs::read_symbolic_ref;
+use crate::refs::write_ref;
+use crate::refs::write_symbolic_ref;
+use crate::refspec::Refspec;
+use crate::revparse::resolve_ref_name;
+use crate::revparse::resolve_revision;
+use crate::status::read_upstream;
+use crate::status::Upstream;
+use crate::GitError;
+use crate::GitObject;
+
+// Which refs `git branch` lists: local branches, remote-tracking ones
+// (`-r`) or both (`-a`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BranchKinds {
+    Local,
+    Remote,
+    All,
+}
+
+// Branch HEAD points to, `None` when it is detached. The branch may be
+// unborn, like `main` right after `git init`.
+pub fn current_branch(git_dir: &Path) -> Result<Option<String>, GitError> {
+    let target: Option<String> = read_symbolic_ref(git_dir, "HEAD")?;
+    Ok(target.and_then(|target| target.strip_prefix("refs/heads/").map(str::to_string)))
+}
+
+// A line of `git branch`: the name shown, its tip, the ref a symbolic
+// ref like `origin/HEAD` stands for, and the local branch whose upstream
+// `-v` shows.
+struct ListedBranch {
+    shown: String,
+    current: bool,
+    sha1_hash: String,
+    target: Option<String>,
+    branch: Option<String>,
+}
+
+// One line per branch, the current one marked with `*`, like `git branch`.
+// `verbose` adds the abbreviated id and subject of each tip, with how far
+// local branches are from their upstream (named too from level 2 on).
+pub fn format_list(git_dir: &Path, object_store: &dyn ObjectStore, kinds: BranchKinds, verbose: usize) -> Result<String, GitError> {
+    let current: Option<String> = read_symbolic_ref(git_dir, "HEAD")?;
+
+    let mut rows: Vec<ListedBranch> = Vec::new();
+    if kinds != BranchKinds::Remote {
+        if let (None, Some(head)) = (&current, read_ref(git_dir, "HEAD")?) {
+            let label: String = format!("(HEAD detached at {})", abbreviate(object_store, &head, ABBREV)?);
+            rows.push(ListedBranch { shown: label, current: true, sha1_hash: head, target: None, branch: None });
+        }
+        for (name, sha1_hash) in list_refs(git_dir, "refs/heads/")? {
+            let branch: String = shorten_ref(&name).to_string();
+            let is_current: bool = current.as_ref() == Some(&name);
+            rows.push(ListedBranch { shown: branch.clone(), current: is_current, sha1_hash, target: None, branch: Some(branch) });
+        }
+    }
+    if kinds != BranchKinds::Local {
+        for (name, sha1_hash) in list_refs(git_dir, "refs/remotes/")? {
+            let shown: String = match kinds {
+                BranchKinds::All => format!("remotes/{}", shorten_ref(&name)),
+                _ => shorten_ref(&name).to_string(),
+            };
+            let target: Option<String> = read_symbolic_ref(git_dir, &name)?;
+            rows.push(ListedBranch { shown, current: false, sha1_hash, target, branch: None });
+        }
+    }
+
+    let width: usize = rows.iter().map(|row| row.shown.chars().count()).max().unwrap_or(0);
+    let mut out: String = String::new();
+    for ListedBranch { shown, current, sha1_hash, target, branch } in rows {
+        let marker: char = if current { '*' } else { ' ' };
+        if let Some(target) = target {
+            match verbose {
+                0 => out.push_str(&format!("{marker} {shown} -> {}\n", shorten_ref(&target))),
+                _ => out.push_str(&format!("{marker} {shown:<width$} -> {}\n", shorten_ref(&target))),
+            }
+            continue;
+        }
+        if verbose == 0 {
+            out.push_str(&format!("{marker} {shown}\n"));
+            continue;
+        }
+
+        let tracking: String = match branch {
+            Some(branch) => match read_upstream(git_dir, object_store, &branch, Some(&sha1_hash))? {
+                Some(upstream) => format_tracking(&upstream, verbose > 1),
+                None => String::new(),
+            },
+            None => String::new(),
+        };
+        let summary: String = match read_git_object(object_store, &sha1_hash)? {
+            GitObject::Commit { content } => subject(&content),
+            _ => String::new(),
+        };
+        out.push_str(&format!(
+            "{marker} {shown:<width$} {} {tracking}{summary}\n",
+            abbreviate(object_store, &sha1_hash, ABBREV)?
+        ));
+    }
+    Ok(out)
+}
+
+// Like `[origin/main: ahead 1, behind 2] `, nothing when up to date unless
+// the upstream is named.
+fn format_tracking(upstream: &Upstream, named: bool) -> String {
+    let state: String = match upstream.ahead_behind {
+        None => "gone".to_string(),
+        Some((0, 0)) => String::new(),
+        Some((ahead, 0)) => format!("ahead {ahead}"),
+        Some((0, behind)) => format!("behind {behind}"),
+        Some((ahead, behind)) => format!("ahead {ahead}, behind {behind}"),
+    };
+    match (named, state.is_empty()) {
+        (false, true) => String::new(),
+        (false, false) => format!("[{state}] "),
+        (true, true) => format!("[{}] ", upstream.name),
+        (true, false) => format!("[{}: {state}] ", upstream.name),
+    }
+}
+
+// Creates `name` at the commit `start_point` designates, or moves it there
+// with `force`. Starting from a remote-tracking branch sets it as the
+// upstream, whose short name is returned.
+pub fn create_branch(
+    git_dir: &Path,
+    object_store: &dyn ObjectStore,
+    name: &str,
+    start_point: &str,
+    force: bool,
+) -> Result<Option<String>, GitError> {
+    let full_name: String = format!("refs/heads/{name}");
+    if !check_ref_format(&full_name, false) {
+        return Err(GitError::Branch(format!("'{name}' is not a valid branch name")));
+    }
+    if read_ref(git_dir, &full_name)?.is_some() {
+        if !force {
+            return Err(GitError::Branch(format!("a branch named '{name}' already exists")));
+        }
+        if current_branch(git_dir)?.as_deref() == Some(name) {
+            return Err(GitError::Branch(format!("cannot force update the branch '{name}' checked out at '{}'", worktree_path(git_dir))));
+        }
+    }
+
+    let Ok(start) = resolve_revision(git_dir, object_store, start_point) else {
+        // On an unborn branch, git names the branch rather than HEAD.
+        let shown: String = match start_point {
+            "HEAD" => current_branch(git_dir)?.unwrap_or_else(|| start_point.to_string()),
+            _ => start_point.to_string(),
+        };
+        return Err(GitError::Branch(format!("not a valid object name: '{shown}'")));
+    };
+    let Ok(commit) = resolve_revision(git_dir, object_store, &format!("{start}^{{commit}}")) else {
+        return Err(GitError::Branch(format!("not a valid branch point: '{start_point}'")));
+    };
+    write_ref(git_dir, &full_name, &commit)?;
+
+    // Remote-tracking refs no remote fetches into are not tracked.
+    match resolve_ref_name(git_dir, start_point)? {
+        Some(upstream) if upstream.starts_with("refs/remotes/") => Ok(set_upstream(git_dir, name, start_point).ok()),
+        _ => Ok(None),
+    }
+}
+
+// Deletes `name` and its configuration, returning the commit it pointed
+// to. Without `force`, the branch must be merged into its upstream, or
+// into HEAD when it has none.
+pub fn delete_branch(git_dir: &Path, object_store: &dyn ObjectStore, name: &str, force: bool) -> Result<String, GitError> {
+    let full_name: String = format!("refs/heads/{name}");
+    if !check_ref_format(&full_name, false) {
+        return Err(GitError::Branch(format!("branch '{name}' not found.")));
+    }
+    let Some(sha1_hash) = read_ref(git_dir, &full_name)? else {
+        return Err(GitError::Branch(format!("branch '{name}' not found.")));
+    };
+    if current_branch(git_dir)?.as_deref() == Some(name) {
+        return Err(GitError::Branch(format!("Cannot delete branch '{name}' checked out at '{}'", worktree_path(git_dir))));
+    }
+
+    if !force {
+        let config: Config = Config::load(&git_dir.join("config"))?;
+        let upstream: Option<String> = match branch_upstream(&config, name)? {
+            Some(upstream) => read_ref(git_dir, &upstream)?,
+            None => None,
+        };
+        let merged: bool = match upstream.or(read_ref(git_dir, "HEAD")?) {
+            Some(into) => is_ancestor(object_store, &sha1_hash, &into)?,
+            None => false,
+        };
+        if !merged {
+            return Err(GitError::Branch(format!(
+                "The branch '{name}' is not fully merged.\nIf you are sure you want to delete it, run 'git branch -D {name}'."
+            )));
+        }
+    }
+
+    delete_ref(git_dir, &full_name)?;
+    Config::remove_section(&git_dir.join("config"), "branch", Some(name))?;
+    Ok(sha1_hash)
+}
+
+// Renames `from` to `to` with its configuration, following it with HEAD
+// when it is the current branch, which may not have any commit yet.
+pub fn rename_branch(git_dir: &Path, from: &str, to: &str, force: bool) -> Result<(), GitError> {
+    let (from_ref, to_ref): (String, String) = (format!("refs/heads/{from}"), format!("refs/heads/{to}"));
+    if !check_ref_format(&from_ref, false) {
+        return Err(GitError::Branch(format!("Invalid branch name: '{from}'")));
+    }
+    let is_current: bool = current_branch(git_dir)?.as_deref() == Some(from);
+    let sha1_hash: Option<String> = read_ref(git_dir, &from_ref)?;
+    if sha1_hash.is_none() && !is_current {
+        return Err(GitError::Branch(format!("No branch named '{from}'.")));
+    }
+    if !check_ref_format(&to_ref, false) {
+        return Err(GitError::Branch(format!("'{to}' is not a valid branch name")));
+    }
+    if from.eq(to) {
+        return Ok(());
+    }
+    if read_ref(git_dir, &to_ref)?.is_some() {
+        if !force {
+            return Err(GitError::Branch(format!("a branch named '{to}' already exists")));
+        }
+        Config::remove_section(&git_dir.join("config"), "branch", Some(to))?;
+    }
+
+    if let Some(sha1_hash) = sha1_hash {
+        write_ref(git_dir, &to_ref, &sha1_hash)?;
+        delete_ref(git_dir, &from_ref)?;
+    }
+    if is_current {
+        write_symbolic_ref(git_dir, "HEAD", &to_ref)?;
+    }
+    Config::rename_section(&git_dir.join("config"), "branch", from, to)?;
+    Ok(())
+}
+
+// Makes `branch` track `upstream`, a local branch or a remote-tracking
+// one that the fetch refspecs of a remote map back to the remote branch,
+// and returns the short name of the upstream.
+pub fn set_upstream(git_dir: &Path, branch: &str, upstream: &str) -> Result<String, GitError> {
+    let branch_ref: String = format!("refs/heads/{branch}");
+    if !check_ref_format(&branch_ref, false) || read_ref(git_dir, &branch_ref)?.is_none() {
+        return Err(GitError::Branch(format!("branch '{branch}' does not exist")));
+    }
+    let full_name: String = match resolve_ref_name(git_dir, upstream)? {
+        Some(full_name)
+            if (full_name.starts_with("refs/heads/") || full_name.starts_with("refs/remotes/"))
+                && read_ref(git_dir, &full_name)?.is_some() =>
+        {
+            full_name
+        }
+        _ => return Err(GitError::Branch(format!("the requested upstream branch '{upstream}' does not exist"))),
+    };
+
+    let config_path: PathBuf = git_dir.join("config");
+    let (remote, merge): (String, String) = match full_name.starts_with("refs/heads/") {
+        true => (".".to_string(), full_name.clone()),
+        false => {
+            let config: Config = Config::load(&config_path)?;
+            let mut tracked: Option<(String, String)> = None;
+            'remotes: for remote in config.subsections("remote") {
+                for fetch in config.get_all("remote", Some(remote), "fetch") {
+                    let refspec: Refspec = Refspec::parse(fetch)?;
+                    let Some(dst) = refspec.dst else {
+                        continue;
+                    };
+                    // The remote branch whose fetch lands on `full_name`.
+                    let reversed: Refspec = Refspec { force: false, src: dst, dst: Some(refspec.src) };
+                    if let Some(merge) = reversed.map(&full_name) {
+                        tracked = Some((remote.to_string(), merge));
+                        break 'remotes;
+                    }
+                }
+            }
+            tracked.ok_or_else(|| {
+                GitError::Branch(format!("cannot set up tracking information; starting point '{upstream}' is not a branch"))
+            })?
+        }
+    };
+
+    Config::set_value(&config_path, "branch", Some(branch), "remote", Some(&remote))?;
+    Config::set_value(&config_path, "branch", Some(branch), "merge", Some(&merge))?;
+    Ok(shorten_ref(&full_name).to_string())
+}
+
+// Forgets the upstream of `branch`. Returns whether it had one.
+pub fn unset_upstream(git_dir: &Path, branch: &str) -> Result<bool, GitError> {
+    let config_path: PathBuf = git_dir.join("config");
+    let config: Config = Config::load(&config_path)?;
+    if config.get("branch", Some(branch), "merge").is_none() {
+        return Ok(false);
+    }
+    Config::set_value(&config_path, "branch", Some(branch), "remote", None)?;
+    Config::set_value(&config_path, "branch", Some(branch), "merge", None)?;
+    Ok(true)
+}
+
+fn worktree_path(git_dir: &Path) -> String {
+    let git_dir: PathBuf = git_dir.canonicalize().unwrap_or_else(|_| git_dir.to_path_buf());
+    git_dir.parent().unwrap_or(&git_dir).display().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::open_object_store_at;
+    use crate::test_support::git;
+    use crate::test_support::TempRepo;
+
+    #[test]
+    fn test_branch_matches_git() {
+        let root: TempRepo = TempRepo::new("branch");
+        let (origin, repo): (PathBuf, PathBuf) = (root.join("origin"), root.join("repo"));
+        fs::create_dir_all(&origin).unwrap();
+        git(&origin, &["init", "-q", "-b", "main"]);
+        git(&origin, &["commit", "-q", "--allow-empty", "-m", "first"]);
+        git(&origin, &["commit", "-q", "--allow-empty", "-m", "second"]);
+        git(&origin, &["branch", "side", "HEAD~1"]);
+        git(&root, &["clone", "-q", "origin", "repo"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "local"]);
+
+        let git_dir: PathBuf = repo.join(".git");
+        let object_store: Box<dyn ObjectStore> = open_object_store_at(&git_dir.join("objects"));
+        let object_store: &dyn ObjectStore = object_store.as_ref();
+
+        assert_eq!(Some("main".to_string()), current_branch(&git_dir).unwrap());
+        assert_eq!(Some("origin/side".to_string()), create_branch(&git_dir, object_store, "topic", "origin/side", false).unwrap());
+        assert_eq!(None, create_branch(&git_dir, object_store, "work", "HEAD~1", false).unwrap());
+        assert!(create_branch(&git_dir, object_store, "work", "HEAD", false).is_err());
+        assert!(create_branch(&git_dir, object_store, "a..b", "HEAD", false).is_err());
+        assert!(delete_branch(&git_dir, object_store, "../../config", true).is_err());
+        assert!(rename_branch(&git_dir, "../../config", "x", false).is_err());
+        assert!(git_dir.join("config").is_file());
+        assert_eq!("origin/main", set_upstream(&git_dir, "work", "origin/main").unwrap());
+        for (kinds, verbose, args) in [
+            (BranchKinds::Local, 0, vec!["branch"]),
+            (BranchKinds::Remote, 1, vec!["branch", "-r", "-v"]),
+            (BranchKinds::All, 1, vec!["branch", "-a", "-v"]),
+            (BranchKinds::Local, 2, vec!["branch", "-vv"]),
+        ] {
+            assert_eq!(git(&repo, &args), format_list(&git_dir, object_store, kinds, verbose).unwrap().trim_end());
+        }
+
+        assert!(delete_branch(&git_dir, object_store, "main", false).is_err());
+        rename_branch(&git_dir, "topic", "feature", false).unwrap();
+        assert!(rename_branch(&git_dir, "feature", "work", false).is_err());
+        assert_eq!(git(&repo, &["rev-parse", "origin/side"]), git(&repo, &["rev-parse", "feature"]));
+        assert_eq!("refs/heads/side", git(&repo, &["config", "branch.feature.merge"]));
+        create_branch(&git_dir, object_store, "extra", "HEAD", false).unwrap();
+        set_upstream(&git_dir, "extra", "main").unwrap();
+        assert!(delete_branch(&git_dir, object_store, "extra", false).is_ok());
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "more"]);
+        create_branch(&git_dir, object_store, "ahead", "HEAD", false).unwrap();
+        set_upstream(&git_dir, "ahead", "origin/main").unwrap();
+        assert!(delete_branch(&git_dir, object_store, "ahead", false).is_err());
+        assert!(delete_branch(&git_dir, object_store, "ahead", true).is_ok());
+        assert!(unset_upstream(&git_dir, "work").unwrap());
+        assert!(!unset_upstream(&git_dir, "work").unwrap());
+        assert_eq!("feature\nmain\nwork", git(&repo, &["for-each-ref", "--format=%(refname:short)", "refs/heads/"]));
+        assert_eq!(
+            "branch.main.remote origin\nbranch.main.merge refs/heads/main\nbranch.feature.remote origin\nbranch.feature.merge refs/heads/side",
+            git(&repo, &["config", "--get-regexp", "^branch\\."])
+        );
+
+        // Renaming the current branch moves HEAD, even before any commit.
+        rename_branch(&git_dir, "main", "trunk", false).unwrap();
+        assert_eq!("trunk", git(&repo, &["branch", "--show-current"]));
+        let fresh: PathBuf = root.join("fresh");
+        fs::create_dir_all(&fresh).unwrap();
+        git(&fresh, &["init", "-q", "-b", "main"]);
+        rename_branch(&fresh.join(".git"), "main", "trunk", false).unwrap();
+        assert_eq!("trunk", git(&fresh, &["branch", "--show-current"]));
+    }
+}
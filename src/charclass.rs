@@ -0,0 +1,66 @@
+// Bracket expressions shared by gitignore patterns and `--grep`
+// regular expressions: whether `c` is in the class starting after `[`,
+// and what follows the closing `]`. `None` when the class is not closed.
+pub fn match_class(pattern: &[u8], c: u8) -> Option<(bool, &[u8])> {
+    let (negated, mut index): (bool, usize) = match pattern.first() {
+        Some(b'!' | b'^') => (true, 1),
+        _ => (false, 0),
+    };
+
+    let mut matched: bool = false;
+    let mut first: bool = true;
+    loop {
+        let &current = pattern.get(index)?;
+        if current == b']' && !first {
+            return Some((matched != negated, &pattern[index + 1..]));
+        }
+        first = false;
+
+        if current == b'[' && pattern.get(index + 1) == Some(&b':') {
+            let end: usize = pattern[index + 2..].windows(2).position(|w| w == b":]")? + index + 2;
+            matched |= match &pattern[index + 2..end] {
+                b"alnum" => c.is_ascii_alphanumeric(),
+                b"alpha" => c.is_ascii_alphabetic(),
+                b"digit" => c.is_ascii_digit(),
+                b"lower" => c.is_ascii_lowercase(),
+                b"upper" => c.is_ascii_uppercase(),
+                b"space" => c.is_ascii_whitespace(),
+                b"punct" => c.is_ascii_punctuation(),
+                b"xdigit" => c.is_ascii_hexdigit(),
+                _ => false,
+            };
+            index = end + 2;
+            continue;
+        }
+
+        let current: u8 = match current {
+            b'\\' => {
+                index += 1;
+                *pattern.get(index)?
+            }
+            _ => current,
+        };
+        if pattern.get(index + 1) == Some(&b'-') && pattern.get(index + 2).is_some_and(|&end| end != b']') {
+            let end: u8 = pattern[index + 2];
+            matched |= (current..=end).contains(&c);
+            index += 3;
+        } else {
+            matched |= current == c;
+            index += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_class() {
+        assert_eq!(Some((true, b"x".as_slice())), match_class(b"a-c]x", b'b'));
+        assert_eq!(Some((false, b"".as_slice())), match_class(b"!a-c]", b'b'));
+        assert_eq!(Some((true, b"".as_slice())), match_class(b"^[:digit:]_]", b'x'));
+        assert_eq!(Some((true, b"".as_slice())), match_class(b"]]", b']'));
+        assert_eq!(None, match_class(b"abc", b'a'));
+    }
+}
@@ -0,0 +1,499 @@
+use std::fs;
+use std::fs::File;
+
+use std::io;
+
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+
+use std::ffi::OsStr;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::index::Index;
+use crate::index::StatData;
+use crate::index_pack::index_pack;
+use crate::index_pack::store_pack;
+use crate::index_pack::IndexedPack;
+use crate::init_git_dir;
+use crate::odb::ObjectStore;
+use crate::open_object_store_at;
+use crate::protocol::fetch_pack;
+use crate::protocol::RefAdvertisement;
+use crate::read_git_object;
+use crate::refs::write_ref;
+use crate::refs::write_symbolic_ref;
+use crate::transport::local_path;
+use crate::transport::open_transport;
+use crate::transport::Transport;
+use crate::transport::UPLOAD_PACK;
+use crate::EntryMode;
+use crate::GitError;
+use crate::GitObject;
+
+// `git clone https://host/path/repo.git` clones into `repo`.
+pub fn default_directory(url: &str) -> String {
+    let path: &str = url.trim_end_matches('/');
+    let path: &str = path.strip_suffix("/.git").unwrap_or(path);
+    let name: &str = path.rsplit(['/', ':']).next().unwrap_or(path);
+    name.strip_suffix(".git").unwrap_or(name).to_string()
+}
+
+// Fetches every branch and tag of the remote into a new repository in
+// `dir`, branches as `refs/remotes/origin/*`, then checks out the branch
+// the remote HEAD points to, with the remote's progress shown unless
+// `quiet`. A failed clone leaves nothing behind: `dir` is removed if it
+// was created, emptied again otherwise.
+pub fn clone_repository(url: &str, dir: &Path, quiet: bool) -> Result<(), GitError> {
+    if fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(GitError::Checkout(format!(
+            "destination path '{}' already exists and is not an empty directory",
+            dir.display()
+        )));
+    }
+
+    let existed: bool = dir.exists();
+    let result: Result<(), GitError> = clone_into(url, dir, quiet);
+    if result.is_err() {
+        if existed {
+            if let Ok(entries) = fs::read_dir(dir) {
+                for entry in entries.map_while(Result::ok) {
+                    let path: PathBuf = entry.path();
+                    let _ = if path.is_dir() && !path.is_symlink() { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+                }
+            }
+        } else {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+
+    result
+}
+
+fn clone_into(url: &str, dir: &Path, quiet: bool) -> Result<(), GitError> {
+    let mut transport: Box<dyn Transport> = open_transport(url)?;
+    let advertisement: RefAdvertisement = transport.discover_refs(UPLOAD_PACK)?;
+
+    // Local remotes are recorded by absolute path.
+    let url: String = match local_path(url).and_then(|path| fs::canonicalize(path).ok()) {
+        Some(path) => path.display().to_string(),
+        None => url.to_string(),
+    };
+
+    let git_dir: PathBuf = dir.join(".git");
+    if let Err(err) = init_git_dir(&git_dir) {
+        return Err(GitError::Checkout(format!("cannot create '{}': {err}", git_dir.display())));
+    }
+
+    let mut wants: Vec<String> = advertisement
+        .refs
+        .iter()
+        .filter(|r| r.name.starts_with("refs/heads/") || r.name.starts_with("refs/tags/"))
+        .map(|r| r.sha1_hash.clone())
+        .collect();
+    wants.sort();
+    wants.dedup();
+
+    // An empty remote may still name the unborn branch its HEAD is on.
+    let head_target: Option<String> = advertisement
+        .head_target()
+        .filter(|target| target.starts_with("refs/heads/") && (wants.is_empty() || advertisement.find(target).is_some()));
+    let branch: Option<&str> = head_target.as_deref().and_then(|target| target.strip_prefix("refs/heads/"));
+    write_clone_config(&git_dir, &url, branch)?;
+
+    if wants.is_empty() {
+        eprintln!("warning: You appear to have cloned an empty repository.");
+        if let Some(target) = &head_target {
+            write_symbolic_ref(&git_dir, "HEAD", target)?;
+        }
+        return Ok(());
+    }
+
+    let pack: Vec<u8> = fetch_pack(transport.as_mut(), &advertisement, &wants, &[], quiet)?;
+
+    let objects_dir: PathBuf = git_dir.join("objects");
+    let indexed: IndexedPack = index_pack(open_object_store_at(&objects_dir).as_ref(), pack, false)?;
+    store_pack(&objects_dir, &indexed)?;
+
+    for remote_ref in &advertisement.refs {
+        if let Some(name) = remote_ref.name.strip_prefix("refs/heads/") {
+            write_ref(&git_dir, &format!("refs/remotes/origin/{name}"), &remote_ref.sha1_hash)?;
+        } else if remote_ref.name.starts_with("refs/tags/") {
+            write_ref(&git_dir, &remote_ref.name, &remote_ref.sha1_hash)?;
+        }
+    }
+
+    // A detached remote HEAD leaves the clone detached too.
+    let head_sha: String = match (&head_target, branch) {
+        (Some(target), Some(branch)) => {
+            let Some(head) = advertisement.find(target) else {
+                return Err(GitError::InvalidRef(target.clone()));
+            };
+            write_symbolic_ref(&git_dir, "refs/remotes/origin/HEAD", &format!("refs/remotes/origin/{branch}"))?;
+            write_ref(&git_dir, target, &head.sha1_hash)?;
+            write_symbolic_ref(&git_dir, "HEAD", target)?;
+            head.sha1_hash.clone()
+        }
+        _ => match advertisement.find("HEAD") {
+            Some(head) => {
+                write_ref(&git_dir, "HEAD", &head.sha1_hash)?;
+                head.sha1_hash.clone()
+            }
+            None => {
+                eprintln!("warning: remote HEAD refers to nonexistent ref, unable to checkout");
+                return Ok(());
+            }
+        },
+    };
+
+    let object_store: Box<dyn ObjectStore> = open_object_store_at(&objects_dir);
+    let tree_sha: String = match read_git_object(object_store.as_ref(), &head_sha)? {
+        GitObject::Commit { content } => content.tree,
+        _ => return Err(GitError::InvalidCommit(head_sha)),
+    };
+    checkout_tree(object_store.as_ref(), &tree_sha, dir)?;
+
+    // The index records what was just written, stat data included, so
+    // that the checkout reads as clean.
+    let mut index: Index = Index::from_tree(object_store.as_ref(), &tree_sha)?;
+    for entry in &mut index.entries {
+        if let Ok(metadata) = fs::symlink_metadata(dir.join(&entry.path)) {
+            entry.stat = StatData::from_metadata(&metadata);
+        }
+    }
+    index.write(&git_dir.join("index"))
+}
+
+fn write_clone_config(git_dir: &Path, url: &str, branch: Option<&str>) -> Result<(), GitError> {
+    let mut config: String = String::from(
+        "[core]\n\
+         \trepositoryformatversion = 0\n\
+         \tfilemode = true\n\
+         \tbare = false\n\
+         \tlogallrefupdates = true\n",
+    );
+    config.push_str(&format!(
+        "[remote \"origin\"]\n\turl = {url}\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n"
+    ));
+    if let Some(branch) = branch {
+        config.push_str(&format!("[branch \"{branch}\"]\n\tremote = origin\n\tmerge = refs/heads/{branch}\n"));
+    }
+
+    match fs::write(git_dir.join("config"), config) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(GitError::Checkout(format!("cannot write config: {err}"))),
+    }
+}
+
+// Writes the content of a tree under `dir`, with the executable bit and
+// symbolic links restored. Submodules are left as empty directories.
+pub fn checkout_tree(object_store: &dyn ObjectStore, tree_sha: &str, dir: &Path) -> Result<(), GitError> {
+    let checkout_error = |path: &Path, err: io::Error| GitError::Checkout(format!("{}: {err}", path.display()));
+
+    let entries = match read_git_object(object_store, tree_sha)? {
+        GitObject::Tree { content } => content,
+        _ => return Err(GitError::InvalidTreeEntry),
+    };
+
+    if let Some(entry) = entries.iter().find(|entry| !is_safe_entry_name(&entry.name)) {
+        return Err(GitError::Checkout(format!("invalid path '{}'", entry.name)));
+    }
+    if let Err(err) = fs::create_dir_all(dir) {
+        return Err(checkout_error(dir, err));
+    }
+
+    for entry in entries {
+        let path: PathBuf = dir.join(&entry.name);
+
+        match entry.mode {
+            EntryMode::Directory => checkout_tree(object_store, &entry.sha1_hash, &path)?,
+            EntryMode::Submodule => {
+                if let Err(err) = fs::create_dir_all(&path) {
+                    return Err(checkout_error(&path, err));
+                }
+            }
+            EntryMode::SymbolicLink => {
+                let target: Vec<u8> = object_store.read(&entry.sha1_hash)?.content;
+                if let Err(err) = std::os::unix::fs::symlink(OsStr::from_bytes(&target), &path) {
+                    return Err(checkout_error(&path, err));
+                }
+            }
+            EntryMode::RegularFile | EntryMode::ExecutableFile => {
+                let mut reader = object_store.open(&entry.sha1_hash)?;
+                let written = File::create(&path).and_then(|mut file| io::copy(&mut reader, &mut file));
+                if let Err(err) = written {
+                    return Err(checkout_error(&path, err));
+                }
+
+                if entry.mode == EntryMode::ExecutableFile {
+                    if let Err(err) = fs::set_permissions(&path, fs::Permissions::from_mode(0o755)) {
+                        return Err(checkout_error(&path, err));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Tree entries come from the remote: like git's verify_path, names that
+// would leave the directory or reach into `.git` are refused.
+fn is_safe_entry_name(name: &str) -> bool {
+    !name.is_empty() && !matches!(name, "." | "..") && !name.contains(['/', '\0']) && !name.eq_ignore_ascii_case(".git")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::BufRead;
+    use std::io::BufReader;
+    use std::io::Read;
+    use std::io::Write;
+
+    use std::net::TcpListener;
+    use std::net::TcpStream;
+
+    use std::process::Command;
+    use std::process::Stdio;
+
+    use std::thread;
+
+    use crate::odb::MemoryObjectStore;
+    use crate::pkt_line::encode_pkt_line;
+    use crate::pkt_line::FLUSH_PKT;
+    use crate::test_support::git;
+    use crate::test_support::TempRepo;
+    use crate::TreeEntry;
+
+    // Smart HTTP in front of `git upload-pack --stateless-rpc`, one
+    // request per connection.
+    fn serve_repository(repo: PathBuf) -> String {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url: String = format!("http://{}/fixture.git", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for stream in listener.incoming().map_while(Result::ok) {
+                handle_request(stream, &repo);
+            }
+        });
+
+        url
+    }
+
+    fn handle_request(mut stream: TcpStream, repo: &Path) {
+        let mut reader: BufReader<TcpStream> = BufReader::new(stream.try_clone().unwrap());
+        let mut request_line: String = String::new();
+        reader.read_line(&mut request_line).unwrap();
+
+        let mut content_length: usize = 0;
+        loop {
+            let mut header: String = String::new();
+            reader.read_line(&mut header).unwrap();
+            if header.trim_end().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body: Vec<u8> = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+
+        let (content_type, response): (&str, Vec<u8>) = if request_line.starts_with("GET ") {
+            let mut response: Vec<u8> = encode_pkt_line(b"# service=git-upload-pack\n");
+            response.extend_from_slice(FLUSH_PKT);
+            let output = Command::new("git")
+                .args(["upload-pack", "--stateless-rpc", "--advertise-refs"])
+                .arg(repo)
+                .output()
+                .unwrap();
+            response.extend(output.stdout);
+            ("application/x-git-upload-pack-advertisement", response)
+        } else {
+            let mut child = Command::new("git")
+                .args(["upload-pack", "--stateless-rpc"])
+                .arg(repo)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            child.stdin.take().unwrap().write_all(&body).unwrap();
+            ("application/x-git-upload-pack-result", child.wait_with_output().unwrap().stdout)
+        };
+
+        let header: String = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            response.len()
+        );
+        stream.write_all(header.as_bytes()).unwrap();
+        stream.write_all(&response).unwrap();
+    }
+
+    // Advertises an empty repository whose HEAD is on the unborn `branch`.
+    fn serve_empty_repository(branch: &str) -> String {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url: String = format!("http://{}/empty.git", listener.local_addr().unwrap());
+
+        let mut response: Vec<u8> = encode_pkt_line(b"# service=git-upload-pack\n");
+        response.extend_from_slice(FLUSH_PKT);
+        let capabilities: String = format!("{} capabilities^{{}}\0symref=HEAD:refs/heads/{branch}\n", "0".repeat(40));
+        response.extend(encode_pkt_line(capabilities.as_bytes()));
+        response.extend_from_slice(FLUSH_PKT);
+
+        thread::spawn(move || {
+            for mut stream in listener.incoming().map_while(Result::ok) {
+                let mut reader: BufReader<TcpStream> = BufReader::new(stream.try_clone().unwrap());
+                let mut line: String = String::new();
+                while reader.read_line(&mut line).is_ok_and(|read| read > 0) && !line.trim_end().is_empty() {
+                    line.clear();
+                }
+
+                let header: String = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/x-git-upload-pack-advertisement\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    response.len()
+                );
+                stream.write_all(header.as_bytes()).unwrap();
+                stream.write_all(&response).unwrap();
+            }
+        });
+
+        url
+    }
+
+    #[test]
+    fn test_default_directory() {
+        assert_eq!("repo", default_directory("https://example.com/user/repo.git"));
+        assert_eq!("repo", default_directory("https://example.com/user/repo/"));
+        assert_eq!("repo", default_directory("git@example.com:repo.git"));
+        assert_eq!("repo", default_directory("/srv/repo/.git"));
+    }
+
+    // Two commits on `dev` with an executable, a symlink and an annotated
+    // tag, plus a second branch. Returns the ids of `dev` and `other`.
+    fn create_fixture(fixture: &Path) -> (String, String) {
+        fs::create_dir_all(fixture.join("src")).unwrap();
+
+        git(fixture, &["init", "-q", "-b", "dev"]);
+        fs::write(fixture.join("README.md"), "hello\n").unwrap();
+        fs::write(fixture.join("src/run.sh"), "#!/bin/sh\necho run\n").unwrap();
+        fs::set_permissions(fixture.join("src/run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        std::os::unix::fs::symlink("README.md", fixture.join("link")).unwrap();
+        git(fixture, &["add", "."]);
+        git(fixture, &["commit", "-q", "-m", "first"]);
+        git(fixture, &["tag", "-a", "v1", "-m", "version 1"]);
+        fs::write(fixture.join("README.md"), "hello again\n").unwrap();
+        git(fixture, &["commit", "-q", "-am", "second"]);
+        git(fixture, &["branch", "other", "HEAD~1"]);
+
+        (git(fixture, &["rev-parse", "HEAD"]), git(fixture, &["rev-parse", "other"]))
+    }
+
+    fn assert_cloned(dir: &Path, head: &str, other: &str) {
+        let git_dir: PathBuf = dir.join(".git");
+        assert_eq!("ref: refs/heads/dev\n", fs::read_to_string(git_dir.join("HEAD")).unwrap());
+        assert_eq!(format!("{head}\n"), fs::read_to_string(git_dir.join("refs/heads/dev")).unwrap());
+        assert_eq!(format!("{other}\n"), fs::read_to_string(git_dir.join("refs/remotes/origin/other")).unwrap());
+        assert_eq!(
+            "ref: refs/remotes/origin/dev\n",
+            fs::read_to_string(git_dir.join("refs/remotes/origin/HEAD")).unwrap()
+        );
+        assert!(git_dir.join("refs/tags/v1").is_file());
+
+        assert_eq!("hello again\n", fs::read_to_string(dir.join("README.md")).unwrap());
+        assert_eq!(0o755, fs::metadata(dir.join("src/run.sh")).unwrap().permissions().mode() & 0o777);
+        assert_eq!(Path::new("README.md"), fs::read_link(dir.join("link")).unwrap());
+
+        git(dir, &["fsck", "--full"]);
+        assert_eq!(head, git(dir, &["rev-parse", "HEAD"]));
+        assert_eq!("", git(dir, &["status", "--porcelain"]));
+    }
+
+    #[test]
+    fn test_checkout_tree_rejects_unsafe_names() {
+        let root: TempRepo = TempRepo::new("checkout_unsafe");
+        let object_store: MemoryObjectStore = MemoryObjectStore::new();
+        let blob: String = object_store.write("blob", b"escaped\n").unwrap();
+
+        for name in ["..", ".", "", "a/b", ".GIT", "../escaped"] {
+            let tree: GitObject = GitObject::Tree {
+                content: vec![TreeEntry { mode: EntryMode::RegularFile, name: name.to_string(), sha1_hash: blob.clone() }],
+            };
+            let tree: String = object_store.write("tree", &tree.content_bytes().unwrap()).unwrap();
+            assert!(checkout_tree(&object_store, &tree, &root.join("worktree")).is_err(), "{name:?}");
+        }
+        assert_eq!(0, fs::read_dir(&root).unwrap().count());
+    }
+
+    #[test]
+    fn test_failed_clone_leaves_nothing_behind() {
+        let root: TempRepo = TempRepo::new("clone_failed");
+        let fixture: PathBuf = root.join("fixture");
+        fs::create_dir(&fixture).unwrap();
+        git(&fixture, &["init", "-q", "-b", "dev"]);
+
+        // A tree the checkout refuses to write.
+        let object_store: Box<dyn ObjectStore> = open_object_store_at(&fixture.join(".git/objects"));
+        let blob: String = object_store.write("blob", b"escaped\n").unwrap();
+        let tree: GitObject = GitObject::Tree {
+            content: vec![TreeEntry { mode: EntryMode::RegularFile, name: ".GIT".to_string(), sha1_hash: blob }],
+        };
+        let tree: String = object_store.write("tree", &tree.content_bytes().unwrap()).unwrap();
+        let commit: String = git(&fixture, &["commit-tree", &tree, "-m", "unsafe"]);
+        git(&fixture, &["update-ref", "refs/heads/dev", &commit]);
+
+        let dir: PathBuf = root.join("clone");
+        assert!(clone_repository(fixture.to_str().unwrap(), &dir, true).is_err());
+        assert!(!dir.exists());
+
+        // An empty directory given to clone stays, empty.
+        fs::create_dir(&dir).unwrap();
+        assert!(clone_repository(fixture.to_str().unwrap(), &dir, true).is_err());
+        assert_eq!(0, fs::read_dir(&dir).unwrap().count());
+    }
+
+    #[test]
+    fn test_clone_over_http() {
+        let root: TempRepo = TempRepo::new("clone_over_http");
+        let fixture: PathBuf = root.join("fixture");
+        let (head, other): (String, String) = create_fixture(&fixture);
+
+        let url: String = serve_repository(fixture.clone());
+        let dir: PathBuf = root.join("clone");
+        clone_repository(&url, &dir, true).unwrap();
+        assert_cloned(&dir, &head, &other);
+
+        assert!(clone_repository(&url, &dir, true).is_err());
+
+        // An empty clone starts on the branch the remote HEAD names.
+        let dir: PathBuf = root.join("empty");
+        clone_repository(&serve_empty_repository("trunk"), &dir, true).unwrap();
+        assert_eq!("ref: refs/heads/trunk\n", fs::read_to_string(dir.join(".git/HEAD")).unwrap());
+        assert!(fs::read_to_string(dir.join(".git/config")).unwrap().contains("[branch \"trunk\"]"));
+    }
+
+    #[test]
+    fn test_clone_from_local_repository() {
+        let root: TempRepo = TempRepo::new("clone_local");
+        let fixture: PathBuf = root.join("fixture");
+        let (head, other): (String, String) = create_fixture(&fixture);
+
+        let dir: PathBuf = root.join("clone");
+        clone_repository(&format!("file://{}", fixture.display()), &dir, true).unwrap();
+        assert_cloned(&dir, &head, &other);
+
+        let dir: PathBuf = root.join("clone_path");
+        clone_repository(fixture.to_str().unwrap(), &dir, true).unwrap();
+        assert_cloned(&dir, &head, &other);
+        assert!(fs::read_to_string(dir.join(".git/config")).unwrap().contains(&format!("url = {}", fixture.display())));
+
+        git(&root, &["init", "-q", "--bare", "empty.git"]);
+        let dir: PathBuf = root.join("empty");
+        clone_repository(root.join("empty.git").to_str().unwrap(), &dir, true).unwrap();
+        assert!(dir.join(".git/HEAD").is_file());
+    }
+}
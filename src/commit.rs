@@ -0,0 +1,198 @@
+use crate::signature::Signature;
+use crate::GitError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub tree: String,
+    pub parents: Vec<String>,
+    pub author: Signature,
+    pub committer: Signature,
+    pub encoding: Option<Vec<u8>>,
+    // Headers git does not interpret itself (gpgsig, mergetag, ...), in
+    // their original order. Multi-line values are stored without the
+    // leading continuation space.
+    pub extra_headers: Vec<(String, Vec<u8>)>,
+    // `None` when not even the empty line before the message is there.
+    pub message: Option<Vec<u8>>,
+}
+
+impl Commit {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, GitError> {
+        let (headers, message): (ObjectHeaders, Option<Vec<u8>>) = parse_object_headers(bytes)?;
+
+        let mut tree: Option<String> = None;
+        let mut parents: Vec<String> = Vec::new();
+        let mut author: Option<Signature> = None;
+        let mut committer: Option<Signature> = None;
+        let mut encoding: Option<Vec<u8>> = None;
+        let mut extra_headers: Vec<(String, Vec<u8>)> = Vec::new();
+
+        let object_id = |value: Vec<u8>| String::from_utf8(value).map_err(|_| GitError::InvalidCommit("bad object id".to_string()));
+        for (key, value) in headers {
+            match key.as_str() {
+                "tree" if tree.is_none() => tree = Some(object_id(value)?),
+                "parent" => parents.push(object_id(value)?),
+                "author" if author.is_none() => author = Some(Signature::from_line(&value)?),
+                "committer" if committer.is_none() => {
+                    committer = Some(Signature::from_line(&value)?)
+                }
+                "encoding" if encoding.is_none() => encoding = Some(value),
+                _ => extra_headers.push((key, value)),
+            }
+        }
+
+        let Some(tree) = tree else {
+            return Err(GitError::InvalidCommit("missing tree".to_string()));
+        };
+        let Some(author) = author else {
+            return Err(GitError::InvalidCommit("missing author".to_string()));
+        };
+        let Some(committer) = committer else {
+            return Err(GitError::InvalidCommit("missing committer".to_string()));
+        };
+
+        Ok(Self {
+            tree,
+            parents,
+            author,
+            committer,
+            encoding,
+            extra_headers,
+            message,
+        })
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+
+        write_object_header(&mut bytes, "tree", self.tree.as_bytes());
+        for parent in &self.parents {
+            write_object_header(&mut bytes, "parent", parent.as_bytes());
+        }
+        write_object_header(&mut bytes, "author", &self.author.as_bytes());
+        write_object_header(&mut bytes, "committer", &self.committer.as_bytes());
+        if let Some(encoding) = &self.encoding {
+            write_object_header(&mut bytes, "encoding", encoding);
+        }
+        for (key, value) in &self.extra_headers {
+            write_object_header(&mut bytes, key, value);
+        }
+
+        if let Some(message) = &self.message {
+            bytes.push(b'\n');
+            bytes.extend_from_slice(message);
+        }
+        bytes
+    }
+
+    // Message bytes, empty when there is none.
+    pub fn message(&self) -> &[u8] {
+        self.message.as_deref().unwrap_or_default()
+    }
+
+    pub fn get_summary(&self) -> String {
+        let message: String = String::from_utf8_lossy(self.message()).to_string();
+        message.lines().next().unwrap_or("").to_string()
+    }
+}
+
+// Header values are raw bytes: names in signatures may use any encoding.
+pub type ObjectHeaders = Vec<(String, Vec<u8>)>;
+
+// Splits `key value` header lines (with space-prefixed continuation lines)
+// from the message that follows the first empty line, `None` when there
+// is no such line.
+pub fn parse_object_headers(bytes: &[u8]) -> Result<(ObjectHeaders, Option<Vec<u8>>), GitError> {
+    let mut headers: ObjectHeaders = Vec::new();
+
+    let mut index: usize = 0;
+    while index < bytes.len() {
+        let line_end: usize = match bytes[index..].iter().position(|&b| b == b'\n') {
+            Some(pos) => index + pos,
+            None => bytes.len(),
+        };
+        let line: &[u8] = &bytes[index..line_end];
+        index = line_end + 1;
+
+        if line.is_empty() {
+            return Ok((headers, Some(bytes[index.min(bytes.len())..].to_vec())));
+        }
+
+        if let Some(continuation) = line.strip_prefix(b" ") {
+            let Some((_, value)) = headers.last_mut() else {
+                return Err(GitError::InvalidGitObject);
+            };
+            value.push(b'\n');
+            value.extend_from_slice(continuation);
+            continue;
+        }
+
+        let Some(space) = line.iter().position(|&b| b == b' ') else {
+            return Err(GitError::InvalidGitObject);
+        };
+        let Ok(key): Result<&str, _> = std::str::from_utf8(&line[..space]) else {
+            return Err(GitError::InvalidGitObject);
+        };
+        headers.push((key.to_string(), line[space + 1..].to_vec()));
+    }
+
+    Ok((headers, None))
+}
+
+pub fn write_object_header(bytes: &mut Vec<u8>, key: &str, value: &[u8]) {
+    bytes.extend_from_slice(key.as_bytes());
+    bytes.push(b' ');
+    for &byte in value {
+        bytes.push(byte);
+        if byte == b'\n' {
+            bytes.push(b' ');
+        }
+    }
+    bytes.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_round_trip() {
+        let raw: &[u8] = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+parent 0123456789012345678901234567890123456789\n\
+parent 9876543210987654321098765432109876543210\n\
+author A U Thor <a@example.com> 1700000000 +0100\n\
+committer C O Mitter <c@example.com> 1700000060 -0500\n\
+encoding ISO-8859-1\n\
+gpgsig -----BEGIN PGP SIGNATURE-----\n \n abcdef\n -----END PGP SIGNATURE-----\n\
+\n\
+Merge things\n\nWith a body.\n";
+
+        let commit: Commit = Commit::from_bytes(raw).unwrap();
+
+        assert_eq!("4b825dc642cb6eb9a060e54bf8d69288fbee4904", commit.tree);
+        assert_eq!(2, commit.parents.len());
+        assert_eq!(b"C O Mitter", &commit.committer.name[..]);
+        assert_eq!(Some(b"ISO-8859-1".to_vec()), commit.encoding);
+        assert_eq!("gpgsig", commit.extra_headers[0].0);
+        assert_eq!("Merge things", commit.get_summary());
+        assert_eq!(raw, &commit.as_bytes()[..]);
+    }
+
+    #[test]
+    fn test_commit_round_trip_raw_bytes() {
+        // A Latin-1 author, and no message at all.
+        let raw: &[u8] = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+author Ren\xe9 <rene@example.com> 1700000000 +0100\n\
+committer Ren\xe9 <rene@example.com> 1700000000 +0100\n\
+encoding ISO-8859-1\n";
+        let commit: Commit = Commit::from_bytes(raw).unwrap();
+        assert_eq!(b"Ren\xe9", &commit.author.name[..]);
+        assert_eq!(None, commit.message);
+        assert_eq!(raw, &commit.as_bytes()[..]);
+
+        // An empty message keeps its separating line.
+        let mut raw: Vec<u8> = raw.to_vec();
+        raw.push(b'\n');
+        assert_eq!(raw, Commit::from_bytes(&raw).unwrap().as_bytes());
+    }
+}
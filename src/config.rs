@@ -0,0 +1,516 @@
+use std::ffi::OsString;
+
+use std::fs;
+use std::io;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::GitError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ConfigEntry {
+    // Section and key names are case insensitive, stored lowercased.
+    section: String,
+    subsection: Option<String>,
+    key: String,
+    value: String,
+}
+
+// `.git/config`: `[section "subsection"]` headers followed by
+// `key = value` lines. A key without `=` is a boolean set to true.
+#[derive(Debug, Default)]
+pub struct Config {
+    entries: Vec<ConfigEntry>,
+}
+
+impl Config {
+    // A missing file is an empty configuration.
+    pub fn load(path: &Path) -> Result<Self, GitError> {
+        match fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(GitError::InvalidConfig(format!("{}: {err}", path.display()))),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Self, GitError> {
+        let mut entries: Vec<ConfigEntry> = Vec::new();
+        let mut section: Option<(String, Option<String>)> = None;
+
+        let mut lines = content.lines().enumerate();
+        while let Some((number, line)) = lines.next() {
+            let line: &str = line.trim_start();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            let invalid = || GitError::InvalidConfig(format!("bad config line {}", number + 1));
+
+            if let Some(header) = line.strip_prefix('[') {
+                let Some((header, rest)) = header.split_once(']') else {
+                    return Err(invalid());
+                };
+                section = Some(parse_section_header(header).ok_or_else(invalid)?);
+
+                // `[section] key = value` on a single line.
+                let rest: &str = rest.trim();
+                if rest.is_empty() || rest.starts_with('#') || rest.starts_with(';') {
+                    continue;
+                }
+                let Some((section, subsection)) = section.clone() else {
+                    return Err(invalid());
+                };
+                let (key, value) = parse_key_value(rest).ok_or_else(invalid)?;
+                entries.push(ConfigEntry { section, subsection, key, value });
+                continue;
+            }
+
+            let Some((section, subsection)) = section.clone() else {
+                return Err(invalid());
+            };
+
+            // A trailing backslash continues the value on the next line.
+            let mut line: String = line.to_string();
+            while line.ends_with('\\') && !line.ends_with("\\\\") {
+                line.pop();
+                match lines.next() {
+                    Some((_, next)) => line.push_str(next),
+                    None => break,
+                }
+            }
+
+            let (key, value) = parse_key_value(&line).ok_or_else(invalid)?;
+            entries.push(ConfigEntry { section, subsection, key, value });
+        }
+
+        Ok(Self { entries })
+    }
+
+    // Last value wins, like `git config --get`.
+    pub fn get(&self, section: &str, subsection: Option<&str>, key: &str) -> Option<&str> {
+        self.get_all(section, subsection, key).pop()
+    }
+
+    pub fn get_all(&self, section: &str, subsection: Option<&str>, key: &str) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|entry| {
+                entry.section.eq_ignore_ascii_case(section)
+                    && entry.subsection.as_deref() == subsection
+                    && entry.key.eq_ignore_ascii_case(key)
+            })
+            .map(|entry| entry.value.as_str())
+            .collect()
+    }
+
+    // Subsections of `section`, e.g. every remote name for "remote".
+    pub fn subsections(&self, section: &str) -> Vec<&str> {
+        let mut subsections: Vec<&str> = Vec::new();
+        for entry in &self.entries {
+            if let Some(subsection) = &entry.subsection {
+                if entry.section.eq_ignore_ascii_case(section) && !subsections.contains(&subsection.as_str()) {
+                    subsections.push(subsection);
+                }
+            }
+        }
+        subsections
+    }
+}
+
+// Edits of the file at `path` in place, as `git config` makes them:
+// the lines they do not touch are kept as written, comments included.
+impl Config {
+    // Sets `key` in the section, replacing its existing values, or unsets
+    // it when `value` is `None`. New keys go after the last entry of the
+    // section, and a missing section is added at the end of the file; a
+    // section left empty by an unset is removed.
+    pub fn set_value(path: &Path, section: &str, subsection: Option<&str>, key: &str, value: Option<&str>) -> Result<(), GitError> {
+        let mut lines: Vec<ConfigLine> = read_lines(path)?;
+        let target: (String, Option<String>) = (section.to_ascii_lowercase(), subsection.map(str::to_string));
+        let matching: Vec<usize> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.in_section(&target) && !line.header && line.key.as_deref() == Some(&key.to_ascii_lowercase()))
+            .map(|(position, _)| position)
+            .collect();
+
+        let Some(value) = value else {
+            for &position in matching.iter().rev() {
+                lines.remove(position);
+            }
+            if !lines.iter().any(|line| line.in_section(&target) && line.key.is_some()) {
+                lines.retain(|line| !line.in_section(&target));
+            }
+            return write_lines(path, &lines);
+        };
+
+        let entry: ConfigLine = ConfigLine {
+            text: format!("\t{key} = {}\n", format_value(value)),
+            section: Some(target.clone()),
+            header: false,
+            key: Some(key.to_ascii_lowercase()),
+        };
+        if let Some((&last, others)) = matching.split_last() {
+            lines[last] = entry;
+            for &position in others.iter().rev() {
+                lines.remove(position);
+            }
+        } else if let Some(last) = lines.iter().rposition(|line| line.in_section(&target) && (line.header || line.key.is_some())) {
+            lines.insert(last + 1, entry);
+        } else {
+            lines.push(ConfigLine {
+                text: format_section_header(section, subsection),
+                section: Some(target),
+                header: true,
+                key: None,
+            });
+            lines.push(entry);
+        }
+        write_lines(path, &lines)
+    }
+
+    // Renames `[section "from"]` to `[section "to"]`, keeping its entries.
+    // Returns whether the section was found.
+    pub fn rename_section(path: &Path, section: &str, from: &str, to: &str) -> Result<bool, GitError> {
+        let mut lines: Vec<ConfigLine> = read_lines(path)?;
+        let target: (String, Option<String>) = (section.to_ascii_lowercase(), Some(from.to_string()));
+        let renamed: (String, Option<String>) = (section.to_ascii_lowercase(), Some(to.to_string()));
+
+        let mut found: bool = false;
+        for line in lines.iter_mut().filter(|line| line.in_section(&target)) {
+            // A key on the header line goes on a line of its own.
+            if line.header {
+                line.text = match line.key {
+                    Some(_) => format!("{}\t{}", format_section_header(section, Some(to)), header_rest(&line.text).trim_start()),
+                    None => format_section_header(section, Some(to)),
+                };
+                found = true;
+            }
+            line.section = Some(renamed.clone());
+        }
+        if found {
+            write_lines(path, &lines)?;
+        }
+        Ok(found)
+    }
+
+    // Removes the section with all its entries. Returns whether it was
+    // found.
+    pub fn remove_section(path: &Path, section: &str, subsection: Option<&str>) -> Result<bool, GitError> {
+        let mut lines: Vec<ConfigLine> = read_lines(path)?;
+        let target: (String, Option<String>) = (section.to_ascii_lowercase(), subsection.map(str::to_string));
+
+        let count: usize = lines.len();
+        lines.retain(|line| !line.in_section(&target));
+        if lines.len() == count {
+            return Ok(false);
+        }
+        write_lines(path, &lines)?;
+        Ok(true)
+    }
+}
+
+// A line of the file as written, a value continued over several lines
+// counting as one, with the section it falls in and the key it sets.
+#[derive(Debug, Clone)]
+struct ConfigLine {
+    text: String,
+    section: Option<(String, Option<String>)>,
+    header: bool,
+    key: Option<String>,
+}
+
+impl ConfigLine {
+    fn in_section(&self, target: &(String, Option<String>)) -> bool {
+        self.section.as_ref() == Some(target)
+    }
+}
+
+fn read_lines(path: &Path) -> Result<Vec<ConfigLine>, GitError> {
+    let content: String = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(GitError::InvalidConfig(format!("{}: {err}", path.display()))),
+    };
+
+    let mut lines: Vec<ConfigLine> = Vec::new();
+    let mut section: Option<(String, Option<String>)> = None;
+
+    let mut raw_lines = content.split_inclusive('\n').enumerate();
+    while let Some((number, raw)) = raw_lines.next() {
+        let invalid = || GitError::InvalidConfig(format!("bad config line {}", number + 1));
+        let line: &str = raw.trim_end_matches(['\r', '\n']).trim_start();
+
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            lines.push(ConfigLine { text: raw.to_string(), section: section.clone(), header: false, key: None });
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let Some((header, _)) = header.split_once(']') else {
+                return Err(invalid());
+            };
+            section = Some(parse_section_header(header).ok_or_else(invalid)?);
+            let rest: &str = header_rest(line).trim();
+            let key: Option<String> = match rest.is_empty() || rest.starts_with('#') || rest.starts_with(';') {
+                true => None,
+                false => Some(parse_key_value(rest).ok_or_else(invalid)?.0),
+            };
+            lines.push(ConfigLine { text: raw.to_string(), section: section.clone(), header: true, key });
+            continue;
+        }
+
+        let mut text: String = raw.to_string();
+        let mut joined: String = line.to_string();
+        while joined.ends_with('\\') && !joined.ends_with("\\\\") {
+            joined.pop();
+            match raw_lines.next() {
+                Some((_, next)) => {
+                    text.push_str(next);
+                    joined.push_str(next.trim_end_matches(['\r', '\n']));
+                }
+                None => break,
+            }
+        }
+        let (key, _) = parse_key_value(&joined).ok_or_else(invalid)?;
+        lines.push(ConfigLine { text, section: section.clone(), header: false, key: Some(key) });
+    }
+
+    Ok(lines)
+}
+
+// Through `<path>.lock`, renamed over the file once fully written.
+fn write_lines(path: &Path, lines: &[ConfigLine]) -> Result<(), GitError> {
+    let mut content: String = String::new();
+    for line in lines {
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&line.text);
+    }
+
+    let mut lock_path: OsString = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock_path: PathBuf = PathBuf::from(lock_path);
+
+    if let Err(err) = fs::write(&lock_path, content).and_then(|()| fs::rename(&lock_path, path)) {
+        let _ = fs::remove_file(&lock_path);
+        return Err(GitError::InvalidConfig(format!("cannot write {}: {err}", path.display())));
+    }
+    Ok(())
+}
+
+// What follows the `]` of a header line.
+fn header_rest(line: &str) -> &str {
+    let line: &str = line.trim_start().strip_prefix('[').unwrap_or(line);
+    // `]` may appear escaped inside a quoted subsection.
+    let mut in_quotes: bool = false;
+    let mut escaped: bool = false;
+    for (position, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ']' if !in_quotes => return &line[position + 1..],
+            _ => {}
+        }
+    }
+    ""
+}
+
+fn format_section_header(section: &str, subsection: Option<&str>) -> String {
+    match subsection {
+        Some(subsection) => format!("[{section} \"{}\"]\n", subsection.replace('\\', "\\\\").replace('"', "\\\"")),
+        None => format!("[{section}]\n"),
+    }
+}
+
+// Quoted when spaces at either end or comment characters would otherwise
+// be lost.
+fn format_value(value: &str) -> String {
+    let mut escaped: String = String::new();
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{8}' => escaped.push_str("\\b"),
+            _ => escaped.push(c),
+        }
+    }
+
+    let quoted: bool = value.starts_with(' ') || value.ends_with(' ') || value.contains(['#', ';']);
+    match quoted {
+        true => format!("\"{escaped}\""),
+        false => escaped,
+    }
+}
+
+// `section`, `section "subsection"` or the legacy `section.subsection`.
+fn parse_section_header(header: &str) -> Option<(String, Option<String>)> {
+    let header: &str = header.trim();
+
+    if let Some((name, quoted)) = header.split_once(' ') {
+        let quoted: &str = quoted.trim().strip_prefix('"')?.strip_suffix('"')?;
+        let mut subsection: String = String::new();
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => subsection.push(chars.next()?),
+                _ => subsection.push(c),
+            }
+        }
+        return valid_name(name).then(|| (name.to_ascii_lowercase(), Some(subsection)));
+    }
+
+    match header.split_once('.') {
+        Some((name, subsection)) => valid_name(name).then(|| (name.to_ascii_lowercase(), Some(subsection.to_ascii_lowercase()))),
+        None => valid_name(header).then(|| (header.to_ascii_lowercase(), None)),
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+fn parse_key_value(line: &str) -> Option<(String, String)> {
+    let (key, value): (&str, Option<&str>) = match line.split_once('=') {
+        Some((key, value)) => (key.trim(), Some(value)),
+        None => (strip_comment(line).trim(), None),
+    };
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return None;
+    }
+
+    let value: String = match value {
+        Some(value) => parse_value(value)?,
+        None => "true".to_string(),
+    };
+    Some((key.to_ascii_lowercase(), value))
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find(['#', ';']) {
+        Some(position) => &line[..position],
+        None => line,
+    }
+}
+
+// Quotes keep whitespace and comment characters, backslash escapes
+// `\n`, `\t`, `\b`, `\"` and `\\`.
+fn parse_value(raw: &str) -> Option<String> {
+    let mut value: String = String::new();
+    let mut in_quotes: bool = false;
+    // Whitespace is only kept when followed by something else.
+    let mut pending_space: String = String::new();
+
+    let mut chars = raw.trim_start().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '#' | ';' if !in_quotes => break,
+            ' ' | '\t' if !in_quotes => {
+                pending_space.push(c);
+                continue;
+            }
+            '\\' => {
+                let escaped: char = match chars.next()? {
+                    'n' => '\n',
+                    't' => '\t',
+                    'b' => '\u{8}',
+                    c @ ('"' | '\\') => c,
+                    _ => return None,
+                };
+                value.push_str(&pending_space);
+                value.push(escaped);
+            }
+            _ => {
+                value.push_str(&pending_space);
+                value.push(c);
+            }
+        }
+        pending_space.clear();
+    }
+
+    (!in_quotes).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_support::TempRepo;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = Config::parse(
+            "# comment\n\
+             [core]\n\
+             \tbare = false\n\
+             \tFileMode\n\
+             [remote \"origin\"]\n\
+             \turl = https://example.com/repo.git ; trailing comment\n\
+             \tfetch = +refs/heads/*:refs/remotes/origin/*\n\
+             \tfetch = +refs/tags/*:refs/tags/*\n\
+             [branch \"main\"]\n\
+             \tremote = origin\n\
+             \tmerge = refs/heads/main\n\
+             [alias]\n\
+             \tlg = \"log --oneline  # not a comment\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(Some("false"), config.get("core", None, "bare"));
+        assert_eq!(Some("true"), config.get("CORE", None, "filemode"));
+        assert_eq!(Some("https://example.com/repo.git"), config.get("remote", Some("origin"), "url"));
+        assert_eq!(2, config.get_all("remote", Some("origin"), "fetch").len());
+        assert_eq!(Some("log --oneline  # not a comment"), config.get("alias", None, "lg"));
+        assert_eq!(vec!["origin", "main"], config.subsections("remote").into_iter().chain(config.subsections("branch")).collect::<Vec<_>>());
+        assert!(config.get("remote", Some("ORIGIN"), "url").is_none());
+        assert!(Config::parse("key = value\n").is_err());
+    }
+
+    #[test]
+    fn test_edit_config() {
+        let dir: TempRepo = TempRepo::new("edit_config");
+        let path: PathBuf = dir.join("config");
+        fs::write(
+            &path,
+            "# kept\n\
+             [core]\n\
+             \tbare = false\n\
+             [branch \"main\"]\n\
+             \tremote = origin\n\
+             \tmerge = refs/heads/main\n\
+             [alias]\n\
+             \tlg = log \\\n\
+             --oneline\n",
+        )
+        .unwrap();
+
+        Config::set_value(&path, "core", None, "bare", Some("true")).unwrap();
+        Config::set_value(&path, "core", None, "editor", Some(" vi # padded")).unwrap();
+        Config::set_value(&path, "branch", Some("side"), "remote", Some(".")).unwrap();
+        Config::set_value(&path, "alias", None, "lg", None).unwrap();
+        assert!(Config::rename_section(&path, "branch", "main", "trunk").unwrap());
+        Config::set_value(&path, "branch", Some("trunk"), "remote", None).unwrap();
+        assert_eq!(
+            "# kept\n\
+             [core]\n\
+             \tbare = true\n\
+             \teditor = \" vi # padded\"\n\
+             [branch \"trunk\"]\n\
+             \tmerge = refs/heads/main\n\
+             [branch \"side\"]\n\
+             \tremote = .\n",
+            fs::read_to_string(&path).unwrap()
+        );
+
+        let config: Config = Config::load(&path).unwrap();
+        assert_eq!(Some(" vi # padded"), config.get("core", None, "editor"));
+        assert!(Config::remove_section(&path, "branch", Some("trunk")).unwrap());
+        assert!(!Config::remove_section(&path, "branch", Some("trunk")).unwrap());
+        assert_eq!(vec!["side"], Config::load(&path).unwrap().subsections("branch"));
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use crate::GitError;
+
+// Matches are looked up by blocks of this many bytes from the base.
+const DELTA_BLOCK_SIZE: usize = 16;
+const MAX_COPY_SIZE: usize = 0xff_ffff;
+const MAX_INSERT_SIZE: usize = 0x7f;
+
+// Git delta format: `<base size><result size>` as little endian base-128
+// varints, then instructions. An instruction with its MSB set copies a
+// range of the base, the low 7 bits telling which offset/size bytes follow.
+// Otherwise the byte is a count of literal bytes to insert.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, GitError> {
+    let mut index: usize = 0;
+    let base_size: usize = read_size(delta, &mut index)?;
+    let result_size: usize = read_size(delta, &mut index)?;
+
+    if base_size != base.len() {
+        return Err(GitError::InvalidDelta(format!(
+            "base size {} does not match expected {base_size}",
+            base.len()
+        )));
+    }
+
+    let mut result: Vec<u8> = Vec::with_capacity(result_size);
+
+    while index < delta.len() {
+        let instruction: u8 = delta[index];
+        index += 1;
+
+        if instruction & 0x80 != 0 {
+            let mut offset: usize = 0;
+            for i in 0..4 {
+                if instruction & (1 << i) != 0 {
+                    offset |= (*byte_at(delta, index)? as usize) << (8 * i);
+                    index += 1;
+                }
+            }
+
+            let mut size: usize = 0;
+            for i in 0..3 {
+                if instruction & (0x10 << i) != 0 {
+                    size |= (*byte_at(delta, index)? as usize) << (8 * i);
+                    index += 1;
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+
+            let Some(chunk) = base.get(offset..offset + size) else {
+                return Err(GitError::InvalidDelta("copy out of base bounds".to_string()));
+            };
+            result.extend_from_slice(chunk);
+        }
+        else if instruction != 0 {
+            let size: usize = instruction as usize;
+            let Some(chunk) = delta.get(index..index + size) else {
+                return Err(GitError::InvalidDelta("insert past end of delta".to_string()));
+            };
+            result.extend_from_slice(chunk);
+            index += size;
+        }
+        else {
+            return Err(GitError::InvalidDelta("reserved instruction 0".to_string()));
+        }
+    }
+
+    if result.len() != result_size {
+        return Err(GitError::InvalidDelta(format!(
+            "result size {} does not match expected {result_size}",
+            result.len()
+        )));
+    }
+
+    Ok(result)
+}
+
+// Size of the object a delta produces, without applying it.
+pub fn delta_result_size(delta: &[u8]) -> Result<usize, GitError> {
+    let mut index: usize = 0;
+    let _base_size: usize = read_size(delta, &mut index)?;
+    read_size(delta, &mut index)
+}
+
+// Encodes `target` as instructions against `base`: the base is indexed by
+// aligned blocks, and every match found while scanning the target is
+// extended both ways before being emitted as a copy.
+pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta: Vec<u8> = Vec::new();
+    write_size(&mut delta, base.len());
+    write_size(&mut delta, target.len());
+
+    let mut blocks: HashMap<&[u8], usize> = HashMap::new();
+    for (i, block) in base.chunks_exact(DELTA_BLOCK_SIZE).enumerate().rev() {
+        blocks.insert(block, i * DELTA_BLOCK_SIZE);
+    }
+
+    let mut insert_start: usize = 0;
+    let mut index: usize = 0;
+    while index + DELTA_BLOCK_SIZE <= target.len() {
+        let Some(&base_start) = blocks.get(&target[index..index + DELTA_BLOCK_SIZE]) else {
+            index += 1;
+            continue;
+        };
+
+        let mut base_start: usize = base_start;
+        let mut target_start: usize = index;
+        while target_start > insert_start
+            && base_start > 0
+            && base[base_start - 1] == target[target_start - 1]
+        {
+            base_start -= 1;
+            target_start -= 1;
+        }
+
+        let mut length: usize = 0;
+        while base_start + length < base.len()
+            && target_start + length < target.len()
+            && base[base_start + length] == target[target_start + length]
+        {
+            length += 1;
+        }
+
+        write_insert(&mut delta, &target[insert_start..target_start]);
+        write_copy(&mut delta, base_start, length);
+
+        index = target_start + length;
+        insert_start = index;
+    }
+    write_insert(&mut delta, &target[insert_start..]);
+
+    delta
+}
+
+fn write_size(delta: &mut Vec<u8>, mut size: usize) {
+    loop {
+        let byte: u8 = (size & 0x7f) as u8;
+        size >>= 7;
+        if size == 0 {
+            delta.push(byte);
+            return;
+        }
+        delta.push(byte | 0x80);
+    }
+}
+
+fn write_insert(delta: &mut Vec<u8>, bytes: &[u8]) {
+    for chunk in bytes.chunks(MAX_INSERT_SIZE) {
+        delta.push(chunk.len() as u8);
+        delta.extend_from_slice(chunk);
+    }
+}
+
+fn write_copy(delta: &mut Vec<u8>, mut offset: usize, mut length: usize) {
+    while length > 0 {
+        let size: usize = length.min(MAX_COPY_SIZE);
+
+        let mut instruction: u8 = 0x80;
+        let mut operands: Vec<u8> = Vec::new();
+        for i in 0..4 {
+            let byte: u8 = (offset >> (8 * i)) as u8;
+            if byte != 0 {
+                instruction |= 1 << i;
+                operands.push(byte);
+            }
+        }
+        for i in 0..3 {
+            let byte: u8 = (size >> (8 * i)) as u8;
+            if byte != 0 {
+                instruction |= 0x10 << i;
+                operands.push(byte);
+            }
+        }
+
+        delta.push(instruction);
+        delta.extend(operands);
+
+        offset += size;
+        length -= size;
+    }
+}
+
+fn read_size(delta: &[u8], index: &mut usize) -> Result<usize, GitError> {
+    let mut size: usize = 0;
+    let mut shift: usize = 0;
+
+    loop {
+        let byte: u8 = *byte_at(delta, *index)?;
+        *index += 1;
+
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+        if shift > 63 {
+            return Err(GitError::InvalidDelta("size varint too long".to_string()));
+        }
+    }
+}
+
+fn byte_at(delta: &[u8], index: usize) -> Result<&u8, GitError> {
+    match delta.get(index) {
+        Some(byte) => Ok(byte),
+        None => Err(GitError::InvalidDelta("truncated delta".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_delta() {
+        let base: &[u8] = b"hello world";
+        // base size 11, result size 14: copy 6 bytes at 0, insert "there", copy 3 at 5.
+        let delta: Vec<u8> = vec![
+            11, 14, 0x90, 6, 5, b't', b'h', b'e', b'r', b'e', 0x91, 5, 3,
+        ];
+
+        assert_eq!(b"hello there wo".to_vec(), apply_delta(base, &delta).unwrap());
+        assert_eq!(14, delta_result_size(&delta).unwrap());
+    }
+
+    #[test]
+    fn test_create_delta_round_trip() {
+        let base: Vec<u8> = (0..2000).flat_map(|i: u32| format!("line {i}\n").into_bytes()).collect();
+        let mut target: Vec<u8> = base.clone();
+        target.splice(5000..5010, b"an edit in the middle".iter().copied());
+        target.extend_from_slice(b"and a new tail\n");
+
+        let delta: Vec<u8> = create_delta(&base, &target);
+
+        assert!(delta.len() < 100);
+        assert_eq!(target, apply_delta(&base, &delta).unwrap());
+        assert_eq!(b"new".to_vec(), apply_delta(b"", &create_delta(b"", b"new")).unwrap());
+    }
+
+    #[test]
+    fn test_apply_delta_rejects_wrong_base() {
+        let delta: Vec<u8> = vec![3, 1, 1, b'x'];
+
+        assert!(apply_delta(b"hello", &delta).is_err());
+    }
+}
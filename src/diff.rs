@@ -0,0 +1,578 @@
+use std::collections::BTreeMap;
+
+use std::fs;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::fetch::ABBREV;
+use crate::index::Index;
+use crate::index::IndexEntry;
+use crate::odb;
+use crate::odb::ObjectStore;
+use crate::pathspec::Pathspec;
+use crate::read_link_target;
+use crate::worktree::index_mtime;
+use crate::worktree::is_stat_clean;
+use crate::worktree::GITLINK_MODE;
+use crate::EntryMode;
+use crate::GitError;
+
+pub const DEFAULT_CONTEXT: usize = 3;
+
+const ZERO_ID: &str = "0000000000000000000000000000000000000000";
+
+// Like git, a NUL in the first 8000 bytes makes a file binary.
+const BINARY_CHECK_LENGTH: usize = 8000;
+
+// Hunk headers show at most this much of the enclosing function line.
+const FUNCNAME_LENGTH: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Equal,
+    Delete,
+    Insert,
+}
+
+// Shortest edit script turning `old` into `new`, one `Edit` per line
+// kept, deleted or inserted, as found by Myers' O(ND) algorithm in its
+// linear space variant.
+pub fn myers_diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let mut edits: Vec<Edit> = Vec::with_capacity(old.len().max(new.len()));
+    diff_into(old, new, &mut edits);
+    edits
+}
+
+// Lines common to both ends are matched first, then the middle is split
+// where the forward and backward searches meet and each side is diffed
+// the same way, so only two frontiers are kept at any time.
+fn diff_into<T: PartialEq>(old: &[T], new: &[T], edits: &mut Vec<Edit>) {
+    let prefix: usize = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix: usize = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_middle, new_middle): (&[T], &[T]) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    edits.extend(std::iter::repeat(Edit::Equal).take(prefix));
+    if old_middle.is_empty() || new_middle.is_empty() {
+        edits.extend(std::iter::repeat(Edit::Delete).take(old_middle.len()));
+        edits.extend(std::iter::repeat(Edit::Insert).take(new_middle.len()));
+    } else {
+        match middle_split(old_middle, new_middle) {
+            Some((x, y)) => {
+                diff_into(&old_middle[..x], &new_middle[..y], edits);
+                diff_into(&old_middle[x..], &new_middle[y..], edits);
+            }
+            None => {
+                edits.extend(std::iter::repeat(Edit::Delete).take(old_middle.len()));
+                edits.extend(std::iter::repeat(Edit::Insert).take(new_middle.len()));
+            }
+        }
+    }
+    edits.extend(std::iter::repeat(Edit::Equal).take(suffix));
+}
+
+// Point where a shortest edit script crosses the middle snake, found by
+// searching from both corners at once. `forward[k]` is the furthest x
+// reached from the start on diagonal k = x - y, `backward[k]` how far
+// from the end paths got on the diagonal counted from the other corner.
+// Diagonals that leave the grid are no longer extended.
+fn middle_split<T: PartialEq>(old: &[T], new: &[T]) -> Option<(usize, usize)> {
+    let (n, m): (isize, isize) = (old.len() as isize, new.len() as isize);
+    let max_d: isize = (n + m + 1) / 2;
+    let offset: isize = max_d + 1;
+    let mut forward: Vec<isize> = vec![-1; 2 * max_d as usize + 3];
+    let mut backward: Vec<isize> = vec![-1; 2 * max_d as usize + 3];
+    forward[offset as usize + 1] = 0;
+    backward[offset as usize + 1] = 0;
+
+    let delta: isize = n - m;
+    // With an odd delta the paths meet during a forward step, otherwise
+    // during a backward one.
+    let front: bool = delta % 2 != 0;
+    let (mut forward_start, mut forward_end, mut backward_start, mut backward_end): (isize, isize, isize, isize) = (0, 0, 0, 0);
+    let at = |v: &[isize], k: isize| v.get((k + offset) as usize).copied().unwrap_or(-1);
+
+    for d in 0..=max_d {
+        for k in (-d + forward_start..=d - forward_end).step_by(2) {
+            let mut x: isize = match k == -d || (k != d && at(&forward, k - 1) < at(&forward, k + 1)) {
+                true => at(&forward, k + 1),
+                false => at(&forward, k - 1) + 1,
+            };
+            let mut y: isize = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[(k + offset) as usize] = x;
+
+            if x > n {
+                forward_end += 2;
+            } else if y > m {
+                forward_start += 2;
+            } else if front {
+                let reached: isize = at(&backward, delta - k);
+                if reached != -1 && x >= n - reached {
+                    return Some((x as usize, y as usize));
+                }
+            }
+        }
+
+        for k in (-d + backward_start..=d - backward_end).step_by(2) {
+            let mut x: isize = match k == -d || (k != d && at(&backward, k - 1) < at(&backward, k + 1)) {
+                true => at(&backward, k + 1),
+                false => at(&backward, k - 1) + 1,
+            };
+            let mut y: isize = x - k;
+            while x < n && y < m && old[(n - x - 1) as usize] == new[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[(k + offset) as usize] = x;
+
+            if x > n {
+                backward_end += 2;
+            } else if y > m {
+                backward_start += 2;
+            } else if !front {
+                let forward_x: isize = at(&forward, delta - k);
+                if forward_x != -1 && forward_x >= n - x {
+                    let forward_y: isize = forward_x - (delta - k);
+                    return Some((forward_x as usize, forward_y as usize));
+                }
+            }
+        }
+    }
+    None
+}
+
+pub fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(BINARY_CHECK_LENGTH)].contains(&0)
+}
+
+// Hunks of a unified diff between two texts, with `context` unchanged
+// lines around changes. Changes closer than twice the context share a
+// hunk.
+pub fn unified_diff(old: &[u8], new: &[u8], context: usize) -> Vec<u8> {
+    let old_lines: Vec<&[u8]> = old.split_inclusive(|&b| b == b'\n').collect();
+    let new_lines: Vec<&[u8]> = new.split_inclusive(|&b| b == b'\n').collect();
+    let edits: Vec<Edit> = group_changes(myers_diff(&old_lines, &new_lines));
+
+    // Line of each edit in both files.
+    let mut positions: Vec<(usize, usize)> = Vec::with_capacity(edits.len());
+    let (mut old_line, mut new_line): (usize, usize) = (0, 0);
+    for edit in &edits {
+        positions.push((old_line, new_line));
+        match edit {
+            Edit::Equal => {
+                old_line += 1;
+                new_line += 1;
+            }
+            Edit::Delete => old_line += 1,
+            Edit::Insert => new_line += 1,
+        }
+    }
+
+    let changes: Vec<usize> = (0..edits.len()).filter(|&i| edits[i] != Edit::Equal).collect();
+    let mut out: Vec<u8> = Vec::new();
+    let mut first: usize = 0;
+    while first < changes.len() {
+        let mut last: usize = first;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] - 1 <= 2 * context {
+            last += 1;
+        }
+
+        let start: usize = changes[first].saturating_sub(context);
+        let end: usize = (changes[last] + context + 1).min(edits.len());
+        let (old_start, new_start): (usize, usize) = positions[start];
+        let old_count: usize = edits[start..end].iter().filter(|&&edit| edit != Edit::Insert).count();
+        let new_count: usize = edits[start..end].iter().filter(|&&edit| edit != Edit::Delete).count();
+
+        out.extend(format!("@@ -{} +{} @@", hunk_range(old_start, old_count), hunk_range(new_start, new_count)).into_bytes());
+        if let Some(funcname) = find_funcname(&old_lines[..old_start]) {
+            out.push(b' ');
+            out.extend(funcname);
+        }
+        out.push(b'\n');
+
+        for (edit, &(old_line, new_line)) in edits[start..end].iter().zip(&positions[start..end]) {
+            let (prefix, line): (u8, &[u8]) = match edit {
+                Edit::Equal => (b' ', old_lines[old_line]),
+                Edit::Delete => (b'-', old_lines[old_line]),
+                Edit::Insert => (b'+', new_lines[new_line]),
+            };
+            out.push(prefix);
+            out.extend(line);
+            if !line.ends_with(b"\n") {
+                out.extend(b"\n\\ No newline at end of file\n");
+            }
+        }
+        first = last + 1;
+    }
+    out
+}
+
+// Deletions before insertions within each run of changes, as git shows
+// them.
+fn group_changes(edits: Vec<Edit>) -> Vec<Edit> {
+    let mut grouped: Vec<Edit> = Vec::with_capacity(edits.len());
+    for run in edits.split_inclusive(|&edit| edit == Edit::Equal) {
+        let (changes, equal): (&[Edit], &[Edit]) = match run.last() {
+            Some(Edit::Equal) => (&run[..run.len() - 1], &run[run.len() - 1..]),
+            _ => (run, &[]),
+        };
+        grouped.extend(changes.iter().filter(|&&edit| edit == Edit::Delete));
+        grouped.extend(changes.iter().filter(|&&edit| edit == Edit::Insert));
+        grouped.extend(equal);
+    }
+    grouped
+}
+
+// `start,count` with 1-based lines; an empty range names the line before.
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{count}", start + 1),
+    }
+}
+
+// Last line before the hunk starting like an identifier, which git's
+// default funcname pattern takes for a function header.
+fn find_funcname<'a>(lines: &[&'a [u8]]) -> Option<&'a [u8]> {
+    let line: &[u8] = lines
+        .iter()
+        .rev()
+        .find(|line| line.first().is_some_and(|&c| c.is_ascii_alphabetic() || c == b'_' || c == b'$'))?;
+    let line: &[u8] = &line[..line.len().min(FUNCNAME_LENGTH)];
+    let end: usize = line.iter().rposition(|c| !c.is_ascii_whitespace()).map_or(0, |end| end + 1);
+    Some(&line[..end])
+}
+
+// One side of a file pair. Worktree files carry their content, blobs are
+// read from the object store when needed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffFile {
+    pub mode: u32,
+    pub sha1_hash: String,
+    pub content: Option<Vec<u8>>,
+}
+
+// A path that differs between two snapshots, missing on the side where it
+// does not exist. Both sides are missing for unmerged paths.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePair {
+    pub path: String,
+    pub old: Option<DiffFile>,
+    pub new: Option<DiffFile>,
+}
+
+// Files of a tree, the index or the worktree by path.
+pub type Snapshot = BTreeMap<String, DiffFile>;
+
+fn entry_file(entry: &IndexEntry) -> DiffFile {
+    DiffFile { mode: entry.mode, sha1_hash: entry.sha1_hash.clone(), content: None }
+}
+
+pub fn tree_snapshot(object_store: &dyn ObjectStore, tree_sha: &str) -> Result<Snapshot, GitError> {
+    let index: Index = Index::from_tree(object_store, tree_sha)?;
+    Ok(index.entries.iter().map(|entry| (entry.path.clone(), entry_file(entry))).collect())
+}
+
+// Stage 0 entries, and the paths left unmerged.
+pub fn index_snapshot(index: &Index) -> (Snapshot, Vec<String>) {
+    let mut snapshot: Snapshot = Snapshot::new();
+    let mut unmerged: Vec<String> = Vec::new();
+    for entry in &index.entries {
+        if entry.stage != 0 {
+            if unmerged.last() != Some(&entry.path) {
+                unmerged.push(entry.path.clone());
+            }
+        } else if !entry.intent_to_add {
+            snapshot.insert(entry.path.clone(), entry_file(entry));
+        }
+    }
+    (snapshot, unmerged)
+}
+
+// The worktree files the index tracks. Those whose stat data matches
+// their entry are taken as the entry without being read.
+pub fn worktree_snapshot(git_dir: &Path, worktree: &Path, index: &Index) -> Result<Snapshot, GitError> {
+    let index_mtime: Option<(u32, u32)> = index_mtime(&git_dir.join("index"));
+    let mut snapshot: Snapshot = Snapshot::new();
+
+    for entry in index.entries.iter().filter(|entry| entry.stage == 0) {
+        let path: PathBuf = worktree.join(&entry.path);
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+
+        let file: DiffFile = if entry.mode == GITLINK_MODE {
+            if !metadata.is_dir() {
+                continue;
+            }
+            entry_file(entry)
+        } else if metadata.is_dir() {
+            continue;
+        } else if !entry.intent_to_add && is_stat_clean(entry, &metadata, index_mtime) {
+            entry_file(entry)
+        } else {
+            let mode: EntryMode = EntryMode::from_metadata(&metadata);
+            let content: Vec<u8> = match mode {
+                EntryMode::SymbolicLink => read_link_target(&path)?,
+                _ => match fs::read(&path) {
+                    Ok(content) => content,
+                    Err(err) => return Err(GitError::CreateBlob(format!("fs::read: {err}"))),
+                },
+            };
+            DiffFile {
+                mode: mode.as_index_mode(),
+                sha1_hash: odb::hash_object("blob", &content),
+                content: Some(content),
+            }
+        };
+        snapshot.insert(entry.path.clone(), file);
+    }
+
+    Ok(snapshot)
+}
+
+// Paths whose mode or content differ, in path order.
+pub fn compare_snapshots(old: &Snapshot, new: &Snapshot, pathspec: &Pathspec) -> Vec<FilePair> {
+    let mut paths: Vec<&String> = old.keys().chain(new.keys()).collect();
+    paths.sort();
+    paths.dedup();
+
+    paths
+        .into_iter()
+        .filter(|path| pathspec.matches(path))
+        .filter_map(|path| {
+            let (old, new): (Option<&DiffFile>, Option<&DiffFile>) = (old.get(path), new.get(path));
+            let unchanged: bool = match (old, new) {
+                (Some(old), Some(new)) => old.mode == new.mode && old.sha1_hash.eq(&new.sha1_hash),
+                _ => false,
+            };
+            (!unchanged).then(|| FilePair { path: path.clone(), old: old.cloned(), new: new.cloned() })
+        })
+        .collect()
+}
+
+// `git diff` output for the pairs: a `diff --git` header, mode and index
+// lines, then the hunks or a note for binary files. Changing between a
+// file, a symbolic link and a submodule shows as a deletion followed by
+// an addition.
+pub fn format_patch(object_store: &dyn ObjectStore, pairs: &[FilePair], context: usize) -> Result<Vec<u8>, GitError> {
+    let mut out: Vec<u8> = Vec::new();
+    for pair in pairs {
+        match (&pair.old, &pair.new) {
+            (None, None) => out.extend(format!("* Unmerged path {}\n", pair.path).into_bytes()),
+            (Some(old), Some(new)) if (old.mode & 0o170000) != (new.mode & 0o170000) => {
+                format_file_pair(object_store, &pair.path, Some(old), None, context, &mut out)?;
+                format_file_pair(object_store, &pair.path, None, Some(new), context, &mut out)?;
+            }
+            (old, new) => format_file_pair(object_store, &pair.path, old.as_ref(), new.as_ref(), context, &mut out)?,
+        }
+    }
+    Ok(out)
+}
+
+fn format_file_pair(
+    object_store: &dyn ObjectStore,
+    path: &str,
+    old: Option<&DiffFile>,
+    new: Option<&DiffFile>,
+    context: usize,
+    out: &mut Vec<u8>,
+) -> Result<(), GitError> {
+    let mut header: String = format!("diff --git a/{path} b/{path}\n");
+    match (old, new) {
+        (None, Some(new)) => header.push_str(&format!("new file mode {:06o}\n", new.mode)),
+        (Some(old), None) => header.push_str(&format!("deleted file mode {:06o}\n", old.mode)),
+        (Some(old), Some(new)) if old.mode != new.mode => {
+            header.push_str(&format!("old mode {:06o}\nnew mode {:06o}\n", old.mode, new.mode));
+        }
+        _ => {}
+    }
+
+    let old_id: &str = old.map_or(ZERO_ID, |old| &old.sha1_hash);
+    let new_id: &str = new.map_or(ZERO_ID, |new| &new.sha1_hash);
+    if old_id.eq(new_id) {
+        out.extend(header.into_bytes());
+        return Ok(());
+    }
+    header.push_str(&format!("index {}..{}", &old_id[..ABBREV], &new_id[..ABBREV]));
+    match (old, new) {
+        (Some(old), Some(new)) if old.mode == new.mode => header.push_str(&format!(" {:06o}\n", old.mode)),
+        _ => header.push('\n'),
+    }
+    out.extend(header.into_bytes());
+
+    let old_content: Vec<u8> = match old {
+        Some(old) => read_content(object_store, old)?,
+        None => Vec::new(),
+    };
+    let new_content: Vec<u8> = match new {
+        Some(new) => read_content(object_store, new)?,
+        None => Vec::new(),
+    };
+    let old_name: String = old.map_or("/dev/null".to_string(), |_| format!("a/{path}"));
+    let new_name: String = new.map_or("/dev/null".to_string(), |_| format!("b/{path}"));
+
+    if is_binary(&old_content) || is_binary(&new_content) {
+        out.extend(format!("Binary files {old_name} and {new_name} differ\n").into_bytes());
+        return Ok(());
+    }
+
+    let hunks: Vec<u8> = unified_diff(&old_content, &new_content, context);
+    if !hunks.is_empty() {
+        out.extend(format!("--- {old_name}\n+++ {new_name}\n").into_bytes());
+        out.extend(hunks);
+    }
+    Ok(())
+}
+
+// Submodules show as the commit they point to.
+fn read_content(object_store: &dyn ObjectStore, file: &DiffFile) -> Result<Vec<u8>, GitError> {
+    if let Some(content) = &file.content {
+        return Ok(content.clone());
+    }
+    if file.mode == GITLINK_MODE {
+        return Ok(format!("Subproject commit {}\n", file.sha1_hash).into_bytes());
+    }
+    Ok(object_store.read(&file.sha1_hash)?.content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::open_object_store_at;
+    use crate::test_support::git;
+    use crate::test_support::git_output;
+    use crate::test_support::TempRepo;
+
+    fn lcs_length(old: &[u8], new: &[u8]) -> usize {
+        let mut lengths: Vec<Vec<usize>> = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for i in 0..old.len() {
+            for j in 0..new.len() {
+                lengths[i + 1][j + 1] = match old[i] == new[j] {
+                    true => lengths[i][j] + 1,
+                    false => lengths[i][j + 1].max(lengths[i + 1][j]),
+                };
+            }
+        }
+        lengths[old.len()][new.len()]
+    }
+
+    #[test]
+    fn test_myers_diff_is_minimal() {
+        let mut seed: u64 = 42;
+        let mut random = |bound: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % bound
+        };
+
+        for _ in 0..200 {
+            let old: Vec<u8> = (0..random(30)).map(|_| random(4) as u8).collect();
+            let new: Vec<u8> = (0..random(30)).map(|_| random(4) as u8).collect();
+            let edits: Vec<Edit> = myers_diff(&old, &new);
+
+            // The script turns `old` into `new`...
+            let (mut i, mut j): (usize, usize) = (0, 0);
+            let mut rebuilt: Vec<u8> = Vec::new();
+            for edit in &edits {
+                match edit {
+                    Edit::Equal => {
+                        assert_eq!(old[i], new[j]);
+                        rebuilt.push(old[i]);
+                        i += 1;
+                        j += 1;
+                    }
+                    Edit::Delete => i += 1,
+                    Edit::Insert => {
+                        rebuilt.push(new[j]);
+                        j += 1;
+                    }
+                }
+            }
+            assert_eq!((old.len(), &new), (i, &rebuilt));
+
+            // ... keeping a longest common subsequence.
+            let kept: usize = edits.iter().filter(|&&edit| edit == Edit::Equal).count();
+            assert_eq!(lcs_length(&old, &new), kept);
+        }
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let old: &[u8] = b"fn main() {\n    a();\n    b();\n    c();\n    d();\n    e();\n    f();\n    g();\n}\nend";
+        let new: &[u8] = b"fn main() {\n    a();\n    B();\n    c();\n    d();\n    e();\n    f();\n    g();\n}\nend\n";
+        assert_eq!(
+            "@@ -1,10 +1,10 @@\n fn main() {\n     a();\n-    b();\n+    B();\n     c();\n     d();\n     e();\n     f();\n\
+             \x20    g();\n }\n-end\n\\ No newline at end of file\n+end\n",
+            String::from_utf8(unified_diff(old, new, 3)).unwrap()
+        );
+        assert_eq!(
+            "@@ -1,5 +1,5 @@\n fn main() {\n     a();\n-    b();\n+    B();\n     c();\n     d();\n\
+             @@ -8,3 +8,3 @@ fn main() {\n     g();\n }\n-end\n\\ No newline at end of file\n+end\n",
+            String::from_utf8(unified_diff(old, new, 2)).unwrap()
+        );
+        assert_eq!(
+            "@@ -3 +3 @@ fn main() {\n-    b();\n+    B();\n@@ -10 +10 @@ fn main() {\n-end\n\\ No newline at end of file\n+end\n",
+            String::from_utf8(unified_diff(old, new, 0)).unwrap()
+        );
+        assert_eq!("@@ -0,0 +1,2 @@\n+a\n+b\n", String::from_utf8(unified_diff(b"", b"a\nb\n", 3)).unwrap());
+        assert!(unified_diff(b"same\n", b"same\n", 3).is_empty());
+        assert!(is_binary(b"a\0b"));
+    }
+
+    #[test]
+    fn test_diff_matches_git() {
+        let repo: TempRepo = TempRepo::new("diff");
+        git(&repo, &["init", "-q"]);
+        let numbers: String = (1..=30).map(|n| format!("{n}\n")).collect();
+        fs::write(repo.join("numbers"), &numbers).unwrap();
+        fs::write(repo.join("binary"), b"bin\0ary").unwrap();
+        fs::write(repo.join("script"), "echo\n").unwrap();
+        fs::write(repo.join("removed"), "removed\n").unwrap();
+        std::os::unix::fs::symlink("numbers", repo.join("link")).unwrap();
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-q", "-m", "first"]);
+        let first: String = git(&repo, &["rev-parse", "HEAD^{tree}"]);
+
+        fs::write(repo.join("numbers"), numbers.replace("4\n", "four\n").replace("25\n", "")).unwrap();
+        fs::write(repo.join("binary"), b"bin\0ary\0changed").unwrap();
+        fs::set_permissions(repo.join("script"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_file(repo.join("removed")).unwrap();
+        fs::remove_file(repo.join("link")).unwrap();
+        fs::write(repo.join("link"), "a file now\n").unwrap();
+        fs::write(repo.join("added"), "added\n").unwrap();
+        git(&repo, &["add", "added"]);
+
+        let object_store: Box<dyn ObjectStore> = open_object_store_at(&repo.join(".git/objects"));
+        let index: Index = Index::load(&repo.join(".git/index")).unwrap();
+        let (index_files, _): (Snapshot, Vec<String>) = index_snapshot(&index);
+        let worktree_files: Snapshot = worktree_snapshot(&repo.join(".git"), &repo, &index).unwrap();
+        let head_files: Snapshot = tree_snapshot(object_store.as_ref(), &first).unwrap();
+        let everything: Pathspec = Pathspec::default();
+
+        let pairs: Vec<FilePair> = compare_snapshots(&index_files, &worktree_files, &everything);
+        assert_eq!(git_output(&repo, &["diff"], None), format_patch(object_store.as_ref(), &pairs, DEFAULT_CONTEXT).unwrap());
+        assert_eq!(git_output(&repo, &["diff", "-U1"], None), format_patch(object_store.as_ref(), &pairs, 1).unwrap());
+        let pairs: Vec<FilePair> = compare_snapshots(&head_files, &index_files, &everything);
+        assert_eq!(git_output(&repo, &["diff", "--cached"], None), format_patch(object_store.as_ref(), &pairs, DEFAULT_CONTEXT).unwrap());
+
+        git(&repo, &["add", "-A"]);
+        git(&repo, &["commit", "-q", "-m", "second"]);
+        let second: String = git(&repo, &["rev-parse", "HEAD^{tree}"]);
+        let second_files: Snapshot = tree_snapshot(object_store.as_ref(), &second).unwrap();
+        let pairs: Vec<FilePair> = compare_snapshots(&head_files, &second_files, &Pathspec::new(&["numbers".to_string()]));
+        assert_eq!(git_output(&repo, &["diff", &first, &second, "--", "numbers"], None), format_patch(object_store.as_ref(), &pairs, DEFAULT_CONTEXT).unwrap());
+    }
+}
@@ -0,0 +1,495 @@
+use std::collections::HashSet;
+
+use std::fs;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::index_pack::index_pack;
+use crate::index_pack::store_pack;
+use crate::index_pack::IndexedPack;
+use crate::is_ancestor;
+use crate::odb::ObjectStore;
+use crate::open_object_store_at;
+use crate::protocol::fetch_pack;
+use crate::protocol::RefAdvertisement;
+use crate::protocol::RemoteRef;
+use crate::refs::list_refs;
+use crate::refs::read_ref;
+use crate::refs::read_symbolic_ref;
+use crate::refs::write_ref;
+use crate::refspec::Refspec;
+use crate::transport::open_transport;
+use crate::transport::Transport;
+use crate::transport::UPLOAD_PACK;
+use crate::GitError;
+
+pub const ABBREV: usize = 7;
+// Room for `<abbrev>...<abbrev>`.
+pub const SUMMARY_WIDTH: usize = 2 * ABBREV + 3;
+// Narrowest source column, as git pads short branch names.
+const MIN_SRC_WIDTH: usize = 10;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FetchOptions {
+    // `-q`: no progress from the remote and no report of updated refs.
+    pub quiet: bool,
+    // `-u`: the checked out branch may be updated too.
+    pub update_head_ok: bool,
+}
+
+// One line of the fetch report, like ` + 1234567...89abcde main -> origin/main`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RefUpdate {
+    pub flag: char,
+    pub summary: String,
+    pub src: String,
+    pub dst: String,
+    pub note: Option<String>,
+}
+
+impl RefUpdate {
+    pub fn is_rejected(&self) -> bool {
+        self.flag == '!'
+    }
+
+    fn format(&self, src_width: usize) -> String {
+        let note: String = match &self.note {
+            Some(note) => format!("  ({note})"),
+            None => String::new(),
+        };
+        format!(
+            " {} {:<SUMMARY_WIDTH$} {:<src_width$} -> {}{note}",
+            self.flag, self.summary, self.src, self.dst
+        )
+    }
+}
+
+// Remote branch for `git fetch` with no argument: the upstream remote of
+// the current branch, or origin.
+pub fn default_remote(git_dir: &Path) -> Result<String, GitError> {
+    let config: Config = Config::load(&git_dir.join("config"))?;
+    let branch: Option<String> = read_symbolic_ref(git_dir, "HEAD")?
+        .and_then(|target| target.strip_prefix("refs/heads/").map(str::to_string));
+
+    let remote: Option<&str> = branch.and_then(|branch| config.get("branch", Some(&branch), "remote"));
+    Ok(remote.unwrap_or("origin").to_string())
+}
+
+// Remote-tracking ref the branch follows, from `branch.<name>.remote` and
+// `branch.<name>.merge` mapped through the remote's fetch refspecs, or the
+// merge ref itself when the remote is ".".
+pub fn branch_upstream(config: &Config, branch: &str) -> Result<Option<String>, GitError> {
+    let (Some(remote), Some(merge)) = (
+        config.get("branch", Some(branch), "remote"),
+        config.get("branch", Some(branch), "merge"),
+    ) else {
+        return Ok(None);
+    };
+    if remote.eq(".") {
+        return Ok(Some(merge.to_string()));
+    }
+
+    let (_, refspecs): (String, Vec<Refspec>) = remote_url_and_refspecs(config, remote)?;
+    Ok(refspecs.iter().find_map(|refspec| refspec.map(merge)))
+}
+
+// Fetches from `remote` (a configured remote or a URL) the refs named by
+// `refspecs`, or by `remote.<name>.fetch` when there are none. Objects the
+// local refs already reach are announced as `have`s so that only what is
+// missing gets sent. Returns the ref updates, rejected ones included.
+pub fn fetch(git_dir: &Path, remote: &str, refspecs: &[String], options: FetchOptions) -> Result<Vec<RefUpdate>, GitError> {
+    let config: Config = Config::load(&git_dir.join("config"))?;
+    let (url, configured): (String, Vec<Refspec>) = remote_url_and_refspecs(&config, remote)?;
+
+    let mut transport: Box<dyn Transport> = open_transport(&url)?;
+    let advertisement: RefAdvertisement = transport.discover_refs(UPLOAD_PACK)?;
+
+    // Refs written to FETCH_HEAD (with whether they are for merging), and
+    // local refs to update (with whether non fast-forwards are allowed).
+    let mut fetched: Vec<(RemoteRef, bool)> = Vec::new();
+    let mut updates: Vec<(RemoteRef, String, bool)> = Vec::new();
+
+    if refspecs.is_empty() {
+        let merge_ref: Option<String> = read_symbolic_ref(git_dir, "HEAD")?
+            .and_then(|target| target.strip_prefix("refs/heads/").map(str::to_string))
+            .filter(|branch| config.get("branch", Some(branch), "remote") == Some(remote))
+            .and_then(|branch| config.get("branch", Some(&branch), "merge").map(str::to_string));
+
+        for refspec in &configured {
+            for remote_ref in matching_refs(&advertisement, refspec)? {
+                fetched.push((remote_ref.clone(), merge_ref.as_ref() == Some(&remote_ref.name)));
+                if let Some(dst) = refspec.map(&remote_ref.name) {
+                    updates.push((remote_ref.clone(), dst, refspec.force));
+                }
+            }
+        }
+
+        if configured.is_empty() {
+            let Some(head) = advertisement.find("HEAD") else {
+                return Err(GitError::InvalidRef("couldn't find remote ref HEAD".to_string()));
+            };
+            fetched.push((head.clone(), true));
+        }
+    } else {
+        for refspec in refspecs {
+            let refspec: Refspec = Refspec::parse(refspec)?;
+            for remote_ref in matching_refs(&advertisement, &refspec)? {
+                fetched.push((remote_ref.clone(), true));
+
+                let dst: Option<String> = match (&refspec.dst, refspec.is_glob()) {
+                    (Some(_), true) => refspec.map(&remote_ref.name),
+                    (Some(dst), false) => Some(expand_local_ref(dst, &remote_ref.name)),
+                    (None, _) => None,
+                };
+                if let Some(dst) = dst {
+                    updates.push((remote_ref.clone(), dst, refspec.force));
+                }
+
+                // Remote-tracking refs are updated too, as the configured
+                // refspecs say.
+                for configured in &configured {
+                    if let Some(dst) = configured.map(&remote_ref.name) {
+                        updates.push((remote_ref.clone(), dst, configured.force));
+                    }
+                }
+            }
+        }
+    }
+    let mut seen: HashSet<String> = HashSet::new();
+    fetched.retain(|(remote_ref, _)| seen.insert(remote_ref.name.clone()));
+    let mut seen: HashSet<String> = HashSet::new();
+    updates.retain(|(_, dst, _)| seen.insert(dst.clone()));
+
+    // The work tree would no longer match its branch.
+    if !options.update_head_ok {
+        if let Some(head) = read_symbolic_ref(git_dir, "HEAD")?.filter(|head| updates.iter().any(|(_, dst, _)| dst.eq(head))) {
+            let git_dir: PathBuf = fs::canonicalize(git_dir).unwrap_or_else(|_| git_dir.to_path_buf());
+            let worktree: &Path = git_dir.parent().unwrap_or(&git_dir);
+            return Err(GitError::InvalidRef(format!(
+                "refusing to fetch into branch '{head}' checked out at '{}'",
+                worktree.display()
+            )));
+        }
+    }
+
+    let objects_dir: PathBuf = git_dir.join("objects");
+    let mut object_store: Box<dyn ObjectStore> = open_object_store_at(&objects_dir);
+
+    let mut wants: Vec<String> = fetched
+        .iter()
+        .map(|(remote_ref, _)| remote_ref.sha1_hash.clone())
+        .filter(|sha1_hash| !object_store.contains(sha1_hash))
+        .collect();
+    // Tags pointing at what we have or are about to get follow along.
+    let followed: Vec<RemoteRef> = followed_tags(git_dir, &advertisement, &updates, object_store.as_ref(), &wants)?;
+    wants.extend(followed.iter().map(|tag| tag.sha1_hash.clone()).filter(|sha1_hash| !object_store.contains(sha1_hash)));
+    wants.sort();
+    wants.dedup();
+
+    if !wants.is_empty() {
+        let mut haves: Vec<String> = list_refs(git_dir, "refs/")?
+            .into_iter()
+            .map(|(_, sha1_hash)| sha1_hash)
+            .chain(read_ref(git_dir, "HEAD")?)
+            .filter(|sha1_hash| object_store.contains(sha1_hash))
+            .collect();
+        haves.sort();
+        haves.dedup();
+
+        let pack: Vec<u8> = fetch_pack(transport.as_mut(), &advertisement, &wants, &haves, options.quiet)?;
+        let indexed: IndexedPack = index_pack(object_store.as_ref(), pack, true)?;
+        store_pack(&objects_dir, &indexed)?;
+        object_store = open_object_store_at(&objects_dir);
+    }
+
+    for tag in followed {
+        if object_store.contains(&tag.sha1_hash) {
+            fetched.push((tag.clone(), false));
+            updates.push((tag.clone(), tag.name.clone(), false));
+        }
+    }
+
+    write_fetch_head(git_dir, &url, &fetched)?;
+
+    let mut reported: Vec<RefUpdate> = Vec::new();
+    for (remote_ref, dst, force) in &updates {
+        if let Some(update) = update_ref(git_dir, object_store.as_ref(), remote_ref, dst, *force)? {
+            reported.push(update);
+        }
+    }
+
+    if !reported.is_empty() && !options.quiet {
+        eprintln!("From {}", display_url(&url));
+        let src_width: usize = reported.iter().map(|update| update.src.len()).fold(MIN_SRC_WIDTH, usize::max);
+        for update in &reported {
+            eprintln!("{}", update.format(src_width));
+        }
+    }
+
+    Ok(reported)
+}
+
+// URL and fetch refspecs of a configured remote, or `remote` itself as
+// the URL with no refspecs.
+pub fn remote_url_and_refspecs(config: &Config, remote: &str) -> Result<(String, Vec<Refspec>), GitError> {
+    let Some(url) = config.get("remote", Some(remote), "url") else {
+        return Ok((remote.to_string(), Vec::new()));
+    };
+
+    let refspecs: Result<Vec<Refspec>, GitError> = config
+        .get_all("remote", Some(remote), "fetch")
+        .into_iter()
+        .map(Refspec::parse)
+        .collect();
+    Ok((url.to_string(), refspecs?))
+}
+
+// Remote refs a refspec source names: every match of a glob, otherwise
+// the first of `<src>`, `refs/<src>`, `refs/tags/<src>`, `refs/heads/<src>`...
+fn matching_refs<'a>(advertisement: &'a RefAdvertisement, refspec: &Refspec) -> Result<Vec<&'a RemoteRef>, GitError> {
+    if refspec.is_glob() {
+        return Ok(advertisement.refs.iter().filter(|r| refspec.matches(&r.name)).collect());
+    }
+
+    let src: &str = &refspec.src;
+    let candidates: [String; 6] = [
+        src.to_string(),
+        format!("refs/{src}"),
+        format!("refs/tags/{src}"),
+        format!("refs/heads/{src}"),
+        format!("refs/remotes/{src}"),
+        format!("refs/remotes/{src}/HEAD"),
+    ];
+    match candidates.iter().find_map(|name| advertisement.find(name)) {
+        Some(remote_ref) => Ok(vec![remote_ref]),
+        None => Err(GitError::InvalidRef(format!("couldn't find remote ref {src}"))),
+    }
+}
+
+// `main:copy` stores into `refs/heads/copy`, or `refs/tags/copy` for tags.
+fn expand_local_ref(dst: &str, remote_name: &str) -> String {
+    if dst.starts_with("refs/") || dst.eq("HEAD") {
+        dst.to_string()
+    } else if remote_name.starts_with("refs/tags/") {
+        format!("refs/tags/{dst}")
+    } else {
+        format!("refs/heads/{dst}")
+    }
+}
+
+// Remote tags not known locally whose target is already here or wanted.
+fn followed_tags(
+    git_dir: &Path,
+    advertisement: &RefAdvertisement,
+    updates: &[(RemoteRef, String, bool)],
+    object_store: &dyn ObjectStore,
+    wants: &[String],
+) -> Result<Vec<RemoteRef>, GitError> {
+    let mut tags: Vec<RemoteRef> = Vec::new();
+    for remote_ref in advertisement.refs.iter().filter(|r| r.name.starts_with("refs/tags/")) {
+        if updates.iter().any(|(_, dst, _)| dst.eq(&remote_ref.name)) || read_ref(git_dir, &remote_ref.name)?.is_some() {
+            continue;
+        }
+
+        let target: &str = remote_ref.peeled.as_deref().unwrap_or(&remote_ref.sha1_hash);
+        if object_store.contains(target) || wants.iter().any(|want| want.eq(target)) {
+            tags.push(remote_ref.clone());
+        }
+    }
+    Ok(tags)
+}
+
+fn update_ref(
+    git_dir: &Path,
+    object_store: &dyn ObjectStore,
+    remote_ref: &RemoteRef,
+    dst: &str,
+    force: bool,
+) -> Result<Option<RefUpdate>, GitError> {
+    let new: &str = &remote_ref.sha1_hash;
+    let mut update: RefUpdate = RefUpdate {
+        flag: ' ',
+        summary: String::new(),
+        src: shorten_ref(&remote_ref.name).to_string(),
+        dst: shorten_ref(dst).to_string(),
+        note: None,
+    };
+
+    match read_ref(git_dir, dst)? {
+        Some(old) if old.eq(new) => return Ok(None),
+        None => {
+            update.flag = '*';
+            update.summary = if dst.starts_with("refs/tags/") {
+                "[new tag]"
+            } else if remote_ref.name.starts_with("refs/heads/") {
+                "[new branch]"
+            } else {
+                "[new ref]"
+            }
+            .to_string();
+        }
+        Some(_) if dst.starts_with("refs/tags/") && !force => {
+            update.flag = '!';
+            update.summary = "[rejected]".to_string();
+            update.note = Some("would clobber existing tag".to_string());
+        }
+        Some(old) if is_ancestor(object_store, &old, new)? => {
+            update.summary = format!("{}..{}", &old[..ABBREV], &new[..ABBREV]);
+        }
+        Some(old) if force => {
+            update.flag = '+';
+            update.summary = format!("{}...{}", &old[..ABBREV], &new[..ABBREV]);
+            update.note = Some("forced update".to_string());
+        }
+        Some(_) => {
+            update.flag = '!';
+            update.summary = "[rejected]".to_string();
+            update.note = Some("non-fast-forward".to_string());
+        }
+    }
+
+    if !update.is_rejected() {
+        write_ref(git_dir, dst, new)?;
+    }
+    Ok(Some(update))
+}
+
+// `<id>\t[not-for-merge]\t<kind> '<name>' of <url>`, refs to merge first.
+fn write_fetch_head(git_dir: &Path, url: &str, fetched: &[(RemoteRef, bool)]) -> Result<(), GitError> {
+    let url: &str = display_url(url);
+
+    let mut content: String = String::new();
+    for for_merge in [true, false] {
+        for (remote_ref, _) in fetched.iter().filter(|(_, merge)| *merge == for_merge) {
+            let name: &str = &remote_ref.name;
+            let description: String = if let Some(branch) = name.strip_prefix("refs/heads/") {
+                format!("branch '{branch}' of {url}")
+            } else if let Some(tag) = name.strip_prefix("refs/tags/") {
+                format!("tag '{tag}' of {url}")
+            } else if let Some(branch) = name.strip_prefix("refs/remotes/") {
+                format!("remote-tracking branch '{branch}' of {url}")
+            } else if name.eq("HEAD") {
+                url.to_string()
+            } else {
+                format!("'{name}' of {url}")
+            };
+
+            let merge: &str = if for_merge { "" } else { "not-for-merge" };
+            content.push_str(&format!("{}\t{merge}\t{description}\n", remote_ref.sha1_hash));
+        }
+    }
+
+    match fs::write(git_dir.join("FETCH_HEAD"), content) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(GitError::InvalidRef(format!("cannot write FETCH_HEAD: {err}"))),
+    }
+}
+
+// Without trailing slashes and `.git`, as git shows remotes.
+pub fn display_url(url: &str) -> &str {
+    let url: &str = url.trim_end_matches('/');
+    url.strip_suffix(".git").unwrap_or(url)
+}
+
+pub fn shorten_ref(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::clone::clone_repository;
+    use crate::test_support::commit_file;
+    use crate::test_support::git;
+    use crate::test_support::TempRepo;
+
+    // Objects in the only pack whose name is not in `before`.
+    fn new_pack_object_count(git_dir: &Path, before: &[PathBuf]) -> usize {
+        let pack_dir: PathBuf = git_dir.join("objects/pack");
+        let idx: PathBuf = fs::read_dir(&pack_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|ext| ext == "idx") && !before.contains(path))
+            .unwrap();
+        let idx: Vec<u8> = fs::read(idx).unwrap();
+        // Last fanout entry of a v2 index.
+        let total: [u8; 4] = idx[8 + 255 * 4..8 + 256 * 4].try_into().unwrap();
+        u32::from_be_bytes(total) as usize
+    }
+
+    #[test]
+    fn test_fetch_updates_remote_tracking_refs() {
+        let root: TempRepo = TempRepo::new("fetch");
+        let upstream: PathBuf = root.join("upstream");
+        fs::create_dir_all(&upstream).unwrap();
+        git(&upstream, &["init", "-q", "-b", "main"]);
+        commit_file(&upstream, "a", "one");
+        let old_main: String = commit_file(&upstream, "a", "two");
+        git(&upstream, &["branch", "rewrite"]);
+
+        let dir: PathBuf = root.join("clone");
+        clone_repository(upstream.to_str().unwrap(), &dir, true).unwrap();
+        let git_dir: PathBuf = dir.join(".git");
+        write_ref(&git_dir, "refs/heads/pinned", &old_main).unwrap();
+        let packs: Vec<PathBuf> = fs::read_dir(git_dir.join("objects/pack")).unwrap().map(|entry| entry.unwrap().path()).collect();
+
+        let new_main: String = commit_file(&upstream, "a", "three");
+        git(&upstream, &["checkout", "-q", "rewrite"]);
+        git(&upstream, &["reset", "-q", "--hard", "HEAD~1"]);
+        let rewritten: String = commit_file(&upstream, "b", "rewritten");
+        git(&upstream, &["checkout", "-q", "main"]);
+        git(&upstream, &["tag", "-a", "-m", "v1", "v1", &new_main]);
+
+        let quiet: FetchOptions = FetchOptions { quiet: true, ..FetchOptions::default() };
+        let updates: Vec<RefUpdate> = fetch(&git_dir, "origin", &[], quiet).unwrap();
+        let lines: Vec<String> = updates.iter().map(|update| update.format(MIN_SRC_WIDTH)).collect();
+        assert_eq!(
+            vec![
+                format!("   {}..{}  main       -> origin/main", &old_main[..7], &new_main[..7]),
+                format!(" + {}...{} rewrite    -> origin/rewrite  (forced update)", &old_main[..7], &rewritten[..7]),
+                " * [new tag]         v1         -> v1".to_string(),
+            ],
+            lines
+        );
+        assert_eq!(Some(new_main.clone()), read_ref(&git_dir, "refs/remotes/origin/main").unwrap());
+        assert_eq!(Some(rewritten.clone()), read_ref(&git_dir, "refs/remotes/origin/rewrite").unwrap());
+        assert!(read_ref(&git_dir, "refs/tags/v1").unwrap().is_some());
+
+        // Two commits, their trees and blobs, and the tag: nothing the
+        // clone already had.
+        assert_eq!(7, new_pack_object_count(&git_dir, &packs));
+
+        let fetch_head: String = fs::read_to_string(git_dir.join("FETCH_HEAD")).unwrap();
+        let url: String = upstream.display().to_string();
+        assert!(fetch_head.starts_with(&format!("{new_main}\t\tbranch 'main' of {url}\n")));
+        assert!(fetch_head.contains(&format!("{rewritten}\tnot-for-merge\tbranch 'rewrite' of {url}\n")));
+
+        // Without `+` a non fast-forward is refused and the ref left alone.
+        let updates: Vec<RefUpdate> = fetch(&git_dir, "origin", &["rewrite:pinned".to_string()], quiet).unwrap();
+        assert_eq!(1, updates.len());
+        assert!(updates[0].is_rejected());
+        assert_eq!(Some("non-fast-forward".to_string()), updates[0].note);
+        assert_eq!(Some(old_main.clone()), read_ref(&git_dir, "refs/heads/pinned").unwrap());
+
+        let updates: Vec<RefUpdate> = fetch(&git_dir, "origin", &["+rewrite:pinned".to_string()], quiet).unwrap();
+        assert_eq!('+', updates[0].flag);
+        assert_eq!(Some(rewritten), read_ref(&git_dir, "refs/heads/pinned").unwrap());
+
+        // The checked out branch is only updated with `-u`.
+        let refspecs: [String; 1] = ["main:main".to_string()];
+        let Err(GitError::InvalidRef(message)) = fetch(&git_dir, "origin", &refspecs, quiet) else {
+            panic!("fetched into the checked out branch");
+        };
+        assert!(message.starts_with("refusing to fetch into branch 'refs/heads/main' checked out at"));
+        assert_eq!(Some(old_main), read_ref(&git_dir, "refs/heads/main").unwrap());
+
+        fetch(&git_dir, "origin", &refspecs, FetchOptions { update_head_ok: true, ..quiet }).unwrap();
+        assert_eq!(Some(new_main), read_ref(&git_dir, "refs/heads/main").unwrap());
+    }
+}
@@ -0,0 +1,65 @@
+use std::io::Read;
+
+use crate::pkt_line::Packet;
+use crate::pkt_line::PktLineReader;
+use crate::protocol::read_ref_advertisement;
+use crate::protocol::RefAdvertisement;
+use crate::GitError;
+
+const USER_AGENT: &str = "git/codecrafters-git-0.1";
+
+// Smart HTTP: `GET <url>/info/refs?service=<service>` for the
+// advertisement, then one `POST <url>/<service>` per request.
+pub struct HttpRemote {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl HttpRemote {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new().user_agent(USER_AGENT).build(),
+        }
+    }
+
+    pub fn discover_refs(&self, service: &str) -> Result<RefAdvertisement, GitError> {
+        let url: String = format!("{}/info/refs?service={service}", self.url);
+        let response: ureq::Response = match self.agent.get(&url).call() {
+            Ok(response) => response,
+            Err(err) => return Err(GitError::Transport(err.to_string())),
+        };
+
+        if response.content_type() != format!("application/x-{service}-advertisement") {
+            return Err(GitError::Transport(format!("{} does not speak the smart HTTP protocol", self.url)));
+        }
+
+        // Smart servers first announce the service, then a flush.
+        let mut reader = PktLineReader::new(response.into_reader());
+        let expected: String = format!("# service={service}");
+        match reader.read_packet()? {
+            Packet::Data(line) if line.strip_suffix(b"\n").unwrap_or(&line) == expected.as_bytes() => {}
+            packet => {
+                return Err(GitError::Protocol(format!("expected '{expected}', got {packet:?}")));
+            }
+        }
+        reader.read_lines_until_flush()?;
+
+        read_ref_advertisement(&mut reader)
+    }
+
+    pub fn post(&self, service: &str, body: &[u8]) -> Result<Box<dyn Read + Send + Sync>, GitError> {
+        let url: String = format!("{}/{service}", self.url);
+        let response = self
+            .agent
+            .post(&url)
+            .set("Content-Type", &format!("application/x-{service}-request"))
+            .set("Accept", &format!("application/x-{service}-result"))
+            .send_bytes(body);
+
+        match response {
+            Ok(response) => Ok(response.into_reader()),
+            Err(err) => Err(GitError::Transport(err.to_string())),
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+
+use std::fs;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::charclass::match_class;
+
+// One line of a `.gitignore`, relative to the directory holding it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IgnorePattern {
+    pattern: String,
+    negated: bool,
+    directory_only: bool,
+    // Patterns with a slash match the whole path below their directory,
+    // the others only the last component.
+    anchored: bool,
+}
+
+impl IgnorePattern {
+    fn parse(line: &str) -> Option<Self> {
+        let line: &str = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line): (bool, &str) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+
+        // Trailing spaces are ignored unless escaped.
+        let mut pattern: &str = line;
+        while pattern.ends_with(' ') && !pattern.ends_with("\\ ") {
+            pattern = &pattern[..pattern.len() - 1];
+        }
+
+        let (directory_only, pattern): (bool, &str) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        if pattern.is_empty() {
+            return None;
+        }
+
+        let anchored: bool = pattern.contains('/');
+        Some(Self {
+            pattern: pattern.strip_prefix('/').unwrap_or(pattern).to_string(),
+            negated,
+            directory_only,
+            anchored,
+        })
+    }
+
+    // `path` is relative to the directory of the pattern.
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+
+        if self.anchored {
+            wildmatch(self.pattern.as_bytes(), path.as_bytes())
+        } else {
+            let name: &str = path.rsplit('/').next().unwrap_or(path);
+            wildmatch(self.pattern.as_bytes(), name.as_bytes())
+        }
+    }
+}
+
+// `.gitignore` files of the worktree, read as directories are visited,
+// and `.git/info/exclude`. Deeper files take precedence, and within a file
+// the last matching pattern decides, `!` re-including what an earlier one
+// excluded.
+pub struct IgnoreRules {
+    worktree: PathBuf,
+    excludes: Vec<IgnorePattern>,
+    // Patterns of each directory's `.gitignore`, keyed by the directory
+    // relative to the worktree ("" for the root).
+    directories: HashMap<String, Vec<IgnorePattern>>,
+}
+
+impl IgnoreRules {
+    pub fn new(worktree: &Path, git_dir: &Path) -> Self {
+        Self {
+            worktree: worktree.to_path_buf(),
+            excludes: read_patterns(&git_dir.join("info/exclude")),
+            directories: HashMap::new(),
+        }
+    }
+
+    // Whether `path` (relative to the worktree) is ignored, either itself
+    // or because a directory above it is.
+    pub fn is_ignored(&mut self, path: &str, is_dir: bool) -> bool {
+        let mut prefix: usize = 0;
+        while let Some(slash) = path[prefix..].find('/') {
+            if self.matches(&path[..prefix + slash], true) {
+                return true;
+            }
+            prefix += slash + 1;
+        }
+        self.matches(path, is_dir)
+    }
+
+    // Whether `path` itself is ignored, assuming its parent directories
+    // are not.
+    pub fn matches(&mut self, path: &str, is_dir: bool) -> bool {
+        let mut directories: Vec<&str> = vec![""];
+        directories.extend(path.match_indices('/').map(|(slash, _)| &path[..slash]));
+
+        for directory in directories.into_iter().rev() {
+            let relative: &str = match directory {
+                "" => path,
+                _ => &path[directory.len() + 1..],
+            };
+            let patterns: &Vec<IgnorePattern> = self.directory_patterns(directory);
+            if let Some(pattern) = patterns.iter().rev().find(|pattern| pattern.matches(relative, is_dir)) {
+                return !pattern.negated;
+            }
+        }
+
+        match self.excludes.iter().rev().find(|pattern| pattern.matches(path, is_dir)) {
+            Some(pattern) => !pattern.negated,
+            None => false,
+        }
+    }
+
+    fn directory_patterns(&mut self, directory: &str) -> &Vec<IgnorePattern> {
+        let worktree: &Path = &self.worktree;
+        self.directories
+            .entry(directory.to_string())
+            .or_insert_with(|| read_patterns(&worktree.join(directory).join(".gitignore")))
+    }
+}
+
+fn read_patterns(path: &Path) -> Vec<IgnorePattern> {
+    match fs::read_to_string(path) {
+        Ok(content) => content.lines().filter_map(IgnorePattern::parse).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// git's wildmatch with WM_PATHNAME: `*` and `?` stop at slashes, `**`
+// between slashes (or at either end) spans directories, `[...]` is a
+// character class and a backslash escapes the next character.
+pub fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    wildmatch_at(pattern, text, true, true)
+}
+
+// Plain fnmatch, where wildcards match slashes too, as pathspecs use.
+pub fn fnmatch(pattern: &[u8], text: &[u8]) -> bool {
+    wildmatch_at(pattern, text, true, false)
+}
+
+// `component_start` tells whether the pattern so far ended with a slash,
+// which `**` needs to be special.
+fn wildmatch_at(pattern: &[u8], text: &[u8], component_start: bool, pathname: bool) -> bool {
+    let Some((&first, rest)) = pattern.split_first() else {
+        return text.is_empty();
+    };
+
+    match first {
+        b'*' => {
+            let stars: usize = pattern.iter().take_while(|&&c| c == b'*').count();
+            let after: &[u8] = &pattern[stars..];
+            if pathname && stars >= 2 && component_start && (after.is_empty() || after[0] == b'/') {
+                // `**` at the end matches everything left, `**/` any number
+                // of leading directories.
+                let Some(after_slash) = after.get(1..) else {
+                    return true;
+                };
+                if wildmatch_at(after_slash, text, true, pathname) {
+                    return true;
+                }
+                return text
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &c)| c == b'/')
+                    .any(|(slash, _)| wildmatch_at(after_slash, &text[slash + 1..], true, pathname));
+            }
+
+            // Otherwise stars stay within one path component.
+            for skip in 0..=text.len() {
+                if wildmatch_at(after, &text[skip..], false, pathname) {
+                    return true;
+                }
+                if pathname && text.get(skip) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        b'?' => match text.split_first() {
+            Some((&c, text_rest)) if !pathname || c != b'/' => wildmatch_at(rest, text_rest, false, pathname),
+            _ => false,
+        },
+        b'[' => {
+            let Some((&c, text_rest)) = text.split_first() else {
+                return false;
+            };
+            match match_class(rest, c) {
+                Some((true, pattern_rest)) if !pathname || c != b'/' => wildmatch_at(pattern_rest, text_rest, false, pathname),
+                Some(_) => false,
+                // An unterminated class is a literal `[`.
+                None => c == b'[' && wildmatch_at(rest, text_rest, false, pathname),
+            }
+        }
+        b'\\' if !rest.is_empty() => match text.split_first() {
+            Some((&c, text_rest)) if c == rest[0] => wildmatch_at(&rest[1..], text_rest, c == b'/', pathname),
+            _ => false,
+        },
+        _ => match text.split_first() {
+            Some((&c, text_rest)) if c == first => wildmatch_at(rest, text_rest, c == b'/', pathname),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_support::TempRepo;
+
+    #[test]
+    fn test_wildmatch() {
+        assert!(wildmatch(b"*.log", b"debug.log"));
+        assert!(!wildmatch(b"*.log", b"logs/debug.log"));
+        assert!(wildmatch(b"logs/**/debug.log", b"logs/debug.log"));
+        assert!(wildmatch(b"logs/**/debug.log", b"logs/a/b/debug.log"));
+        assert!(wildmatch(b"**/build", b"a/b/build"));
+        assert!(wildmatch(b"logs/**", b"logs/a/b"));
+        assert!(!wildmatch(b"logs/**", b"logs"));
+        assert!(!wildmatch(b"a**b", b"a/b"));
+        assert!(wildmatch(b"debug[0-9].lo?", b"debug7.log"));
+        assert!(!wildmatch(b"debug[!0-9].log", b"debug7.log"));
+        assert!(wildmatch(b"[[:upper:]]*", b"README"));
+        assert!(wildmatch(b"\\*star", b"*star"));
+        assert!(!wildmatch(b"\\*star", b"xstar"));
+        assert!(fnmatch(b"*.rs", b"src/main.rs"));
+    }
+
+    #[test]
+    fn test_ignore_rules() {
+        let worktree: TempRepo = TempRepo::new("ignore_rules");
+        fs::create_dir_all(worktree.join(".git/info")).unwrap();
+        fs::create_dir_all(worktree.join("src/generated")).unwrap();
+        fs::write(worktree.join(".git/info/exclude"), "*.swp\n").unwrap();
+        fs::write(worktree.join(".gitignore"), "# comment\n*.log\n!keep.log\n/target\nout/\nsrc/*.tmp\n").unwrap();
+        fs::write(worktree.join("src/.gitignore"), "generated/\n!important.log\n").unwrap();
+
+        let mut rules: IgnoreRules = IgnoreRules::new(&worktree, &worktree.join(".git"));
+        assert!(rules.is_ignored("debug.log", false));
+        assert!(rules.is_ignored("a/b/debug.log", false));
+        assert!(!rules.is_ignored("keep.log", false));
+        assert!(!rules.is_ignored("src/important.log", false));
+        assert!(rules.is_ignored("target", true));
+        assert!(!rules.is_ignored("src/target", true));
+        assert!(rules.is_ignored("out", true));
+        assert!(!rules.is_ignored("out", false));
+        assert!(rules.is_ignored("out/file.txt", false));
+        assert!(rules.is_ignored("src/x.tmp", false));
+        assert!(!rules.is_ignored("src/deep/x.tmp", false));
+        assert!(rules.is_ignored("src/generated/keep.log", false));
+        assert!(rules.is_ignored("notes.swp", false));
+    }
+}
@@ -0,0 +1,868 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::ops::Range;
+
+use std::os::unix::fs::MetadataExt;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::bytes_slice_to_hex;
+use crate::compute_sha1_hash;
+use crate::hex_to_bytes;
+use crate::odb::ObjectStore;
+use crate::pack_writer::encode_ofs_distance;
+use crate::read_git_object;
+use crate::EntryMode;
+use crate::GitError;
+use crate::GitObject;
+
+const SIGNATURE: &[u8] = b"DIRC";
+const EXTENSION_TREE: &[u8] = b"TREE";
+const EXTENSION_RESOLVE_UNDO: &[u8] = b"REUC";
+
+const DEFAULT_VERSION: u32 = 2;
+
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_SHIFT: u16 = 12;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+const EXTENDED_SKIP_WORKTREE: u16 = 0x4000;
+const EXTENDED_INTENT_TO_ADD: u16 = 0x2000;
+
+// What `lstat` said about a file when it was staged, compared with the
+// worktree to skip hashing files that did not change. Values are
+// truncated to 32 bits like git does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatData {
+    pub ctime: u32,
+    pub ctime_nsec: u32,
+    pub mtime: u32,
+    pub mtime_nsec: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+}
+
+impl StatData {
+    pub fn from_metadata(metadata: &fs::Metadata) -> Self {
+        Self {
+            ctime: metadata.ctime() as u32,
+            ctime_nsec: metadata.ctime_nsec() as u32,
+            mtime: metadata.mtime() as u32,
+            mtime_nsec: metadata.mtime_nsec() as u32,
+            dev: metadata.dev() as u32,
+            ino: metadata.ino() as u32,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.size() as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub stat: StatData,
+    // Unix mode as stored: 0o100644, 0o100755, 0o120000 or 0o160000.
+    pub mode: u32,
+    pub sha1_hash: String,
+    // 0 once merged, 1 to 3 for the base, ours and theirs of a conflict.
+    pub stage: u8,
+    pub assume_valid: bool,
+    pub skip_worktree: bool,
+    pub intent_to_add: bool,
+    pub path: String,
+}
+
+impl IndexEntry {
+    pub fn new(path: &str, mode: u32, sha1_hash: &str, stat: StatData) -> Self {
+        Self {
+            stat,
+            mode,
+            sha1_hash: sha1_hash.to_string(),
+            stage: 0,
+            assume_valid: false,
+            skip_worktree: false,
+            intent_to_add: false,
+            path: path.to_string(),
+        }
+    }
+
+    fn is_extended(&self) -> bool {
+        self.skip_worktree || self.intent_to_add
+    }
+}
+
+// `TREE` extension: the tree ids of directories whose entries did not
+// change since they were last written, an `entry_count` of -1 marking the
+// ones that did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheTree {
+    pub name: String,
+    pub entry_count: i32,
+    pub sha1_hash: Option<String>,
+    pub children: Vec<CacheTree>,
+}
+
+impl CacheTree {
+    // Invalidates every directory on the way to `path`.
+    fn invalidate(&mut self, path: &str) {
+        self.entry_count = -1;
+        self.sha1_hash = None;
+
+        let Some((directory, rest)) = path.split_once('/') else {
+            return;
+        };
+        if let Some(child) = self.children.iter_mut().find(|child| child.name.eq(directory)) {
+            child.invalidate(rest);
+        }
+    }
+}
+
+// `REUC` extension: the stages a resolved conflict had, to be able to
+// recreate it. Missing stages have a mode of 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveUndo {
+    pub path: String,
+    pub modes: [u32; 3],
+    pub sha1_hashes: [Option<String>; 3],
+}
+
+// `.git/index`: a `DIRC` header, entries sorted by path then stage,
+// extensions, and the SHA-1 of everything before it. Version 3 adds
+// extended flags, version 4 stores each path as what it shares with the
+// previous one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    pub version: u32,
+    pub entries: Vec<IndexEntry>,
+    pub cache_tree: Option<CacheTree>,
+    pub resolve_undo: Vec<ResolveUndo>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Self {
+            version: DEFAULT_VERSION,
+            entries: Vec::new(),
+            cache_tree: None,
+            resolve_undo: Vec::new(),
+        }
+    }
+}
+
+impl Index {
+    // A missing index is an empty one.
+    pub fn load(path: &Path) -> Result<Self, GitError> {
+        match fs::read(path) {
+            Ok(bytes) => Self::parse(&bytes),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(GitError::InvalidIndex(format!("{}: {err}", path.display()))),
+        }
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, GitError> {
+        let Some(body_length) = bytes.len().checked_sub(20) else {
+            return Err(GitError::InvalidIndex("index file smaller than expected".to_string()));
+        };
+        let (body, checksum): (&[u8], &[u8]) = bytes.split_at(body_length);
+        // `index.skipHash` leaves the checksum zeroed.
+        if checksum.iter().any(|&b| b != 0) && bytes_slice_to_hex(checksum) != compute_sha1_hash(body) {
+            return Err(GitError::InvalidIndex("index file corrupt: bad checksum".to_string()));
+        }
+
+        let mut reader: ByteReader = ByteReader { bytes: body, position: 0 };
+        if reader.take(4)? != SIGNATURE {
+            return Err(GitError::InvalidIndex("bad signature".to_string()));
+        }
+        let version: u32 = reader.read_u32()?;
+        if !(2..=4).contains(&version) {
+            return Err(GitError::InvalidIndex(format!("bad index version {version}")));
+        }
+        let entry_count: u32 = reader.read_u32()?;
+
+        let mut index: Index = Index {
+            version,
+            ..Index::default()
+        };
+        let mut previous_path: Vec<u8> = Vec::new();
+        for _ in 0..entry_count {
+            let entry: IndexEntry = parse_entry(&mut reader, version, &mut previous_path)?;
+            index.entries.push(entry);
+        }
+
+        while reader.position < body.len() {
+            let signature: &[u8] = reader.take(4)?;
+            let size: usize = reader.read_u32()? as usize;
+            let data: &[u8] = reader.take(size)?;
+            match signature {
+                EXTENSION_TREE => index.cache_tree = Some(parse_cache_tree(&mut ByteReader { bytes: data, position: 0 })?),
+                EXTENSION_RESOLVE_UNDO => index.resolve_undo = parse_resolve_undo(data)?,
+                // Extensions starting with an uppercase letter are optional.
+                [b'A'..=b'Z', ..] => {}
+                _ => {
+                    return Err(GitError::InvalidIndex(format!(
+                        "unsupported extension '{}'",
+                        String::from_utf8_lossy(signature)
+                    )));
+                }
+            }
+        }
+
+        Ok(index)
+    }
+
+    // Entries of `tree` at stage 0 with empty stat data, and the cache tree
+    // that goes with them, as `git read-tree` does.
+    pub fn from_tree(object_store: &dyn ObjectStore, tree_sha: &str) -> Result<Self, GitError> {
+        let mut index: Index = Index::default();
+        let cache_tree: CacheTree = read_tree(object_store, tree_sha, "", "", &mut index.entries)?;
+        index.cache_tree = Some(cache_tree);
+        Ok(index)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, GitError> {
+        // Extended flags need at least version 3.
+        let version: u32 = match self.version {
+            2 if self.entries.iter().any(IndexEntry::is_extended) => 3,
+            version => version,
+        };
+
+        let mut bytes: Vec<u8> = SIGNATURE.to_vec();
+        bytes.extend(version.to_be_bytes());
+        bytes.extend((self.entries.len() as u32).to_be_bytes());
+
+        let mut previous_path: &[u8] = &[];
+        for entry in &self.entries {
+            write_entry(&mut bytes, entry, version, previous_path)?;
+            previous_path = entry.path.as_bytes();
+        }
+
+        if let Some(cache_tree) = &self.cache_tree {
+            let mut data: Vec<u8> = Vec::new();
+            write_cache_tree(&mut data, cache_tree)?;
+            write_extension(&mut bytes, EXTENSION_TREE, &data);
+        }
+        if !self.resolve_undo.is_empty() {
+            let mut data: Vec<u8> = Vec::new();
+            for resolve_undo in &self.resolve_undo {
+                write_resolve_undo(&mut data, resolve_undo)?;
+            }
+            write_extension(&mut bytes, EXTENSION_RESOLVE_UNDO, &data);
+        }
+
+        let checksum: String = compute_sha1_hash(&bytes);
+        bytes.extend(hex_to_bytes(&checksum)?);
+        Ok(bytes)
+    }
+
+    // Written to `index.lock` then renamed over the index, like git.
+    pub fn write(&self, path: &Path) -> Result<(), GitError> {
+        let lock_path: PathBuf = PathBuf::from(format!("{}.lock", path.display()));
+        let bytes: Vec<u8> = self.to_bytes()?;
+        let written = fs::write(&lock_path, bytes).and_then(|()| fs::rename(&lock_path, path));
+        if let Err(err) = written {
+            let _ = fs::remove_file(&lock_path);
+            return Err(GitError::InvalidIndex(format!("cannot write {}: {err}", path.display())));
+        }
+        Ok(())
+    }
+
+    // Where the entry for `path` at `stage` is, or would be inserted.
+    pub fn position(&self, path: &str, stage: u8) -> Result<usize, usize> {
+        self.entries
+            .binary_search_by(|entry| entry.path.as_bytes().cmp(path.as_bytes()).then(entry.stage.cmp(&stage)))
+    }
+
+    pub fn find(&self, path: &str, stage: u8) -> Option<&IndexEntry> {
+        self.position(path, stage).ok().map(|position| &self.entries[position])
+    }
+
+    // Adds or replaces an entry. A merged entry replaces the conflict
+    // stages of its path, and files and directories of the same name
+    // replace each other.
+    pub fn add(&mut self, entry: IndexEntry) {
+        if entry.stage == 0 {
+            self.record_resolve_undo(&entry.path);
+        }
+        for position in self.replaced_by(&entry).into_iter().rev() {
+            self.entries.remove(position);
+        }
+
+        self.invalidate(&entry.path);
+        match self.position(&entry.path, entry.stage) {
+            Ok(position) => self.entries[position] = entry,
+            Err(position) => self.entries.insert(position, entry),
+        }
+    }
+
+    // Like `add` for many entries, new ones being appended and sorted
+    // once. The entries must not replace each other.
+    pub fn add_all(&mut self, entries: Vec<IndexEntry>) {
+        let mut replaced: HashSet<usize> = HashSet::new();
+        let mut appended: Vec<IndexEntry> = Vec::new();
+        for entry in entries {
+            if entry.stage == 0 {
+                self.record_resolve_undo(&entry.path);
+            }
+            replaced.extend(self.replaced_by(&entry));
+
+            self.invalidate(&entry.path);
+            match self.position(&entry.path, entry.stage) {
+                Ok(position) => self.entries[position] = entry,
+                Err(_) => appended.push(entry),
+            }
+        }
+
+        if !replaced.is_empty() {
+            let mut position: usize = 0;
+            self.entries.retain(|_| {
+                position += 1;
+                !replaced.contains(&(position - 1))
+            });
+        }
+        if !appended.is_empty() {
+            self.entries.extend(appended);
+            self.entries
+                .sort_by(|a, b| a.path.as_bytes().cmp(b.path.as_bytes()).then(a.stage.cmp(&b.stage)));
+        }
+    }
+
+    // Positions of the entries `entry` replaces: the conflict stages of a
+    // merged entry, the files under it and the files it is under, each of
+    // them next to where its path sorts.
+    fn replaced_by(&self, entry: &IndexEntry) -> Vec<usize> {
+        let mut positions: Vec<usize> = Vec::new();
+        if entry.stage == 0 {
+            positions.extend(self.run_from(&entry.path, 1, |other| other.path.eq(&entry.path)));
+        }
+        let directory: String = format!("{}/", entry.path);
+        positions.extend(self.run_from(&directory, 0, |other| other.path.starts_with(&directory)));
+        for (end, _) in entry.path.match_indices('/') {
+            let parent: &str = &entry.path[..end];
+            positions.extend(self.run_from(parent, 0, |other| other.path.eq(parent)));
+        }
+        positions.sort_unstable();
+        positions
+    }
+
+    // The entries from where `path` at `stage` sorts on that `keep` accepts.
+    fn run_from(&self, path: &str, stage: u8, keep: impl Fn(&IndexEntry) -> bool) -> Range<usize> {
+        let start: usize = self.position(path, stage).unwrap_or_else(|position| position);
+        start..start + self.entries[start..].iter().take_while(|entry| keep(entry)).count()
+    }
+
+    // Removes every stage of `path`, returns whether there was any.
+    pub fn remove(&mut self, path: &str) -> bool {
+        let count: usize = self.entries.len();
+        self.entries.retain(|entry| entry.path.ne(path));
+        if self.entries.len() == count {
+            return false;
+        }
+
+        self.invalidate(path);
+        true
+    }
+
+    // Keeps the conflict stages of `path`, about to be resolved, in REUC.
+    fn record_resolve_undo(&mut self, path: &str) {
+        let mut resolve_undo: ResolveUndo = ResolveUndo {
+            path: path.to_string(),
+            modes: [0; 3],
+            sha1_hashes: [None, None, None],
+        };
+        for position in self.run_from(path, 1, |entry| entry.path.eq(path)) {
+            let entry: &IndexEntry = &self.entries[position];
+            let stage: usize = entry.stage as usize - 1;
+            resolve_undo.modes[stage] = entry.mode;
+            resolve_undo.sha1_hashes[stage] = Some(entry.sha1_hash.clone());
+        }
+        if resolve_undo.modes == [0; 3] {
+            return;
+        }
+
+        self.resolve_undo.retain(|other| other.path.ne(path));
+        let position: usize = self.resolve_undo.partition_point(|other| other.path.as_bytes() < path.as_bytes());
+        self.resolve_undo.insert(position, resolve_undo);
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.entries.iter().any(|entry| entry.stage != 0)
+    }
+
+    fn invalidate(&mut self, path: &str) {
+        if let Some(cache_tree) = &mut self.cache_tree {
+            cache_tree.invalidate(path);
+        }
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], GitError> {
+        let Some(bytes) = self.bytes.get(self.position..self.position + length) else {
+            return Err(GitError::InvalidIndex("truncated index".to_string()));
+        };
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, GitError> {
+        let bytes: &[u8] = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_u16(&mut self) -> Result<u16, GitError> {
+        let bytes: &[u8] = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    // Up to the next NUL, which is skipped.
+    fn read_until_nul(&mut self) -> Result<&'a [u8], GitError> {
+        let rest: &[u8] = &self.bytes[self.position.min(self.bytes.len())..];
+        let Some(nul) = rest.iter().position(|&b| b == 0) else {
+            return Err(GitError::InvalidIndex("unterminated path".to_string()));
+        };
+        self.position += nul + 1;
+        Ok(&rest[..nul])
+    }
+
+    // The varint of version 4 paths, encoded like OFS_DELTA distances.
+    fn read_varint(&mut self) -> Result<usize, GitError> {
+        let mut byte: u8 = self.take(1)?[0];
+        let mut value: usize = (byte & 0x7f) as usize;
+        while byte & 0x80 != 0 {
+            byte = self.take(1)?[0];
+            value = ((value + 1) << 7) | (byte & 0x7f) as usize;
+        }
+        Ok(value)
+    }
+
+    // ASCII decimal or octal number ended by `terminator`.
+    fn read_number(&mut self, terminator: u8, radix: u32) -> Result<i64, GitError> {
+        let rest: &[u8] = &self.bytes[self.position.min(self.bytes.len())..];
+        let end: Option<usize> = rest.iter().position(|&b| b == terminator);
+        let number: Option<i64> = end
+            .and_then(|end| std::str::from_utf8(&rest[..end]).ok())
+            .and_then(|number| i64::from_str_radix(number, radix).ok());
+        match (end, number) {
+            (Some(end), Some(number)) => {
+                self.position += end + 1;
+                Ok(number)
+            }
+            _ => Err(GitError::InvalidIndex("bad number in extension".to_string())),
+        }
+    }
+
+    fn read_sha1_hash(&mut self) -> Result<String, GitError> {
+        Ok(bytes_slice_to_hex(self.take(20)?))
+    }
+}
+
+fn parse_entry(reader: &mut ByteReader, version: u32, previous_path: &mut Vec<u8>) -> Result<IndexEntry, GitError> {
+    let start: usize = reader.position;
+
+    let stat: StatData = StatData {
+        ctime: reader.read_u32()?,
+        ctime_nsec: reader.read_u32()?,
+        mtime: reader.read_u32()?,
+        mtime_nsec: reader.read_u32()?,
+        dev: reader.read_u32()?,
+        ino: reader.read_u32()?,
+        // Mode comes between the inode and the owner.
+        ..StatData::default()
+    };
+    let mode: u32 = reader.read_u32()?;
+    let stat: StatData = StatData {
+        uid: reader.read_u32()?,
+        gid: reader.read_u32()?,
+        size: reader.read_u32()?,
+        ..stat
+    };
+    let sha1_hash: String = reader.read_sha1_hash()?;
+
+    let flags: u16 = reader.read_u16()?;
+    let extended_flags: u16 = if flags & FLAG_EXTENDED != 0 {
+        if version < 3 {
+            return Err(GitError::InvalidIndex("extended flags in a version 2 index".to_string()));
+        }
+        reader.read_u16()?
+    } else {
+        0
+    };
+
+    let path: Vec<u8> = if version == 4 {
+        let strip: usize = reader.read_varint()?;
+        let Some(keep) = previous_path.len().checked_sub(strip) else {
+            return Err(GitError::InvalidIndex("bad path prefix length".to_string()));
+        };
+        let mut path: Vec<u8> = previous_path[..keep].to_vec();
+        path.extend_from_slice(reader.read_until_nul()?);
+        path
+    } else {
+        let length: usize = (flags & FLAG_NAME_MASK) as usize;
+        let path: Vec<u8> = if length < FLAG_NAME_MASK as usize {
+            reader.take(length)?.to_vec()
+        } else {
+            reader.read_until_nul()?.to_vec()
+        };
+        // Entries are NUL padded to a multiple of eight bytes.
+        let padded: usize = (reader.position - start + 8) & !7;
+        reader.position = start + padded;
+        if reader.position > reader.bytes.len() {
+            return Err(GitError::InvalidIndex("truncated index".to_string()));
+        }
+        path
+    };
+    previous_path.clone_from(&path);
+
+    let Ok(path) = String::from_utf8(path) else {
+        return Err(GitError::InvalidIndex("path is not valid UTF-8".to_string()));
+    };
+
+    Ok(IndexEntry {
+        stat,
+        mode,
+        sha1_hash,
+        stage: ((flags >> FLAG_STAGE_SHIFT) & 0x3) as u8,
+        assume_valid: flags & FLAG_ASSUME_VALID != 0,
+        skip_worktree: extended_flags & EXTENDED_SKIP_WORKTREE != 0,
+        intent_to_add: extended_flags & EXTENDED_INTENT_TO_ADD != 0,
+        path,
+    })
+}
+
+fn write_entry(bytes: &mut Vec<u8>, entry: &IndexEntry, version: u32, previous_path: &[u8]) -> Result<(), GitError> {
+    let start: usize = bytes.len();
+    let stat: &StatData = &entry.stat;
+    for value in [stat.ctime, stat.ctime_nsec, stat.mtime, stat.mtime_nsec, stat.dev, stat.ino, entry.mode, stat.uid, stat.gid, stat.size] {
+        bytes.extend(value.to_be_bytes());
+    }
+    bytes.extend(hex_to_bytes(&entry.sha1_hash)?);
+
+    let path: &[u8] = entry.path.as_bytes();
+    let mut flags: u16 = path.len().min(FLAG_NAME_MASK as usize) as u16;
+    flags |= ((entry.stage & 0x3) as u16) << FLAG_STAGE_SHIFT;
+    if entry.assume_valid {
+        flags |= FLAG_ASSUME_VALID;
+    }
+    if entry.is_extended() {
+        flags |= FLAG_EXTENDED;
+    }
+    bytes.extend(flags.to_be_bytes());
+
+    if entry.is_extended() {
+        let mut extended_flags: u16 = 0;
+        if entry.skip_worktree {
+            extended_flags |= EXTENDED_SKIP_WORKTREE;
+        }
+        if entry.intent_to_add {
+            extended_flags |= EXTENDED_INTENT_TO_ADD;
+        }
+        bytes.extend(extended_flags.to_be_bytes());
+    }
+
+    if version == 4 {
+        let common: usize = previous_path.iter().zip(path).take_while(|(a, b)| a == b).count();
+        bytes.extend(encode_ofs_distance((previous_path.len() - common) as u64));
+        bytes.extend_from_slice(&path[common..]);
+        bytes.push(0);
+    } else {
+        bytes.extend_from_slice(path);
+        let padded: usize = (bytes.len() - start + 8) & !7;
+        bytes.resize(start + padded, 0);
+    }
+    Ok(())
+}
+
+fn write_extension(bytes: &mut Vec<u8>, signature: &[u8], data: &[u8]) {
+    bytes.extend_from_slice(signature);
+    bytes.extend((data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(data);
+}
+
+// `<name>\0<entry count> <subtree count>\n`, the tree id unless
+// invalidated, then the subtrees.
+fn parse_cache_tree(reader: &mut ByteReader) -> Result<CacheTree, GitError> {
+    let Ok(name) = String::from_utf8(reader.read_until_nul()?.to_vec()) else {
+        return Err(GitError::InvalidIndex("cache tree name is not valid UTF-8".to_string()));
+    };
+    let entry_count: i64 = reader.read_number(b' ', 10)?;
+    let subtree_count: i64 = reader.read_number(b'\n', 10)?;
+
+    let sha1_hash: Option<String> = match entry_count {
+        0.. => Some(reader.read_sha1_hash()?),
+        _ => None,
+    };
+    let mut children: Vec<CacheTree> = Vec::new();
+    for _ in 0..subtree_count {
+        children.push(parse_cache_tree(reader)?);
+    }
+
+    Ok(CacheTree {
+        name,
+        entry_count: entry_count as i32,
+        sha1_hash,
+        children,
+    })
+}
+
+fn write_cache_tree(bytes: &mut Vec<u8>, cache_tree: &CacheTree) -> Result<(), GitError> {
+    bytes.extend_from_slice(cache_tree.name.as_bytes());
+    bytes.push(0);
+    bytes.extend(format!("{} {}\n", cache_tree.entry_count, cache_tree.children.len()).into_bytes());
+    if let (0.., Some(sha1_hash)) = (cache_tree.entry_count, &cache_tree.sha1_hash) {
+        bytes.extend(hex_to_bytes(sha1_hash)?);
+    }
+    for child in &cache_tree.children {
+        write_cache_tree(bytes, child)?;
+    }
+    Ok(())
+}
+
+// `<path>\0` and three octal modes each ended by a NUL, then the object
+// id of each stage whose mode is not 0.
+fn parse_resolve_undo(data: &[u8]) -> Result<Vec<ResolveUndo>, GitError> {
+    let mut reader: ByteReader = ByteReader { bytes: data, position: 0 };
+    let mut resolve_undo: Vec<ResolveUndo> = Vec::new();
+
+    while reader.position < data.len() {
+        let Ok(path) = String::from_utf8(reader.read_until_nul()?.to_vec()) else {
+            return Err(GitError::InvalidIndex("resolve undo path is not valid UTF-8".to_string()));
+        };
+        let mut modes: [u32; 3] = [0; 3];
+        for mode in &mut modes {
+            *mode = reader.read_number(0, 8)? as u32;
+        }
+        let mut sha1_hashes: [Option<String>; 3] = [None, None, None];
+        for (sha1_hash, mode) in sha1_hashes.iter_mut().zip(modes) {
+            if mode != 0 {
+                *sha1_hash = Some(reader.read_sha1_hash()?);
+            }
+        }
+        resolve_undo.push(ResolveUndo { path, modes, sha1_hashes });
+    }
+
+    Ok(resolve_undo)
+}
+
+fn write_resolve_undo(bytes: &mut Vec<u8>, resolve_undo: &ResolveUndo) -> Result<(), GitError> {
+    bytes.extend_from_slice(resolve_undo.path.as_bytes());
+    bytes.push(0);
+    for mode in resolve_undo.modes {
+        bytes.extend(format!("{mode:o}").into_bytes());
+        bytes.push(0);
+    }
+    for (sha1_hash, mode) in resolve_undo.sha1_hashes.iter().zip(resolve_undo.modes) {
+        if let (true, Some(sha1_hash)) = (mode != 0, sha1_hash) {
+            bytes.extend(hex_to_bytes(sha1_hash)?);
+        }
+    }
+    Ok(())
+}
+
+// Appends the entries under `tree` and returns its cache tree. Git keeps
+// subtrees ordered by name length first.
+fn read_tree(
+    object_store: &dyn ObjectStore,
+    tree_sha: &str,
+    name: &str,
+    prefix: &str,
+    entries: &mut Vec<IndexEntry>,
+) -> Result<CacheTree, GitError> {
+    let GitObject::Tree { content } = read_git_object(object_store, tree_sha)? else {
+        return Err(GitError::InvalidTreeEntry);
+    };
+
+    let first_entry: usize = entries.len();
+    let mut children: Vec<CacheTree> = Vec::new();
+    for tree_entry in content {
+        let path: String = format!("{prefix}{}", tree_entry.name);
+        match tree_entry.mode {
+            EntryMode::Directory => {
+                let child: CacheTree = read_tree(object_store, &tree_entry.sha1_hash, &tree_entry.name, &format!("{path}/"), entries)?;
+                children.push(child);
+            }
+            mode => entries.push(IndexEntry::new(&path, mode.as_index_mode(), &tree_entry.sha1_hash, StatData::default())),
+        }
+    }
+    children.sort_by(|a, b| a.name.len().cmp(&b.name.len()).then(a.name.cmp(&b.name)));
+
+    Ok(CacheTree {
+        name: name.to_string(),
+        entry_count: (entries.len() - first_entry) as i32,
+        sha1_hash: Some(tree_sha.to_string()),
+        children,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::PermissionsExt;
+
+    use std::process::Command;
+
+    use crate::open_object_store_at;
+    use crate::test_support::git;
+    use crate::test_support::TempRepo;
+
+    // Parses the index git wrote and checks writing it back gives the
+    // same bytes.
+    fn assert_round_trip(repo: &Path) -> Index {
+        let bytes: Vec<u8> = fs::read(repo.join(".git/index")).unwrap();
+        let index: Index = Index::parse(&bytes).unwrap();
+        assert_eq!(bytes, index.to_bytes().unwrap());
+        index
+    }
+
+    #[test]
+    fn test_read_indexes_written_by_git() {
+        let repo: TempRepo = TempRepo::new("index_read");
+        fs::create_dir_all(repo.join("dir/sub")).unwrap();
+        git(&repo, &["init", "-q", "-b", "main"]);
+        fs::write(repo.join("a.txt"), "a\n").unwrap();
+        fs::write(repo.join("dir/b.txt"), "b\n").unwrap();
+        fs::write(repo.join("dir/sub/c"), "c\n").unwrap();
+        fs::write(repo.join("run.sh"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(repo.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        std::os::unix::fs::symlink("a.txt", repo.join("link")).unwrap();
+        git(&repo, &["add", "."]);
+        let tree: String = git(&repo, &["write-tree"]);
+
+        let index: Index = assert_round_trip(&repo);
+        assert_eq!(2, index.version);
+        assert_eq!(
+            vec!["a.txt", "dir/b.txt", "dir/sub/c", "link", "run.sh"],
+            index.entries.iter().map(|entry| entry.path.as_str()).collect::<Vec<&str>>()
+        );
+        assert_eq!(0o100755, index.find("run.sh", 0).unwrap().mode);
+        assert_eq!(0o120000, index.find("link", 0).unwrap().mode);
+        assert_eq!(2, index.find("a.txt", 0).unwrap().stat.size);
+        let cache_tree: &CacheTree = index.cache_tree.as_ref().unwrap();
+        assert_eq!((5, Some(tree)), (cache_tree.entry_count, cache_tree.sha1_hash.clone()));
+
+        git(&repo, &["update-index", "--index-version", "4"]);
+        assert_eq!(4, assert_round_trip(&repo).version);
+
+        git(&repo, &["update-index", "--index-version", "2"]);
+        fs::write(repo.join("new"), "new\n").unwrap();
+        git(&repo, &["add", "-N", "new"]);
+        git(&repo, &["update-index", "--skip-worktree", "dir/b.txt"]);
+        let index: Index = assert_round_trip(&repo);
+        assert_eq!(3, index.version);
+        assert!(index.find("new", 0).unwrap().intent_to_add);
+        assert!(index.find("dir/b.txt", 0).unwrap().skip_worktree);
+        assert_eq!(-1, index.cache_tree.unwrap().entry_count);
+
+        // A conflict, then its resolution recorded in REUC.
+        git(&repo, &["update-index", "--no-skip-worktree", "dir/b.txt"]);
+        git(&repo, &["rm", "-q", "--cached", "new"]);
+        git(&repo, &["commit", "-q", "-m", "base"]);
+        git(&repo, &["checkout", "-q", "-b", "other"]);
+        fs::write(repo.join("a.txt"), "other\n").unwrap();
+        git(&repo, &["commit", "-q", "-am", "other"]);
+        git(&repo, &["checkout", "-q", "main"]);
+        fs::write(repo.join("a.txt"), "main\n").unwrap();
+        git(&repo, &["commit", "-q", "-am", "main"]);
+        // The merge stops on the conflict, leaving stages 1 to 3.
+        let merged = Command::new("git")
+            .args(["-c", "user.name=Fixture", "-c", "user.email=fixture@example.com", "merge", "-q", "other"])
+            .current_dir(&repo)
+            .output()
+            .unwrap();
+        assert!(String::from_utf8_lossy(&merged.stdout).contains("CONFLICT"));
+
+        let index: Index = assert_round_trip(&repo);
+        assert!(index.has_conflicts());
+        assert_eq!(vec![1, 2, 3], index.entries.iter().filter(|e| e.path.eq("a.txt")).map(|e| e.stage).collect::<Vec<u8>>());
+
+        git(&repo, &["add", "a.txt"]);
+        let index: Index = assert_round_trip(&repo);
+        assert!(!index.has_conflicts());
+        assert_eq!("a.txt", index.resolve_undo[0].path);
+        assert_eq!([0o100644; 3], index.resolve_undo[0].modes);
+
+        let mut bytes: Vec<u8> = fs::read(repo.join(".git/index")).unwrap();
+        bytes[20] ^= 1;
+        assert!(Index::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_git_reads_written_index() {
+        let repo: TempRepo = TempRepo::new("index_write");
+        fs::create_dir_all(repo.join("src/bin")).unwrap();
+        git(&repo, &["init", "-q", "-b", "main"]);
+        for (path, content) in [("README", "readme\n"), ("src/lib.rs", "lib\n"), ("src/bin/main.rs", "main\n"), ("src.txt", "x\n")] {
+            fs::write(repo.join(path), content).unwrap();
+        }
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-q", "-m", "first"]);
+        let tree: String = git(&repo, &["rev-parse", "HEAD^{tree}"]);
+
+        // Same bytes as `git read-tree`, cache tree included.
+        git(&repo, &["read-tree", "HEAD"]);
+        let object_store = open_object_store_at(&repo.join(".git/objects"));
+        let mut index: Index = Index::from_tree(object_store.as_ref(), &tree).unwrap();
+        assert_eq!(fs::read(repo.join(".git/index")).unwrap(), index.to_bytes().unwrap());
+
+        fs::write(repo.join("src/bin/extra.rs"), "lib\n").unwrap();
+        let blob: String = git(&repo, &["hash-object", "-w", "src/bin/extra.rs"]);
+        index.add(IndexEntry::new("src/bin/extra.rs", 0o100644, &blob, StatData::default()));
+        index.remove("README");
+        index.version = 4;
+        index.write(&repo.join(".git/index")).unwrap();
+
+        assert_eq!(
+            format!("100644 {blob} 0\tsrc/bin/extra.rs"),
+            git(&repo, &["ls-files", "--stage", "src/bin/extra.rs"])
+        );
+        assert_eq!("D  README\nA  src/bin/extra.rs", git(&repo, &["status", "--porcelain", "--untracked-files=no"]));
+        // Directories above the changes had their cache tree invalidated.
+        let cache_tree: CacheTree = index.cache_tree.unwrap();
+        assert_eq!(-1, cache_tree.entry_count);
+        assert_eq!(vec!["src"], cache_tree.children.iter().map(|child| child.name.as_str()).collect::<Vec<&str>>());
+        assert_eq!(-1, cache_tree.children[0].entry_count);
+    }
+
+    #[test]
+    fn test_add_replaces_entries() {
+        let blob: &str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+        let entry = |path: &str, stage: u8| {
+            let mut entry: IndexEntry = IndexEntry::new(path, 0o100644, blob, StatData::default());
+            entry.stage = stage;
+            entry
+        };
+        let paths = |index: &Index| -> Vec<(String, u8)> {
+            index.entries.iter().map(|entry| (entry.path.clone(), entry.stage)).collect()
+        };
+        let expected = |paths: &[(&str, u8)]| -> Vec<(String, u8)> {
+            paths.iter().map(|&(path, stage)| (path.to_string(), stage)).collect()
+        };
+
+        let mut index: Index = Index::default();
+        index.add_all(vec![entry("b", 0), entry("a/x", 0), entry("a-b", 0), entry("c", 1), entry("c", 3), entry("d/e/f", 0)]);
+        assert_eq!(expected(&[("a-b", 0), ("a/x", 0), ("b", 0), ("c", 1), ("c", 3), ("d/e/f", 0)]), paths(&index));
+
+        // A file replaces the directory of the same name and the other way
+        // round, a merged entry its conflict stages.
+        let mut added: Index = index.clone();
+        added.add(entry("a", 0));
+        added.add(entry("c", 0));
+        added.add(entry("d/e", 0));
+        added.add(entry("b/y", 0));
+        assert_eq!(expected(&[("a", 0), ("a-b", 0), ("b/y", 0), ("c", 0), ("d/e", 0)]), paths(&added));
+        assert_eq!(vec!["c"], added.resolve_undo.iter().map(|undo| undo.path.as_str()).collect::<Vec<&str>>());
+
+        index.add_all(vec![entry("a", 0), entry("c", 0), entry("d/e", 0), entry("b/y", 0)]);
+        assert_eq!(added, index);
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+
+use std::fs;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use flate2::Crc;
+
+use crate::bytes_slice_to_hex;
+use crate::compute_sha1_hash;
+use crate::delta::apply_delta;
+use crate::hex_to_bytes;
+use crate::odb::object_header;
+use crate::odb::ObjectStore;
+use crate::pack::parse_entry_header;
+use crate::pack::type_id;
+use crate::pack::type_name;
+use crate::pack::DeltaBase;
+use crate::pack::PackEntryHeader;
+use crate::pack_writer::encode_entry_header;
+use crate::pack_writer::write_pack_index;
+use crate::pack_writer::PackedEntry;
+use crate::zlib_compression;
+use crate::zlib_decompression;
+use crate::zlib_decompression_with_length;
+use crate::GitError;
+use crate::GitObjectParts;
+
+const PACK_HEADER_SIZE: usize = 12;
+const PACK_TRAILER_SIZE: usize = 20;
+
+// A pack with everything its `.idx` needs. For completed thin packs
+// `pack` holds the rewritten bytes, external bases appended.
+pub struct IndexedPack {
+    pub pack: Vec<u8>,
+    pub entries: Vec<PackedEntry>,
+    pub checksum: String,
+}
+
+struct RawEntry {
+    offset: u64,
+    end: u64,
+    header: PackEntryHeader,
+    crc32: u32,
+}
+
+// Walks every entry of `pack` and resolves deltas to learn each object id.
+// With `fix_thin`, REF_DELTA bases missing from the pack are taken from
+// `object_store` and appended to the pack so that it stands alone, like
+// `git index-pack --fix-thin`.
+pub fn index_pack(
+    object_store: &dyn ObjectStore,
+    mut pack: Vec<u8>,
+    fix_thin: bool,
+) -> Result<IndexedPack, GitError> {
+    let count: usize = check_pack(&pack)?;
+    let entries: Vec<RawEntry> = read_entries(&pack, count)?;
+
+    let thin_store: Option<&dyn ObjectStore> = if fix_thin { Some(object_store) } else { None };
+    let (shas, external): (Vec<Option<String>>, Vec<String>) = resolve_entries(thin_store, &pack, &entries)?;
+
+    let unresolved: usize = shas.iter().filter(|sha1_hash| sha1_hash.is_none()).count();
+    if unresolved > 0 {
+        return Err(GitError::InvalidPack(format!("pack has {unresolved} unresolved deltas")));
+    }
+
+    let mut indexed: Vec<PackedEntry> = Vec::with_capacity(entries.len() + external.len());
+    for (entry, sha1_hash) in entries.iter().zip(shas.into_iter().flatten()) {
+        indexed.push(PackedEntry {
+            sha1_hash,
+            offset: entry.offset,
+            crc32: entry.crc32,
+        });
+    }
+
+    if !external.is_empty() {
+        // External bases become regular entries at the end of the pack.
+        pack.truncate(pack.len() - PACK_TRAILER_SIZE);
+        for sha1_hash in external {
+            let parts: GitObjectParts<Vec<u8>> = object_store.read(&sha1_hash)?;
+
+            let offset: usize = pack.len();
+            pack.extend(encode_entry_header(type_id(&parts.git_type)?, parts.size));
+            match zlib_compression(&parts.content) {
+                Ok(compressed) => pack.extend(compressed),
+                Err(err) => return Err(GitError::WritePack(format!("zlib_compression: {err}"))),
+            }
+
+            indexed.push(PackedEntry {
+                sha1_hash,
+                offset: offset as u64,
+                crc32: crc32(&pack[offset..]),
+            });
+        }
+
+        let total: u32 = indexed.len() as u32;
+        pack[8..PACK_HEADER_SIZE].copy_from_slice(&total.to_be_bytes());
+        pack.extend(hex_to_bytes(&compute_sha1_hash(&pack))?);
+    }
+
+    let checksum: String = bytes_slice_to_hex(&pack[pack.len() - PACK_TRAILER_SIZE..]);
+    Ok(IndexedPack {
+        pack,
+        entries: indexed,
+        checksum,
+    })
+}
+
+// Checks the signature, version and trailing checksum, returning the
+// number of entries announced by the header.
+fn check_pack(pack: &[u8]) -> Result<usize, GitError> {
+    if pack.len() < PACK_HEADER_SIZE + PACK_TRAILER_SIZE || &pack[..4] != b"PACK" {
+        return Err(GitError::InvalidPack("not a packfile".to_string()));
+    }
+
+    let version: u32 = u32::from_be_bytes([pack[4], pack[5], pack[6], pack[7]]);
+    if version != 2 && version != 3 {
+        return Err(GitError::InvalidPack(format!("unsupported pack version {version}")));
+    }
+
+    let content_end: usize = pack.len() - PACK_TRAILER_SIZE;
+    if hex_to_bytes(&compute_sha1_hash(&pack[..content_end]))? != pack[content_end..] {
+        return Err(GitError::InvalidPack("pack checksum mismatch".to_string()));
+    }
+
+    Ok(u32::from_be_bytes([pack[8], pack[9], pack[10], pack[11]]) as usize)
+}
+
+fn read_entries(pack: &[u8], count: usize) -> Result<Vec<RawEntry>, GitError> {
+    let content_end: usize = pack.len() - PACK_TRAILER_SIZE;
+
+    let mut entries: Vec<RawEntry> = Vec::with_capacity(count);
+    let mut offset: usize = PACK_HEADER_SIZE;
+    for _ in 0..count {
+        let header: PackEntryHeader = parse_entry_header(&pack[offset..content_end], offset as u64)?;
+
+        let data_offset: usize = header.data_offset as usize;
+        let (content, consumed): (Vec<u8>, usize) =
+            match zlib_decompression_with_length(&pack[data_offset..content_end]) {
+                Ok(inflated) => inflated,
+                Err(err) => return Err(GitError::ZlibDecompressionFailed(err.to_string())),
+            };
+        if content.len() != header.size {
+            return Err(GitError::InvalidDecompressSize);
+        }
+
+        let end: usize = data_offset + consumed;
+        entries.push(RawEntry {
+            offset: offset as u64,
+            end: end as u64,
+            header,
+            crc32: crc32(&pack[offset..end]),
+        });
+        offset = end;
+    }
+
+    if offset != content_end {
+        return Err(GitError::InvalidPack(format!(
+            "{} bytes of garbage after the last entry",
+            content_end - offset
+        )));
+    }
+
+    Ok(entries)
+}
+
+// Object ids in entry order, `None` for deltas whose base could not be
+// found, and the bases that had to be read from `object_store`. Deltas are
+// resolved depth first from each full object so that only one chain is
+// held in memory at a time.
+fn resolve_entries(
+    object_store: Option<&dyn ObjectStore>,
+    pack: &[u8],
+    entries: &[RawEntry],
+) -> Result<(Vec<Option<String>>, Vec<String>), GitError> {
+    let mut by_offset: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut by_sha: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, entry) in entries.iter().enumerate() {
+        match &entry.header.base {
+            Some(DeltaBase::Offset(base_offset)) => by_offset.entry(*base_offset).or_default().push(i),
+            Some(DeltaBase::Sha(sha)) => by_sha.entry(bytes_slice_to_hex(sha)).or_default().push(i),
+            None => {}
+        }
+    }
+
+    let mut shas: Vec<Option<String>> = vec![None; entries.len()];
+    // Entry index (none for external bases), type and content.
+    let mut pending: Vec<(Option<usize>, u8, Vec<u8>)> = Vec::new();
+
+    let mut resolve = |pending: &mut Vec<(Option<usize>, u8, Vec<u8>)>,
+                       by_sha: &mut HashMap<String, Vec<usize>>|
+     -> Result<(), GitError> {
+        while let Some((index, object_type, content)) = pending.pop() {
+            let sha1_hash: String = object_id(object_type, &content)?;
+
+            let mut children: Vec<usize> = by_sha.remove(&sha1_hash).unwrap_or_default();
+            if let Some(index) = index {
+                children.extend(by_offset.remove(&entries[index].offset).unwrap_or_default());
+                shas[index] = Some(sha1_hash);
+            }
+
+            for child in children {
+                let delta: Vec<u8> = inflate(pack, &entries[child])?;
+                let result: Vec<u8> = apply_delta(&content, &delta)?;
+                pending.push((Some(child), object_type, result));
+            }
+        }
+        Ok(())
+    };
+
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.header.base.is_none() {
+            pending.push((Some(i), entry.header.type_id, inflate(pack, entry)?));
+            resolve(&mut pending, &mut by_sha)?;
+        }
+    }
+
+    // Whatever REF_DELTA base is still waiting is not in the pack.
+    let mut external: Vec<String> = Vec::new();
+    if let Some(object_store) = object_store {
+        let mut missing: Vec<String> = by_sha.keys().cloned().collect();
+        missing.sort();
+        for sha1_hash in missing {
+            if !by_sha.contains_key(&sha1_hash) || !object_store.contains(&sha1_hash) {
+                continue;
+            }
+
+            let parts: GitObjectParts<Vec<u8>> = object_store.read(&sha1_hash)?;
+            pending.push((None, type_id(&parts.git_type)?, parts.content));
+            resolve(&mut pending, &mut by_sha)?;
+            external.push(sha1_hash);
+        }
+    }
+
+    Ok((shas, external))
+}
+
+fn inflate(pack: &[u8], entry: &RawEntry) -> Result<Vec<u8>, GitError> {
+    match zlib_decompression(&pack[entry.header.data_offset as usize..entry.end as usize]) {
+        Ok(content) => Ok(content),
+        Err(err) => Err(GitError::ZlibDecompressionFailed(err.to_string())),
+    }
+}
+
+fn object_id(object_type: u8, content: &[u8]) -> Result<String, GitError> {
+    let mut bytes: Vec<u8> = object_header(type_name(object_type)?, content.len() as u64).into_bytes();
+    bytes.extend_from_slice(content);
+    Ok(compute_sha1_hash(&bytes))
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc: Crc = Crc::new();
+    crc.update(bytes);
+    crc.sum()
+}
+
+// Moves an indexed pack into `objects/pack` as `pack-<checksum>.pack`
+// next to its `.idx`, returning the path of the pack.
+pub fn store_pack(objects_dir: &Path, indexed: &IndexedPack) -> Result<PathBuf, GitError> {
+    let pack_dir: PathBuf = objects_dir.join("pack");
+    let pack_path: PathBuf = pack_dir.join(format!("pack-{}.pack", indexed.checksum));
+    let idx_path: PathBuf = pack_path.with_extension("idx");
+    let index: Vec<u8> = write_pack_index(&indexed.entries, &indexed.checksum)?;
+
+    let written = fs::create_dir_all(&pack_dir)
+        .and_then(|()| fs::write(&pack_path, &indexed.pack))
+        .and_then(|()| fs::write(&idx_path, index));
+    if let Err(err) = written {
+        return Err(GitError::WritePack(format!("fs::write: {err}")));
+    }
+
+    Ok(pack_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::delta::create_delta;
+    use crate::odb::hash_object;
+    use crate::odb::MemoryObjectStore;
+    use crate::pack::OBJ_REF_DELTA;
+    use crate::pack_writer::write_pack;
+    use crate::pack_writer::PackObjectInput;
+    use crate::pack_writer::PackSummary;
+
+    fn sample_blob(version: u32) -> Vec<u8> {
+        let mut content: Vec<u8> = (0..200).flat_map(|i: u32| format!("line {i}\n").into_bytes()).collect();
+        content.extend(format!("version {version}\n").into_bytes());
+        content
+    }
+
+    #[test]
+    fn test_index_pack_matches_writer() {
+        let object_store: MemoryObjectStore = MemoryObjectStore::new();
+        let objects: Vec<PackObjectInput> = (0..4)
+            .map(|version| PackObjectInput {
+                sha1_hash: object_store.write("blob", &sample_blob(version)).unwrap(),
+                name: None,
+            })
+            .collect();
+
+        let mut pack: Vec<u8> = Vec::new();
+        let summary: PackSummary = write_pack(&object_store, &objects, 10, 50, &mut pack).unwrap();
+        let indexed: IndexedPack = index_pack(&MemoryObjectStore::new(), pack, false).unwrap();
+
+        assert_eq!(summary.checksum, indexed.checksum);
+        for (written, read) in summary.entries.iter().zip(&indexed.entries) {
+            assert_eq!(
+                (&written.sha1_hash, written.offset, written.crc32),
+                (&read.sha1_hash, read.offset, read.crc32)
+            );
+        }
+    }
+
+    #[test]
+    fn test_index_pack_fixes_thin_pack() {
+        let object_store: MemoryObjectStore = MemoryObjectStore::new();
+        let base: Vec<u8> = sample_blob(0);
+        let base_sha: String = object_store.write("blob", &base).unwrap();
+        let target: Vec<u8> = sample_blob(1);
+        let delta: Vec<u8> = create_delta(&base, &target);
+
+        let mut pack: Vec<u8> = b"PACK".to_vec();
+        pack.extend(2u32.to_be_bytes());
+        pack.extend(1u32.to_be_bytes());
+        pack.extend(encode_entry_header(OBJ_REF_DELTA, delta.len()));
+        pack.extend(hex_to_bytes(&base_sha).unwrap());
+        pack.extend(zlib_compression(&delta).unwrap());
+        pack.extend(hex_to_bytes(&compute_sha1_hash(&pack)).unwrap());
+
+        assert!(index_pack(&object_store, pack.clone(), false).is_err());
+
+        let indexed: IndexedPack = index_pack(&object_store, pack, true).unwrap();
+        let mut shas: Vec<&str> = indexed.entries.iter().map(|e| e.sha1_hash.as_str()).collect();
+        shas.sort();
+        let mut expected: Vec<String> = vec![base_sha, hash_object("blob", &target)];
+        expected.sort();
+
+        assert_eq!(expected, shas);
+        assert_eq!(2, u32::from_be_bytes(indexed.pack[8..12].try_into().unwrap()));
+    }
+}
@@ -0,0 +1,184 @@
+use std::io::Write;
+
+use crate::commit::Commit;
+use crate::fetch::ABBREV;
+use crate::odb::abbreviate;
+use crate::odb::ObjectStore;
+use crate::regex;
+use crate::revwalk::RevWalk;
+use crate::GitError;
+
+#[derive(Debug, Default, Clone)]
+pub struct LogOptions {
+    // `-n`: at most this many commits, counted after filtering.
+    pub max_count: Option<usize>,
+    pub oneline: bool,
+    // `--abbrev=<n>`: at least this many digits in abbreviated ids, which
+    // `--abbrev-commit` (implied by `--oneline`) uses for commit lines.
+    pub abbrev: Option<usize>,
+    pub abbrev_commit: bool,
+    pub reverse: bool,
+    // Commits match when their author matches any of `authors` and their
+    // message any of `greps`.
+    pub authors: Vec<String>,
+    pub greps: Vec<String>,
+    pub ignore_case: bool,
+}
+
+// Writes the commits of the walk that pass the filters, in the default
+// `medium` format or one line each.
+pub fn write_log(
+    object_store: &dyn ObjectStore,
+    mut walk: RevWalk,
+    options: &LogOptions,
+    out: &mut dyn Write,
+) -> Result<(), GitError> {
+    let mut commits: Vec<(String, Commit)> = Vec::new();
+    while let Some((sha1_hash, commit)) = walk.next_commit()? {
+        if !matches_filters(&commit, options) {
+            continue;
+        }
+        if options.max_count.is_some_and(|max_count| commits.len() >= max_count) {
+            break;
+        }
+        // Nothing needs to be held back unless the order is reversed.
+        if !options.reverse {
+            write_commit(object_store, out, &sha1_hash, &commit, options, commits.is_empty())?;
+        }
+        commits.push((sha1_hash, commit));
+    }
+
+    if options.reverse {
+        for (position, (sha1_hash, commit)) in commits.iter().rev().enumerate() {
+            write_commit(object_store, out, sha1_hash, commit, options, position == 0)?;
+        }
+    }
+    Ok(())
+}
+
+fn matches_filters(commit: &Commit, options: &LogOptions) -> bool {
+    let author: Vec<u8> = [&commit.author.name[..], b" <", &commit.author.email[..], b">"].concat();
+    let author_matches: bool = options.authors.is_empty()
+        || options
+            .authors
+            .iter()
+            .any(|pattern| regex::is_match(pattern.as_bytes(), &author, options.ignore_case));
+    let message_matches: bool = options.greps.is_empty()
+        || options.greps.iter().any(|pattern| {
+            commit
+                .message()
+                .split(|&b| b == b'\n')
+                .any(|line| regex::is_match(pattern.as_bytes(), line, options.ignore_case))
+        });
+    author_matches && message_matches
+}
+
+fn write_commit(
+    object_store: &dyn ObjectStore,
+    out: &mut dyn Write,
+    sha1_hash: &str,
+    commit: &Commit,
+    options: &LogOptions,
+    first: bool,
+) -> Result<(), GitError> {
+    let abbreviated = |sha1_hash: &str| abbreviate(object_store, sha1_hash, options.abbrev.unwrap_or(ABBREV));
+
+    let mut text: String = String::new();
+    if options.oneline {
+        text.push_str(&format!("{} {}\n", abbreviated(sha1_hash)?, subject(commit)));
+    } else {
+        if !first {
+            text.push('\n');
+        }
+        match options.abbrev_commit {
+            true => text.push_str(&format!("commit {}\n", abbreviated(sha1_hash)?)),
+            false => text.push_str(&format!("commit {sha1_hash}\n")),
+        }
+        if commit.parents.len() > 1 {
+            let parents: Vec<String> = commit.parents.iter().map(|parent| abbreviated(parent)).collect::<Result<_, _>>()?;
+            text.push_str(&format!("Merge: {}\n", parents.join(" ")));
+        }
+        text.push_str(&format!(
+            "Author: {} <{}>\n",
+            String::from_utf8_lossy(&commit.author.name),
+            String::from_utf8_lossy(&commit.author.email)
+        ));
+        text.push_str(&format!("Date:   {}\n\n", commit.author.format_date()));
+
+        let message: String = String::from_utf8_lossy(commit.message()).to_string();
+        for line in message.trim_end_matches('\n').lines() {
+            text.push_str(&format!("    {line}\n"));
+        }
+    }
+
+    match out.write_all(text.as_bytes()) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(GitError::WriteOutput(err.to_string())),
+    }
+}
+
+// First paragraph of the message on one line, as `--oneline` shows it.
+pub fn subject(commit: &Commit) -> String {
+    let message: String = String::from_utf8_lossy(commit.message()).to_string();
+    let lines: Vec<&str> = message
+        .lines()
+        .skip_while(|line| line.trim().is_empty())
+        .take_while(|line| !line.trim().is_empty())
+        .map(str::trim)
+        .collect();
+    lines.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use crate::odb::ObjectStore;
+    use crate::open_object_store_at;
+    use crate::test_support::git_output;
+    use crate::test_support::TempRepo;
+
+    fn git(dir: &Path, args: &[&str]) -> Vec<u8> {
+        git_output(dir, args, Some("1700000000 -0130"))
+    }
+
+    fn log(object_store: &dyn ObjectStore, tip: &str, options: &LogOptions) -> Vec<u8> {
+        let mut walk: RevWalk = RevWalk::new(object_store);
+        walk.push(tip).unwrap();
+        let mut out: Vec<u8> = Vec::new();
+        write_log(object_store, walk, options, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_log_matches_git() {
+        let repo: TempRepo = TempRepo::new("log");
+        git(&repo, &["init", "-q", "-b", "main"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "first\n\nwith a body\n\nin two paragraphs"]);
+        git(&repo, &["checkout", "-q", "-b", "side"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "fix the\nlong subject"]);
+        git(&repo, &["checkout", "-q", "main"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "--author", "Other <other@example.com>", "-m", "second"]);
+        git(&repo, &["merge", "-q", "--no-ff", "side", "-m", "merge side"]);
+
+        let main: String = String::from_utf8(git(&repo, &["rev-parse", "main"])).unwrap().trim().to_string();
+        let object_store: Box<dyn ObjectStore> = open_object_store_at(&repo.join(".git/objects"));
+        let object_store: &dyn ObjectStore = object_store.as_ref();
+
+        assert_eq!(git(&repo, &["log", "main"]), log(object_store, &main, &LogOptions::default()));
+        let oneline: LogOptions = LogOptions { oneline: true, ..LogOptions::default() };
+        assert_eq!(git(&repo, &["log", "--oneline", "main"]), log(object_store, &main, &oneline));
+        let reversed: LogOptions = LogOptions { reverse: true, max_count: Some(2), ..LogOptions::default() };
+        assert_eq!(git(&repo, &["log", "--reverse", "-n", "2", "main"]), log(object_store, &main, &reversed));
+        let filtered: LogOptions = LogOptions {
+            oneline: true,
+            authors: vec!["fixture".to_string()],
+            greps: vec!["^F.X".to_string()],
+            ignore_case: true,
+            ..LogOptions::default()
+        };
+        assert_eq!(git(&repo, &["log", "--oneline", "-i", "--author=fixture", "--grep=^F.X", "main"]), log(object_store, &main, &filtered));
+    }
+}
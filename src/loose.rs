@@ -0,0 +1,311 @@
+use std::fs;
+use std::fs::File;
+use std::fs::ReadDir;
+
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use std::process;
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use flate2::Compression;
+
+use crate::odb::hash_stream;
+use crate::odb::ObjectReader;
+use crate::odb::ObjectStore;
+use crate::GitError;
+use crate::GitObjectParts;
+
+static TMP_OBJECT_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Objects stored one zlib deflated file each, under `objects/xx/yyyy...`.
+pub struct LooseObjectStore {
+    objects_dir: PathBuf,
+}
+
+impl LooseObjectStore {
+    pub fn new<P: AsRef<Path>>(objects_dir: P) -> Self {
+        Self {
+            objects_dir: objects_dir.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn sha1_to_file_path(&self, hash: &str) -> Result<(PathBuf, PathBuf), GitError> {
+        if hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(GitError::ObjectNotFound(hash.to_string()));
+        }
+
+        let folder_path: PathBuf = self.objects_dir.join(&hash[..2]);
+        let file_path: PathBuf = folder_path.join(&hash[2..]);
+        Ok((folder_path, file_path))
+    }
+
+    fn open_file(&self, sha1_hash: &str) -> Result<ObjectReader<'static>, GitError> {
+        let (_, file_path): (PathBuf, PathBuf) = self.sha1_to_file_path(sha1_hash)?;
+
+        let file: File = match File::open(file_path) {
+            Ok(file) => file,
+            Err(err) => {
+                return Err(GitError::FailedToReadGitObjectFile(format!("File::open: {err}")));
+            }
+        };
+
+        let mut decoder = ZlibDecoder::new(BufReader::new(file));
+        let (git_type, size): (String, usize) = read_object_header(&mut decoder)?;
+
+        Ok(ObjectReader::new(git_type, size, Box::new(decoder)))
+    }
+
+    fn move_into_place(&self, tmp_path: &Path, sha1_hash: &str) -> Result<(), GitError> {
+        let (folder_path, file_path): (PathBuf, PathBuf) = self.sha1_to_file_path(sha1_hash)?;
+
+        // Objects are content addressed, an existing file already holds these bytes.
+        if file_path.exists() {
+            let _ = fs::remove_file(tmp_path);
+            return Ok(());
+        }
+
+        let moved = fs::create_dir_all(&folder_path).and_then(|()| fs::rename(tmp_path, &file_path));
+        if let Err(err) = moved {
+            let _ = fs::remove_file(tmp_path);
+            return Err(GitError::WriteGitObject(format!("fs::rename: {err}")));
+        }
+
+        Ok(())
+    }
+}
+
+impl ObjectStore for LooseObjectStore {
+    fn read(&self, sha1_hash: &str) -> Result<GitObjectParts<Vec<u8>>, GitError> {
+        self.open_file(sha1_hash)?.read_content()
+    }
+
+    // Only inflates up to the end of the header.
+    fn read_header(&self, sha1_hash: &str) -> Result<(String, usize), GitError> {
+        let reader: ObjectReader = self.open_file(sha1_hash)?;
+        Ok((reader.git_type, reader.size))
+    }
+
+    fn write(&self, git_type: &str, content: &[u8]) -> Result<String, GitError> {
+        self.write_stream(git_type, content.len() as u64, &mut &content[..])
+    }
+
+    fn contains(&self, sha1_hash: &str) -> bool {
+        match self.sha1_to_file_path(sha1_hash) {
+            Ok((_, file_path)) => file_path.is_file(),
+            Err(_) => false,
+        }
+    }
+
+    // Every loose object id, sorted like `git cat-file --batch-all-objects`.
+    fn list(&self) -> Result<Vec<String>, GitError> {
+        let mut objects: Vec<String> = Vec::new();
+
+        let folders: ReadDir = match fs::read_dir(&self.objects_dir) {
+            Ok(rd) => rd,
+            Err(err) => {
+                return Err(GitError::FailedToReadGitObjectFile(format!("fs::read_dir: {err}")));
+            }
+        };
+
+        for folder in folders.map_while(Result::ok) {
+            let folder_name: String = folder.file_name().to_string_lossy().to_string();
+            if folder_name.len() != 2 || !folder_name.chars().all(|c| c.is_ascii_hexdigit()) {
+                continue;
+            }
+
+            let Ok(files): Result<ReadDir, _> = fs::read_dir(folder.path()) else {
+                continue;
+            };
+            for file in files.map_while(Result::ok) {
+                let file_name: String = file.file_name().to_string_lossy().to_string();
+                if file_name.len() == 38 && file_name.chars().all(|c| c.is_ascii_hexdigit()) {
+                    objects.push(format!("{folder_name}{file_name}"));
+                }
+            }
+        }
+
+        objects.sort();
+        Ok(objects)
+    }
+
+    // Only the folder of the first two digits is read.
+    fn find_prefix(&self, prefix: &str) -> Result<Vec<String>, GitError> {
+        if prefix.len() < 2 {
+            return Ok(self.list()?.into_iter().filter(|sha1_hash| sha1_hash.starts_with(prefix)).collect());
+        }
+
+        let (folder_name, file_prefix): (&str, &str) = prefix.split_at(2);
+        let Ok(files): Result<ReadDir, _> = fs::read_dir(self.objects_dir.join(folder_name)) else {
+            return Ok(Vec::new());
+        };
+        let mut objects: Vec<String> = files
+            .map_while(Result::ok)
+            .map(|file| file.file_name().to_string_lossy().to_string())
+            .filter(|file_name| file_name.len() == 38 && file_name.starts_with(file_prefix))
+            .filter(|file_name| file_name.chars().all(|c| c.is_ascii_hexdigit()))
+            .map(|file_name| format!("{folder_name}{file_name}"))
+            .collect();
+        objects.sort();
+        Ok(objects)
+    }
+
+    fn open(&self, sha1_hash: &str) -> Result<ObjectReader<'_>, GitError> {
+        self.open_file(sha1_hash)
+    }
+
+    // Hashes and deflates into a temporary file chunk by chunk, the file is
+    // renamed once the object id is known.
+    fn write_stream(
+        &self,
+        git_type: &str,
+        size: u64,
+        reader: &mut dyn Read,
+    ) -> Result<String, GitError> {
+        let tmp_path: PathBuf = self.objects_dir.join(format!(
+            "tmp_obj_{}_{}",
+            process::id(),
+            TMP_OBJECT_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let file: File = match File::create(&tmp_path) {
+            Ok(file) => file,
+            Err(err) => {
+                return Err(GitError::WriteGitObject(format!("File::create: {err}")));
+            }
+        };
+        let mut zlib_encoder = ZlibEncoder::new(BufWriter::new(file), Compression::default());
+
+        let hashed: Result<String, GitError> = hash_stream(git_type, size, reader, |chunk| {
+            match zlib_encoder.write_all(chunk) {
+                Ok(()) => Ok(()),
+                Err(err) => Err(GitError::WriteGitObject(format!("ZlibEncoder::write_all: {err}"))),
+            }
+        });
+        let sha1_hash: String = match hashed {
+            Ok(sha1_hash) => sha1_hash,
+            Err(err) => {
+                let _ = fs::remove_file(&tmp_path);
+                return Err(err);
+            }
+        };
+
+        if let Err(err) = zlib_encoder.finish().and_then(|mut writer| writer.flush()) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(GitError::WriteGitObject(format!("ZlibEncoder::finish: {err}")));
+        }
+
+        self.move_into_place(&tmp_path, &sha1_hash)?;
+        Ok(sha1_hash)
+    }
+
+    fn disk_size(&self, sha1_hash: &str) -> u64 {
+        match self.sha1_to_file_path(sha1_hash) {
+            Ok((_, file_path)) => fs::metadata(file_path).map(|m| m.len()).unwrap_or(0),
+            Err(_) => 0,
+        }
+    }
+}
+
+// Reads `<type> <size>\0` without consuming anything past the NUL.
+pub fn read_object_header<R: Read>(reader: &mut R) -> Result<(String, usize), GitError> {
+    let mut header: Vec<u8> = Vec::new();
+    let mut byte: [u8; 1] = [0];
+
+    loop {
+        match reader.read(&mut byte) {
+            Ok(0) => return Err(GitError::InvalidGitObject),
+            Ok(_) if byte[0] == b'\0' => break,
+            Ok(_) => header.push(byte[0]),
+            Err(err) => return Err(GitError::ZlibDecompressionFailed(err.to_string())),
+        }
+
+        if header.len() > 32 {
+            return Err(GitError::InvalidGitObject);
+        }
+    }
+
+    let Ok(header): Result<String, _> = String::from_utf8(header) else {
+        return Err(GitError::InvalidGitObject);
+    };
+    let Some((git_type, size)): Option<(&str, &str)> = header.split_once(' ') else {
+        return Err(GitError::InvalidGitObject);
+    };
+    let Ok(size): Result<usize, _> = size.parse::<usize>() else {
+        return Err(GitError::InvalidGitObject);
+    };
+
+    Ok((git_type.to_string(), size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use crate::odb::abbreviate;
+    use crate::test_support::TempRepo;
+
+    #[test]
+    fn test_read_object_header_stops_at_nul() {
+        let mut bytes: &[u8] = b"commit 42\0tree ...";
+
+        assert_eq!(
+            ("commit".to_string(), 42),
+            read_object_header(&mut bytes).unwrap()
+        );
+        assert_eq!(b"tree ...", bytes);
+    }
+
+    #[test]
+    fn test_loose_object_round_trip() {
+        let objects_dir: TempRepo = TempRepo::new("loose_round_trip");
+        let object_store: LooseObjectStore = LooseObjectStore::new(&objects_dir);
+
+        let sha1_hash: String = object_store.write("blob", b"hello world\n").unwrap();
+
+        assert_eq!("3b18e512dba79e4c8300dd08aeb37f8e728b8dad", sha1_hash);
+        assert!(object_store.contains(&sha1_hash));
+        assert_eq!(vec![sha1_hash.clone()], object_store.list().unwrap());
+        assert_eq!(("blob".to_string(), 12), object_store.read_header(&sha1_hash).unwrap());
+        assert_eq!(b"hello world\n".to_vec(), object_store.read(&sha1_hash).unwrap().content);
+    }
+
+    #[test]
+    fn test_find_prefix_and_abbreviate() {
+        let objects_dir: TempRepo = TempRepo::new("loose_prefix");
+        let object_store: LooseObjectStore = LooseObjectStore::new(&objects_dir);
+
+        // Enough blobs for two of them to share their first 4 digits.
+        let mut by_prefix: HashMap<String, String> = HashMap::new();
+        let (first, second): (String, String) = (0..)
+            .find_map(|i| {
+                let sha1_hash: String = object_store.write("blob", format!("blob {i}\n").as_bytes()).unwrap();
+                let other: Option<String> = by_prefix.insert(sha1_hash[..4].to_string(), sha1_hash.clone());
+                other.map(|other| (other, sha1_hash))
+            })
+            .unwrap();
+
+        let mut both: Vec<String> = vec![first.clone(), second.clone()];
+        both.sort();
+        assert_eq!(both, object_store.find_prefix(&first[..4]).unwrap());
+        assert_eq!(vec![first.clone()], object_store.find_prefix(&first).unwrap());
+        assert_eq!(object_store.list().unwrap().len(), object_store.find_prefix("").unwrap().len());
+
+        let shared: usize = first.chars().zip(second.chars()).take_while(|(a, b)| a == b).count();
+        assert_eq!(first[..shared + 1], abbreviate(&object_store, &first, 4).unwrap());
+        assert_eq!(first[..shared.max(9) + 1], abbreviate(&object_store, &first, shared.max(9) + 1).unwrap());
+        assert!(object_store.sha1_to_file_path(&first[..1]).is_err());
+    }
+}
@@ -1,13 +1,23 @@
 #![allow(dead_code)]
 
 use std::env;
+use std::process;
+
+use std::collections::HashSet;
 
 use std::fs;
 use std::fs::File;
 
+use std::io;
 use std::io::Read;
 use std::io::Write;
 
+use std::os::unix::fs::PermissionsExt;
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::fs::ReadDir;
+
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 
@@ -16,10 +26,59 @@ use flate2::Compression;
 use crypto::digest::Digest;
 use crypto::sha1::Sha1;
 
+mod add;
+mod branch;
+mod charclass;
+mod clone;
+mod commit;
+mod config;
+mod delta;
+mod diff;
+mod fetch;
+mod http;
+mod ignore;
+mod index;
+mod index_pack;
+mod log;
+mod loose;
+mod odb;
+mod pack;
+mod pack_writer;
+mod pathspec;
+mod pkt_line;
+mod protocol;
+mod push;
+mod refs;
+mod refspec;
+mod regex;
+mod revparse;
+mod revwalk;
+mod signature;
+mod status;
+mod tag;
+#[cfg(test)]
+mod test_support;
+mod timezone;
+mod transport;
+mod worktree;
+
+use commit::Commit;
+use index_pack::IndexedPack;
+use loose::LooseObjectStore;
+use pack::PackObjectStore;
+use pack_writer::PackObjectInput;
+use odb::hash_stream;
+use odb::CombinedObjectStore;
+use odb::ObjectReader;
+use odb::ObjectStore;
+use signature::Signature;
+use tag::Tag;
+
 #[derive(Debug)]
 enum GitError {
     FailedToReadGitObjectFile(String),
     InvalidGitObject,
+    InvalidObjectId(String),
     ZlibDecompressionFailed(String),
     InvalidDecompressSize,
     UnknownGitType,
@@ -27,6 +86,27 @@ enum GitError {
     InvalidTreeEntry,
 	CreateBlob(String),
 	CreateTree(String),
+    InvalidSignature(String),
+    InvalidCommit(String),
+    InvalidTag(String),
+    WriteGitObject(String),
+    InvalidDate(String),
+    InvalidBatchFormat(String),
+    ObjectNotFound(String),
+    InvalidDelta(String),
+    InvalidPack(String),
+    WritePack(String),
+    Protocol(String),
+    Transport(String),
+    InvalidRef(String),
+    Checkout(String),
+    InvalidConfig(String),
+    InvalidRefspec(String),
+    InvalidIndex(String),
+    InvalidPathspec(String),
+    WriteOutput(String),
+    AmbiguousObject(String),
+    Branch(String),
 }
 
 struct GitObjectParts<T> {
@@ -37,13 +117,14 @@ struct GitObjectParts<T> {
 
 #[derive(Debug)]
 enum GitObject {
-    Blob { content: String },
+    Blob { content: Vec<u8> },
     Tree { content: Vec<TreeEntry> },
-    Commit,
+    Commit { content: Box<Commit> },
+    Tag { content: Box<Tag> },
 }
 
 impl GitObject {
-    fn from_parts_string(parts: GitObjectParts<String>) -> Result<Self, GitError> {
+    fn from_parts_bytes(parts: GitObjectParts<Vec<u8>>) -> Result<Self, GitError> {
         if parts.size != parts.content.len() {
             return Err(GitError::InvalidGitObject);
         }
@@ -52,32 +133,43 @@ impl GitObject {
             "blob" => Ok(GitObject::Blob {
                 content: parts.content,
             }),
-            _ => Err(GitError::UnknownGitType),
-        }
-    }
-
-    fn from_parts_bytes(parts: GitObjectParts<Vec<u8>>) -> Result<Self, GitError> {
-        if parts.size != parts.content.len() {
-            return Err(GitError::InvalidGitObject);
-        }
-
-        match parts.git_type.as_str() {
             "tree" => {
                 let content: Vec<TreeEntry> = parse_str_tree_entry_vec(&parts.content)?;
                 Ok(GitObject::Tree { content })
             }
+            "commit" => Ok(GitObject::Commit {
+                content: Box::new(Commit::from_bytes(&parts.content)?),
+            }),
+            "tag" => Ok(GitObject::Tag {
+                content: Box::new(Tag::from_bytes(&parts.content)?),
+            }),
             _ => Err(GitError::UnknownGitType),
         }
     }
 
-    fn create_blob_with_content(content: String) -> Self {
+    fn create_blob_with_content(content: Vec<u8>) -> Self {
         GitObject::Blob { content }
     }
 
-    fn as_string(&self) -> String {
+    fn as_bytes(&self) -> Result<Vec<u8>, GitError> {
+        let content: Vec<u8> = self.content_bytes()?;
+        let mut bytes: Vec<u8> = format!("{} {}\0", self.get_type(), content.len()).into_bytes();
+        bytes.extend_from_slice(&content);
+        Ok(bytes)
+    }
+
+    fn content_bytes(&self) -> Result<Vec<u8>, GitError> {
         match self {
-            GitObject::Blob { content } => format!("blob {}\0{content}", content.len()),
-            _ => unimplemented!(),
+            GitObject::Blob { content } => Ok(content.clone()),
+            GitObject::Commit { content } => Ok(content.as_bytes()),
+            GitObject::Tag { content } => Ok(content.as_bytes()),
+            GitObject::Tree { content } => {
+                let mut bytes: Vec<u8> = Vec::new();
+                for te in content {
+                    bytes.extend(te.as_bytes()?);
+                }
+                Ok(bytes)
+            }
         }
     }
 
@@ -85,28 +177,28 @@ impl GitObject {
         match self {
             GitObject::Blob { .. } => "blob".to_string(),
             GitObject::Tree { .. } => "tree".to_string(),
-            GitObject::Commit => "commit".to_string(),
+            GitObject::Commit { .. } => "commit".to_string(),
+            GitObject::Tag { .. } => "tag".to_string(),
         }
     }
 
-    fn get_size(&self) -> usize {
+    fn get_size(&self) -> Result<usize, GitError> {
         match self {
-            GitObject::Blob { content } => content.len(),
-            _ => unimplemented!(),
+            GitObject::Blob { content } => Ok(content.len()),
+            GitObject::Commit { content } => Ok(content.as_bytes().len()),
+            GitObject::Tag { content } => Ok(content.as_bytes().len()),
+            GitObject::Tree { .. } => Ok(self.content_bytes()?.len()),
         }
     }
 
-    fn get_blob_content(&self) -> &str {
+    // Human readable content for `cat-file -p`, trees use the ls-tree format.
+    fn pretty_print(&self) -> Result<Vec<u8>, GitError> {
         match self {
-            GitObject::Blob { content } => content,
-            _ => unimplemented!(),
-        }
-    }
-
-    fn get_tree_content(&self) -> &Vec<TreeEntry> {
-        match self {
-            GitObject::Tree { content } => content,
-            _ => unimplemented!(),
+            GitObject::Tree { content } => Ok(content
+                .iter()
+                .flat_map(|te| format!("{}\n", te.as_ls_tree_line()).into_bytes())
+                .collect()),
+            _ => self.content_bytes(),
         }
     }
 }
@@ -126,41 +218,48 @@ impl TreeEntry {
         let mode = EntryMode::from_mode_value(mode)?;
         Ok(Self{ mode, name, sha1_hash: byte_sha_hex })
     }
-}
-
-fn parse_tree_entry_bytes(teb: &[u8]) -> Result<(usize, String, String), GitError> {
-    let mut mode = String::new();
 
-    let mut index: usize = 0;
-    loop {
-        if teb[index].eq(&b' ') {
-            break;
-        }
+    fn as_ls_tree_line(&self) -> String {
+        format!(
+            "{:06} {} {}\t{}",
+            self.mode.as_mode_value(),
+            self.mode.get_object_type(),
+            self.sha1_hash,
+            self.name
+        )
+    }
 
-        mode.push(teb[index] as char);
-        index += 1;
+    fn as_bytes(&self) -> Result<Vec<u8>, GitError> {
+        let mut bytes: Vec<u8> = format!("{} {}\0", self.mode.as_mode_value(), self.name).into_bytes();
+        bytes.extend(hex_to_bytes(&self.sha1_hash)?);
+        Ok(bytes)
     }
-    index += 1;
+}
 
-    let mode: usize = match mode.parse::<usize>() {
-        Ok(value) => value,
-        Err(_err) => return Err(GitError::InvalidTreeEntry),
+fn parse_tree_entry_bytes(teb: &[u8]) -> Result<(usize, String, String), GitError> {
+    let Some(nul): Option<usize> = teb.iter().position(|&byte| byte == b'\0') else {
+        return Err(GitError::InvalidTreeEntry);
+    };
+    let Some(space): Option<usize> = teb[..nul].iter().position(|&byte| byte == b' ') else {
+        return Err(GitError::InvalidTreeEntry);
     };
 
-    let mut name = String::new();
+    let Some(mode): Option<usize> = std::str::from_utf8(&teb[..space]).ok().and_then(|mode| mode.parse::<usize>().ok()) else {
+        return Err(GitError::InvalidTreeEntry);
+    };
 
-    loop {
-        if teb[index].eq(&b'\0') {
-            break;
-        }
+    // Names are kept as they are or not at all, to write the tree back
+    // unchanged.
+    let name: String = match String::from_utf8(teb[space + 1..nul].to_vec()) {
+        Ok(name) => name,
+        Err(_err) => return Err(GitError::InvalidTreeEntry),
+    };
 
-        name.push(teb[index] as char);
-        index += 1;
+    let byte_sha: &[u8] = &teb[nul + 1..];
+    if byte_sha.len() != 20 {
+        return Err(GitError::InvalidTreeEntry);
     }
-    index += 1;
-
-    let byte_sha: Vec<u8> = teb[index..teb.len()].to_vec();
-    let byte_sha_hex: String = bytes_slice_to_hex(&byte_sha[..]);
+    let byte_sha_hex: String = bytes_slice_to_hex(byte_sha);
 
     Ok((mode, name, byte_sha_hex))
 }
@@ -170,12 +269,24 @@ fn bytes_slice_to_hex(slice: &[u8]) -> String {
     hex.replace(", ", "").replace(['[', ']'], "")
 }
 
-#[derive(Debug)]
+// The 20 raw bytes of a full hex object id.
+fn hex_to_bytes(hex: &str) -> Result<Vec<u8>, GitError> {
+    if hex.len() != 40 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(GitError::InvalidObjectId(hex.to_string()));
+    }
+
+    Ok((0..hex.len() / 2)
+        .map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap())
+        .collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum EntryMode {
     RegularFile = 100644,
     ExecutableFile = 100755,
     SymbolicLink = 120000,
     Directory = 40000,
+    Submodule = 160000,
 }
 
 impl EntryMode {
@@ -185,15 +296,67 @@ impl EntryMode {
             100755 => Ok(EntryMode::ExecutableFile),
             120000 => Ok(EntryMode::SymbolicLink),
             40000 => Ok(EntryMode::Directory),
+            160000 => Ok(EntryMode::Submodule),
             _ => Err(GitError::UnknownEntryMode)
         }
     }
+
+    fn from_metadata(metadata: &fs::Metadata) -> Self {
+        if metadata.is_dir() {
+            EntryMode::Directory
+        }
+        else if metadata.file_type().is_symlink() {
+            EntryMode::SymbolicLink
+        }
+        else if metadata.permissions().mode() & 0o111 != 0 {
+            EntryMode::ExecutableFile
+        }
+        else {
+            EntryMode::RegularFile
+        }
+    }
+
+    // The index stores modes as the octal numbers the values read as.
+    fn from_index_mode(mode: u32) -> Result<Self, GitError> {
+        Self::from_mode_value(format!("{mode:o}").parse().unwrap_or(0))
+    }
+
+    fn as_index_mode(&self) -> u32 {
+        u32::from_str_radix(&self.as_mode_value().to_string(), 8).unwrap_or(0)
+    }
+
+    fn as_mode_value(&self) -> usize {
+        *self as usize
+    }
+
+    fn get_object_type(&self) -> &'static str {
+        match self {
+            EntryMode::Directory => "tree",
+            EntryMode::Submodule => "commit",
+            _ => "blob",
+        }
+    }
 }
 
 const GIT_COMMAND_INIT: &str = "init";
 const GIT_COMMAND_CAT_FILE: &str = "cat-file";
 const GIT_COMMAND_HASH_OBJECT: &str = "hash-object";
 const GIT_COMMAND_LS_TREE: &str = "ls-tree";
+const GIT_COMMAND_WRITE_TREE: &str = "write-tree";
+const GIT_COMMAND_COMMIT_TREE: &str = "commit-tree";
+const GIT_COMMAND_PACK_OBJECTS: &str = "pack-objects";
+const GIT_COMMAND_INDEX_PACK: &str = "index-pack";
+const GIT_COMMAND_CLONE: &str = "clone";
+const GIT_COMMAND_FETCH: &str = "fetch";
+const GIT_COMMAND_PUSH: &str = "push";
+const GIT_COMMAND_ADD: &str = "add";
+const GIT_COMMAND_STATUS: &str = "status";
+const GIT_COMMAND_DIFF: &str = "diff";
+const GIT_COMMAND_LOG: &str = "log";
+const GIT_COMMAND_SHOW_REF: &str = "show-ref";
+const GIT_COMMAND_CHECK_REF_FORMAT: &str = "check-ref-format";
+const GIT_COMMAND_REV_PARSE: &str = "rev-parse";
+const GIT_COMMAND_BRANCH: &str = "branch";
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -208,87 +371,263 @@ fn main() {
         GIT_COMMAND_CAT_FILE => git_cat_file(&args[..]),
         GIT_COMMAND_HASH_OBJECT => git_hash_object(&args[..]),
         GIT_COMMAND_LS_TREE => git_ls_tree(&args[..]),
+        GIT_COMMAND_WRITE_TREE => git_write_tree(),
+        GIT_COMMAND_COMMIT_TREE => git_commit_tree(&args[..]),
+        GIT_COMMAND_PACK_OBJECTS => git_pack_objects(&args[..]),
+        GIT_COMMAND_INDEX_PACK => git_index_pack(&args[..]),
+        GIT_COMMAND_CLONE => git_clone(&args[..]),
+        GIT_COMMAND_FETCH => git_fetch(&args[..]),
+        GIT_COMMAND_PUSH => git_push(&args[..]),
+        GIT_COMMAND_ADD => git_add(&args[..]),
+        GIT_COMMAND_STATUS => git_status(&args[..]),
+        GIT_COMMAND_DIFF => git_diff(&args[..]),
+        GIT_COMMAND_LOG => git_log(&args[..]),
+        GIT_COMMAND_SHOW_REF => git_show_ref(&args[..]),
+        GIT_COMMAND_CHECK_REF_FORMAT => git_check_ref_format(&args[..]),
+        GIT_COMMAND_REV_PARSE => git_rev_parse(&args[..]),
+        GIT_COMMAND_BRANCH => git_branch(&args[..]),
         _ => println!("unknown command: {}", args[1]),
     }
 }
 
 fn git_init() {
-    fs::create_dir(".git").unwrap();
-    fs::create_dir(".git/objects").unwrap();
-    fs::create_dir(".git/refs").unwrap();
-    fs::write(".git/HEAD", "ref: refs/heads/main\n").unwrap();
+    init_git_dir(Path::new(".git")).unwrap();
     println!("Initialized git directory");
 }
 
+fn init_git_dir(git_dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(git_dir.join("objects"))?;
+    fs::create_dir_all(git_dir.join("refs/heads"))?;
+    fs::create_dir_all(git_dir.join("refs/tags"))?;
+    fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n")
+}
+
 fn git_cat_file(args: &[String]) {
-    if args.len() < 3 {
-        println!("git cat-file needs 2 arguments.");
+    if args.len() >= 3 && args[2..].iter().any(|arg| arg.starts_with("--batch")) {
+        git_cat_file_batch(&args[2..]);
         return;
     }
 
-    let (option, blob_sha): (Option<&str>, &str) = if args.len() == 3 {
-        (None, args[2].as_str())
-    } else {
-        (Some(args[2].as_str()), args[3].as_str())
-    };
+    if args.len() < 4 {
+        println!("git cat-file needs 2 arguments.");
+        return;
+    }
 
-    let (folder_path, file_name): (String, String) = sha1_to_file_path(blob_sha);
-    let file_path: String = format!("{folder_path}/{file_name}");
+    let (option, revision): (&str, &str) = (args[2].as_str(), args[3].as_str());
+    let object_store: Box<dyn ObjectStore> = open_object_store();
 
-    let file: File = match File::open(file_path) {
-        Ok(file) => file,
+    let object_sha: String = match revparse::resolve_revision(Path::new(GIT_DIR_PATH), object_store.as_ref(), revision) {
+        Ok(object_sha) => object_sha,
+        Err(_) if option.eq("-e") => process::exit(1),
         Err(err) => {
-            println!("File::open: {err}");
-            return;
+            print_ambiguity(&err);
+            println!("fatal: Not a valid object name {revision}");
+            println!("revparse::resolve_revision: {err:?}");
+            process::exit(128);
         }
     };
+    let object_sha: &str = &object_sha;
 
-    let bytes: Vec<u8> = match get_file_bytes(file) {
-        Ok(bytes) => bytes,
+    // Only the header is inflated unless the content is actually needed.
+    let mut reader: ObjectReader = match object_store.open(object_sha) {
+        Ok(reader) => reader,
         Err(err) => {
-            println!("get_file_bytes: {err}");
+            if option.eq("-e") {
+                process::exit(1);
+            }
+            println!("fatal: Not a valid object name {object_sha}");
+            println!("ObjectStore::open: {err:?}");
             return;
         }
     };
 
-    let decompressed_bytes: Vec<u8> = match zlib_decompression(&bytes[..]) {
-        Ok(s) => s,
-        Err(err) => {
-            println!("zlib_decompression: {err}");
+    let mut stdout = io::stdout().lock();
+
+    let content: Vec<u8> = match option {
+        "-e" => return,
+        "-t" => format!("{}\n", reader.git_type).into_bytes(),
+        "-s" => format!("{}\n", reader.size).into_bytes(),
+        _ if reader.git_type.eq(option) || (option.eq("-p") && reader.git_type.eq("blob")) => {
+            if let Err(err) = io::copy(&mut reader, &mut stdout) {
+                println!("io::copy: {err}");
+            }
             return;
         }
-    };
+        "-p" | "blob" | "tree" | "commit" | "tag" => {
+            let git_object: GitObject = match read_git_object(object_store.as_ref(), object_sha) {
+                Ok(git_object) => git_object,
+                Err(err) => {
+                    println!("read_git_object: {err:?}");
+                    return;
+                }
+            };
 
-    let content: String = match String::from_utf8(decompressed_bytes) {
-        Ok(s) => s,
-        Err(err) => {
-            println!("String::from_utf8: {err}");
+            let content: Result<Vec<u8>, GitError> = if option.eq("-p") {
+                git_object.pretty_print()
+            } else {
+                match peel_to_type(object_store.as_ref(), git_object, option) {
+                    Ok(git_object) => git_object.content_bytes(),
+                    Err(err) => {
+                        println!("fatal: git cat-file {revision}: bad file");
+                        println!("peel_to_type: {err:?}");
+                        return;
+                    }
+                }
+            };
+
+            match content {
+                Ok(content) => content,
+                Err(err) => {
+                    println!("GitObject::content_bytes: {err:?}");
+                    return;
+                }
+            }
+        }
+        _ => {
+            println!("Unknow option {option}.");
             return;
         }
     };
 
-    let git_object_parts: GitObjectParts<String> =
-        match parse_str_to_git_object_parts_string(&content) {
-            Ok(parts) => parts,
+    if let Err(err) = stdout.write_all(&content) {
+        println!("Stdout::write_all: {err}");
+    }
+}
+
+const DEFAULT_BATCH_FORMAT: &str = "%(objectname) %(objecttype) %(objectsize)";
+
+fn git_cat_file_batch(options: &[String]) {
+    let mut format: Option<String> = None;
+    let mut with_content: bool = false;
+    let mut all_objects: bool = false;
+
+    for option in options {
+        match option.split_once('=') {
+            Some(("--batch", custom)) => (format, with_content) = (Some(custom.to_string()), true),
+            Some(("--batch-check", custom)) => (format, with_content) = (Some(custom.to_string()), false),
+            _ => match option.as_str() {
+                "--batch" => with_content = true,
+                "--batch-check" => with_content = false,
+                "--batch-all-objects" => all_objects = true,
+                _ => {
+                    println!("Unknow option {option}.");
+                    return;
+                }
+            },
+        }
+    }
+
+    let format: String = format.unwrap_or_else(|| DEFAULT_BATCH_FORMAT.to_string());
+    let split_rest: bool = format.contains("%(rest)");
+    let git_dir: &Path = Path::new(GIT_DIR_PATH);
+    let object_store: Box<dyn ObjectStore> = open_object_store();
+
+    let inputs: Vec<String> = if all_objects {
+        match object_store.list() {
+            Ok(objects) => objects,
             Err(err) => {
-                println!("parse_str_to_git_object_parts: {err:?}");
+                println!("ObjectStore::list: {err:?}");
                 return;
             }
+        }
+    } else {
+        io::stdin().lines().map_while(Result::ok).collect()
+    };
+
+    let mut stdout = io::stdout().lock();
+    for input in inputs {
+        // Like git, the line is only split when the format asks for %(rest).
+        let (revision, rest): (&str, &str) = match input.split_once(char::is_whitespace) {
+            Some((revision, rest)) if split_rest => (revision, rest.trim_start()),
+            _ => (input.as_str(), ""),
         };
 
-    let git_object: GitObject = match GitObject::from_parts_string(git_object_parts) {
-        Ok(git_object) => git_object,
-        Err(err) => {
-            println!("GitObject::from_parts: {err:?}");
+        let opened: Result<(String, ObjectReader), GitError> =
+            revparse::resolve_revision(git_dir, object_store.as_ref(), revision).and_then(|object_sha| {
+                let reader: ObjectReader = object_store.open(&object_sha)?;
+                Ok((object_sha, reader))
+            });
+        let (object_sha, mut reader): (String, ObjectReader) = match opened {
+            Ok(opened) => opened,
+            Err(err) => {
+                let problem: &str = match err {
+                    GitError::AmbiguousObject(_) => "ambiguous",
+                    _ => "missing",
+                };
+                if let Err(err) = writeln!(stdout, "{revision} {problem}").and_then(|()| stdout.flush()) {
+                    println!("Stdout::write_all: {err}");
+                    return;
+                }
+                continue;
+            }
+        };
+
+        let header: String = match format_batch_line(
+            object_store.as_ref(),
+            &format,
+            &object_sha,
+            &reader.git_type,
+            reader.size,
+            rest,
+        ) {
+            Ok(header) => header,
+            Err(err) => {
+                println!("fatal: {err:?}");
+                return;
+            }
+        };
+
+        let mut written: io::Result<()> = writeln!(stdout, "{header}");
+        if with_content {
+            written = written
+                .and_then(|()| io::copy(&mut reader, &mut stdout).map(|_| ()))
+                .and_then(|()| stdout.write_all(b"\n"));
+        }
+
+        // Flush per object so callers can drive the batch interactively.
+        if let Err(err) = written.and_then(|()| stdout.flush()) {
+            println!("Stdout::write_all: {err}");
             return;
         }
-    };
+    }
+}
 
-    if let Some(option) = option {
-        if option.eq("-p") {
-            print!("{}", git_object.get_blob_content());
+fn format_batch_line(
+    object_store: &dyn ObjectStore,
+    format: &str,
+    object_sha: &str,
+    git_type: &str,
+    size: usize,
+    rest: &str,
+) -> Result<String, GitError> {
+    let mut line: String = String::new();
+
+    let mut remaining: &str = format;
+    while let Some(start) = remaining.find("%(") {
+        line.push_str(&remaining[..start]);
+        let Some(end) = remaining[start..].find(')') else {
+            return Err(GitError::InvalidBatchFormat(format.to_string()));
+        };
+
+        let atom: &str = &remaining[start + 2..start + end];
+        match atom {
+            "objectname" => line.push_str(object_sha),
+            "objecttype" => line.push_str(git_type),
+            "objectsize" => line.push_str(&size.to_string()),
+            "objectsize:disk" => line.push_str(&object_store.disk_size(object_sha).to_string()),
+            "deltabase" => match object_store.delta_base(object_sha) {
+                Some(delta_base) => line.push_str(&delta_base),
+                None => line.push_str(&"0".repeat(40)),
+            },
+            "rest" => line.push_str(rest),
+            _ => return Err(GitError::InvalidBatchFormat(atom.to_string())),
         }
+
+        remaining = &remaining[start + end + 1..];
     }
+    line.push_str(remaining);
+
+    Ok(line)
 }
 
 fn git_hash_object(args: &[String]) {
@@ -303,287 +642,1548 @@ fn git_hash_object(args: &[String]) {
         (Some(args[2].as_str()), args[3].as_str())
     };
 
-    let mut file: File = match File::open(file_path) {
-        Ok(file) => file,
-        Err(err) => {
-            println!("File::open: {err}");
-            return;
-        }
+    let hashed: Result<String, GitError> = if option.is_some_and(|option| option.eq("-w")) {
+        create_blob_object(open_object_store().as_ref(), Path::new(file_path))
+    } else {
+        hash_blob_object(Path::new(file_path))
     };
 
-    let mut content: String = String::new();
-    let _read_bytes: usize = match file.read_to_string(&mut content) {
-        Ok(read_bytes) => read_bytes,
+    let sha1_hash: String = match hashed {
+        Ok(sha1_hash) => sha1_hash,
         Err(err) => {
-            println!("File::read_to_string: {err}");
+            println!("create_blob_object: {err:?}");
             return;
         }
     };
 
-    let git_object = GitObject::create_blob_with_content(content);
-    let str_git_object: String = git_object.as_string();
-    let sha1_hash: String = compute_sha1_hash(&str_git_object);
-    let bytes: Vec<u8> = match zlib_compression(&str_git_object) {
-        Ok(bytes) => bytes,
-        Err(err) => {
-            println!("zlib_compression: {err}");
-            return;
-        }
-    };
+    println!("{sha1_hash}");
+}
 
-    if let Some(option) = option {
-        if option.eq("-w") {
-            let (folder_path, file_name): (String, String) = sha1_to_file_path(&sha1_hash);
-            match write_bytes_to_file(&folder_path, &file_name, &bytes[..]) {
-                Ok(()) => {}
-                Err(err) => {
-                    println!("write_bytes_to_file: {err}");
+fn git_ls_tree(args: &[String]) {
+    let mut name_only: bool = false;
+    let mut abbrev: Option<usize> = None;
+    let mut revision: Option<&str> = None;
+    for arg in &args[2..] {
+        match arg.as_str() {
+            "--name-only" => name_only = true,
+            "--abbrev" => abbrev = Some(fetch::ABBREV),
+            _ if arg.starts_with("--abbrev=") => match arg["--abbrev=".len()..].parse() {
+                Ok(length) => abbrev = Some(length),
+                Err(_) => {
+                    println!("error: option `abbrev' expects a numerical value");
+                    process::exit(129);
                 }
+            },
+            _ if arg.starts_with('-') => {
+                println!("Unknow option {arg}.");
+                return;
             }
+            _ => revision = revision.or(Some(arg)),
         }
     }
-
-    println!("{sha1_hash}");
-}
-
-fn git_ls_tree(args: &[String]) {
-    if args.len() < 3 {
+    let Some(revision) = revision else {
         println!("git ls-tree needs at least 2 arguments.");
         return;
-    }
-
-    let (option, blob_sha): (Option<&str>, &str) = if args.len() == 3 {
-        (None, args[2].as_str())
-    } else {
-        (Some(args[2].as_str()), args[3].as_str())
     };
 
-    let (folder_path, file_name): (String, String) = sha1_to_file_path(blob_sha);
-    let file_path: String = format!("{folder_path}/{file_name}");
+    let object_store: Box<dyn ObjectStore> = open_object_store();
 
-    let file: File = match File::open(file_path) {
-        Ok(file) => file,
+    let blob_sha: String = match revparse::resolve_revision(Path::new(GIT_DIR_PATH), object_store.as_ref(), revision) {
+        Ok(blob_sha) => blob_sha,
         Err(err) => {
-            println!("File::open: {err}");
-            return;
+            print_ambiguity(&err);
+            println!("fatal: Not a valid object name {revision}");
+            println!("revparse::resolve_revision: {err:?}");
+            process::exit(128);
         }
     };
 
-    let bytes: Vec<u8> = match get_file_bytes(file) {
-        Ok(bytes) => bytes,
+    let git_object: GitObject = match read_git_object(object_store.as_ref(), &blob_sha) {
+        Ok(git_object) => git_object,
         Err(err) => {
-            println!("get_file_bytes: {err}");
+            println!("read_git_object: {err:?}");
             return;
         }
     };
 
-    let decompressed_bytes: Vec<u8> = match zlib_decompression(&bytes[..]) {
-        Ok(s) => s,
+    let git_object: GitObject = match peel_git_object(object_store.as_ref(), git_object) {
+        Ok(git_object) => git_object,
         Err(err) => {
-            println!("zlib_decompression: {err}");
+            println!("peel_git_object: {err:?}");
             return;
         }
     };
 
-    let git_object_parts: GitObjectParts<Vec<u8>> =
-        match parse_str_to_git_object_parts_bytes(&decompressed_bytes) {
-            Ok(parts) => parts,
+    let git_object: GitObject = match git_object {
+        GitObject::Commit { content } => match read_git_object(object_store.as_ref(), &content.tree) {
+            Ok(git_object) => git_object,
             Err(err) => {
-                println!("parse_str_to_git_object_parts: {err:?}");
+                println!("read_git_object: {err:?}");
                 return;
             }
-        };
-
-    let git_object: GitObject = match GitObject::from_parts_bytes(git_object_parts) {
-        Ok(git_object) => git_object,
-        Err(err) => {
-            println!("GitObject::from_parts: {err:?}");
+        },
+        GitObject::Tree { .. } => git_object,
+        _ => {
+            println!("fatal: not a tree object");
             return;
         }
     };
 
-    if let Some(option) = option {
-        if option.eq("--name-only") {
-            let tree_entry: &Vec<TreeEntry> = git_object.get_tree_content();
-            tree_entry.iter().for_each(|te| println!("{}", te.name));
-        }
-        else {
-            println!("Unknow option {option}.");
+    let GitObject::Tree { content: tree_entry } = git_object else {
+        println!("fatal: not a tree object");
+        return;
+    };
+    if name_only {
+        tree_entry.iter().for_each(|te| println!("{}", te.name));
+        return;
+    }
+
+    for te in &tree_entry {
+        let Some(length) = abbrev else {
+            println!("{}", te.as_ls_tree_line());
+            continue;
+        };
+        match odb::abbreviate(object_store.as_ref(), &te.sha1_hash, length) {
+            Ok(sha1_hash) => {
+                let abbreviated: TreeEntry = TreeEntry { mode: te.mode, name: te.name.clone(), sha1_hash };
+                println!("{}", abbreviated.as_ls_tree_line());
+            }
+            Err(err) => {
+                println!("odb::abbreviate: {err:?}");
+                return;
+            }
         }
     }
 }
 
-fn git_write_tree() -> String {
-    todo!()
+fn git_write_tree() {
+    match create_tree_object(open_object_store().as_ref(), Path::new(".")) {
+        Ok(sha1_hash) => println!("{sha1_hash}"),
+        Err(err) => println!("create_tree_object: {err:?}"),
+    }
 }
 
-use std::path::Path;
-use std::fs::ReadDir;
-
-fn create_tree_object(dir: &Path) -> Result<String, GitError> {
-    if !dir.is_dir() {
-        return Err(GitError::CreateTree("Tree dir is not a directory.".to_string()));
+fn git_commit_tree(args: &[String]) {
+    if args.len() < 3 {
+        println!("git commit-tree needs at least 1 argument.");
+        return;
     }
 
-    let entries: ReadDir = match fs::read_dir(dir) {
-        Ok(rd) => rd,
-        Err(err) => {
-            return Err(GitError::CreateTree(format!("fs::read_dir: {err}.")));
-        }
-    };
-
-    for entry in entries {
-        let entry = match entry {
-            Ok(entry) => entry,
-            Err(err) => {
-                return Err(GitError::CreateTree(format!("entry: {err}.")));
+    let mut tree_sha: Option<&str> = None;
+    let mut parents: Vec<String> = Vec::new();
+    let mut message: Option<Vec<u8>> = None;
+
+    let mut index: usize = 2;
+    while index < args.len() {
+        let arg: &str = args[index].as_str();
+        let value: Option<&String> = args.get(index + 1);
+
+        match (arg, value) {
+            ("-p", Some(parent)) => {
+                if parents.contains(parent) {
+                    eprintln!("error: duplicate parent {parent} ignored");
+                } else {
+                    parents.push(parent.clone());
+                }
+                index += 1;
+            }
+            ("-m", Some(paragraph)) => {
+                // Each -m is its own paragraph, as with git commit.
+                let message: &mut Vec<u8> = message.get_or_insert_with(Vec::new);
+                if !message.is_empty() {
+                    message.push(b'\n');
+                }
+                message.extend_from_slice(paragraph.as_bytes());
+                message.push(b'\n');
+                index += 1;
+            }
+            ("-F", Some(file_path)) => {
+                let content: io::Result<Vec<u8>> = if file_path.eq("-") {
+                    let mut content: Vec<u8> = Vec::new();
+                    io::stdin().read_to_end(&mut content).map(|_| content)
+                } else {
+                    fs::read(file_path)
+                };
+                match content {
+                    Ok(content) => message.get_or_insert_with(Vec::new).extend(content),
+                    Err(err) => {
+                        println!("fatal: could not read log file '{file_path}': {err}");
+                        return;
+                    }
+                }
+                index += 1;
+            }
+            ("-p" | "-m" | "-F", None) => {
+                println!("fatal: option '{arg}' requires a value");
+                return;
+            }
+            (_, _) if tree_sha.is_none() => tree_sha = Some(arg),
+            (_, _) => {
+                println!("fatal: unexpected argument {arg}");
+                return;
             }
-        };
-
-        let path = entry.path();
-        if path.is_dir() {
-            let tree_sha: String = create_tree_object(&path)?;
-        }
-        else {
-            let Some(filepath) = path.to_str() else {
-                return Err(GitError::CreateTree("path.to_str.".to_string()));
-            };
-            let blob_sha: String = create_blob_object(filepath)?;
         }
+        index += 1;
     }
 
-    todo!()
-}
-
-fn create_blob_object(file_path: &str) -> Result<String, GitError> {
-	let mut file: File = match File::open(file_path) {
-        Ok(file) => file,
-        Err(err) => {
-            return Err(GitError::CreateBlob(format!("File::open: {err}")));
-        }
+    let Some(tree_sha) = tree_sha else {
+        println!("fatal: must give exactly one tree");
+        return;
     };
 
-    let mut content: String = String::new();
-    let _read_bytes: usize = match file.read_to_string(&mut content) {
-        Ok(read_bytes) => read_bytes,
-        Err(err) => {
-            return Err(GitError::CreateBlob(format!("File::read_to_string: {err}")));
+    let message: Vec<u8> = match message {
+        Some(message) => message,
+        None => {
+            let mut content: Vec<u8> = Vec::new();
+            if let Err(err) = io::stdin().read_to_end(&mut content) {
+                println!("Stdin::read_to_end: {err}");
+                return;
+            }
+            content
         }
     };
 
-    let git_object = GitObject::create_blob_with_content(content);
-    let str_git_object: String = git_object.as_string();
-    let sha1_hash: String = compute_sha1_hash(&str_git_object);
-    let bytes: Vec<u8> = match zlib_compression(&str_git_object) {
-        Ok(bytes) => bytes,
+    // The tree and parents may be given in any revision syntax.
+    let object_store: Box<dyn ObjectStore> = open_object_store();
+    let resolve = |revision: &str| match revparse::resolve_revision(Path::new(GIT_DIR_PATH), object_store.as_ref(), revision) {
+        Ok(sha1_hash) => sha1_hash,
         Err(err) => {
-            return Err(GitError::CreateBlob(format!("zlib_compression: {err}")));
+            print_ambiguity(&err);
+            println!("fatal: not a valid object name {revision}");
+            println!("revparse::resolve_revision: {err:?}");
+            process::exit(128);
         }
     };
+    let tree_sha: String = resolve(tree_sha);
+    let parents: Vec<String> = parents.iter().map(|parent| resolve(parent)).collect();
 
-	let (folder_path, file_name): (String, String) = sha1_to_file_path(&sha1_hash);
-	match write_bytes_to_file(&folder_path, &file_name, &bytes[..]) {
-		Ok(()) => {}
-		Err(err) => {
-			return Err(GitError::CreateBlob(format!("write_bytes_to_file: {err}")));
-		}
-	}
-
-    Ok(sha1_hash)
+    match create_commit_object(object_store.as_ref(), &tree_sha, parents, message) {
+        Ok(sha1_hash) => println!("{sha1_hash}"),
+        Err(err) => println!("create_commit_object: {err:?}"),
+    }
 }
 
-const GIT_OBJECT_FOLDER_PATH: &str = ".git/objects";
+fn git_pack_objects(args: &[String]) {
+    let mut to_stdout: bool = false;
+    let mut window: usize = pack_writer::DEFAULT_WINDOW;
+    let mut depth: usize = pack_writer::DEFAULT_DEPTH;
+    let mut base_name: Option<&str> = None;
+
+    for arg in &args[2..] {
+        if arg.eq("--stdout") {
+            to_stdout = true;
+        } else if let Some(value) = arg.strip_prefix("--window=") {
+            let Ok(value) = value.parse::<usize>() else {
+                println!("fatal: invalid --window value '{value}'");
+                return;
+            };
+            window = value;
+        } else if let Some(value) = arg.strip_prefix("--depth=") {
+            let Ok(value) = value.parse::<usize>() else {
+                println!("fatal: invalid --depth value '{value}'");
+                return;
+            };
+            depth = value;
+        } else if base_name.is_none() && !arg.starts_with('-') {
+            base_name = Some(arg.as_str());
+        } else {
+            println!("fatal: unexpected argument {arg}");
+            return;
+        }
+    }
 
-fn sha1_to_file_path(hash: &str) -> (String, String) {
-    let folder_path = format!("{GIT_OBJECT_FOLDER_PATH}/{}", &hash[..2]);
-    let file_name = (hash[2..]).to_string();
-    (folder_path, file_name)
-}
+    if to_stdout == base_name.is_some() {
+        println!("usage: git pack-objects [--window=<n>] [--depth=<n>] (--stdout | <base-name>)");
+        return;
+    }
 
-fn get_file_bytes(mut file: File) -> std::io::Result<Vec<u8>> {
-    let mut buffer: Vec<u8> = Vec::new();
-    file.read_to_end(&mut buffer)?;
-    Ok(buffer)
-}
+    // One object per line, optionally followed by the path it was found at.
+    let mut objects: Vec<PackObjectInput> = Vec::new();
+    for line in io::stdin().lines() {
+        let line: String = match line {
+            Ok(line) => line,
+            Err(err) => {
+                println!("Stdin::lines: {err}");
+                return;
+            }
+        };
+        let (sha1_hash, name): (&str, Option<&str>) = match line.split_once(' ') {
+            Some((sha1_hash, name)) => (sha1_hash, Some(name)),
+            None => (line.as_str(), None),
+        };
+        if sha1_hash.is_empty() {
+            continue;
+        }
+        objects.push(PackObjectInput {
+            sha1_hash: sha1_hash.to_string(),
+            name: name.map(str::to_string),
+        });
+    }
 
-fn zlib_decompression(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
-    let mut zlib_decoder = ZlibDecoder::new(bytes);
-    let mut content: Vec<u8> = Vec::new();
-    zlib_decoder.read_to_end(&mut content)?;
-    Ok(content)
-}
+    let object_store: Box<dyn ObjectStore> = open_object_store();
+    let written: Result<String, GitError> = match base_name {
+        Some(base_name) => pack_writer::write_pack_files(
+            object_store.as_ref(),
+            &objects,
+            window,
+            depth,
+            Path::new(base_name),
+        ),
+        None => pack_writer::write_pack(object_store.as_ref(), &objects, window, depth, io::stdout().lock())
+            .map(|summary| summary.checksum),
+    };
 
-fn zlib_compression(content: &str) -> std::io::Result<Vec<u8>> {
-    let mut zlib_encode = ZlibEncoder::new(Vec::new(), Compression::default());
-    zlib_encode.write_all(content.as_bytes())?;
-    zlib_encode.finish()
+    match written {
+        Ok(checksum) if base_name.is_some() => println!("{checksum}"),
+        Ok(_) => {}
+        Err(err) => eprintln!("pack_objects: {err:?}"),
+    }
 }
 
-fn parse_str_to_git_object_parts_string(s: &str) -> Result<GitObjectParts<String>, GitError> {
-    let Some((first, content)): Option<(&str, &str)> = s.split_once("\0") else {
-        return Err(GitError::InvalidGitObject);
+fn git_index_pack(args: &[String]) {
+    let mut from_stdin: bool = false;
+    let mut fix_thin: bool = false;
+    let mut idx_path: Option<PathBuf> = None;
+    let mut pack_path: Option<PathBuf> = None;
+
+    let mut index: usize = 2;
+    while index < args.len() {
+        match (args[index].as_str(), args.get(index + 1)) {
+            ("--stdin", _) => from_stdin = true,
+            ("--fix-thin", _) => fix_thin = true,
+            ("-o", Some(path)) => {
+                idx_path = Some(PathBuf::from(path));
+                index += 1;
+            }
+            ("-o", None) => {
+                println!("fatal: option '-o' requires a value");
+                return;
+            }
+            (arg, _) if pack_path.is_none() && !arg.starts_with('-') => pack_path = Some(PathBuf::from(arg)),
+            (arg, _) => {
+                println!("fatal: unexpected argument {arg}");
+                return;
+            }
+        }
+        index += 1;
+    }
+
+    if from_stdin == pack_path.is_some() {
+        println!("usage: git index-pack [-o <index-file>] [--fix-thin] (--stdin | <pack-file>)");
+        return;
+    }
+
+    let mut pack: Vec<u8> = Vec::new();
+    let read: io::Result<()> = match &pack_path {
+        Some(pack_path) => fs::read(pack_path).map(|bytes| pack = bytes),
+        None => io::stdin().read_to_end(&mut pack).map(|_| ()),
     };
+    if let Err(err) = read {
+        println!("fatal: cannot read pack: {err}");
+        return;
+    }
 
-    let Some((git_type, size)): Option<(&str, &str)> = first.split_once(" ") else {
-        return Err(GitError::InvalidGitObject);
+    let object_store: Box<dyn ObjectStore> = open_object_store();
+    let indexed: IndexedPack = match index_pack::index_pack(object_store.as_ref(), pack, fix_thin) {
+        Ok(indexed) => indexed,
+        Err(err) => {
+            println!("fatal: index_pack: {err:?}");
+            return;
+        }
     };
 
-    let git_type: String = git_type.to_string();
-    let Ok(size): Result<usize, _> = size.parse::<usize>() else {
-        return Err(GitError::InvalidGitObject);
+    let Some(pack_path) = pack_path else {
+        match index_pack::store_pack(Path::new(GIT_OBJECT_FOLDER_PATH), &indexed) {
+            Ok(_) => println!("pack\t{}", indexed.checksum),
+            Err(err) => println!("fatal: store_pack: {err:?}"),
+        }
+        return;
     };
-    let content: String = content.to_string();
 
-    Ok(GitObjectParts {
-        git_type,
-        size,
-        content,
+    let idx_path: PathBuf = idx_path.unwrap_or_else(|| pack_path.with_extension("idx"));
+    let idx_bytes: Vec<u8> = match pack_writer::write_pack_index(&indexed.entries, &indexed.checksum) {
+        Ok(idx_bytes) => idx_bytes,
+        Err(err) => {
+            println!("fatal: write_pack_index: {err:?}");
+            return;
+        }
+    };
+    // A completed thin pack replaces the original.
+    let written = if fix_thin {
+        fs::write(&pack_path, &indexed.pack).and_then(|()| fs::write(&idx_path, idx_bytes))
+    } else {
+        fs::write(&idx_path, idx_bytes)
+    };
+    match written {
+        Ok(()) => println!("{}", indexed.checksum),
+        Err(err) => println!("fatal: cannot write index: {err}"),
+    }
+}
+
+fn git_clone(args: &[String]) {
+    let mut quiet: bool = false;
+    let mut positional: Vec<&str> = Vec::new();
+    for arg in args.iter().skip(2) {
+        match arg.as_str() {
+            "-q" | "--quiet" => quiet = true,
+            _ => positional.push(arg),
+        }
+    }
+
+    let Some(&url) = positional.first() else {
+        println!("usage: git clone [-q] <url> [<dir>]");
+        return;
+    };
+    let dir: PathBuf = match positional.get(1) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(clone::default_directory(url)),
+    };
+
+    if !quiet {
+        eprintln!("Cloning into '{}'...", dir.display());
+    }
+    if let Err(err) = clone::clone_repository(url, &dir, quiet) {
+        println!("fatal: clone_repository: {err:?}");
+        process::exit(128);
+    }
+}
+
+fn git_fetch(args: &[String]) {
+    let git_dir: &Path = Path::new(GIT_DIR_PATH);
+
+    let mut options: fetch::FetchOptions = fetch::FetchOptions::default();
+    let mut positional: Vec<String> = Vec::new();
+    for arg in args.iter().skip(2) {
+        match arg.as_str() {
+            "-q" | "--quiet" => options.quiet = true,
+            "-u" | "--update-head-ok" => options.update_head_ok = true,
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    let remote: String = match positional.first() {
+        Some(remote) => remote.clone(),
+        None => match fetch::default_remote(git_dir) {
+            Ok(remote) => remote,
+            Err(err) => {
+                println!("fatal: default_remote: {err:?}");
+                process::exit(128);
+            }
+        },
+    };
+    let refspecs: &[String] = positional.get(1..).unwrap_or(&[]);
+
+    match fetch::fetch(git_dir, &remote, refspecs, options) {
+        Ok(updates) if updates.iter().any(|update| update.is_rejected()) => {
+            eprintln!("error: some local refs could not be updated");
+            process::exit(1);
+        }
+        Ok(_) => {}
+        Err(err) => {
+            println!("fatal: fetch: {err:?}");
+            process::exit(128);
+        }
+    }
+}
+
+fn git_push(args: &[String]) {
+    let git_dir: &Path = Path::new(GIT_DIR_PATH);
+
+    let mut force: bool = false;
+    let mut positional: Vec<String> = Vec::new();
+    for arg in args.iter().skip(2) {
+        match arg.as_str() {
+            "-f" | "--force" => force = true,
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    let remote: String = match positional.first() {
+        Some(remote) => remote.clone(),
+        None => match fetch::default_remote(git_dir) {
+            Ok(remote) => remote,
+            Err(err) => {
+                println!("fatal: default_remote: {err:?}");
+                process::exit(128);
+            }
+        },
+    };
+    let refspecs: &[String] = positional.get(1..).unwrap_or(&[]);
+
+    match push::push(git_dir, &remote, refspecs, force) {
+        Ok(updates) if updates.iter().any(|update| update.is_rejected()) => process::exit(1),
+        Ok(_) => {}
+        Err(err) => {
+            println!("fatal: push: {err:?}");
+            process::exit(128);
+        }
+    }
+}
+
+fn git_add(args: &[String]) {
+    let mut options: add::AddOptions = add::AddOptions::default();
+    let mut all: bool = false;
+    let mut verbose: bool = false;
+    let mut paths: Vec<String> = Vec::new();
+
+    let mut args_iter = args.iter().skip(2);
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "-A" | "--all" => all = true,
+            "-u" | "--update" => options.update = true,
+            "-n" | "--dry-run" => options.dry_run = true,
+            "-f" | "--force" => options.force = true,
+            "-v" | "--verbose" => verbose = true,
+            "--" => paths.extend(args_iter.by_ref().cloned()),
+            _ => paths.push(arg.clone()),
+        }
+    }
+
+    if paths.is_empty() && !all && !options.update {
+        eprintln!("Nothing specified, nothing added.");
+        eprintln!("hint: Maybe you wanted to say 'git add .'?");
+        return;
+    }
+
+    let pathspec: pathspec::Pathspec = pathspec::Pathspec::new(&paths);
+    match add::add(Path::new(GIT_DIR_PATH), Path::new("."), &pathspec, options) {
+        Ok(summary) => {
+            if options.dry_run || verbose {
+                for change in &summary.changes {
+                    println!("{change}");
+                }
+            }
+            if !summary.ignored.is_empty() {
+                eprintln!("The following paths are ignored by one of your .gitignore files:");
+                for path in &summary.ignored {
+                    eprintln!("{path}");
+                }
+                eprintln!("hint: Use -f if you really want to add them.");
+                process::exit(1);
+            }
+        }
+        Err(err) => {
+            println!("fatal: add: {err:?}");
+            process::exit(128);
+        }
+    }
+}
+
+fn git_status(args: &[String]) {
+    let mut format: &str = "long";
+    let mut branch: bool = false;
+    let mut untracked: status::UntrackedFiles = status::UntrackedFiles::Normal;
+    let mut paths: Vec<String> = Vec::new();
+
+    let mut args_iter = args.iter().skip(2);
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "-s" | "--short" => format = "short",
+            "-sb" | "-bs" => {
+                format = "short";
+                branch = true;
+            }
+            "--long" => format = "long",
+            "--porcelain" | "--porcelain=v1" => format = "porcelain",
+            "--porcelain=v2" => format = "porcelain=v2",
+            "-b" | "--branch" => branch = true,
+            "-u" | "-uall" | "--untracked-files" | "--untracked-files=all" => {
+                untracked = status::UntrackedFiles::All
+            }
+            "-unormal" | "--untracked-files=normal" => untracked = status::UntrackedFiles::Normal,
+            "-uno" | "--untracked-files=no" => untracked = status::UntrackedFiles::No,
+            "--" => paths.extend(args_iter.by_ref().cloned()),
+            _ if arg.starts_with('-') => {
+                println!("error: unknown option '{arg}'");
+                process::exit(129);
+            }
+            _ => paths.push(arg.clone()),
+        }
+    }
+
+    let pathspec: pathspec::Pathspec = pathspec::Pathspec::new(&paths);
+    let status: status::Status = match status::status(Path::new(GIT_DIR_PATH), Path::new("."), &pathspec, untracked) {
+        Ok(status) => status,
+        Err(err) => {
+            println!("fatal: status: {err:?}");
+            process::exit(128);
+        }
+    };
+
+    let output: String = match format {
+        "short" | "porcelain" => status::format_short(&status, branch),
+        "porcelain=v2" => status::format_porcelain_v2(&status, branch),
+        _ => status::format_long(&status, untracked != status::UntrackedFiles::No),
+    };
+    print!("{output}");
+}
+
+fn git_diff(args: &[String]) {
+    let git_dir: &Path = Path::new(GIT_DIR_PATH);
+    let object_store: Box<dyn ObjectStore> = open_object_store();
+    let mut cached: bool = false;
+    let mut context: usize = diff::DEFAULT_CONTEXT;
+    let mut trees: Vec<String> = Vec::new();
+    let mut paths: Vec<String> = Vec::new();
+
+    let mut args_iter = args.iter().skip(2);
+    while let Some(arg) = args_iter.next() {
+        let unified: Option<&str> = arg.strip_prefix("--unified=").or_else(|| arg.strip_prefix("-U"));
+        match arg.as_str() {
+            "--cached" | "--staged" => cached = true,
+            "--" => paths.extend(args_iter.by_ref().cloned()),
+            _ if unified.is_some() => match unified.and_then(|n| n.parse().ok()) {
+                Some(n) => context = n,
+                None => {
+                    println!("error: invalid context length '{arg}'");
+                    process::exit(129);
+                }
+            },
+            _ if arg.starts_with('-') => {
+                println!("error: unknown option '{arg}'");
+                process::exit(129);
+            }
+            // Revisions come first, then paths.
+            _ if paths.is_empty() => {
+                let revisions: Vec<&str> = match arg.split_once("..") {
+                    Some((from, to)) => vec![from, to],
+                    None => vec![arg.as_str()],
+                };
+                let resolved: Result<Vec<String>, GitError> = revisions
+                    .iter()
+                    .map(|revision| {
+                        let revision: &str = if revision.is_empty() { "HEAD" } else { revision };
+                        revparse::resolve_tree_ish(git_dir, object_store.as_ref(), revision)
+                    })
+                    .collect();
+                match resolved {
+                    Ok(resolved) => trees.extend(resolved),
+                    Err(_) if Path::new(arg).exists() => paths.push(arg.clone()),
+                    Err(err) => {
+                        print_ambiguity(&err);
+                        println!("fatal: ambiguous argument '{arg}': unknown revision or path not in the working tree.");
+                        process::exit(128);
+                    }
+                }
+            }
+            _ => paths.push(arg.clone()),
+        }
+    }
+
+    let pathspec: pathspec::Pathspec = pathspec::Pathspec::new(&paths);
+    match diff_pairs(git_dir, object_store.as_ref(), &trees, cached, &pathspec)
+        .and_then(|pairs| diff::format_patch(object_store.as_ref(), &pairs, context))
+    {
+        Ok(patch) => {
+            if let Err(err) = io::stdout().lock().write_all(&patch) {
+                println!("Stdout::write_all: {err}");
+            }
+        }
+        Err(err) => {
+            println!("fatal: diff: {err:?}");
+            process::exit(128);
+        }
+    }
+}
+
+fn git_log(args: &[String]) {
+    let git_dir: &Path = Path::new(GIT_DIR_PATH);
+    let object_store: Box<dyn ObjectStore> = open_object_store();
+    let mut walk: revwalk::RevWalk = revwalk::RevWalk::new(object_store.as_ref());
+    let mut options: log::LogOptions = log::LogOptions::default();
+    let mut revisions: Vec<String> = Vec::new();
+
+    let mut args_iter = args.iter().skip(2);
+    while let Some(arg) = args_iter.next() {
+        let count: Option<&str> = arg
+            .strip_prefix("--max-count=")
+            .or_else(|| arg.strip_prefix("-n").filter(|count| !count.is_empty()))
+            .or_else(|| arg.strip_prefix('-').filter(|count| count.starts_with(|c: char| c.is_ascii_digit())));
+        match arg.as_str() {
+            "-n" => match args_iter.next().and_then(|count| count.parse().ok()) {
+                Some(count) => options.max_count = Some(count),
+                None => {
+                    println!("error: switch `n' requires a numeric value");
+                    process::exit(129);
+                }
+            },
+            _ if count.is_some() => match count.and_then(|count| count.parse().ok()) {
+                Some(count) => options.max_count = Some(count),
+                None => {
+                    println!("fatal: '{arg}': not an integer");
+                    process::exit(128);
+                }
+            },
+            "--oneline" => options.oneline = true,
+            "--abbrev-commit" => options.abbrev_commit = true,
+            "--no-abbrev-commit" => options.abbrev_commit = false,
+            _ if arg.starts_with("--abbrev=") => match arg["--abbrev=".len()..].parse() {
+                Ok(length) => options.abbrev = Some(length),
+                Err(_) => {
+                    println!("fatal: '{arg}': not an integer");
+                    process::exit(128);
+                }
+            },
+            "--reverse" => options.reverse = true,
+            "--first-parent" => walk.set_first_parent(true),
+            "--topo-order" => walk.set_sort(revwalk::RevSort::Topo),
+            "--date-order" => walk.set_sort(revwalk::RevSort::DateOrder),
+            "-i" | "--regexp-ignore-case" => options.ignore_case = true,
+            "--author" => options.authors.extend(args_iter.next().cloned()),
+            "--grep" => options.greps.extend(args_iter.next().cloned()),
+            _ if arg.starts_with("--author=") => options.authors.push(arg["--author=".len()..].to_string()),
+            _ if arg.starts_with("--grep=") => options.greps.push(arg["--grep=".len()..].to_string()),
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                println!("fatal: unrecognized argument: {arg}");
+                process::exit(128);
+            }
+            _ => revisions.push(arg.clone()),
+        }
+    }
+
+    if revisions.is_empty() {
+        if let Ok(None) = refs::read_ref(git_dir, "HEAD") {
+            let branch: String = refs::read_symbolic_ref(git_dir, "HEAD").ok().flatten().unwrap_or_default();
+            let branch: &str = branch.strip_prefix("refs/heads/").unwrap_or(&branch);
+            println!("fatal: your current branch '{branch}' does not have any commits yet");
+            process::exit(128);
+        }
+        revisions.push("HEAD".to_string());
+    }
+
+    if let Err(err) = push_revisions(git_dir, object_store.as_ref(), &mut walk, &revisions) {
+        print_ambiguity(&err);
+        println!("fatal: log: {err:?}");
+        process::exit(128);
+    }
+
+    match log::write_log(object_store.as_ref(), walk, &options, &mut io::stdout().lock()) {
+        Ok(()) => {}
+        // The reader went away, like `git log | head`.
+        Err(GitError::WriteOutput(_)) => process::exit(141),
+        Err(err) => {
+            println!("fatal: log: {err:?}");
+            process::exit(128);
+        }
+    }
+}
+
+fn git_show_ref(args: &[String]) {
+    let git_dir: &Path = Path::new(GIT_DIR_PATH);
+    let object_store: Box<dyn ObjectStore> = open_object_store();
+    let mut prefixes: Vec<&str> = Vec::new();
+    let mut head: bool = false;
+    let mut dereference: bool = false;
+    let mut hash_only: bool = false;
+    let mut patterns: Vec<&str> = Vec::new();
+
+    for arg in args.iter().skip(2) {
+        match arg.as_str() {
+            "--heads" => prefixes.push("refs/heads/"),
+            "--tags" => prefixes.push("refs/tags/"),
+            "--head" => head = true,
+            "-d" | "--dereference" => dereference = true,
+            "-s" | "--hash" => hash_only = true,
+            _ if arg.starts_with('-') => {
+                println!("error: unknown option `{arg}'");
+                process::exit(129);
+            }
+            _ => patterns.push(arg),
+        }
+    }
+    if prefixes.is_empty() {
+        prefixes.push("refs/");
+    }
+
+    let listed = || -> Result<Vec<(String, String)>, GitError> {
+        let mut refs: Vec<(String, String)> = Vec::new();
+        if head {
+            refs.extend(refs::read_ref(git_dir, "HEAD")?.map(|sha1_hash| ("HEAD".to_string(), sha1_hash)));
+        }
+        for prefix in &prefixes {
+            refs.extend(refs::list_refs(git_dir, prefix)?);
+        }
+        // A pattern matches whole trailing components of the name.
+        refs.retain(|(name, _)| {
+            patterns.is_empty()
+                || patterns.iter().any(|pattern| name.eq(pattern) || name.ends_with(&format!("/{pattern}")))
+        });
+        Ok(refs)
+    };
+    let refs: Vec<(String, String)> = match listed() {
+        Ok(refs) => refs,
+        Err(err) => {
+            println!("fatal: show-ref: {err:?}");
+            process::exit(128);
+        }
+    };
+
+    for (name, sha1_hash) in &refs {
+        let print = |sha1_hash: &str, name: &str| match hash_only {
+            true => println!("{sha1_hash}"),
+            false => println!("{sha1_hash} {name}"),
+        };
+        print(sha1_hash, name);
+
+        if dereference {
+            match peel_ref(git_dir, object_store.as_ref(), name, sha1_hash) {
+                Ok(Some(peeled)) => print(&peeled, &format!("{name}^{{}}")),
+                Ok(None) => {}
+                Err(err) => {
+                    println!("fatal: show-ref: {err:?}");
+                    process::exit(128);
+                }
+            }
+        }
+    }
+
+    if refs.is_empty() {
+        process::exit(1);
+    }
+}
+
+// What a ref to an annotated tag peels to, from `packed-refs` when it
+// is recorded there, `None` for refs to other objects.
+fn peel_ref(git_dir: &Path, object_store: &dyn ObjectStore, name: &str, sha1_hash: &str) -> Result<Option<String>, GitError> {
+    if let Some(peeled) = refs::read_packed_peeled(git_dir, name)? {
+        return Ok(Some(peeled));
+    }
+
+    let mut peeled: Option<String> = None;
+    let mut git_object: GitObject = read_git_object(object_store, sha1_hash)?;
+    while let GitObject::Tag { content } = git_object {
+        git_object = read_git_object(object_store, &content.object)?;
+        peeled = Some(content.object);
+    }
+    Ok(peeled)
+}
+
+fn git_check_ref_format(args: &[String]) {
+    let mut allow_onelevel: bool = false;
+    let mut normalize: bool = false;
+    let mut names: Vec<&str> = Vec::new();
+
+    for arg in args.iter().skip(2) {
+        match arg.as_str() {
+            "--allow-onelevel" => allow_onelevel = true,
+            "--no-allow-onelevel" => allow_onelevel = false,
+            "--normalize" | "--print" => normalize = true,
+            _ if arg.starts_with('-') => {
+                println!("error: unknown option `{arg}'");
+                process::exit(129);
+            }
+            _ => names.push(arg),
+        }
+    }
+    let [name] = names[..] else {
+        println!("usage: git check-ref-format [--normalize] [<options>] <refname>");
+        process::exit(129);
+    };
+
+    // Normalizing drops leading slashes and collapses repeated ones.
+    let name: String = match normalize {
+        true => {
+            let mut normalized: String = String::new();
+            for c in name.trim_start_matches('/').chars() {
+                if c != '/' || !normalized.ends_with('/') {
+                    normalized.push(c);
+                }
+            }
+            normalized
+        }
+        false => name.to_string(),
+    };
+    if !refs::check_ref_format(&name, allow_onelevel) {
+        process::exit(1);
+    }
+    if normalize {
+        println!("{name}");
+    }
+}
+
+fn git_rev_parse(args: &[String]) {
+    let git_dir: &Path = Path::new(GIT_DIR_PATH);
+    let object_store: Box<dyn ObjectStore> = open_object_store();
+    let mut verify: bool = false;
+    let mut quiet: bool = false;
+    let mut full_name: bool = false;
+    let mut abbrev_ref: bool = false;
+    let mut short: Option<usize> = None;
+
+    // Options apply to all revisions, wherever they are.
+    let mut revisions: usize = 0;
+    for arg in args.iter().skip(2).take_while(|arg| !arg.eq(&"--")) {
+        match arg.as_str() {
+            "--verify" => verify = true,
+            "-q" | "--quiet" => quiet = true,
+            "--symbolic-full-name" => full_name = true,
+            "--abbrev-ref" => abbrev_ref = true,
+            "--short" => (verify, short) = (true, Some(fetch::ABBREV)),
+            _ if arg.starts_with("--short=") => match arg["--short=".len()..].parse() {
+                Ok(length) => (verify, short) = (true, Some(length)),
+                Err(_) => {
+                    println!("fatal: bad --short value: {arg}");
+                    process::exit(128);
+                }
+            },
+            _ if arg.starts_with('-') => {}
+            _ if arg.contains("..") => revisions += 2,
+            _ => revisions += 1,
+        }
+    }
+
+    if verify && revisions != 1 {
+        if !quiet {
+            println!("fatal: Needed a single revision");
+        }
+        process::exit(if quiet { 1 } else { 128 });
+    }
+
+    let mut args_iter = args.iter().skip(2);
+    while let Some(arg) = args_iter.next() {
+        let revision: &str = match arg.as_str() {
+            "--verify" | "-q" | "--quiet" | "--symbolic-full-name" | "--abbrev-ref" | "--short" => continue,
+            _ if arg.starts_with("--short=") => continue,
+            "--git-dir" => {
+                println!("{GIT_DIR_PATH}");
+                continue;
+            }
+            // What follows are paths, passed through like unknown options.
+            "--" => {
+                println!("--");
+                args_iter.by_ref().for_each(|path| println!("{path}"));
+                continue;
+            }
+            _ if arg.starts_with('-') => {
+                println!("{arg}");
+                continue;
+            }
+            revision => revision,
+        };
+
+        let resolve = |revision: &str| {
+            let revision: &str = if revision.is_empty() { "HEAD" } else { revision };
+            let resolved: Result<String, GitError> = revparse::resolve_revision(git_dir, object_store.as_ref(), revision);
+            let resolved: Result<String, GitError> = match short {
+                Some(length) => resolved.and_then(|sha1_hash| odb::abbreviate(object_store.as_ref(), &sha1_hash, length)),
+                None => resolved,
+            };
+            match resolved {
+                Ok(sha1_hash) => sha1_hash,
+                Err(_) if verify && quiet => process::exit(1),
+                Err(err) if verify => {
+                    print_ambiguity(&err);
+                    println!("fatal: Needed a single revision");
+                    process::exit(128);
+                }
+                Err(err) => {
+                    print_ambiguity(&err);
+                    println!("fatal: ambiguous argument '{revision}': unknown revision or path not in the working tree.");
+                    process::exit(128);
+                }
+            }
+        };
+
+        if let Some((a, b)) = revision.split_once("...") {
+            let (a, b): (String, String) = (resolve(a), resolve(b));
+            println!("{b}\n{a}");
+            match revwalk::merge_bases(object_store.as_ref(), &a, &b) {
+                Ok(bases) => bases.iter().for_each(|base| println!("^{base}")),
+                Err(err) => {
+                    println!("fatal: rev-parse: {err:?}");
+                    process::exit(128);
+                }
+            }
+        } else if let Some((a, b)) = revision.split_once("..") {
+            println!("{}\n^{}", resolve(b), resolve(a));
+        } else if let Some(hidden) = revision.strip_prefix('^') {
+            println!("^{}", resolve(hidden));
+        } else if full_name || abbrev_ref {
+            // Revisions that are not refs print nothing.
+            resolve(revision);
+            let name: Option<String> = revparse::resolve_ref_name(git_dir, revision).ok().flatten();
+            match name {
+                Some(name) if abbrev_ref => println!("{}", fetch::shorten_ref(&name)),
+                Some(name) => println!("{name}"),
+                None => {}
+            }
+        } else {
+            println!("{}", resolve(revision));
+        }
+    }
+}
+
+// Tips and hidden commits of a walk from `A`, `^A`, `A..B` (B but not A)
+// and `A...B` (either but not both) revisions, an empty side meaning HEAD.
+fn push_revisions(
+    git_dir: &Path,
+    object_store: &dyn ObjectStore,
+    walk: &mut revwalk::RevWalk,
+    revisions: &[String],
+) -> Result<(), GitError> {
+    let resolve = |revision: &str| match revision {
+        "" => revparse::resolve_revision(git_dir, object_store, "HEAD"),
+        _ => revparse::resolve_revision(git_dir, object_store, revision),
+    };
+
+    for revision in revisions {
+        if let Some((a, b)) = revision.split_once("...") {
+            let (a, b): (String, String) = (resolve(a)?, resolve(b)?);
+            walk.push(&a)?;
+            walk.push(&b)?;
+            for base in revwalk::merge_bases(object_store, &a, &b)? {
+                walk.hide(&base)?;
+            }
+        } else if let Some((a, b)) = revision.split_once("..") {
+            walk.hide(&resolve(a)?)?;
+            walk.push(&resolve(b)?)?;
+        } else if let Some(hidden) = revision.strip_prefix('^') {
+            walk.hide(&resolve(hidden)?)?;
+        } else {
+            walk.push(&resolve(revision)?)?;
+        }
+    }
+    Ok(())
+}
+
+fn git_branch(args: &[String]) {
+    let git_dir: &Path = Path::new(GIT_DIR_PATH);
+    let object_store: Box<dyn ObjectStore> = open_object_store();
+    let mut mode: &str = "list";
+    let mut kinds: branch::BranchKinds = branch::BranchKinds::Local;
+    let mut verbose: usize = 0;
+    let mut force: bool = false;
+    let mut upstream: Option<&str> = None;
+    let mut names: Vec<&str> = Vec::new();
+    let mut listing: bool = false;
+
+    let mut args_iter = args.iter().skip(2);
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "-l" | "--list" => listing = true,
+            "-a" | "--all" => kinds = branch::BranchKinds::All,
+            "-r" | "--remotes" => kinds = branch::BranchKinds::Remote,
+            "-v" | "--verbose" => verbose += 1,
+            "-vv" => verbose += 2,
+            "-f" | "--force" => force = true,
+            "-d" | "--delete" => mode = "delete",
+            "-D" => {
+                mode = "delete";
+                force = true;
+            }
+            "-m" | "--move" => mode = "move",
+            "-M" => {
+                mode = "move";
+                force = true;
+            }
+            "-u" | "--set-upstream-to" => {
+                let Some(value) = args_iter.next() else {
+                    println!("error: option `set-upstream-to' requires a value");
+                    process::exit(129);
+                };
+                mode = "set-upstream";
+                upstream = Some(value);
+            }
+            "--unset-upstream" => mode = "unset-upstream",
+            "--show-current" => mode = "show-current",
+            _ if arg.starts_with("--set-upstream-to=") => {
+                mode = "set-upstream";
+                upstream = arg.strip_prefix("--set-upstream-to=");
+            }
+            _ if arg.starts_with('-') => {
+                println!("error: unknown option `{arg}'");
+                process::exit(129);
+            }
+            _ => names.push(arg),
+        }
+    }
+    if mode.eq("list") && !names.is_empty() && !listing {
+        mode = "create";
+    }
+
+    // git's own message for branch errors, the debug form for the others.
+    let fatal = |err: GitError| -> ! {
+        match err {
+            GitError::Branch(message) => println!("fatal: {message}"),
+            err => println!("fatal: branch: {err:?}"),
+        }
+        process::exit(128);
+    };
+    let current = || match branch::current_branch(git_dir) {
+        Ok(current) => current,
+        Err(err) => fatal(err),
+    };
+
+    match mode {
+        "create" => {
+            let (name, start_point): (&str, &str) = match names[..] {
+                [name] => (name, "HEAD"),
+                [name, start_point] => (name, start_point),
+                _ => {
+                    println!("fatal: too many arguments to create a branch");
+                    process::exit(128);
+                }
+            };
+            match branch::create_branch(git_dir, object_store.as_ref(), name, start_point, force) {
+                Ok(Some(upstream)) => println!("branch '{name}' set up to track '{upstream}'."),
+                Ok(None) => {}
+                Err(err) => fatal(err),
+            }
+        }
+        "delete" => {
+            if names.is_empty() {
+                println!("fatal: branch name required");
+                process::exit(128);
+            }
+            let mut failed: bool = false;
+            for name in names {
+                let deleted: Result<String, GitError> = branch::delete_branch(git_dir, object_store.as_ref(), name, force)
+                    .and_then(|sha1_hash| odb::abbreviate(object_store.as_ref(), &sha1_hash, fetch::ABBREV));
+                match deleted {
+                    Ok(abbreviated) => println!("Deleted branch {name} (was {abbreviated})."),
+                    Err(GitError::Branch(message)) => {
+                        println!("error: {message}");
+                        failed = true;
+                    }
+                    Err(err) => fatal(err),
+                }
+            }
+            if failed {
+                process::exit(1);
+            }
+        }
+        "move" => {
+            let (from, to): (String, &str) = match names[..] {
+                [to] => match current() {
+                    Some(from) => (from, to),
+                    None => {
+                        println!("fatal: cannot rename the current branch while not on any.");
+                        process::exit(128);
+                    }
+                },
+                [from, to] => (from.to_string(), to),
+                _ => {
+                    println!("fatal: too many arguments for a rename operation");
+                    process::exit(128);
+                }
+            };
+            if let Err(err) = branch::rename_branch(git_dir, &from, to, force) {
+                fatal(err);
+            }
+        }
+        "set-upstream" | "unset-upstream" => {
+            let name: String = match names[..] {
+                [] => match current() {
+                    Some(name) => name,
+                    None if mode.eq("set-upstream") => {
+                        println!(
+                            "fatal: could not set upstream of HEAD to {} when it does not point to any branch.",
+                            upstream.unwrap_or_default()
+                        );
+                        process::exit(128);
+                    }
+                    None => {
+                        println!("fatal: could not unset upstream of HEAD when it does not point to any branch.");
+                        process::exit(128);
+                    }
+                },
+                [name] => name.to_string(),
+                _ => {
+                    println!("fatal: too many arguments to set new upstream");
+                    process::exit(128);
+                }
+            };
+            match upstream {
+                Some(upstream) => match branch::set_upstream(git_dir, &name, upstream) {
+                    Ok(upstream) => println!("branch '{name}' set up to track '{upstream}'."),
+                    Err(err) => fatal(err),
+                },
+                None => match branch::unset_upstream(git_dir, &name) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        println!("fatal: Branch '{name}' has no upstream information");
+                        process::exit(128);
+                    }
+                    Err(err) => fatal(err),
+                },
+            }
+        }
+        "show-current" => {
+            if let Some(name) = current() {
+                println!("{name}");
+            }
+        }
+        _ => match branch::format_list(git_dir, object_store.as_ref(), kinds, verbose) {
+            Ok(output) => print!("{output}"),
+            Err(err) => fatal(err),
+        },
+    }
+}
+
+// What `git diff` compares: the index and the worktree, a tree (HEAD by
+// default with `--cached`) and the index or the worktree, or two trees.
+fn diff_pairs(
+    git_dir: &Path,
+    object_store: &dyn ObjectStore,
+    trees: &[String],
+    cached: bool,
+    pathspec: &pathspec::Pathspec,
+) -> Result<Vec<diff::FilePair>, GitError> {
+    if let [old, new] = trees {
+        let old: diff::Snapshot = diff::tree_snapshot(object_store, old)?;
+        let new: diff::Snapshot = diff::tree_snapshot(object_store, new)?;
+        return Ok(diff::compare_snapshots(&old, &new, pathspec));
+    }
+    if trees.len() > 2 {
+        return Err(GitError::InvalidRef("too many revisions".to_string()));
+    }
+
+    let index: index::Index = index::Index::load(&git_dir.join("index"))?;
+    let (index_files, unmerged): (diff::Snapshot, Vec<String>) = diff::index_snapshot(&index);
+    let worktree = || diff::worktree_snapshot(git_dir, Path::new("."), &index);
+    let mut pairs: Vec<diff::FilePair> = match (trees.first(), cached) {
+        (None, false) => diff::compare_snapshots(&index_files, &worktree()?, pathspec),
+        (tree, true) => {
+            let tree: Option<String> = match tree {
+                Some(tree) => Some(tree.clone()),
+                None if refs::read_ref(git_dir, "HEAD")?.is_some() => Some(revparse::resolve_tree_ish(git_dir, object_store, "HEAD")?),
+                None => None,
+            };
+            let tree_files: diff::Snapshot = match tree {
+                Some(tree) => diff::tree_snapshot(object_store, &tree)?,
+                None => diff::Snapshot::new(),
+            };
+            diff::compare_snapshots(&tree_files, &index_files, pathspec)
+        }
+        (Some(tree), false) => diff::compare_snapshots(&diff::tree_snapshot(object_store, tree)?, &worktree()?, pathspec),
+    };
+
+    for path in unmerged.into_iter().filter(|path| pathspec.matches(path)) {
+        pairs.retain(|pair| !pair.path.eq(&path));
+        pairs.push(diff::FilePair { path, old: None, new: None });
+    }
+    pairs.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(pairs)
+}
+
+fn create_commit_object(
+    object_store: &dyn ObjectStore,
+    tree_sha: &str,
+    parents: Vec<String>,
+    message: Vec<u8>,
+) -> Result<String, GitError> {
+    let tree: GitObject = read_git_object(object_store, tree_sha)?;
+    if !matches!(tree, GitObject::Tree { .. }) {
+        return Err(GitError::InvalidCommit(format!("{tree_sha} is not a valid 'tree' object")));
+    }
+
+    for parent in &parents {
+        let git_object: GitObject = read_git_object(object_store, parent)?;
+        if !matches!(git_object, GitObject::Commit { .. }) {
+            return Err(GitError::InvalidCommit(format!("{parent} is not a valid 'commit' object")));
+        }
+    }
+
+    let commit: Commit = Commit {
+        tree: tree_sha.to_string(),
+        parents,
+        author: Signature::from_env("AUTHOR")?,
+        committer: Signature::from_env("COMMITTER")?,
+        encoding: None,
+        extra_headers: Vec::new(),
+        message: Some(message),
+    };
+
+    write_git_object(object_store, &GitObject::Commit {
+        content: Box::new(commit),
     })
 }
 
-fn parse_str_to_git_object_parts_bytes(s: &[u8]) -> Result<GitObjectParts<Vec<u8>>, GitError> {
-    let mut git_type = String::new();
+fn create_tree_object(object_store: &dyn ObjectStore, dir: &Path) -> Result<String, GitError> {
+    let tree_entries: Vec<TreeEntry> = collect_tree_entries(object_store, dir)?;
+    write_git_object(object_store, &GitObject::Tree {
+        content: tree_entries,
+    })
+}
 
-    let mut index: usize = 0;
-    loop {
-        if s[index].eq(&b' ') {
-            break;
+fn collect_tree_entries(object_store: &dyn ObjectStore, dir: &Path) -> Result<Vec<TreeEntry>, GitError> {
+    if !dir.is_dir() {
+        return Err(GitError::CreateTree("Tree dir is not a directory.".to_string()));
+    }
+
+    let entries: ReadDir = match fs::read_dir(dir) {
+        Ok(rd) => rd,
+        Err(err) => {
+            return Err(GitError::CreateTree(format!("fs::read_dir: {err}.")));
         }
+    };
 
-        git_type.push(s[index] as char);
-        index += 1;
+    let mut tree_entries: Vec<TreeEntry> = Vec::new();
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                return Err(GitError::CreateTree(format!("entry: {err}.")));
+            }
+        };
+
+        let Ok(name): Result<String, _> = entry.file_name().into_string() else {
+            return Err(GitError::CreateTree("file_name.into_string.".to_string()));
+        };
+        if name.eq(".git") {
+            continue;
+        }
+
+        let path = entry.path();
+        let metadata: fs::Metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                return Err(GitError::CreateTree(format!("fs::symlink_metadata: {err}.")));
+            }
+        };
+
+        let (mode, sha1_hash): (EntryMode, String) = if metadata.is_dir() {
+            let sub_entries: Vec<TreeEntry> = collect_tree_entries(object_store, &path)?;
+            // Git does not track empty directories.
+            if sub_entries.is_empty() {
+                continue;
+            }
+            let tree_sha: String = write_git_object(object_store, &GitObject::Tree {
+                content: sub_entries,
+            })?;
+            (EntryMode::Directory, tree_sha)
+        }
+        else {
+            create_worktree_blob(object_store, &path, &metadata)?
+        };
+
+        tree_entries.push(TreeEntry {
+            mode,
+            name,
+            sha1_hash,
+        });
     }
-    index += 1;
 
-    let mut size_bytes: Vec<u8> = Vec::new();
+    sort_tree_entries(&mut tree_entries);
+    Ok(tree_entries)
+}
+
+// Git orders tree entries by name, comparing directories as if their name
+// ended with a '/'.
+fn sort_tree_entries(tree_entries: &mut [TreeEntry]) {
+    tree_entries.sort_by_cached_key(|te| {
+        let mut key: Vec<u8> = te.name.as_bytes().to_vec();
+        if matches!(te.mode, EntryMode::Directory) {
+            key.push(b'/');
+        }
+        key
+    });
+}
+
+fn create_blob_object(object_store: &dyn ObjectStore, file_path: &Path) -> Result<String, GitError> {
+    let (size, mut reader): (u64, Box<dyn Read>) = open_blob_source(file_path)?;
+    object_store.write_stream("blob", size, &mut reader)
+}
+
+// Blob of a worktree path: the content of a file, or the target of a
+// symbolic link. The mode tells which, and whether a file is executable.
+fn create_worktree_blob(
+    object_store: &dyn ObjectStore,
+    path: &Path,
+    metadata: &fs::Metadata,
+) -> Result<(EntryMode, String), GitError> {
+    let mode: EntryMode = EntryMode::from_metadata(metadata);
+    if mode != EntryMode::SymbolicLink {
+        return Ok((mode, create_blob_object(object_store, path)?));
+    }
+
+    let target: Vec<u8> = read_link_target(path)?;
+    let blob_sha: String = write_git_object(object_store, &GitObject::create_blob_with_content(target))?;
+    Ok((mode, blob_sha))
+}
+
+// Same as `create_worktree_blob` without writing the blob.
+fn hash_worktree_blob(path: &Path, metadata: &fs::Metadata) -> Result<(EntryMode, String), GitError> {
+    let mode: EntryMode = EntryMode::from_metadata(metadata);
+    if mode != EntryMode::SymbolicLink {
+        return Ok((mode, hash_blob_object(path)?));
+    }
+
+    Ok((mode, odb::hash_object("blob", &read_link_target(path)?)))
+}
+
+fn read_link_target(path: &Path) -> Result<Vec<u8>, GitError> {
+    match fs::read_link(path) {
+        Ok(target) => Ok(target.into_os_string().into_encoded_bytes()),
+        Err(err) => Err(GitError::CreateBlob(format!("fs::read_link: {err}."))),
+    }
+}
+
+fn hash_blob_object(file_path: &Path) -> Result<String, GitError> {
+    let (size, mut reader): (u64, Box<dyn Read>) = open_blob_source(file_path)?;
+    hash_stream("blob", size, &mut reader, |_| Ok(()))
+}
+
+// Regular files are streamed, their size is known upfront. Anything else
+// (pipes, /dev/stdin) has to be read in full to learn its size.
+fn open_blob_source(file_path: &Path) -> Result<(u64, Box<dyn Read>), GitError> {
+    let mut file: File = match File::open(file_path) {
+        Ok(file) => file,
+        Err(err) => {
+            return Err(GitError::CreateBlob(format!("File::open: {err}")));
+        }
+    };
+
+    let metadata: fs::Metadata = match file.metadata() {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            return Err(GitError::CreateBlob(format!("File::metadata: {err}")));
+        }
+    };
 
+    if metadata.is_file() {
+        return Ok((metadata.len(), Box::new(io::BufReader::new(file))));
+    }
+
+    let mut content: Vec<u8> = Vec::new();
+    if let Err(err) = file.read_to_end(&mut content) {
+        return Err(GitError::CreateBlob(format!("File::read_to_end: {err}")));
+    }
+    Ok((content.len() as u64, Box::new(io::Cursor::new(content))))
+}
+
+fn write_git_object(object_store: &dyn ObjectStore, git_object: &GitObject) -> Result<String, GitError> {
+    object_store.write(&git_object.get_type(), &git_object.content_bytes()?)
+}
+
+const GIT_DIR_PATH: &str = ".git";
+const GIT_OBJECT_FOLDER_PATH: &str = ".git/objects";
+
+// Loose objects first (new objects are written there), then packs.
+fn open_object_store() -> Box<dyn ObjectStore> {
+    open_object_store_at(Path::new(GIT_OBJECT_FOLDER_PATH))
+}
+
+fn open_object_store_at(objects_dir: &Path) -> Box<dyn ObjectStore> {
+    let stores: Vec<Box<dyn ObjectStore>> = vec![
+        Box::new(LooseObjectStore::new(objects_dir)),
+        Box::new(PackObjectStore::open(objects_dir)),
+    ];
+
+    Box::new(CombinedObjectStore::new(stores))
+}
+
+// git lists the candidates of an ambiguous object name before failing.
+fn print_ambiguity(err: &GitError) {
+    if let GitError::AmbiguousObject(hints) = err {
+        println!("{hints}");
+    }
+}
+
+fn read_git_object(object_store: &dyn ObjectStore, sha1_hash: &str) -> Result<GitObject, GitError> {
+    GitObject::from_parts_bytes(object_store.read(sha1_hash)?)
+}
+
+// Peels tags, and commits down to their tree, until an object of the
+// requested type is reached, like `git cat-file <type> <object>`.
+fn peel_to_type(
+    object_store: &dyn ObjectStore,
+    mut git_object: GitObject,
+    git_type: &str,
+) -> Result<GitObject, GitError> {
     loop {
-        if s[index].eq(&b'\0') {
-            break;
+        if git_object.get_type().eq(git_type) {
+            return Ok(git_object);
         }
 
-        size_bytes.push(s[index]);
-        index += 1;
+        git_object = match &git_object {
+            GitObject::Tag { content } => read_git_object(object_store, &content.object)?,
+            GitObject::Commit { content } if git_type.eq("tree") => {
+                read_git_object(object_store, &content.tree)?
+            }
+            _ => return Err(GitError::UnknownGitType),
+        };
     }
-    index += 1;
+}
+
+// Follows annotated tags (possibly tags of tags) to the object they name.
+fn peel_git_object(object_store: &dyn ObjectStore, mut git_object: GitObject) -> Result<GitObject, GitError> {
+    while let GitObject::Tag { content } = &git_object {
+        git_object = read_git_object(object_store, &content.object)?;
+    }
+
+    Ok(git_object)
+}
 
-    let size: String = String::from_utf8(size_bytes).unwrap();
+// Whether `ancestor` can be reached from `descendant` by following commit
+// parents, which makes moving a ref between them a fast-forward.
+fn is_ancestor(object_store: &dyn ObjectStore, ancestor: &str, descendant: &str) -> Result<bool, GitError> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut pending: Vec<String> = vec![descendant.to_string()];
+
+    while let Some(sha1_hash) = pending.pop() {
+        if sha1_hash.eq(ancestor) {
+            return Ok(true);
+        }
+        if !seen.insert(sha1_hash.clone()) {
+            continue;
+        }
+
+        match read_git_object(object_store, &sha1_hash)? {
+            GitObject::Commit { content } => pending.extend(content.parents),
+            _ => return Ok(false),
+        }
+    }
+
+    Ok(false)
+}
+
+fn zlib_decompression(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    zlib_decompression_with_length(bytes).map(|(content, _)| content)
+}
+
+// Also returns how many bytes the zlib stream took, for streams followed
+// by other data like pack entries.
+fn zlib_decompression_with_length(bytes: &[u8]) -> std::io::Result<(Vec<u8>, usize)> {
+    let mut zlib_decoder = ZlibDecoder::new(bytes);
+    let mut content: Vec<u8> = Vec::new();
+    zlib_decoder.read_to_end(&mut content)?;
+    Ok((content, zlib_decoder.total_in() as usize))
+}
+
+fn zlib_compression(content: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut zlib_encode = ZlibEncoder::new(Vec::new(), Compression::default());
+    zlib_encode.write_all(content)?;
+    zlib_encode.finish()
+}
+
+fn parse_str_to_git_object_parts_bytes(s: &[u8]) -> Result<GitObjectParts<Vec<u8>>, GitError> {
+    let Some(nul): Option<usize> = s.iter().position(|&byte| byte == b'\0') else {
+        return Err(GitError::InvalidGitObject);
+    };
+    let Ok(header): Result<&str, _> = std::str::from_utf8(&s[..nul]) else {
+        return Err(GitError::InvalidGitObject);
+    };
+    let Some((git_type, size)): Option<(&str, &str)> = header.split_once(' ') else {
+        return Err(GitError::InvalidGitObject);
+    };
 
     let Ok(size): Result<usize, _> = size.parse::<usize>() else {
         return Err(GitError::InvalidGitObject);
     };
 
-    let content: Vec<u8> = s[index..s.len()].to_vec();
+    let content: Vec<u8> = s[nul + 1..].to_vec();
 
     Ok(GitObjectParts {
-        git_type,
+        git_type: git_type.to_string(),
         size,
         content,
     })
 }
 
 fn parse_str_tree_entry_vec(content: &[u8]) -> Result<Vec<TreeEntry>, GitError> {
-    let pos: Vec<usize> = tree_entry_end_pos(content);
+    let pos: Vec<usize> = tree_entry_end_pos(content)?;
     let tree_entry_bytes: Vec<&[u8]> = extract_from_vec_at(content, &pos[..]);
 
     let mut tree_entry: Vec<TreeEntry> = Vec::new();
@@ -598,12 +2198,25 @@ fn parse_str_tree_entry_vec(content: &[u8]) -> Result<Vec<TreeEntry>, GitError>
     Ok(tree_entry)
 }
 
-fn tree_entry_end_pos(v: &[u8]) -> Vec<usize> {
-    v.iter()
-        .enumerate()
-        .filter(|(_, &byte)| byte == b'\0')
-        .map(|(i, _)| i + 21)
-        .collect::<Vec<usize>>()
+// Entries are `<mode> <name>\0<20 byte sha>`, the raw sha can itself
+// contain NUL bytes so each entry is skipped over as a whole. Bytes left
+// over after the last entry make the tree invalid.
+fn tree_entry_end_pos(v: &[u8]) -> Result<Vec<usize>, GitError> {
+    let mut pos: Vec<usize> = Vec::new();
+
+    let mut index: usize = 0;
+    while index < v.len() {
+        let Some(nul): Option<usize> = v[index..].iter().position(|&byte| byte == b'\0') else {
+            return Err(GitError::InvalidGitObject);
+        };
+        index += nul + 21;
+        if index > v.len() {
+            return Err(GitError::InvalidGitObject);
+        }
+        pos.push(index);
+    }
+
+    Ok(pos)
 }
 
 fn extract_from_vec_at<'a>(vec: &'a [u8], pos: &[usize]) -> Vec<&'a [u8]> {
@@ -619,30 +2232,178 @@ fn extract_from_vec_at<'a>(vec: &'a [u8], pos: &[usize]) -> Vec<&'a [u8]> {
     extract
 }
 
-fn compute_sha1_hash(content: &str) -> String {
+fn compute_sha1_hash(content: &[u8]) -> String {
     let mut hasher = Sha1::new();
-    hasher.input_str(content);
+    hasher.input(content);
     hasher.result_str()
 }
 
-fn write_bytes_to_file(folder_path: &str, file_name: &str, content: &[u8]) -> std::io::Result<()> {
-    fs::create_dir_all(folder_path)?;
-    let mut file = File::create_new(format!("{folder_path}/{file_name}"))?;
-    file.write_all(content)?;
-    Ok(())
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use odb::MemoryObjectStore;
+
     #[test]
     fn test_git_type_fmt() {
         let expected: String = String::from("blob");
         let blob: GitObject = GitObject::Blob {
-            content: String::from("Content."),
+            content: b"Content.".to_vec(),
         };
 
         assert_eq!(expected, blob.get_type());
     }
+
+    #[test]
+    fn test_binary_blob_hash() {
+        let blob: GitObject =
+            GitObject::create_blob_with_content(b"\x00\xff\x01\x80binary".to_vec());
+
+        assert_eq!(
+            "037682319d062a38a2ec167e6e78e7193f9f497f",
+            compute_sha1_hash(&blob.as_bytes().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_tree_entries_sorted_like_git() {
+        let sha1_hash: String = "3b18e512dba79e4c8300dd08aeb37f8e728b8dad".to_string();
+        let mut tree_entries: Vec<TreeEntry> = ["a", "a.txt", "a-b"]
+            .iter()
+            .map(|name| TreeEntry {
+                mode: if name.eq(&"a") {
+                    EntryMode::Directory
+                } else {
+                    EntryMode::RegularFile
+                },
+                name: name.to_string(),
+                sha1_hash: sha1_hash.clone(),
+            })
+            .collect();
+
+        sort_tree_entries(&mut tree_entries);
+
+        let names: Vec<&str> = tree_entries.iter().map(|te| te.name.as_str()).collect();
+        assert_eq!(vec!["a-b", "a.txt", "a"], names);
+    }
+
+    #[test]
+    fn test_tree_hash() {
+        let tree: GitObject = GitObject::Tree {
+            content: vec![TreeEntry {
+                mode: EntryMode::RegularFile,
+                name: "a.txt".to_string(),
+                sha1_hash: "3b18e512dba79e4c8300dd08aeb37f8e728b8dad".to_string(),
+            }],
+        };
+
+        assert_eq!(
+            "ebaa691b5554f29ac9d4f37811a1da6f24d376a1",
+            compute_sha1_hash(&tree.as_bytes().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_parse_tree_with_nul_in_sha() {
+        let tree: GitObject = GitObject::Tree {
+            content: vec![
+                TreeEntry {
+                    mode: EntryMode::RegularFile,
+                    name: "a".to_string(),
+                    sha1_hash: "00000000000000000000000000000000000000ff".to_string(),
+                },
+                TreeEntry {
+                    mode: EntryMode::Directory,
+                    name: "b".to_string(),
+                    sha1_hash: "3b18e512dba79e4c8300dd08aeb37f8e728b8dad".to_string(),
+                },
+            ],
+        };
+
+        let tree_entries: Vec<TreeEntry> = parse_str_tree_entry_vec(&tree.content_bytes().unwrap()).unwrap();
+
+        assert_eq!(2, tree_entries.len());
+        assert_eq!("00000000000000000000000000000000000000ff", tree_entries[0].sha1_hash);
+        assert_eq!("b", tree_entries[1].name);
+
+        // A name that is not UTF-8 could not be written back as it was.
+        let mut content: Vec<u8> = b"100644 caf\xe9\0".to_vec();
+        content.extend([0; 20]);
+        assert!(parse_str_tree_entry_vec(&content).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_objects_are_errors() {
+        for raw in [&b"blob"[..], b"blob 3", b"blob\0abc", b"blob x\0abc", b"blob \xff\0"] {
+            assert!(parse_str_to_git_object_parts_bytes(raw).is_err());
+        }
+
+        let mut content: Vec<u8> = b"100644 a\0".to_vec();
+        content.extend([0; 20]);
+        for end in [3, 9, 20] {
+            assert!(parse_str_tree_entry_vec(&content[..end]).is_err());
+        }
+        content.extend(b"40000 b");
+        assert!(parse_str_tree_entry_vec(&content).is_err());
+
+        assert!(hex_to_bytes(&"ab".repeat(20)).is_ok());
+        assert!(hex_to_bytes(&"ab".repeat(19)).is_err());
+        assert!(hex_to_bytes(&format!("{}zz", "ab".repeat(19))).is_err());
+    }
+
+    #[test]
+    fn test_tree_pretty_print() {
+        let tree: GitObject = GitObject::Tree {
+            content: vec![TreeEntry {
+                mode: EntryMode::Directory,
+                name: "src".to_string(),
+                sha1_hash: "3b18e512dba79e4c8300dd08aeb37f8e728b8dad".to_string(),
+            }],
+        };
+
+        assert_eq!(
+            b"040000 tree 3b18e512dba79e4c8300dd08aeb37f8e728b8dad\tsrc\n".to_vec(),
+            tree.pretty_print().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_format_batch_line() {
+        let object_store: MemoryObjectStore = MemoryObjectStore::new();
+        let sha1_hash: &str = "3b18e512dba79e4c8300dd08aeb37f8e728b8dad";
+
+        assert_eq!(
+            format!("{sha1_hash} blob 12"),
+            format_batch_line(&object_store, DEFAULT_BATCH_FORMAT, sha1_hash, "blob", 12, "")
+                .unwrap()
+        );
+        assert_eq!(
+            "blob: notes",
+            format_batch_line(&object_store, "%(objecttype): %(rest)", sha1_hash, "blob", 12, "notes")
+                .unwrap()
+        );
+        assert!(format_batch_line(&object_store, "%(unknown)", sha1_hash, "blob", 12, "").is_err());
+    }
+
+    #[test]
+    fn test_commit_in_memory_object_store() {
+        let object_store: MemoryObjectStore = MemoryObjectStore::new();
+        let tree_sha: String = write_git_object(&object_store, &GitObject::Tree { content: Vec::new() }).unwrap();
+        assert_eq!("4b825dc642cb6eb9a060e54bf8d69288fbee4904", tree_sha);
+
+        let commit_sha: String =
+            create_commit_object(&object_store, &tree_sha, Vec::new(), b"init\n".to_vec()).unwrap();
+
+        let git_object: GitObject = read_git_object(&object_store, &commit_sha).unwrap();
+        let GitObject::Commit { content: commit } = &git_object else {
+            panic!("not a commit: {git_object:?}");
+        };
+        assert_eq!(tree_sha, commit.tree);
+
+        let tree: GitObject = peel_to_type(&object_store, git_object, "tree").unwrap();
+        let GitObject::Tree { content: tree_entries } = tree else {
+            panic!("not a tree: {tree:?}");
+        };
+        assert!(tree_entries.is_empty());
+    }
 }
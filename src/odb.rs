@@ -0,0 +1,335 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use std::io;
+use std::io::Read;
+
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+
+use crate::GitError;
+use crate::GitObjectParts;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Content of an object being read, after its `<type> <size>\0` header.
+pub struct ObjectReader<'a> {
+    pub git_type: String,
+    pub size: usize,
+    reader: Box<dyn Read + 'a>,
+}
+
+impl<'a> ObjectReader<'a> {
+    pub fn new(git_type: String, size: usize, reader: Box<dyn Read + 'a>) -> Self {
+        Self {
+            git_type,
+            size,
+            reader,
+        }
+    }
+
+    pub fn read_content(mut self) -> Result<GitObjectParts<Vec<u8>>, GitError> {
+        let mut content: Vec<u8> = Vec::with_capacity(self.size);
+        if let Err(err) = self.reader.read_to_end(&mut content) {
+            return Err(GitError::ZlibDecompressionFailed(err.to_string()));
+        }
+
+        if content.len() != self.size {
+            return Err(GitError::InvalidDecompressSize);
+        }
+
+        Ok(GitObjectParts {
+            git_type: self.git_type,
+            size: self.size,
+            content,
+        })
+    }
+}
+
+impl Read for ObjectReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+// Where objects live. Commands only talk to this trait so backends (loose
+// files, memory, packs) can be swapped or combined.
+pub trait ObjectStore {
+    fn read(&self, sha1_hash: &str) -> Result<GitObjectParts<Vec<u8>>, GitError>;
+
+    fn read_header(&self, sha1_hash: &str) -> Result<(String, usize), GitError>;
+
+    fn write(&self, git_type: &str, content: &[u8]) -> Result<String, GitError>;
+
+    fn contains(&self, sha1_hash: &str) -> bool;
+
+    fn list(&self) -> Result<Vec<String>, GitError>;
+
+    // Ids of the objects starting with `prefix`, lowercase hex digits.
+    fn find_prefix(&self, prefix: &str) -> Result<Vec<String>, GitError> {
+        Ok(self.list()?.into_iter().filter(|sha1_hash| sha1_hash.starts_with(prefix)).collect())
+    }
+
+    fn open(&self, sha1_hash: &str) -> Result<ObjectReader<'_>, GitError> {
+        let parts: GitObjectParts<Vec<u8>> = self.read(sha1_hash)?;
+        Ok(ObjectReader::new(
+            parts.git_type,
+            parts.size,
+            Box::new(io::Cursor::new(parts.content)),
+        ))
+    }
+
+    fn write_stream(
+        &self,
+        git_type: &str,
+        size: u64,
+        reader: &mut dyn Read,
+    ) -> Result<String, GitError> {
+        let mut content: Vec<u8> = Vec::new();
+        if let Err(err) = reader.read_to_end(&mut content) {
+            return Err(GitError::WriteGitObject(format!("Read::read_to_end: {err}")));
+        }
+
+        if content.len() as u64 != size {
+            return Err(GitError::InvalidDecompressSize);
+        }
+
+        self.write(git_type, &content)
+    }
+
+    // Bytes used on disk (`%(objectsize:disk)`), 0 when not meaningful.
+    fn disk_size(&self, _sha1_hash: &str) -> u64 {
+        0
+    }
+
+    // Base the object is stored as a delta against, if any.
+    fn delta_base(&self, _sha1_hash: &str) -> Option<String> {
+        None
+    }
+}
+
+// Shortest abbreviated object id looked up or printed.
+pub const MIN_ABBREV: usize = 4;
+
+// Shortest prefix of `sha1_hash`, at least `min_length` hex digits long,
+// that no other object of the store starts with, as `--abbrev` prints.
+pub fn abbreviate(object_store: &dyn ObjectStore, sha1_hash: &str, min_length: usize) -> Result<String, GitError> {
+    let mut length: usize = min_length.clamp(MIN_ABBREV, sha1_hash.len().max(MIN_ABBREV));
+    while length < sha1_hash.len() {
+        let prefix: &str = &sha1_hash[..length];
+        if object_store.find_prefix(prefix)?.iter().all(|candidate| candidate.eq(sha1_hash)) {
+            return Ok(prefix.to_string());
+        }
+        length += 1;
+    }
+    Ok(sha1_hash.to_string())
+}
+
+pub fn object_header(git_type: &str, size: u64) -> String {
+    format!("{git_type} {size}\0")
+}
+
+// Object id of `size` bytes from `reader`, hashed one chunk at a time.
+// `on_chunk` sees every chunk, letting callers write while hashing.
+pub fn hash_stream<F>(
+    git_type: &str,
+    size: u64,
+    reader: &mut dyn Read,
+    mut on_chunk: F,
+) -> Result<String, GitError>
+where
+    F: FnMut(&[u8]) -> Result<(), GitError>,
+{
+    let header: String = object_header(git_type, size);
+
+    let mut hasher = Sha1::new();
+    hasher.input(header.as_bytes());
+    on_chunk(header.as_bytes())?;
+
+    let mut buffer: Vec<u8> = vec![0; CHUNK_SIZE];
+    let mut total: u64 = 0;
+    loop {
+        let read_bytes: usize = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read_bytes) => read_bytes,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                return Err(GitError::CreateBlob(format!("Read::read: {err}")));
+            }
+        };
+
+        total += read_bytes as u64;
+        hasher.input(&buffer[..read_bytes]);
+        on_chunk(&buffer[..read_bytes])?;
+    }
+
+    // The source changed size while being read.
+    if total != size {
+        return Err(GitError::InvalidDecompressSize);
+    }
+
+    Ok(hasher.result_str())
+}
+
+pub fn hash_object(git_type: &str, content: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.input(object_header(git_type, content.len() as u64).as_bytes());
+    hasher.input(content);
+    hasher.result_str()
+}
+
+#[derive(Default)]
+pub struct MemoryObjectStore {
+    objects: RefCell<BTreeMap<String, (String, Vec<u8>)>>,
+}
+
+impl MemoryObjectStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ObjectStore for MemoryObjectStore {
+    fn read(&self, sha1_hash: &str) -> Result<GitObjectParts<Vec<u8>>, GitError> {
+        let objects = self.objects.borrow();
+        let Some((git_type, content)) = objects.get(sha1_hash) else {
+            return Err(GitError::ObjectNotFound(sha1_hash.to_string()));
+        };
+
+        Ok(GitObjectParts {
+            git_type: git_type.clone(),
+            size: content.len(),
+            content: content.clone(),
+        })
+    }
+
+    fn read_header(&self, sha1_hash: &str) -> Result<(String, usize), GitError> {
+        let objects = self.objects.borrow();
+        let Some((git_type, content)) = objects.get(sha1_hash) else {
+            return Err(GitError::ObjectNotFound(sha1_hash.to_string()));
+        };
+
+        Ok((git_type.clone(), content.len()))
+    }
+
+    fn write(&self, git_type: &str, content: &[u8]) -> Result<String, GitError> {
+        let sha1_hash: String = hash_object(git_type, content);
+        self.objects
+            .borrow_mut()
+            .entry(sha1_hash.clone())
+            .or_insert_with(|| (git_type.to_string(), content.to_vec()));
+        Ok(sha1_hash)
+    }
+
+    fn contains(&self, sha1_hash: &str) -> bool {
+        self.objects.borrow().contains_key(sha1_hash)
+    }
+
+    fn list(&self) -> Result<Vec<String>, GitError> {
+        Ok(self.objects.borrow().keys().cloned().collect())
+    }
+}
+
+// Several stores seen as one: reads try each in turn, writes go to the
+// first. Used to put loose objects in front of packs.
+pub struct CombinedObjectStore {
+    stores: Vec<Box<dyn ObjectStore>>,
+}
+
+impl CombinedObjectStore {
+    pub fn new(stores: Vec<Box<dyn ObjectStore>>) -> Self {
+        Self { stores }
+    }
+
+    fn find(&self, sha1_hash: &str) -> Result<&dyn ObjectStore, GitError> {
+        match self.stores.iter().find(|store| store.contains(sha1_hash)) {
+            Some(store) => Ok(store.as_ref()),
+            None => Err(GitError::ObjectNotFound(sha1_hash.to_string())),
+        }
+    }
+}
+
+impl ObjectStore for CombinedObjectStore {
+    fn read(&self, sha1_hash: &str) -> Result<GitObjectParts<Vec<u8>>, GitError> {
+        self.find(sha1_hash)?.read(sha1_hash)
+    }
+
+    fn read_header(&self, sha1_hash: &str) -> Result<(String, usize), GitError> {
+        self.find(sha1_hash)?.read_header(sha1_hash)
+    }
+
+    fn write(&self, git_type: &str, content: &[u8]) -> Result<String, GitError> {
+        match self.stores.first() {
+            Some(store) => store.write(git_type, content),
+            None => Err(GitError::WriteGitObject("no object store".to_string())),
+        }
+    }
+
+    fn contains(&self, sha1_hash: &str) -> bool {
+        self.stores.iter().any(|store| store.contains(sha1_hash))
+    }
+
+    fn list(&self) -> Result<Vec<String>, GitError> {
+        let mut objects: Vec<String> = Vec::new();
+        for store in &self.stores {
+            objects.extend(store.list()?);
+        }
+        objects.sort();
+        objects.dedup();
+        Ok(objects)
+    }
+
+    fn find_prefix(&self, prefix: &str) -> Result<Vec<String>, GitError> {
+        let mut objects: Vec<String> = Vec::new();
+        for store in &self.stores {
+            objects.extend(store.find_prefix(prefix)?);
+        }
+        objects.sort();
+        objects.dedup();
+        Ok(objects)
+    }
+
+    fn open(&self, sha1_hash: &str) -> Result<ObjectReader<'_>, GitError> {
+        self.find(sha1_hash)?.open(sha1_hash)
+    }
+
+    fn write_stream(
+        &self,
+        git_type: &str,
+        size: u64,
+        reader: &mut dyn Read,
+    ) -> Result<String, GitError> {
+        match self.stores.first() {
+            Some(store) => store.write_stream(git_type, size, reader),
+            None => Err(GitError::WriteGitObject("no object store".to_string())),
+        }
+    }
+
+    fn disk_size(&self, sha1_hash: &str) -> u64 {
+        match self.find(sha1_hash) {
+            Ok(store) => store.disk_size(sha1_hash),
+            Err(_) => 0,
+        }
+    }
+
+    fn delta_base(&self, sha1_hash: &str) -> Option<String> {
+        self.find(sha1_hash).ok()?.delta_base(sha1_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_object_store() {
+        let object_store: MemoryObjectStore = MemoryObjectStore::new();
+        let sha1_hash: String = object_store.write("blob", b"hello world\n").unwrap();
+
+        assert_eq!("3b18e512dba79e4c8300dd08aeb37f8e728b8dad", sha1_hash);
+        assert!(object_store.contains(&sha1_hash));
+        assert_eq!(("blob".to_string(), 12), object_store.read_header(&sha1_hash).unwrap());
+        assert_eq!(b"hello world\n".to_vec(), object_store.read(&sha1_hash).unwrap().content);
+        assert!(object_store.read("0000000000000000000000000000000000000000").is_err());
+    }
+}
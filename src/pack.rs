@@ -0,0 +1,686 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use std::fs;
+use std::fs::File;
+
+use std::io;
+use std::io::BufReader;
+use std::io::Read;
+
+use std::os::unix::fs::FileExt;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use std::rc::Rc;
+
+use flate2::bufread::ZlibDecoder;
+
+use crate::bytes_slice_to_hex;
+use crate::delta::apply_delta;
+use crate::delta::delta_result_size;
+use crate::hex_to_bytes;
+use crate::odb::ObjectStore;
+use crate::GitError;
+use crate::GitObjectParts;
+
+pub const OBJ_COMMIT: u8 = 1;
+pub const OBJ_TREE: u8 = 2;
+pub const OBJ_BLOB: u8 = 3;
+pub const OBJ_TAG: u8 = 4;
+pub const OBJ_OFS_DELTA: u8 = 6;
+pub const OBJ_REF_DELTA: u8 = 7;
+
+const IDX_V2_MAGIC: [u8; 4] = [0xff, b't', b'O', b'c'];
+
+// Resolved objects kept around as delta bases, bounded by total size.
+const DELTA_BASE_CACHE_LIMIT: usize = 32 * 1024 * 1024;
+
+// Longest delta chain followed, so that a base cycle is an error rather
+// than an endless loop.
+const MAX_DELTA_CHAIN: usize = 10_000;
+
+pub fn type_name(type_id: u8) -> Result<&'static str, GitError> {
+    match type_id {
+        OBJ_COMMIT => Ok("commit"),
+        OBJ_TREE => Ok("tree"),
+        OBJ_BLOB => Ok("blob"),
+        OBJ_TAG => Ok("tag"),
+        _ => Err(GitError::UnknownGitType),
+    }
+}
+
+pub fn type_id(git_type: &str) -> Result<u8, GitError> {
+    match git_type {
+        "commit" => Ok(OBJ_COMMIT),
+        "tree" => Ok(OBJ_TREE),
+        "blob" => Ok(OBJ_BLOB),
+        "tag" => Ok(OBJ_TAG),
+        _ => Err(GitError::UnknownGitType),
+    }
+}
+
+fn read_u32(bytes: &[u8], index: usize) -> Result<u32, GitError> {
+    match bytes.get(index..index + 4) {
+        Some(b) => Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(GitError::InvalidPack("truncated index".to_string())),
+    }
+}
+
+// `.idx` file: a fanout table then object ids sorted, each with the offset
+// of its entry in the matching `.pack`. Version 2 adds CRC32s and 64 bit
+// offsets for packs over 2GB.
+pub struct PackIndex {
+    pub version: u32,
+    pub shas: Vec<[u8; 20]>,
+    pub crcs: Vec<u32>,
+    pub offsets: Vec<u64>,
+    pub pack_checksum: [u8; 20],
+}
+
+impl PackIndex {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, GitError> {
+        if bytes.len() < 40 {
+            return Err(GitError::InvalidPack("index too small".to_string()));
+        }
+
+        let version: u32 = if bytes[..4] == IDX_V2_MAGIC {
+            read_u32(bytes, 4)?
+        } else {
+            1
+        };
+        let fanout_start: usize = if version == 1 { 0 } else { 8 };
+        if version != 1 && version != 2 {
+            return Err(GitError::InvalidPack(format!("unsupported index version {version}")));
+        }
+
+        let count: usize = read_u32(bytes, fanout_start + 255 * 4)? as usize;
+        let table_start: usize = fanout_start + 256 * 4;
+
+        let mut shas: Vec<[u8; 20]> = Vec::with_capacity(count);
+        let mut crcs: Vec<u32> = Vec::new();
+        let mut offsets: Vec<u64> = Vec::with_capacity(count);
+
+        let sha_at = |start: usize| -> Result<[u8; 20], GitError> {
+            match bytes.get(start..start + 20) {
+                Some(sha) => Ok(sha.try_into().unwrap_or([0; 20])),
+                None => Err(GitError::InvalidPack("truncated index".to_string())),
+            }
+        };
+
+        let trailer_start: usize = if version == 1 {
+            for i in 0..count {
+                let entry: usize = table_start + i * 24;
+                offsets.push(read_u32(bytes, entry)? as u64);
+                shas.push(sha_at(entry + 4)?);
+            }
+            table_start + count * 24
+        } else {
+            let crc_start: usize = table_start + count * 20;
+            let offset_start: usize = crc_start + count * 4;
+            let large_offset_start: usize = offset_start + count * 4;
+            let mut large_offsets: usize = 0;
+
+            for i in 0..count {
+                shas.push(sha_at(table_start + i * 20)?);
+                crcs.push(read_u32(bytes, crc_start + i * 4)?);
+
+                let offset: u32 = read_u32(bytes, offset_start + i * 4)?;
+                if offset & 0x8000_0000 == 0 {
+                    offsets.push(offset as u64);
+                } else {
+                    let large: usize = large_offset_start + (offset & 0x7fff_ffff) as usize * 8;
+                    let high: u64 = read_u32(bytes, large)? as u64;
+                    let low: u64 = read_u32(bytes, large + 4)? as u64;
+                    offsets.push((high << 32) | low);
+                    large_offsets += 1;
+                }
+            }
+            large_offset_start + large_offsets * 8
+        };
+
+        Ok(Self {
+            version,
+            shas,
+            crcs,
+            offsets,
+            pack_checksum: sha_at(trailer_start)?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.shas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shas.is_empty()
+    }
+
+    pub fn find(&self, sha: &[u8]) -> Option<usize> {
+        self.shas.binary_search_by(|probe| probe[..].cmp(sha)).ok()
+    }
+
+    pub fn find_offset(&self, sha: &[u8]) -> Option<u64> {
+        self.find(sha).map(|i| self.offsets[i])
+    }
+
+    // Ids starting with the hex `prefix`, found by binary search as ids are
+    // sorted.
+    pub fn find_prefix(&self, prefix: &str) -> Vec<String> {
+        let start: usize = self.shas.partition_point(|sha| bytes_slice_to_hex(sha).as_str() < prefix);
+        self.shas[start..]
+            .iter()
+            .map(|sha| bytes_slice_to_hex(sha))
+            .take_while(|sha1_hash| sha1_hash.starts_with(prefix))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeltaBase {
+    Offset(u64),
+    Sha([u8; 20]),
+}
+
+// Entry header: type and inflated size as a varint, then for OFS_DELTA the
+// distance back to the base, for REF_DELTA the base object id.
+#[derive(Debug)]
+pub struct PackEntryHeader {
+    pub type_id: u8,
+    pub size: usize,
+    pub data_offset: u64,
+    pub base: Option<DeltaBase>,
+}
+
+pub fn parse_entry_header(bytes: &[u8], offset: u64) -> Result<PackEntryHeader, GitError> {
+    let truncated = || GitError::InvalidPack(format!("truncated entry at {offset}"));
+
+    let mut index: usize = 0;
+    let mut byte: u8 = *bytes.get(index).ok_or_else(truncated)?;
+    index += 1;
+
+    let type_id: u8 = (byte >> 4) & 0x07;
+    let mut size: usize = (byte & 0x0f) as usize;
+    let mut shift: usize = 4;
+    while byte & 0x80 != 0 {
+        if shift > 63 {
+            return Err(GitError::InvalidPack(format!("size varint too long at {offset}")));
+        }
+        byte = *bytes.get(index).ok_or_else(truncated)?;
+        index += 1;
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+    }
+
+    let base: Option<DeltaBase> = match type_id {
+        OBJ_OFS_DELTA => {
+            // Big endian varint where each continuation adds one, so that
+            // no distance has two encodings.
+            byte = *bytes.get(index).ok_or_else(truncated)?;
+            index += 1;
+            let mut distance: u64 = (byte & 0x7f) as u64;
+            while byte & 0x80 != 0 {
+                if distance >> 57 != 0 {
+                    return Err(GitError::InvalidPack(format!("bad delta base offset at {offset}")));
+                }
+                byte = *bytes.get(index).ok_or_else(truncated)?;
+                index += 1;
+                distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+            }
+
+            if distance == 0 || distance > offset {
+                return Err(GitError::InvalidPack(format!("bad delta base offset at {offset}")));
+            }
+            Some(DeltaBase::Offset(offset - distance))
+        }
+        OBJ_REF_DELTA => {
+            let Some(sha) = bytes.get(index..index + 20) else {
+                return Err(truncated());
+            };
+            index += 20;
+            Some(DeltaBase::Sha(sha.try_into().unwrap_or([0; 20])))
+        }
+        OBJ_COMMIT | OBJ_TREE | OBJ_BLOB | OBJ_TAG => None,
+        _ => {
+            return Err(GitError::InvalidPack(format!("unknown entry type {type_id} at {offset}")));
+        }
+    };
+
+    Ok(PackEntryHeader {
+        type_id,
+        size,
+        data_offset: offset + index as u64,
+        base,
+    })
+}
+
+// Positional reads so a shared `&File` can serve concurrent lookups.
+struct FileRangeReader<'a> {
+    file: &'a File,
+    position: u64,
+}
+
+impl Read for FileRangeReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read_bytes: usize = self.file.read_at(buf, self.position)?;
+        self.position += read_bytes as u64;
+        Ok(read_bytes)
+    }
+}
+
+#[derive(Default)]
+struct DeltaBaseCache {
+    entries: HashMap<u64, (u8, Rc<Vec<u8>>)>,
+    order: VecDeque<u64>,
+    total_size: usize,
+}
+
+impl DeltaBaseCache {
+    fn get(&self, offset: u64) -> Option<(u8, Rc<Vec<u8>>)> {
+        self.entries.get(&offset).cloned()
+    }
+
+    fn insert(&mut self, offset: u64, type_id: u8, content: Rc<Vec<u8>>) {
+        if content.len() > DELTA_BASE_CACHE_LIMIT || self.entries.contains_key(&offset) {
+            return;
+        }
+
+        self.total_size += content.len();
+        self.entries.insert(offset, (type_id, content));
+        self.order.push_back(offset);
+
+        while self.total_size > DELTA_BASE_CACHE_LIMIT {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some((_, evicted)) = self.entries.remove(&oldest) {
+                self.total_size -= evicted.len();
+            }
+        }
+    }
+}
+
+pub struct PackFile {
+    pub pack_path: PathBuf,
+    pub index: PackIndex,
+    file: File,
+    pack_size: u64,
+    // Entry offsets in pack order, to find where each entry ends.
+    sorted_offsets: Vec<u64>,
+    cache: RefCell<DeltaBaseCache>,
+}
+
+impl PackFile {
+    pub fn open(idx_path: &Path) -> Result<Self, GitError> {
+        let idx_bytes: Vec<u8> = match fs::read(idx_path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                return Err(GitError::InvalidPack(format!("fs::read: {err}")));
+            }
+        };
+        let index: PackIndex = PackIndex::from_bytes(&idx_bytes)?;
+
+        let pack_path: PathBuf = idx_path.with_extension("pack");
+        let file: File = match File::open(&pack_path) {
+            Ok(file) => file,
+            Err(err) => {
+                return Err(GitError::InvalidPack(format!("File::open: {err}")));
+            }
+        };
+        let pack_size: u64 = match file.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(err) => {
+                return Err(GitError::InvalidPack(format!("File::metadata: {err}")));
+            }
+        };
+
+        let mut header: [u8; 12] = [0; 12];
+        if file.read_exact_at(&mut header, 0).is_err() || &header[..4] != b"PACK" {
+            return Err(GitError::InvalidPack(format!("{} is not a packfile", pack_path.display())));
+        }
+        // The index must describe this very pack, whose trailer is the
+        // checksum it recorded.
+        let mut trailer: [u8; 20] = [0; 20];
+        let read_trailer: bool = pack_size >= 32 && file.read_exact_at(&mut trailer, pack_size - 20).is_ok();
+        if !read_trailer || trailer != index.pack_checksum {
+            return Err(GitError::InvalidPack(format!("{} does not match its index", pack_path.display())));
+        }
+
+        let mut sorted_offsets: Vec<u64> = index.offsets.clone();
+        sorted_offsets.sort_unstable();
+
+        Ok(Self {
+            pack_path,
+            index,
+            file,
+            pack_size,
+            sorted_offsets,
+            cache: RefCell::new(DeltaBaseCache::default()),
+        })
+    }
+
+    pub fn read_entry_header(&self, offset: u64) -> Result<PackEntryHeader, GitError> {
+        // Type/size varint (at most 10 bytes) plus up to a 20 byte base id.
+        let mut bytes: [u8; 32] = [0; 32];
+        let read_bytes: usize = match self.file.read_at(&mut bytes, offset) {
+            Ok(read_bytes) => read_bytes,
+            Err(err) => {
+                return Err(GitError::InvalidPack(format!("File::read_at: {err}")));
+            }
+        };
+        parse_entry_header(&bytes[..read_bytes], offset)
+    }
+
+    fn inflate(&self, data_offset: u64, size: usize) -> Result<Vec<u8>, GitError> {
+        let reader = FileRangeReader {
+            file: &self.file,
+            position: data_offset,
+        };
+        let decoder = ZlibDecoder::new(BufReader::new(reader));
+
+        let mut content: Vec<u8> = Vec::with_capacity(size);
+        if let Err(err) = decoder.take(size as u64).read_to_end(&mut content) {
+            return Err(GitError::ZlibDecompressionFailed(err.to_string()));
+        }
+        if content.len() != size {
+            return Err(GitError::InvalidDecompressSize);
+        }
+
+        Ok(content)
+    }
+
+    fn base_offset(&self, base: &DeltaBase) -> Result<u64, GitError> {
+        match base {
+            DeltaBase::Offset(offset) => Ok(*offset),
+            DeltaBase::Sha(sha) => match self.index.find_offset(sha) {
+                Some(offset) => Ok(offset),
+                None => Err(GitError::ObjectNotFound(bytes_slice_to_hex(sha))),
+            },
+        }
+    }
+
+    // Walks the delta chain down to a full object (or a cached base) and
+    // applies the deltas back up.
+    pub fn read_at(&self, offset: u64) -> Result<(u8, Rc<Vec<u8>>), GitError> {
+        let mut chain: Vec<(u64, PackEntryHeader)> = Vec::new();
+        let mut current: u64 = offset;
+
+        let (type_id, mut content): (u8, Rc<Vec<u8>>) = loop {
+            if let Some(cached) = self.cache.borrow().get(current) {
+                break cached;
+            }
+
+            let header: PackEntryHeader = self.read_entry_header(current)?;
+            match &header.base {
+                None => {
+                    let content: Vec<u8> = self.inflate(header.data_offset, header.size)?;
+                    break (header.type_id, Rc::new(content));
+                }
+                Some(base) => {
+                    let base_offset: u64 = self.base_offset(base)?;
+                    chain.push((current, header));
+                    current = base_offset;
+
+                    if chain.len() > MAX_DELTA_CHAIN {
+                        return Err(GitError::InvalidPack("delta chain too long".to_string()));
+                    }
+                }
+            }
+        };
+
+        self.cache.borrow_mut().insert(current, type_id, Rc::clone(&content));
+
+        while let Some((entry_offset, header)) = chain.pop() {
+            let delta: Vec<u8> = self.inflate(header.data_offset, header.size)?;
+            content = Rc::new(apply_delta(&content, &delta)?);
+            self.cache.borrow_mut().insert(entry_offset, type_id, Rc::clone(&content));
+        }
+
+        // Delta entries take the type of their base.
+        Ok((type_id, content))
+    }
+
+    // Type and size without applying any delta: the base chain is only
+    // followed for the type, the size sits at the start of the delta.
+    pub fn read_header_at(&self, offset: u64) -> Result<(u8, usize), GitError> {
+        let header: PackEntryHeader = self.read_entry_header(offset)?;
+        let Some(base) = &header.base else {
+            return Ok((header.type_id, header.size));
+        };
+
+        let delta_start: Vec<u8> = {
+            let reader = FileRangeReader {
+                file: &self.file,
+                position: header.data_offset,
+            };
+            let decoder = ZlibDecoder::new(BufReader::new(reader));
+            let mut bytes: Vec<u8> = Vec::new();
+            if let Err(err) = decoder.take(20).read_to_end(&mut bytes) {
+                return Err(GitError::ZlibDecompressionFailed(err.to_string()));
+            }
+            bytes
+        };
+        let size: usize = delta_result_size(&delta_start)?;
+
+        let mut current: u64 = self.base_offset(base)?;
+        for _ in 0..MAX_DELTA_CHAIN {
+            let base_header: PackEntryHeader = self.read_entry_header(current)?;
+            match &base_header.base {
+                None => return Ok((base_header.type_id, size)),
+                Some(base) => current = self.base_offset(base)?,
+            }
+        }
+        Err(GitError::InvalidPack("delta chain too long".to_string()))
+    }
+
+    // Bytes the entry occupies in the pack, up to the next entry or trailer.
+    pub fn entry_disk_size(&self, offset: u64) -> u64 {
+        let next: u64 = match self.sorted_offsets.binary_search(&offset) {
+            Ok(i) if i + 1 < self.sorted_offsets.len() => self.sorted_offsets[i + 1],
+            _ => self.pack_size.saturating_sub(20),
+        };
+        next.saturating_sub(offset)
+    }
+
+    pub fn sha_at_offset(&self, offset: u64) -> Option<String> {
+        let i: usize = self.index.offsets.iter().position(|&o| o == offset)?;
+        Some(bytes_slice_to_hex(&self.index.shas[i]))
+    }
+}
+
+// Every `objects/pack/*.idx` with its `.pack`.
+pub struct PackObjectStore {
+    packs: Vec<PackFile>,
+}
+
+impl PackObjectStore {
+    // Packs that cannot be opened are left out with a warning, the others
+    // are still read.
+    pub fn open<P: AsRef<Path>>(objects_dir: P) -> Self {
+        let pack_dir: PathBuf = objects_dir.as_ref().join("pack");
+
+        let mut idx_paths: Vec<PathBuf> = match fs::read_dir(&pack_dir) {
+            Ok(rd) => rd
+                .map_while(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext.eq("idx")))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        idx_paths.sort();
+
+        let mut packs: Vec<PackFile> = Vec::new();
+        for idx_path in idx_paths {
+            match PackFile::open(&idx_path) {
+                Ok(pack) => packs.push(pack),
+                Err(err) => eprintln!("warning: ignoring pack {}: {err:?}", idx_path.display()),
+            }
+        }
+
+        Self { packs }
+    }
+
+    fn locate(&self, sha1_hash: &str) -> Result<(&PackFile, u64), GitError> {
+        if let Ok(sha) = hex_to_bytes(sha1_hash) {
+            for pack in &self.packs {
+                if let Some(offset) = pack.index.find_offset(&sha) {
+                    return Ok((pack, offset));
+                }
+            }
+        }
+
+        Err(GitError::ObjectNotFound(sha1_hash.to_string()))
+    }
+}
+
+impl ObjectStore for PackObjectStore {
+    fn read(&self, sha1_hash: &str) -> Result<GitObjectParts<Vec<u8>>, GitError> {
+        let (pack, offset): (&PackFile, u64) = self.locate(sha1_hash)?;
+        let (type_id, content): (u8, Rc<Vec<u8>>) = pack.read_at(offset)?;
+
+        let content: Vec<u8> = Rc::try_unwrap(content).unwrap_or_else(|rc| (*rc).clone());
+        Ok(GitObjectParts {
+            git_type: type_name(type_id)?.to_string(),
+            size: content.len(),
+            content,
+        })
+    }
+
+    fn read_header(&self, sha1_hash: &str) -> Result<(String, usize), GitError> {
+        let (pack, offset): (&PackFile, u64) = self.locate(sha1_hash)?;
+        let (type_id, size): (u8, usize) = pack.read_header_at(offset)?;
+        Ok((type_name(type_id)?.to_string(), size))
+    }
+
+    fn write(&self, _git_type: &str, _content: &[u8]) -> Result<String, GitError> {
+        Err(GitError::WriteGitObject("packs are read only".to_string()))
+    }
+
+    fn contains(&self, sha1_hash: &str) -> bool {
+        self.locate(sha1_hash).is_ok()
+    }
+
+    fn list(&self) -> Result<Vec<String>, GitError> {
+        let mut objects: Vec<String> = self
+            .packs
+            .iter()
+            .flat_map(|pack| pack.index.shas.iter().map(|sha| bytes_slice_to_hex(sha)))
+            .collect();
+        objects.sort();
+        objects.dedup();
+        Ok(objects)
+    }
+
+    fn find_prefix(&self, prefix: &str) -> Result<Vec<String>, GitError> {
+        let mut objects: Vec<String> = self.packs.iter().flat_map(|pack| pack.index.find_prefix(prefix)).collect();
+        objects.sort();
+        objects.dedup();
+        Ok(objects)
+    }
+
+    fn disk_size(&self, sha1_hash: &str) -> u64 {
+        match self.locate(sha1_hash) {
+            Ok((pack, offset)) => pack.entry_disk_size(offset),
+            Err(_) => 0,
+        }
+    }
+
+    fn delta_base(&self, sha1_hash: &str) -> Option<String> {
+        let (pack, offset): (&PackFile, u64) = self.locate(sha1_hash).ok()?;
+        match pack.read_entry_header(offset).ok()?.base? {
+            DeltaBase::Sha(sha) => Some(bytes_slice_to_hex(&sha)),
+            DeltaBase::Offset(base_offset) => pack.sha_at_offset(base_offset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    use crate::compute_sha1_hash;
+    use crate::test_support::TempRepo;
+
+    #[test]
+    fn test_parse_entry_header() {
+        // blob of size 300: 0b1_011_1100, then 300 >> 4 = 18.
+        let header: PackEntryHeader = parse_entry_header(&[0xbc, 0x12], 12).unwrap();
+        assert_eq!(OBJ_BLOB, header.type_id);
+        assert_eq!(300, header.size);
+        assert_eq!(14, header.data_offset);
+        assert_eq!(None, header.base);
+
+        // OFS_DELTA of size 5 whose base is 200 bytes back: 200 = (0 + 1) << 7 | 72.
+        let header: PackEntryHeader = parse_entry_header(&[0x65, 0x80, 0x48], 1000).unwrap();
+        assert_eq!(OBJ_OFS_DELTA, header.type_id);
+        assert_eq!(Some(DeltaBase::Offset(800)), header.base);
+
+        // Varints running past 64 bits are errors, not overflows.
+        assert!(parse_entry_header(&[0xff; 32], 12).is_err());
+        assert!(parse_entry_header(&[[0x65].as_slice(), &[0xff; 32]].concat(), 1000).is_err());
+    }
+
+    #[test]
+    fn test_pack_index_find_prefix() {
+        let shas: Vec<[u8; 20]> = [[0x12, 0x34], [0x12, 0x35], [0x12, 0x3f], [0x13, 0x00]]
+            .iter()
+            .map(|start| {
+                let mut sha: [u8; 20] = [0; 20];
+                sha[..2].copy_from_slice(start);
+                sha
+            })
+            .collect();
+        let index: PackIndex = PackIndex { version: 2, crcs: vec![0; shas.len()], offsets: vec![0; shas.len()], shas, pack_checksum: [0; 20] };
+
+        assert_eq!(3, index.find_prefix("123").len());
+        assert_eq!(vec![format!("1235{}", "0".repeat(36))], index.find_prefix("1235"));
+        assert!(index.find_prefix("1236").is_empty());
+        assert_eq!(4, index.find_prefix("1").len());
+    }
+
+    // A version 1 index of a pack holding a single entry at offset 12.
+    fn single_entry_index(sha: &[u8], pack_checksum: &[u8]) -> Vec<u8> {
+        let mut idx: Vec<u8> = Vec::new();
+        for i in 0..256 {
+            idx.extend_from_slice(&u32::from(i >= sha[0] as usize).to_be_bytes());
+        }
+        idx.extend_from_slice(&12u32.to_be_bytes());
+        idx.extend_from_slice(sha);
+        idx.extend_from_slice(pack_checksum);
+        idx.extend_from_slice(&[0; 20]);
+        idx
+    }
+
+    #[test]
+    fn test_pack_file_rejects_bad_packs() {
+        let dir: TempRepo = TempRepo::new("pack_file");
+
+        // A REF_DELTA whose base is itself.
+        let sha: Vec<u8> = hex_to_bytes(&"ab".repeat(20)).unwrap();
+        let mut delta: Vec<u8> = Vec::new();
+        ZlibEncoder::new(&mut delta, Compression::default()).write_all(&[1, 1, 0x81, 0]).unwrap();
+        let mut pack: Vec<u8> = [b"PACK".as_slice(), &2u32.to_be_bytes(), &1u32.to_be_bytes(), &[0x74]].concat();
+        pack.extend_from_slice(&sha);
+        pack.extend(delta);
+        let checksum: Vec<u8> = hex_to_bytes(&compute_sha1_hash(&pack)).unwrap();
+        pack.extend_from_slice(&checksum);
+
+        fs::write(dir.join("cycle.pack"), &pack).unwrap();
+        fs::write(dir.join("cycle.idx"), single_entry_index(&sha, &checksum)).unwrap();
+        let pack_file: PackFile = PackFile::open(&dir.join("cycle.idx")).unwrap();
+        assert!(pack_file.read_header_at(12).is_err());
+        assert!(pack_file.read_at(12).is_err());
+
+        // An index written for another pack.
+        fs::write(dir.join("other.pack"), &pack).unwrap();
+        fs::write(dir.join("other.idx"), single_entry_index(&sha, &[0; 20])).unwrap();
+        assert!(PackFile::open(&dir.join("other.idx")).is_err());
+    }
+}
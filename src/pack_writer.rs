@@ -0,0 +1,366 @@
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+use std::fs;
+use std::fs::File;
+
+use std::io::BufWriter;
+use std::io::Write;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use std::process;
+
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+
+use flate2::Crc;
+
+use crate::delta::create_delta;
+use crate::hex_to_bytes;
+use crate::odb::ObjectStore;
+use crate::pack::type_id;
+use crate::pack::OBJ_OFS_DELTA;
+use crate::zlib_compression;
+use crate::GitError;
+use crate::GitObjectParts;
+
+pub const DEFAULT_WINDOW: usize = 10;
+pub const DEFAULT_DEPTH: usize = 50;
+
+// Objects below this size are not worth a delta.
+const MIN_DELTA_SIZE: usize = 50;
+
+pub struct PackObjectInput {
+    pub sha1_hash: String,
+    // Path the object was found at, used to group similar objects.
+    pub name: Option<String>,
+}
+
+// Where each object ended up, what `.idx` files are built from.
+#[derive(Debug, Clone)]
+pub struct PackedEntry {
+    pub sha1_hash: String,
+    pub offset: u64,
+    pub crc32: u32,
+}
+
+pub struct PackSummary {
+    pub entries: Vec<PackedEntry>,
+    pub checksum: String,
+}
+
+struct WindowEntry {
+    content: Vec<u8>,
+    type_id: u8,
+    offset: u64,
+    depth: usize,
+}
+
+// Wraps the output to hash the whole pack while it is written.
+struct HashingWriter<W: Write> {
+    writer: W,
+    hasher: Sha1,
+    written: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), GitError> {
+        self.hasher.input(bytes);
+        self.written += bytes.len() as u64;
+        match self.writer.write_all(bytes) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(GitError::WritePack(format!("Write::write_all: {err}"))),
+        }
+    }
+}
+
+// git's pack_name_hash: the last characters of the path weigh the most,
+// so files with the same name or extension sort next to each other.
+fn name_hash(name: &str) -> u32 {
+    name.bytes()
+        .filter(|c| !c.is_ascii_whitespace())
+        .fold(0u32, |hash, c| (hash >> 2).wrapping_add((c as u32) << 24))
+}
+
+pub fn encode_entry_header(type_id: u8, size: usize) -> Vec<u8> {
+    let mut header: Vec<u8> = Vec::new();
+
+    let mut byte: u8 = (type_id << 4) | (size & 0x0f) as u8;
+    let mut size: usize = size >> 4;
+    while size != 0 {
+        header.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    header.push(byte);
+
+    header
+}
+
+pub fn encode_ofs_distance(mut distance: u64) -> Vec<u8> {
+    let mut bytes: Vec<u8> = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance != 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+// Writes a version 2 pack holding `objects` to `writer`. Objects are
+// ordered by type, name and size, then each one is tried as a delta
+// against the previous `window` objects of the same type, the smallest
+// delta winning if it beats storing the object whole. An object listed
+// more than once is written once, under the first name it came with.
+pub fn write_pack<W: Write>(
+    object_store: &dyn ObjectStore,
+    objects: &[PackObjectInput],
+    window: usize,
+    depth: usize,
+    writer: W,
+) -> Result<PackSummary, GitError> {
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut ordered: Vec<(u8, u32, usize, &str)> = Vec::new();
+    for object in objects {
+        if !seen.insert(&object.sha1_hash) {
+            continue;
+        }
+        let (git_type, size): (String, usize) = object_store.read_header(&object.sha1_hash)?;
+        let hash: u32 = object.name.as_deref().map(name_hash).unwrap_or(0);
+        ordered.push((type_id(&git_type)?, hash, size, &object.sha1_hash));
+    }
+    ordered.sort_by(|a, b| (a.0, a.1, b.2, a.3).cmp(&(b.0, b.1, a.2, b.3)));
+
+    let mut output = HashingWriter {
+        writer,
+        hasher: Sha1::new(),
+        written: 0,
+    };
+
+    output.write_all(b"PACK")?;
+    output.write_all(&2u32.to_be_bytes())?;
+    output.write_all(&(ordered.len() as u32).to_be_bytes())?;
+
+    let mut entries: Vec<PackedEntry> = Vec::with_capacity(ordered.len());
+    let mut recent: VecDeque<WindowEntry> = VecDeque::new();
+
+    for (object_type, _, _, sha1_hash) in ordered {
+        let parts: GitObjectParts<Vec<u8>> = object_store.read(sha1_hash)?;
+        let content: Vec<u8> = parts.content;
+
+        let mut best: Option<(Vec<u8>, u64, usize)> = None;
+        if content.len() >= MIN_DELTA_SIZE {
+            for candidate in recent.iter().rev() {
+                if candidate.type_id != object_type || candidate.depth >= depth {
+                    continue;
+                }
+
+                let delta: Vec<u8> = create_delta(&candidate.content, &content);
+                let limit: usize = match &best {
+                    Some((best_delta, _, _)) => best_delta.len(),
+                    None => content.len() / 2,
+                };
+                if delta.len() < limit {
+                    best = Some((delta, candidate.offset, candidate.depth + 1));
+                }
+            }
+        }
+
+        let offset: u64 = output.written;
+        let mut entry: Vec<u8> = Vec::new();
+        let entry_depth: usize = match best {
+            Some((delta, base_offset, delta_depth)) => {
+                entry.extend(encode_entry_header(OBJ_OFS_DELTA, delta.len()));
+                entry.extend(encode_ofs_distance(offset - base_offset));
+                entry.extend(compress(&delta)?);
+                delta_depth
+            }
+            None => {
+                entry.extend(encode_entry_header(object_type, content.len()));
+                entry.extend(compress(&content)?);
+                0
+            }
+        };
+
+        let mut crc: Crc = Crc::new();
+        crc.update(&entry);
+        output.write_all(&entry)?;
+
+        entries.push(PackedEntry {
+            sha1_hash: sha1_hash.to_string(),
+            offset,
+            crc32: crc.sum(),
+        });
+
+        if window > 0 {
+            if recent.len() == window {
+                recent.pop_front();
+            }
+            recent.push_back(WindowEntry {
+                content,
+                type_id: object_type,
+                offset,
+                depth: entry_depth,
+            });
+        }
+    }
+
+    let checksum: Vec<u8> = {
+        let mut digest: [u8; 20] = [0; 20];
+        output.hasher.result(&mut digest);
+        digest.to_vec()
+    };
+    if let Err(err) = output.writer.write_all(&checksum).and_then(|()| output.writer.flush()) {
+        return Err(GitError::WritePack(format!("Write::write_all: {err}")));
+    }
+
+    Ok(PackSummary {
+        entries,
+        checksum: output.hasher.result_str(),
+    })
+}
+
+// Writes `<base_name>-<checksum>.pack` and its `.idx` like
+// `git pack-objects <base-name>`, returning the pack checksum.
+pub fn write_pack_files(
+    object_store: &dyn ObjectStore,
+    objects: &[PackObjectInput],
+    window: usize,
+    depth: usize,
+    base_name: &Path,
+) -> Result<String, GitError> {
+    let tmp_path: PathBuf = PathBuf::from(format!("{}-tmp_pack_{}", base_name.display(), process::id()));
+    let file: File = match File::create(&tmp_path) {
+        Ok(file) => file,
+        Err(err) => return Err(GitError::WritePack(format!("File::create: {err}"))),
+    };
+
+    let summary: PackSummary = match write_pack(object_store, objects, window, depth, BufWriter::new(file)) {
+        Ok(summary) => summary,
+        Err(err) => {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+    };
+
+    let pack_path: String = format!("{}-{}.pack", base_name.display(), summary.checksum);
+    let idx_path: String = format!("{}-{}.idx", base_name.display(), summary.checksum);
+    let index: Vec<u8> = match write_pack_index(&summary.entries, &summary.checksum) {
+        Ok(index) => index,
+        Err(err) => {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+    };
+
+    let written = fs::rename(&tmp_path, &pack_path).and_then(|()| fs::write(&idx_path, index));
+    if let Err(err) = written {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(GitError::WritePack(format!("fs::write: {err}")));
+    }
+
+    Ok(summary.checksum)
+}
+
+fn compress(bytes: &[u8]) -> Result<Vec<u8>, GitError> {
+    match zlib_compression(bytes) {
+        Ok(compressed) => Ok(compressed),
+        Err(err) => Err(GitError::WritePack(format!("zlib_compression: {err}"))),
+    }
+}
+
+// Version 2 `.idx` content for a pack holding `entries`.
+pub fn write_pack_index(entries: &[PackedEntry], pack_checksum: &str) -> Result<Vec<u8>, GitError> {
+    let mut sorted: Vec<&PackedEntry> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.sha1_hash.cmp(&b.sha1_hash));
+
+    let shas: Vec<Vec<u8>> = sorted.iter().map(|e| hex_to_bytes(&e.sha1_hash)).collect::<Result<_, _>>()?;
+
+    let mut index: Vec<u8> = vec![0xff, b't', b'O', b'c', 0, 0, 0, 2];
+
+    let mut fanout: [u32; 256] = [0; 256];
+    for sha in &shas {
+        for count in fanout.iter_mut().skip(sha[0] as usize) {
+            *count += 1;
+        }
+    }
+    fanout.iter().for_each(|count| index.extend(count.to_be_bytes()));
+
+    shas.iter().for_each(|sha| index.extend(sha));
+    sorted.iter().for_each(|e| index.extend(e.crc32.to_be_bytes()));
+
+    let mut large_offsets: Vec<u64> = Vec::new();
+    for entry in &sorted {
+        if entry.offset < 0x8000_0000 {
+            index.extend((entry.offset as u32).to_be_bytes());
+        } else {
+            index.extend((0x8000_0000 | large_offsets.len() as u32).to_be_bytes());
+            large_offsets.push(entry.offset);
+        }
+    }
+    large_offsets.iter().for_each(|offset| index.extend(offset.to_be_bytes()));
+
+    index.extend(hex_to_bytes(pack_checksum)?);
+
+    let mut hasher = Sha1::new();
+    hasher.input(&index);
+    index.extend(hex_to_bytes(&hasher.result_str())?);
+
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::odb::MemoryObjectStore;
+    use crate::pack::parse_entry_header;
+    use crate::pack::DeltaBase;
+    use crate::pack::PackIndex;
+
+    #[test]
+    fn test_entry_header_encoding() {
+        let header: Vec<u8> = encode_entry_header(3, 300);
+        assert_eq!(vec![0xbc, 0x12], header);
+
+        let mut bytes: Vec<u8> = encode_entry_header(OBJ_OFS_DELTA, 5);
+        bytes.extend(encode_ofs_distance(200));
+        let parsed = parse_entry_header(&bytes, 1000).unwrap();
+        assert_eq!(Some(DeltaBase::Offset(800)), parsed.base);
+    }
+
+    #[test]
+    fn test_write_pack_with_deltas() {
+        let object_store: MemoryObjectStore = MemoryObjectStore::new();
+        let base: Vec<u8> = (0..500).flat_map(|i: u32| format!("line {i}\n").into_bytes()).collect();
+        let mut objects: Vec<PackObjectInput> = Vec::new();
+        for version in 0..5 {
+            let mut content: Vec<u8> = base.clone();
+            content.extend(format!("version {version}\n").into_bytes());
+            objects.push(PackObjectInput {
+                sha1_hash: object_store.write("blob", &content).unwrap(),
+                name: Some("file.txt".to_string()),
+            });
+        }
+        // The same object found under another path is still packed once.
+        objects.push(PackObjectInput {
+            sha1_hash: objects[0].sha1_hash.clone(),
+            name: Some("copy.txt".to_string()),
+        });
+
+        let mut pack: Vec<u8> = Vec::new();
+        let summary: PackSummary =
+            write_pack(&object_store, &objects, DEFAULT_WINDOW, DEFAULT_DEPTH, &mut pack).unwrap();
+
+        assert_eq!(5, summary.entries.len());
+        assert!(pack.len() < base.len());
+
+        let index: PackIndex = PackIndex::from_bytes(&write_pack_index(&summary.entries, &summary.checksum).unwrap()).unwrap();
+        assert_eq!(5, index.len());
+        assert_eq!(hex_to_bytes(&summary.checksum).unwrap(), index.pack_checksum.to_vec());
+    }
+}
@@ -0,0 +1,66 @@
+use crate::ignore::fnmatch;
+
+// Paths given on the command line, relative to the top of the worktree.
+// Each one selects itself and everything below it, and those with
+// wildcards are matched as globs on the whole path. No pathspec at all
+// selects everything.
+#[derive(Debug, Clone, Default)]
+pub struct Pathspec {
+    specs: Vec<String>,
+}
+
+impl Pathspec {
+    pub fn new(args: &[String]) -> Self {
+        let specs: Vec<String> = args
+            .iter()
+            .map(|arg| {
+                let spec: &str = arg.trim_start_matches("./").trim_end_matches('/');
+                match spec {
+                    "." => String::new(),
+                    _ => spec.to_string(),
+                }
+            })
+            .collect();
+        Self { specs }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.specs.is_empty()
+    }
+
+    pub fn specs(&self) -> &[String] {
+        &self.specs
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        self.specs.is_empty() || self.matching_spec(path).is_some()
+    }
+
+    // Position of the first spec selecting `path`.
+    pub fn matching_spec(&self, path: &str) -> Option<usize> {
+        self.specs.iter().position(|spec| spec_matches(spec, path))
+    }
+
+    // Whether a directory may hold paths some spec selects, to avoid
+    // walking the rest of the worktree.
+    pub fn may_match_directory(&self, directory: &str) -> bool {
+        self.specs.is_empty()
+            || self.specs.iter().any(|spec| {
+                spec.is_empty()
+                    || has_wildcard(spec)
+                    || spec_matches(spec, directory)
+                    || spec.starts_with(&format!("{directory}/"))
+            })
+    }
+}
+
+fn spec_matches(spec: &str, path: &str) -> bool {
+    spec.is_empty()
+        || path == spec
+        || path.strip_prefix(spec).is_some_and(|rest| rest.starts_with('/'))
+        || (has_wildcard(spec) && fnmatch(spec.as_bytes(), path.as_bytes()))
+}
+
+fn has_wildcard(spec: &str) -> bool {
+    spec.contains(['*', '?', '['])
+}
@@ -0,0 +1,145 @@
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use crate::GitError;
+
+const MAX_PKT_LINE_SIZE: usize = 65520;
+
+pub const FLUSH_PKT: &[u8] = b"0000";
+
+// Sideband channels of `side-band-64k`.
+const BAND_DATA: u8 = 1;
+const BAND_PROGRESS: u8 = 2;
+const BAND_ERROR: u8 = 3;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Packet {
+    Data(Vec<u8>),
+    Flush,
+    Delim,
+    ResponseEnd,
+}
+
+// `<4 hex digits length including themselves><data>`.
+pub fn encode_pkt_line(data: &[u8]) -> Vec<u8> {
+    let mut line: Vec<u8> = format!("{:04x}", data.len() + 4).into_bytes();
+    line.extend_from_slice(data);
+    line
+}
+
+pub struct PktLineReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> PktLineReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    pub fn read_packet(&mut self) -> Result<Packet, GitError> {
+        let mut length: [u8; 4] = [0; 4];
+        if let Err(err) = self.reader.read_exact(&mut length) {
+            return Err(GitError::Protocol(format!("reading pkt-line length: {err}")));
+        }
+
+        let length: usize = match std::str::from_utf8(&length).ok().and_then(|l| usize::from_str_radix(l, 16).ok()) {
+            Some(length) => length,
+            None => {
+                return Err(GitError::Protocol(format!(
+                    "bad pkt-line length {:?}",
+                    String::from_utf8_lossy(&length)
+                )));
+            }
+        };
+
+        match length {
+            0 => return Ok(Packet::Flush),
+            1 => return Ok(Packet::Delim),
+            2 => return Ok(Packet::ResponseEnd),
+            3 => return Err(GitError::Protocol("bad pkt-line length 3".to_string())),
+            _ if length > MAX_PKT_LINE_SIZE => {
+                return Err(GitError::Protocol(format!("pkt-line too long: {length}")));
+            }
+            _ => {}
+        }
+
+        let mut data: Vec<u8> = vec![0; length - 4];
+        if let Err(err) = self.reader.read_exact(&mut data) {
+            return Err(GitError::Protocol(format!("reading pkt-line data: {err}")));
+        }
+        Ok(Packet::Data(data))
+    }
+
+    // Data packets up to the next flush, trailing newlines removed.
+    pub fn read_lines_until_flush(&mut self) -> Result<Vec<Vec<u8>>, GitError> {
+        let mut lines: Vec<Vec<u8>> = Vec::new();
+        loop {
+            match self.read_packet()? {
+                Packet::Data(mut data) => {
+                    if data.last() == Some(&b'\n') {
+                        data.pop();
+                    }
+                    lines.push(data);
+                }
+                _ => return Ok(lines),
+            }
+        }
+    }
+
+    // Concatenates band 1 until a flush, progress (band 2) goes to
+    // `progress`, and band 3 aborts with the remote's message.
+    pub fn read_sideband(&mut self, progress: &mut dyn Write) -> Result<Vec<u8>, GitError> {
+        let mut data: Vec<u8> = Vec::new();
+        loop {
+            let packet: Vec<u8> = match self.read_packet()? {
+                Packet::Data(packet) => packet,
+                _ => return Ok(data),
+            };
+
+            match packet.split_first() {
+                Some((&BAND_DATA, chunk)) => data.extend_from_slice(chunk),
+                Some((&BAND_PROGRESS, message)) => {
+                    let _ = progress.write_all(message);
+                }
+                Some((&BAND_ERROR, message)) => {
+                    return Err(GitError::Protocol(format!(
+                        "remote error: {}",
+                        String::from_utf8_lossy(message).trim_end()
+                    )));
+                }
+                _ => return Err(GitError::Protocol("bad sideband packet".to_string())),
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for PktLineReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pkt_line_round_trip() {
+        let mut bytes: Vec<u8> = encode_pkt_line(b"want 1234\n");
+        bytes.extend_from_slice(FLUSH_PKT);
+        bytes.extend(encode_pkt_line(b"\x01PACK"));
+        bytes.extend(encode_pkt_line(b"\x02Counting objects\r"));
+        bytes.extend(encode_pkt_line(b"\x01data"));
+        bytes.extend_from_slice(FLUSH_PKT);
+
+        assert_eq!(b"000ewant 1234\n", &bytes[..14]);
+
+        let mut reader = PktLineReader::new(&bytes[..]);
+        assert_eq!(vec![b"want 1234".to_vec()], reader.read_lines_until_flush().unwrap());
+
+        let mut progress: Vec<u8> = Vec::new();
+        assert_eq!(b"PACKdata".to_vec(), reader.read_sideband(&mut progress).unwrap());
+        assert_eq!(b"Counting objects\r".to_vec(), progress);
+    }
+}
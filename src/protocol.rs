@@ -0,0 +1,239 @@
+use std::io;
+use std::io::Read;
+
+use crate::pkt_line::encode_pkt_line;
+use crate::pkt_line::Packet;
+use crate::pkt_line::PktLineReader;
+use crate::pkt_line::FLUSH_PKT;
+use crate::transport::Transport;
+use crate::GitError;
+
+pub const AGENT: &str = "agent=codecrafters-git/0.1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteRef {
+    pub sha1_hash: String,
+    pub name: String,
+    // What an annotated tag points to, from its `^{}` line.
+    pub peeled: Option<String>,
+}
+
+// First response of upload-pack and receive-pack in protocol v0:
+// `<sha> <refname>` lines, the first one carrying capabilities after a NUL.
+#[derive(Debug, Default)]
+pub struct RefAdvertisement {
+    pub refs: Vec<RemoteRef>,
+    pub capabilities: Vec<String>,
+}
+
+impl RefAdvertisement {
+    pub fn from_lines(lines: &[Vec<u8>]) -> Result<Self, GitError> {
+        let mut advertisement: RefAdvertisement = RefAdvertisement::default();
+
+        for (i, line) in lines.iter().enumerate() {
+            let line: &[u8] = match line.iter().position(|&b| b == 0) {
+                Some(nul) if i == 0 => {
+                    advertisement.capabilities = String::from_utf8_lossy(&line[nul + 1..])
+                        .split(' ')
+                        .filter(|c| !c.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                    &line[..nul]
+                }
+                _ => line,
+            };
+
+            let line: String = String::from_utf8_lossy(line).to_string();
+            let Some((sha1_hash, name)) = line.split_once(' ') else {
+                return Err(GitError::Protocol(format!("bad ref advertisement line '{line}'")));
+            };
+            if sha1_hash.len() != 40 || !sha1_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(GitError::Protocol(format!("bad object id in '{line}'")));
+            }
+
+            // Empty repositories advertise capabilities on a fake ref.
+            if name.eq("capabilities^{}") {
+                continue;
+            }
+
+            if let Some(tag_name) = name.strip_suffix("^{}") {
+                match advertisement.refs.last_mut() {
+                    Some(last) if last.name.eq(tag_name) => last.peeled = Some(sha1_hash.to_string()),
+                    _ => return Err(GitError::Protocol(format!("unexpected peeled ref '{name}'"))),
+                }
+                continue;
+            }
+
+            advertisement.refs.push(RemoteRef {
+                sha1_hash: sha1_hash.to_string(),
+                name: name.to_string(),
+                peeled: None,
+            });
+        }
+
+        Ok(advertisement)
+    }
+
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities
+            .iter()
+            .any(|c| c.eq(capability) || c.strip_prefix(capability).is_some_and(|rest| rest.starts_with('=')))
+    }
+
+    // Branch HEAD points to, from the `symref=HEAD:<ref>` capability or
+    // else the first branch sharing its object id.
+    pub fn head_target(&self) -> Option<String> {
+        let symref: Option<&str> = self
+            .capabilities
+            .iter()
+            .find_map(|c| c.strip_prefix("symref=HEAD:"));
+        if let Some(target) = symref {
+            return Some(target.to_string());
+        }
+
+        let head: &RemoteRef = self.refs.iter().find(|r| r.name.eq("HEAD"))?;
+        let branches: Vec<&RemoteRef> = self
+            .refs
+            .iter()
+            .filter(|r| r.name.starts_with("refs/heads/") && r.sha1_hash.eq(&head.sha1_hash))
+            .collect();
+        branches
+            .iter()
+            .find(|r| r.name.eq("refs/heads/main") || r.name.eq("refs/heads/master"))
+            .or(branches.first())
+            .map(|r| r.name.clone())
+    }
+
+    pub fn supports_sideband(&self) -> bool {
+        self.has_capability("side-band-64k") || self.has_capability("side-band")
+    }
+
+    pub fn find(&self, name: &str) -> Option<&RemoteRef> {
+        self.refs.iter().find(|r| r.name.eq(name))
+    }
+}
+
+// Reads an advertisement up to its flush packet.
+pub fn read_ref_advertisement<R: Read>(reader: &mut PktLineReader<R>) -> Result<RefAdvertisement, GitError> {
+    RefAdvertisement::from_lines(&reader.read_lines_until_flush()?)
+}
+
+// `want` lines (capabilities on the first), a flush, then every `have`
+// and `done`: the whole negotiation fits in one round trip, which also
+// suits stateless transports. `quiet` asks the remote for no progress.
+pub fn upload_pack_request(
+    advertisement: &RefAdvertisement,
+    wants: &[String],
+    haves: &[String],
+    quiet: bool,
+) -> Vec<u8> {
+    let mut capabilities: Vec<&str> = Vec::new();
+    if advertisement.has_capability("side-band-64k") {
+        capabilities.push("side-band-64k");
+    } else if advertisement.has_capability("side-band") {
+        capabilities.push("side-band");
+    }
+    for capability in ["ofs-delta", "thin-pack", "include-tag"] {
+        if advertisement.has_capability(capability) {
+            capabilities.push(capability);
+        }
+    }
+    if quiet && advertisement.has_capability("no-progress") {
+        capabilities.push("no-progress");
+    }
+    if advertisement.has_capability("agent") {
+        capabilities.push(AGENT);
+    }
+
+    let mut request: Vec<u8> = Vec::new();
+    for (i, want) in wants.iter().enumerate() {
+        let line: String = if i == 0 {
+            format!("want {want} {}\n", capabilities.join(" "))
+        } else {
+            format!("want {want}\n")
+        };
+        request.extend(encode_pkt_line(line.as_bytes()));
+    }
+    request.extend_from_slice(FLUSH_PKT);
+
+    for have in haves {
+        request.extend(encode_pkt_line(format!("have {have}\n").as_bytes()));
+    }
+    request.extend(encode_pkt_line(b"done\n"));
+
+    request
+}
+
+// Response to `upload_pack_request`: one `ACK <common>` or `NAK`, then the
+// pack, multiplexed when a sideband was negotiated.
+pub fn receive_upload_pack_response<R: Read>(
+    reader: &mut PktLineReader<R>,
+    sideband: bool,
+) -> Result<Vec<u8>, GitError> {
+    loop {
+        match reader.read_packet()? {
+            Packet::Data(line) if line.starts_with(b"NAK") || line.starts_with(b"ACK ") => break,
+            Packet::Data(line) if line.starts_with(b"shallow ") || line.starts_with(b"unshallow ") => {}
+            Packet::Data(line) if line.starts_with(b"ERR ") => {
+                return Err(GitError::Protocol(format!(
+                    "remote error: {}",
+                    String::from_utf8_lossy(&line[4..]).trim_end()
+                )));
+            }
+            packet => {
+                return Err(GitError::Protocol(format!("expected ACK/NAK, got {packet:?}")));
+            }
+        }
+    }
+
+    if sideband {
+        return reader.read_sideband(&mut io::stderr());
+    }
+
+    let mut pack: Vec<u8> = Vec::new();
+    if let Err(err) = reader.read_to_end(&mut pack) {
+        return Err(GitError::Transport(format!("reading pack: {err}")));
+    }
+    Ok(pack)
+}
+
+// Negotiates `wants` against `haves` over any transport and returns the
+// pack the remote sent.
+pub fn fetch_pack(
+    transport: &mut dyn Transport,
+    advertisement: &RefAdvertisement,
+    wants: &[String],
+    haves: &[String],
+    quiet: bool,
+) -> Result<Vec<u8>, GitError> {
+    let request: Vec<u8> = upload_pack_request(advertisement, wants, haves, quiet);
+    let mut response = PktLineReader::new(transport.request(&request)?);
+    receive_upload_pack_response(&mut response, advertisement.supports_sideband())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ref_advertisement() {
+        let lines: Vec<Vec<u8>> = vec![
+            b"1111111111111111111111111111111111111111 HEAD\0multi_ack side-band-64k symref=HEAD:refs/heads/dev agent=git/2.39".to_vec(),
+            b"1111111111111111111111111111111111111111 refs/heads/dev".to_vec(),
+            b"2222222222222222222222222222222222222222 refs/tags/v1".to_vec(),
+            b"3333333333333333333333333333333333333333 refs/tags/v1^{}".to_vec(),
+        ];
+
+        let advertisement: RefAdvertisement = RefAdvertisement::from_lines(&lines).unwrap();
+
+        assert_eq!(3, advertisement.refs.len());
+        assert!(advertisement.has_capability("side-band-64k"));
+        assert!(advertisement.has_capability("agent"));
+        assert!(!advertisement.has_capability("side-band"));
+        assert_eq!(Some("refs/heads/dev".to_string()), advertisement.head_target());
+        assert_eq!(
+            Some("3333333333333333333333333333333333333333".to_string()),
+            advertisement.find("refs/tags/v1").unwrap().peeled
+        );
+    }
+}
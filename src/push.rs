@@ -0,0 +1,565 @@
+use std::collections::HashSet;
+
+use std::io;
+
+use std::path::Path;
+
+use crate::config::Config;
+use crate::fetch::remote_url_and_refspecs;
+use crate::fetch::shorten_ref;
+use crate::fetch::RefUpdate;
+use crate::fetch::ABBREV;
+use crate::fetch::SUMMARY_WIDTH;
+use crate::is_ancestor;
+use crate::odb::ObjectStore;
+use crate::open_object_store_at;
+use crate::pack_writer::write_pack;
+use crate::pack_writer::PackObjectInput;
+use crate::pack_writer::DEFAULT_DEPTH;
+use crate::pack_writer::DEFAULT_WINDOW;
+use crate::pkt_line::encode_pkt_line;
+use crate::pkt_line::PktLineReader;
+use crate::pkt_line::FLUSH_PKT;
+use crate::protocol::RefAdvertisement;
+use crate::protocol::AGENT;
+use crate::read_git_object;
+use crate::refs::delete_ref;
+use crate::refs::dwim_ref;
+use crate::refs::list_refs;
+use crate::refs::read_symbolic_ref;
+use crate::refs::write_ref;
+use crate::refspec::Refspec;
+use crate::transport::open_transport;
+use crate::transport::Transport;
+use crate::transport::RECEIVE_PACK;
+use crate::EntryMode;
+use crate::GitError;
+use crate::GitObject;
+
+// Old id of a ref being created, new id of a ref being deleted.
+const ZERO_ID: &str = "0000000000000000000000000000000000000000";
+
+// `<old> <new> <ref>` as sent to receive-pack, `src` being the local ref
+// (empty for deletions).
+#[derive(Debug)]
+struct PushCommand {
+    src: String,
+    dst: String,
+    old: String,
+    new: String,
+    force: bool,
+}
+
+// Pushes to `remote` (a configured remote or a URL) what `refspecs` name,
+// or the current branch to the branch of the same name. Updates that are
+// not fast-forwards are refused unless forced by `+` or `force`. Returns
+// every update reported, rejected ones included.
+pub fn push(git_dir: &Path, remote: &str, refspecs: &[String], force: bool) -> Result<Vec<RefUpdate>, GitError> {
+    let config: Config = Config::load(&git_dir.join("config"))?;
+    let (url, fetch_refspecs): (String, Vec<Refspec>) = remote_url_and_refspecs(&config, remote)?;
+    let object_store: Box<dyn ObjectStore> = open_object_store_at(&git_dir.join("objects"));
+
+    let refspecs: Vec<Refspec> = if refspecs.is_empty() {
+        let Some(branch) = read_symbolic_ref(git_dir, "HEAD")?.filter(|target| target.starts_with("refs/heads/")) else {
+            return Err(GitError::InvalidRef("You are not currently on a branch.".to_string()));
+        };
+        vec![Refspec {
+            force: false,
+            src: branch.clone(),
+            dst: Some(branch),
+        }]
+    } else {
+        refspecs.iter().map(|refspec| Refspec::parse(refspec)).collect::<Result<_, _>>()?
+    };
+
+    let mut transport: Box<dyn Transport> = open_transport(&url)?;
+    let advertisement: RefAdvertisement = transport.discover_refs(RECEIVE_PACK)?;
+
+    let mut commands: Vec<PushCommand> = Vec::new();
+    for refspec in &refspecs {
+        for (src, dst, new) in resolve_refspec(git_dir, object_store.as_ref(), &advertisement, refspec)? {
+            let old: String = match advertisement.find(&dst) {
+                Some(remote_ref) => remote_ref.sha1_hash.clone(),
+                None => ZERO_ID.to_string(),
+            };
+            commands.push(PushCommand {
+                src,
+                dst,
+                old,
+                new,
+                force: force || refspec.force,
+            });
+        }
+    }
+    let mut seen: HashSet<String> = HashSet::new();
+    commands.retain(|command| seen.insert(command.dst.clone()));
+
+    let mut checked: Vec<(PushCommand, RefUpdate)> = Vec::new();
+    for command in commands {
+        if command.old.eq(&command.new) {
+            continue;
+        }
+        let update: RefUpdate = check_command(object_store.as_ref(), &advertisement, &command)?;
+        checked.push((command, update));
+    }
+
+    if checked.is_empty() {
+        eprintln!("Everything up-to-date");
+        return Ok(Vec::new());
+    }
+
+    let sent: Vec<&PushCommand> = checked
+        .iter()
+        .filter(|(_, update)| !update.is_rejected())
+        .map(|(command, _)| command)
+        .collect();
+    if !sent.is_empty() {
+        let request: Vec<u8> = receive_pack_request(object_store.as_ref(), &advertisement, &sent)?;
+        let mut response = PktLineReader::new(transport.request(&request)?);
+
+        if advertisement.has_capability("report-status") {
+            let report: Vec<Vec<u8>> = if advertisement.supports_sideband() {
+                let data: Vec<u8> = response.read_sideband(&mut io::stderr())?;
+                PktLineReader::new(&data[..]).read_lines_until_flush()?
+            } else {
+                response.read_lines_until_flush()?
+            };
+            apply_report_status(&report, &mut checked)?;
+        }
+    }
+
+    // Remote-tracking refs follow what the remote accepted.
+    for (command, update) in &checked {
+        if update.is_rejected() {
+            continue;
+        }
+        for refspec in &fetch_refspecs {
+            match refspec.map(&command.dst) {
+                Some(tracking) if command.new.eq(ZERO_ID) => delete_ref(git_dir, &tracking)?,
+                Some(tracking) => write_ref(git_dir, &tracking, &command.new)?,
+                None => {}
+            }
+        }
+    }
+
+    eprintln!("To {url}");
+    let updates: Vec<RefUpdate> = checked.into_iter().map(|(_, update)| update).collect();
+    for update in &updates {
+        eprintln!("{}", format_update(update));
+    }
+    if updates.iter().any(|update| update.is_rejected()) {
+        eprintln!("error: failed to push some refs to '{url}'");
+    }
+
+    Ok(updates)
+}
+
+// Local refs (with the object they point to) and the remote ref each one
+// goes to.
+fn resolve_refspec(
+    git_dir: &Path,
+    object_store: &dyn ObjectStore,
+    advertisement: &RefAdvertisement,
+    refspec: &Refspec,
+) -> Result<Vec<(String, String, String)>, GitError> {
+    if refspec.src.is_empty() {
+        let Some(dst) = &refspec.dst else {
+            return Err(GitError::InvalidRefspec(":".to_string()));
+        };
+        let Some(dst) = find_remote_ref(advertisement, dst) else {
+            return Err(GitError::InvalidRef(format!("unable to delete '{dst}': remote ref does not exist")));
+        };
+        return Ok(vec![(String::new(), dst, ZERO_ID.to_string())]);
+    }
+
+    if refspec.is_glob() {
+        let mut resolved: Vec<(String, String, String)> = Vec::new();
+        for (name, sha1_hash) in list_refs(git_dir, "refs/")? {
+            let dst: Option<String> = match &refspec.dst {
+                Some(_) => refspec.map(&name),
+                None => refspec.matches(&name).then(|| name.clone()),
+            };
+            if let Some(dst) = dst {
+                resolved.push((name, dst, sha1_hash));
+            }
+        }
+        return Ok(resolved);
+    }
+
+    let src: &str = &refspec.src;
+    let local: Option<(String, String)> = dwim_ref(git_dir, src)?;
+    // A raw object id pushes without a local ref.
+    let (name, sha1_hash): (String, String) = match local {
+        Some(local) => local,
+        None if src.len() == 40 && object_store.contains(src) => (String::new(), src.to_string()),
+        None => return Err(GitError::InvalidRef(format!("src refspec {src} does not match any"))),
+    };
+
+    let dst: String = match refspec.dst.as_deref() {
+        Some(dst) if dst.starts_with("refs/") => dst.to_string(),
+        Some(dst) => match find_remote_ref(advertisement, dst) {
+            Some(dst) => dst,
+            None if name.starts_with("refs/heads/") => format!("refs/heads/{dst}"),
+            None if name.starts_with("refs/tags/") => format!("refs/tags/{dst}"),
+            None => return Err(GitError::InvalidRef(format!("the destination '{dst}' is not a full refname"))),
+        },
+        None if name.is_empty() => {
+            return Err(GitError::InvalidRef(format!("pushing {src} needs a destination ref")));
+        }
+        None => name.clone(),
+    };
+
+    let src: String = if name.is_empty() { sha1_hash.clone() } else { name };
+    Ok(vec![(src, dst, sha1_hash)])
+}
+
+// Remote ref `name` designates, found like local ones.
+fn find_remote_ref(advertisement: &RefAdvertisement, name: &str) -> Option<String> {
+    let candidates: [String; 4] = [
+        name.to_string(),
+        format!("refs/{name}"),
+        format!("refs/tags/{name}"),
+        format!("refs/heads/{name}"),
+    ];
+    candidates
+        .into_iter()
+        .find(|candidate| advertisement.find(candidate).is_some())
+}
+
+// What git would report for `command` before talking to the remote:
+// creations, deletions, fast-forwards and forced updates go through,
+// anything else is rejected here.
+fn check_command(
+    object_store: &dyn ObjectStore,
+    advertisement: &RefAdvertisement,
+    command: &PushCommand,
+) -> Result<RefUpdate, GitError> {
+    let (old, new): (&str, &str) = (&command.old, &command.new);
+    let rejected = |note: &str| ('!', "[rejected]".to_string(), Some(note.to_string()));
+
+    let (flag, summary, note): (char, String, Option<String>) = if new.eq(ZERO_ID) {
+        if advertisement.has_capability("delete-refs") {
+            ('-', "[deleted]".to_string(), None)
+        } else {
+            rejected("remote does not support deleting refs")
+        }
+    } else if old.eq(ZERO_ID) {
+        let summary: &str = if command.dst.starts_with("refs/tags/") {
+            "[new tag]"
+        } else if command.dst.starts_with("refs/heads/") {
+            "[new branch]"
+        } else {
+            "[new reference]"
+        };
+        ('*', summary.to_string(), None)
+    } else if command.dst.starts_with("refs/tags/") && !command.force {
+        rejected("already exists")
+    } else if !command.force && !object_store.contains(old) {
+        rejected("fetch first")
+    } else if object_store.contains(old) && is_ancestor(object_store, old, new)? {
+        (' ', format!("{}..{}", &old[..ABBREV], &new[..ABBREV]), None)
+    } else if command.force {
+        ('+', format!("{}...{}", &old[..ABBREV], &new[..ABBREV]), Some("forced update".to_string()))
+    } else {
+        rejected("non-fast-forward")
+    };
+
+    Ok(RefUpdate {
+        flag,
+        summary,
+        src: shorten_ref(&command.src).to_string(),
+        dst: shorten_ref(&command.dst).to_string(),
+        note,
+    })
+}
+
+// The commands, capabilities after a NUL on the first one, a flush, then a
+// pack of what the remote lacks unless every command is a deletion.
+fn receive_pack_request(
+    object_store: &dyn ObjectStore,
+    advertisement: &RefAdvertisement,
+    commands: &[&PushCommand],
+) -> Result<Vec<u8>, GitError> {
+    let mut capabilities: Vec<&str> = Vec::new();
+    if advertisement.has_capability("report-status") {
+        capabilities.push("report-status");
+        if advertisement.has_capability("side-band-64k") {
+            capabilities.push("side-band-64k");
+        }
+    }
+    if advertisement.has_capability("agent") {
+        capabilities.push(AGENT);
+    }
+
+    let mut request: Vec<u8> = Vec::new();
+    for (i, command) in commands.iter().enumerate() {
+        let mut line: String = format!("{} {} {}", command.old, command.new, command.dst);
+        if i == 0 {
+            line.push('\0');
+            line.push_str(&capabilities.join(" "));
+        }
+        request.extend(encode_pkt_line(line.as_bytes()));
+    }
+    request.extend_from_slice(FLUSH_PKT);
+
+    let tips: Vec<String> = commands
+        .iter()
+        .map(|command| command.new.clone())
+        .filter(|new| new.ne(ZERO_ID))
+        .collect();
+    if tips.is_empty() {
+        return Ok(request);
+    }
+
+    // Whatever the remote refs reach is already there.
+    let known: Vec<String> = advertisement
+        .refs
+        .iter()
+        .map(|remote_ref| remote_ref.sha1_hash.clone())
+        .filter(|sha1_hash| object_store.contains(sha1_hash))
+        .collect();
+    let objects: Vec<PackObjectInput> = missing_objects(object_store, &tips, &known)?;
+    write_pack(object_store, &objects, DEFAULT_WINDOW, DEFAULT_DEPTH, &mut request)?;
+
+    Ok(request)
+}
+
+// `unpack ok` (or the reason unpacking failed), then `ok <ref>` or
+// `ng <ref> <reason>` for each command.
+fn apply_report_status(report: &[Vec<u8>], checked: &mut [(PushCommand, RefUpdate)]) -> Result<(), GitError> {
+    let mut lines = report.iter().map(|line| String::from_utf8_lossy(line).to_string());
+
+    match lines.next() {
+        Some(line) if line.eq("unpack ok") => {}
+        Some(line) => {
+            let reason: &str = line.strip_prefix("unpack ").unwrap_or(&line);
+            return Err(GitError::Protocol(format!("unpack failed: {reason}")));
+        }
+        None => return Err(GitError::Protocol("missing report-status".to_string())),
+    }
+
+    for line in lines {
+        let Some(rest) = line.strip_prefix("ng ") else {
+            continue;
+        };
+        let (name, reason): (&str, &str) = rest.split_once(' ').unwrap_or((rest, "failed"));
+        if let Some((_, update)) = checked.iter_mut().find(|(command, _)| command.dst.eq(name)) {
+            update.flag = '!';
+            update.summary = "[remote rejected]".to_string();
+            update.note = Some(reason.to_string());
+        }
+    }
+
+    Ok(())
+}
+
+// ` <flag> <summary> <src> -> <dst> (<note>)`, deletions only naming the
+// remote ref.
+fn format_update(update: &RefUpdate) -> String {
+    let mut line: String = format!(" {} {:<SUMMARY_WIDTH$} ", update.flag, update.summary);
+    if update.flag == '-' {
+        line.push_str(&update.dst);
+    } else {
+        line.push_str(&format!("{} -> {}", update.src, update.dst));
+    }
+    if let Some(note) = &update.note {
+        line.push_str(&format!(" ({note})"));
+    }
+    line
+}
+
+// Objects reachable from `tips` but not from `known`. The history behind
+// `known` is skipped, and so is the content of the commits where it meets
+// the new history, which is what the remote can already build on.
+fn missing_objects(object_store: &dyn ObjectStore, tips: &[String], known: &[String]) -> Result<Vec<PackObjectInput>, GitError> {
+    let mut uninteresting: HashSet<String> = HashSet::new();
+    let mut pending: Vec<String> = known.to_vec();
+    while let Some(sha1_hash) = pending.pop() {
+        if !uninteresting.insert(sha1_hash.clone()) {
+            continue;
+        }
+        match read_git_object(object_store, &sha1_hash)? {
+            GitObject::Commit { content } => pending.extend(content.parents.into_iter().filter(|p| object_store.contains(p))),
+            GitObject::Tag { content } => pending.push(content.object),
+            _ => {}
+        }
+    }
+
+    let mut objects: Vec<PackObjectInput> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut trees: Vec<String> = Vec::new();
+    let mut boundary: Vec<String> = Vec::new();
+
+    let mut pending: Vec<String> = tips.to_vec();
+    while let Some(sha1_hash) = pending.pop() {
+        if uninteresting.contains(&sha1_hash) {
+            boundary.push(sha1_hash);
+            continue;
+        }
+        if seen.contains(&sha1_hash) {
+            continue;
+        }
+
+        match read_git_object(object_store, &sha1_hash)? {
+            GitObject::Commit { content } => {
+                trees.push(content.tree);
+                pending.extend(content.parents);
+            }
+            GitObject::Tag { content } => pending.push(content.object),
+            // Trees are walked once every commit is known.
+            GitObject::Tree { .. } => {
+                trees.push(sha1_hash);
+                continue;
+            }
+            GitObject::Blob { .. } => {}
+        }
+        seen.insert(sha1_hash.clone());
+        objects.push(PackObjectInput {
+            sha1_hash,
+            name: None,
+        });
+    }
+
+    let mut known_objects: Vec<PackObjectInput> = Vec::new();
+    for sha1_hash in boundary {
+        match read_git_object(object_store, &sha1_hash)? {
+            GitObject::Commit { content } => walk_tree(object_store, &content.tree, "", &mut seen, &mut known_objects)?,
+            GitObject::Tree { .. } => walk_tree(object_store, &sha1_hash, "", &mut seen, &mut known_objects)?,
+            _ => {}
+        }
+    }
+    for tree in trees {
+        walk_tree(object_store, &tree, "", &mut seen, &mut objects)?;
+    }
+
+    Ok(objects)
+}
+
+// Adds the tree and everything below it not `seen` yet, with the path
+// names the pack writer groups similar objects by.
+fn walk_tree(
+    object_store: &dyn ObjectStore,
+    sha1_hash: &str,
+    name: &str,
+    seen: &mut HashSet<String>,
+    objects: &mut Vec<PackObjectInput>,
+) -> Result<(), GitError> {
+    if !seen.insert(sha1_hash.to_string()) {
+        return Ok(());
+    }
+    objects.push(PackObjectInput {
+        sha1_hash: sha1_hash.to_string(),
+        name: Some(name.to_string()),
+    });
+
+    let GitObject::Tree { content } = read_git_object(object_store, sha1_hash)? else {
+        return Err(GitError::InvalidTreeEntry);
+    };
+    for entry in content {
+        match entry.mode {
+            EntryMode::Directory => walk_tree(object_store, &entry.sha1_hash, &entry.name, seen, objects)?,
+            // Submodule commits live in another repository.
+            EntryMode::Submodule => {}
+            _ => {
+                if seen.insert(entry.sha1_hash.clone()) {
+                    objects.push(PackObjectInput {
+                        sha1_hash: entry.sha1_hash,
+                        name: Some(entry.name),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use std::path::PathBuf;
+
+    use crate::refs::read_ref;
+    use crate::test_support::commit_file;
+    use crate::test_support::git;
+    use crate::test_support::TempRepo;
+
+    // Small pushes are unpacked into loose objects by receive-pack.
+    fn loose_object_count(git_dir: &Path) -> usize {
+        fs::read_dir(git_dir.join("objects"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.file_name().unwrap().len() == 2)
+            .map(|dir| fs::read_dir(dir).unwrap().count())
+            .sum()
+    }
+
+    fn push_specs(git_dir: &Path, refspecs: &[&str], force: bool) -> Vec<RefUpdate> {
+        let refspecs: Vec<String> = refspecs.iter().map(|refspec| refspec.to_string()).collect();
+        push(git_dir, "origin", &refspecs, force).unwrap()
+    }
+
+    #[test]
+    fn test_push_to_local_repository() {
+        let root: TempRepo = TempRepo::new("push");
+        let upstream: PathBuf = root.join("upstream.git");
+        let dir: PathBuf = root.join("work");
+        fs::create_dir_all(&dir).unwrap();
+        git(&root, &["init", "-q", "--bare", "-b", "main", "upstream.git"]);
+        git(&dir, &["init", "-q", "-b", "main"]);
+        git(&dir, &["remote", "add", "origin", upstream.to_str().unwrap()]);
+        let git_dir: PathBuf = dir.join(".git");
+
+        commit_file(&dir, "a", "one");
+        let first: String = commit_file(&dir, "a", "two");
+        git(&dir, &["branch", "topic"]);
+        git(&dir, &["tag", "-a", "-m", "v1", "v1"]);
+
+        let updates: Vec<RefUpdate> = push_specs(&git_dir, &["main", "topic", "v1"], false);
+        assert_eq!(
+            vec![
+                " * [new branch]      main -> main",
+                " * [new branch]      topic -> topic",
+                " * [new tag]         v1 -> v1",
+            ],
+            updates.iter().map(format_update).collect::<Vec<String>>()
+        );
+        assert_eq!(first, git(&upstream, &["rev-parse", "main"]));
+        assert_eq!(Some(first.clone()), read_ref(&git_dir, "refs/remotes/origin/main").unwrap());
+
+        // Only the new commit, its tree and its blob are sent.
+        let before: usize = loose_object_count(&upstream);
+        let second: String = commit_file(&dir, "a", "three");
+        let updates: Vec<RefUpdate> = push(&git_dir, "origin", &[], false).unwrap();
+        assert_eq!(format!("{}..{}", &first[..7], &second[..7]), updates[0].summary);
+        assert_eq!(before + 3, loose_object_count(&upstream));
+        assert!(push(&git_dir, "origin", &[], false).unwrap().is_empty());
+
+        git(&dir, &["reset", "-q", "--hard", "HEAD~1"]);
+        let rewritten: String = commit_file(&dir, "b", "rewritten");
+        let updates: Vec<RefUpdate> = push_specs(&git_dir, &["main"], false);
+        assert_eq!(" ! [rejected]        main -> main (non-fast-forward)", format_update(&updates[0]));
+        assert_eq!(second, git(&upstream, &["rev-parse", "main"]));
+
+        let updates: Vec<RefUpdate> = push_specs(&git_dir, &["+main"], false);
+        assert_eq!('+', updates[0].flag);
+        assert_eq!(rewritten, git(&upstream, &["rev-parse", "main"]));
+
+        git(&dir, &["reset", "-q", "--hard", "HEAD~1"]);
+        let forced: String = commit_file(&dir, "c", "forced");
+        let updates: Vec<RefUpdate> = push_specs(&git_dir, &["main", "main:refs/heads/topic"], true);
+        assert_eq!(vec!['+', ' '], updates.iter().map(|update| update.flag).collect::<Vec<char>>());
+        assert_eq!(forced, git(&upstream, &["rev-parse", "main"]));
+        assert_eq!(forced, git(&upstream, &["rev-parse", "topic"]));
+
+        let updates: Vec<RefUpdate> = push_specs(&git_dir, &[":topic"], false);
+        assert_eq!(" - [deleted]         topic", format_update(&updates[0]));
+        assert_eq!("refs/heads/main", git(&upstream, &["for-each-ref", "--format=%(refname)", "refs/heads/"]));
+        assert!(read_ref(&git_dir, "refs/remotes/origin/topic").unwrap().is_none());
+
+        git(&upstream, &["fsck", "--full", "--no-dangling"]);
+    }
+}
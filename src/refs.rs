@@ -0,0 +1,385 @@
+use std::fs;
+use std::io;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::GitError;
+
+// Symbolic refs pointing to symbolic refs are followed this deep.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+// Loose refs: one file per ref under the git directory, holding an object
+// id or `ref: <target>` for symbolic refs.
+pub fn write_ref(git_dir: &Path, name: &str, sha1_hash: &str) -> Result<(), GitError> {
+    write_ref_file(git_dir, name, &format!("{sha1_hash}\n"))
+}
+
+pub fn write_symbolic_ref(git_dir: &Path, name: &str, target: &str) -> Result<(), GitError> {
+    write_ref_file(git_dir, name, &format!("ref: {target}\n"))
+}
+
+fn write_ref_file(git_dir: &Path, name: &str, content: &str) -> Result<(), GitError> {
+    if !is_valid_ref_name(name) {
+        return Err(GitError::InvalidRef(format!("'{name}' is not a valid ref name")));
+    }
+
+    let ref_path: PathBuf = git_dir.join(name);
+    let lock_path: PathBuf = git_dir.join(format!("{name}.lock"));
+
+    let written = match ref_path.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+    .and_then(|()| fs::write(&lock_path, content))
+    .and_then(|()| fs::rename(&lock_path, &ref_path));
+
+    if let Err(err) = written {
+        let _ = fs::remove_file(&lock_path);
+        return Err(GitError::InvalidRef(format!("cannot update ref '{name}': {err}")));
+    }
+
+    Ok(())
+}
+
+// Removes a ref, from `packed-refs` too, and the directories its loose
+// file leaves empty.
+pub fn delete_ref(git_dir: &Path, name: &str) -> Result<(), GitError> {
+    // Names like `../config` or `config` would reach files that are not refs.
+    if !is_valid_ref_name(name) {
+        return Err(GitError::InvalidRef(format!("'{name}' is not a valid ref name")));
+    }
+    let packed_refs: Vec<PackedRef> = read_packed_refs(git_dir)?;
+    if packed_refs.iter().any(|packed_ref| packed_ref.name.eq(name)) {
+        let kept: Vec<PackedRef> = packed_refs.into_iter().filter(|packed_ref| !packed_ref.name.eq(name)).collect();
+        write_packed_refs(git_dir, &kept)?;
+    }
+
+    match fs::remove_file(git_dir.join(name)) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(GitError::InvalidRef(format!("cannot delete ref '{name}': {err}"))),
+    }
+
+    // Directories left empty go too, down to `refs/<kind>`.
+    for parent in Path::new(name).ancestors().skip(1) {
+        if parent.components().count() <= 2 || fs::remove_dir(git_dir.join(parent)).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// Target of a symbolic ref like HEAD, `None` for regular or missing refs.
+pub fn read_symbolic_ref(git_dir: &Path, name: &str) -> Result<Option<String>, GitError> {
+    let content: Option<String> = read_ref_file(git_dir, name)?;
+    Ok(content.and_then(|content| content.strip_prefix("ref: ").map(str::to_string)))
+}
+
+// Object id of `name`, following symbolic refs, `None` when it does not
+// exist (like HEAD on an unborn branch). Loose refs take precedence over
+// `packed-refs`, which only holds regular refs.
+pub fn read_ref(git_dir: &Path, name: &str) -> Result<Option<String>, GitError> {
+    let mut name: String = name.to_string();
+    for _ in 0..MAX_SYMREF_DEPTH {
+        let Some(content) = read_ref_file(git_dir, &name)? else {
+            let packed_ref: Option<PackedRef> = read_packed_refs(git_dir)?.into_iter().find(|packed_ref| packed_ref.name.eq(&name));
+            return Ok(packed_ref.map(|packed_ref| packed_ref.sha1_hash));
+        };
+        match content.strip_prefix("ref: ") {
+            Some(target) => name = target.to_string(),
+            None if is_object_id(&content) => return Ok(Some(content)),
+            None => return Err(GitError::InvalidRef(format!("'{name}' does not point to a valid object"))),
+        }
+    }
+
+    Err(GitError::InvalidRef(format!("symbolic ref loop at '{name}'")))
+}
+
+// Full name and object id of the ref a short name designates, trying
+// `<name>`, `refs/<name>`, `refs/tags/<name>`, `refs/heads/<name>`,
+// `refs/remotes/<name>` and `refs/remotes/<name>/HEAD` in turn.
+pub fn dwim_ref(git_dir: &Path, name: &str) -> Result<Option<(String, String)>, GitError> {
+    let candidates: [String; 6] = [
+        name.to_string(),
+        format!("refs/{name}"),
+        format!("refs/tags/{name}"),
+        format!("refs/heads/{name}"),
+        format!("refs/remotes/{name}"),
+        format!("refs/remotes/{name}/HEAD"),
+    ];
+    for candidate in candidates.into_iter().filter(|candidate| is_valid_ref_name(candidate)) {
+        if let Some(sha1_hash) = read_ref(git_dir, &candidate)? {
+            return Ok(Some((candidate, sha1_hash)));
+        }
+    }
+    Ok(None)
+}
+
+fn read_ref_file(git_dir: &Path, name: &str) -> Result<Option<String>, GitError> {
+    if !is_valid_ref_name(name) {
+        return Err(GitError::InvalidRef(format!("'{name}' is not a valid ref name")));
+    }
+
+    let path: PathBuf = git_dir.join(name);
+    if path.is_dir() {
+        return Ok(None);
+    }
+
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content.trim_end().to_string())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(GitError::InvalidRef(format!("cannot read ref '{name}': {err}"))),
+    }
+}
+
+// Every ref under `prefix` (like "refs/" or "refs/heads/") with its object
+// id, loose or packed, sorted by name.
+pub fn list_refs(git_dir: &Path, prefix: &str) -> Result<Vec<(String, String)>, GitError> {
+    let prefix: &str = prefix.trim_end_matches('/');
+    let mut refs: Vec<(String, String)> = Vec::new();
+    collect_refs(git_dir, prefix, &mut refs)?;
+
+    for packed_ref in read_packed_refs(git_dir)? {
+        let under_prefix: bool = match packed_ref.name.strip_prefix(prefix) {
+            Some(rest) => prefix.is_empty() || rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        };
+        if under_prefix && !refs.iter().any(|(name, _)| name.eq(&packed_ref.name)) {
+            refs.push((packed_ref.name, packed_ref.sha1_hash));
+        }
+    }
+
+    refs.sort();
+    Ok(refs)
+}
+
+fn collect_refs(git_dir: &Path, name: &str, refs: &mut Vec<(String, String)>) -> Result<(), GitError> {
+    let path: PathBuf = git_dir.join(name);
+    if path.is_file() {
+        if let Some(sha1_hash) = read_ref(git_dir, name)? {
+            refs.push((name.to_string(), sha1_hash));
+        }
+        return Ok(());
+    }
+
+    let Ok(entries) = fs::read_dir(&path) else {
+        return Ok(());
+    };
+    for entry in entries.map_while(Result::ok) {
+        let file_name: String = entry.file_name().to_string_lossy().to_string();
+        if file_name.ends_with(".lock") {
+            continue;
+        }
+        collect_refs(git_dir, &format!("{name}/{file_name}"), refs)?;
+    }
+
+    Ok(())
+}
+
+// One line of `packed-refs`, with the object an annotated tag peels to
+// when a `^<id>` line follows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRef {
+    pub name: String,
+    pub sha1_hash: String,
+    pub peeled: Option<String>,
+}
+
+const PACKED_REFS_HEADER: &str = "# pack-refs with: peeled fully-peeled sorted \n";
+
+pub fn read_packed_refs(git_dir: &Path) -> Result<Vec<PackedRef>, GitError> {
+    let content: String = match fs::read_to_string(git_dir.join("packed-refs")) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(GitError::InvalidRef(format!("cannot read packed-refs: {err}"))),
+    };
+
+    let mut packed_refs: Vec<PackedRef> = Vec::new();
+    for line in content.lines().filter(|line| !line.starts_with('#') && !line.is_empty()) {
+        if let Some(peeled) = line.strip_prefix('^') {
+            match packed_refs.last_mut() {
+                Some(packed_ref) if packed_ref.peeled.is_none() => packed_ref.peeled = Some(peeled.to_string()),
+                _ => return Err(GitError::InvalidRef(format!("unexpected line in packed-refs: '{line}'"))),
+            }
+            continue;
+        }
+
+        match line.split_once(' ') {
+            Some((sha1_hash, name)) if sha1_hash.len() == 40 => packed_refs.push(PackedRef {
+                name: name.to_string(),
+                sha1_hash: sha1_hash.to_string(),
+                peeled: None,
+            }),
+            _ => return Err(GitError::InvalidRef(format!("unexpected line in packed-refs: '{line}'"))),
+        }
+    }
+
+    Ok(packed_refs)
+}
+
+// Rewrites `packed-refs` through its lock file, sorted by name.
+fn write_packed_refs(git_dir: &Path, packed_refs: &[PackedRef]) -> Result<(), GitError> {
+    let mut packed_refs: Vec<&PackedRef> = packed_refs.iter().collect();
+    packed_refs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut content: String = PACKED_REFS_HEADER.to_string();
+    for packed_ref in packed_refs {
+        content.push_str(&format!("{} {}\n", packed_ref.sha1_hash, packed_ref.name));
+        if let Some(peeled) = &packed_ref.peeled {
+            content.push_str(&format!("^{peeled}\n"));
+        }
+    }
+
+    let lock_path: PathBuf = git_dir.join("packed-refs.lock");
+    if let Err(err) = fs::write(&lock_path, content).and_then(|()| fs::rename(&lock_path, git_dir.join("packed-refs"))) {
+        let _ = fs::remove_file(&lock_path);
+        return Err(GitError::InvalidRef(format!("cannot update packed-refs: {err}")));
+    }
+
+    Ok(())
+}
+
+// What an annotated tag ref peels to according to `packed-refs`, unless a
+// loose ref overrides the packed one.
+pub fn read_packed_peeled(git_dir: &Path, name: &str) -> Result<Option<String>, GitError> {
+    if read_ref_file(git_dir, name)?.is_some() {
+        return Ok(None);
+    }
+    let packed_ref: Option<PackedRef> = read_packed_refs(git_dir)?.into_iter().find(|packed_ref| packed_ref.name.eq(name));
+    Ok(packed_ref.and_then(|packed_ref| packed_ref.peeled))
+}
+
+// Names that may be turned into a path under the git directory: refs
+// under `refs/`, and pseudo-refs like `HEAD` or `FETCH_HEAD` at the top.
+fn is_valid_ref_name(name: &str) -> bool {
+    if name.starts_with("refs/") {
+        check_ref_format(name, false)
+    } else {
+        !name.is_empty() && name.bytes().all(|b| b.is_ascii_uppercase() || b == b'_' || b == b'-')
+    }
+}
+
+fn is_object_id(content: &str) -> bool {
+    content.len() == 40 && content.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// git's `check-ref-format` rules: slash separated components that neither
+// start with a dot nor end with `.lock`, no `..`, `@{`, control characters
+// or any of ` ~^:?*[\`, no empty component, no trailing dot and not `@`
+// alone. Names need two components unless `allow_onelevel`.
+pub fn check_ref_format(name: &str, allow_onelevel: bool) -> bool {
+    if name.is_empty() || name == "@" || name.ends_with('.') || name.contains("..") || name.contains("@{") {
+        return false;
+    }
+    if name.bytes().any(|b| b < 0x20 || b == 0x7f || b" ~^:?*[\\".contains(&b)) {
+        return false;
+    }
+
+    let components: Vec<&str> = name.split('/').collect();
+    if components.len() < 2 && !allow_onelevel {
+        return false;
+    }
+    components
+        .iter()
+        .all(|component| !component.is_empty() && !component.starts_with('.') && !component.ends_with(".lock"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_support::TempRepo;
+
+    const ONE: &str = "1111111111111111111111111111111111111111";
+    const TWO: &str = "2222222222222222222222222222222222222222";
+    const THREE: &str = "3333333333333333333333333333333333333333";
+
+    #[test]
+    fn test_packed_refs() {
+        let git_dir: TempRepo = TempRepo::new("packed_refs");
+        fs::create_dir_all(git_dir.join("refs/heads")).unwrap();
+        fs::write(
+            git_dir.join("packed-refs"),
+            format!("{PACKED_REFS_HEADER}{ONE} refs/heads/main\n{TWO} refs/tags/v1\n^{THREE}\n{ONE} refs/tags/v2\n"),
+        )
+        .unwrap();
+        write_symbolic_ref(&git_dir, "HEAD", "refs/heads/main").unwrap();
+
+        assert_eq!(Some(ONE.to_string()), read_ref(&git_dir, "HEAD").unwrap());
+        assert_eq!(Some(THREE.to_string()), read_packed_peeled(&git_dir, "refs/tags/v1").unwrap());
+        assert_eq!(None, read_packed_peeled(&git_dir, "refs/tags/v2").unwrap());
+
+        // Loose refs override packed ones.
+        write_ref(&git_dir, "refs/heads/main", TWO).unwrap();
+        write_ref(&git_dir, "refs/heads/topic", THREE).unwrap();
+        assert_eq!(Some(TWO.to_string()), read_ref(&git_dir, "refs/heads/main").unwrap());
+        assert_eq!(
+            vec![
+                ("refs/heads/main".to_string(), TWO.to_string()),
+                ("refs/heads/topic".to_string(), THREE.to_string()),
+            ],
+            list_refs(&git_dir, "refs/heads/").unwrap()
+        );
+        assert_eq!(2, list_refs(&git_dir, "refs/tags").unwrap().len());
+        // Prefixes are matched on whole components.
+        assert!(list_refs(&git_dir, "refs/ta").unwrap().is_empty());
+
+        delete_ref(&git_dir, "refs/heads/main").unwrap();
+        delete_ref(&git_dir, "refs/tags/v1").unwrap();
+        assert!(delete_ref(&git_dir, "refs/heads/../../packed-refs").is_err());
+        fs::write(git_dir.join("config"), "").unwrap();
+        assert!(delete_ref(&git_dir, "config").is_err());
+        assert!(git_dir.join("config").is_file());
+        assert_eq!(None, read_ref(&git_dir, "HEAD").unwrap());
+        assert_eq!(
+            format!("{PACKED_REFS_HEADER}{ONE} refs/tags/v2\n"),
+            fs::read_to_string(git_dir.join("packed-refs")).unwrap()
+        );
+
+        // Symbolic refs pointing to each other are not followed forever.
+        write_symbolic_ref(&git_dir, "refs/heads/a", "refs/heads/b").unwrap();
+        write_symbolic_ref(&git_dir, "refs/heads/b", "refs/heads/a").unwrap();
+        assert!(read_ref(&git_dir, "refs/heads/a").is_err());
+
+        // Names leaving the refs and contents that are not ids are errors.
+        assert!(read_ref(&git_dir, "../../etc/passwd").is_err());
+        assert!(read_ref(&git_dir, "config").is_err());
+        assert_eq!(None, dwim_ref(&git_dir, "../packed-refs").unwrap());
+        fs::write(git_dir.join("refs/heads/garbage"), "not an id\n").unwrap();
+        assert!(read_ref(&git_dir, "refs/heads/garbage").is_err());
+    }
+
+    #[test]
+    fn test_check_ref_format() {
+        for valid in ["refs/heads/main", "refs/tags/v1.0", "refs/heads/feature/x-y_z", "refs/heads/@x"] {
+            assert!(check_ref_format(valid, false), "{valid}");
+        }
+        for invalid in [
+            "main",
+            "refs/heads/.hidden",
+            "refs/heads/a..b",
+            "refs/heads/x.lock",
+            "refs/heads/a/",
+            "/refs/heads/a",
+            "refs//heads",
+            "refs/heads/a.",
+            "refs/heads/a b",
+            "refs/heads/a~1",
+            "refs/heads/a^",
+            "refs/heads/a:b",
+            "refs/heads/a?",
+            "refs/heads/a*",
+            "refs/heads/a[",
+            "refs/heads/a\\b",
+            "refs/heads/a@{1}",
+            "refs/heads/\x01",
+            "@",
+        ] {
+            assert!(!check_ref_format(invalid, false), "{invalid}");
+        }
+        assert!(check_ref_format("HEAD", true));
+        assert!(!check_ref_format("@", true));
+    }
+}
@@ -0,0 +1,86 @@
+use crate::GitError;
+
+// `[+]<src>[:<dst>]`, where both sides may hold a single `*` matching any
+// suffix, slashes included. `+` allows non fast-forward updates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refspec {
+    pub force: bool,
+    pub src: String,
+    pub dst: Option<String>,
+}
+
+impl Refspec {
+    pub fn parse(spec: &str) -> Result<Self, GitError> {
+        let (force, rest): (bool, &str) = match spec.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+
+        let (src, dst): (&str, Option<&str>) = match rest.split_once(':') {
+            Some((src, dst)) => (src, Some(dst).filter(|dst| !dst.is_empty())),
+            None => (rest, None),
+        };
+
+        let stars = |side: &str| side.matches('*').count();
+        if stars(src) > 1 || dst.is_some_and(|dst| stars(dst) != stars(src)) {
+            return Err(GitError::InvalidRefspec(spec.to_string()));
+        }
+
+        Ok(Self {
+            force,
+            src: src.to_string(),
+            dst: dst.map(str::to_string),
+        })
+    }
+
+    pub fn is_glob(&self) -> bool {
+        self.src.contains('*')
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        glob_match(&self.src, name).is_some()
+    }
+
+    // Destination of `name` when the source side matches it.
+    pub fn map(&self, name: &str) -> Option<String> {
+        let star: &str = glob_match(&self.src, name)?;
+        let dst: &str = self.dst.as_deref()?;
+        Some(dst.replacen('*', star, 1))
+    }
+}
+
+// What `*` stood for in `pattern` to give `name`, the empty string when
+// `pattern` has no `*` and equals `name`.
+fn glob_match<'a>(pattern: &str, name: &'a str) -> Option<&'a str> {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            let rest: &str = name.strip_prefix(prefix)?;
+            let star: &str = rest.strip_suffix(suffix)?;
+            (!star.is_empty()).then_some(star)
+        }
+        None => pattern.eq(name).then_some(""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refspec_mapping() {
+        let refspec: Refspec = Refspec::parse("+refs/heads/*:refs/remotes/origin/*").unwrap();
+
+        assert!(refspec.force);
+        assert!(refspec.is_glob());
+        assert_eq!(Some("refs/remotes/origin/feature/x".to_string()), refspec.map("refs/heads/feature/x"));
+        assert_eq!(None, refspec.map("refs/tags/v1"));
+
+        let refspec: Refspec = Refspec::parse("main:refs/heads/copy").unwrap();
+        assert!(!refspec.force);
+        assert_eq!(Some("refs/heads/copy".to_string()), refspec.map("main"));
+        assert_eq!(None, Refspec::parse("main").unwrap().map("main"));
+
+        assert!(Refspec::parse("refs/heads/*:refs/remotes/origin/main").is_err());
+        assert!(Refspec::parse("refs/*/*:refs/*/*").is_err());
+    }
+}
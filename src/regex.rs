@@ -0,0 +1,84 @@
+use crate::charclass::match_class;
+
+// Basic regular expressions as `git log --grep` takes them by default:
+// `.`, `[...]` classes, `*` and the `\+` and `\?` extensions repeat the
+// previous atom, `^` and `$` anchor at the ends and a backslash makes
+// anything else literal. Groups and alternations are not supported.
+pub fn is_match(pattern: &[u8], text: &[u8], ignore_case: bool) -> bool {
+    if let Some(pattern) = pattern.strip_prefix(b"^") {
+        return match_here(pattern, text, ignore_case);
+    }
+    (0..=text.len()).any(|start| match_here(pattern, &text[start..], ignore_case))
+}
+
+fn match_here(pattern: &[u8], text: &[u8], ignore_case: bool) -> bool {
+    if pattern.is_empty() {
+        return true;
+    }
+    if pattern == b"$" {
+        return text.is_empty();
+    }
+
+    let atom_length: usize = match pattern {
+        [b'\\', _, ..] => 2,
+        [b'[', rest @ ..] => match match_class(rest, 0) {
+            Some((_, after)) => pattern.len() - after.len(),
+            None => 1,
+        },
+        _ => 1,
+    };
+    let (atom, rest): (&[u8], &[u8]) = pattern.split_at(atom_length);
+
+    // Repetitions, as few as allowed first then more while the atom
+    // keeps matching.
+    let (min, max, rest): (usize, usize, &[u8]) = match rest {
+        [b'*', rest @ ..] => (0, usize::MAX, rest),
+        [b'\\', b'+', rest @ ..] => (1, usize::MAX, rest),
+        [b'\\', b'?', rest @ ..] => (0, 1, rest),
+        _ => (1, 1, rest),
+    };
+    let mut count: usize = 0;
+    loop {
+        if count >= min && match_here(rest, &text[count..], ignore_case) {
+            return true;
+        }
+        if count >= max || !text.get(count).is_some_and(|&c| atom_matches(atom, c, ignore_case)) {
+            return false;
+        }
+        count += 1;
+    }
+}
+
+fn atom_matches(atom: &[u8], c: u8, ignore_case: bool) -> bool {
+    let candidates: [u8; 2] = match ignore_case {
+        true => [c.to_ascii_lowercase(), c.to_ascii_uppercase()],
+        false => [c, c],
+    };
+    candidates.iter().any(|&c| match atom {
+        [b'.'] => c != b'\n',
+        [b'\\', escaped] => c == *escaped,
+        [b'[', rest @ ..] if atom.len() > 1 => match_class(rest, c).is_some_and(|(matched, _)| matched),
+        [literal] => c == *literal,
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_match() {
+        assert!(is_match(b"fix", b"a fix for it", false));
+        assert!(!is_match(b"^fix", b"a fix for it", false));
+        assert!(is_match(b"^a.*it$", b"a fix for it", false));
+        assert!(!is_match(b"for$", b"a fix for it", false));
+        assert!(is_match(b"FIX", b"a fix", true));
+        assert!(is_match(b"v[0-9]\\+\\.[0-9]", b"release v12.3", false));
+        assert!(!is_match(b"v[0-9]\\+\\.[0-9]", b"release v.3", false));
+        assert!(is_match(b"colou\\?r", b"color", false));
+        assert!(is_match(b"a+b", b"a+b", false));
+        assert!(is_match(b"[[:upper:]]x*y", b"Ay", false));
+        assert!(is_match(b"", b"", false));
+    }
+}
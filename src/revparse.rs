@@ -0,0 +1,383 @@
+use std::fs;
+
+use std::path::Path;
+
+use crate::commit::Commit;
+use crate::config::Config;
+use crate::fetch::branch_upstream;
+use crate::fetch::ABBREV;
+use crate::index::Index;
+use crate::index::IndexEntry;
+use crate::log::subject;
+use crate::odb::abbreviate;
+use crate::odb::ObjectStore;
+use crate::odb::MIN_ABBREV;
+use crate::read_git_object;
+use crate::refs::dwim_ref;
+use crate::refs::list_refs;
+use crate::refs::read_ref;
+use crate::refs::read_symbolic_ref;
+use crate::regex;
+use crate::revwalk::RevWalk;
+use crate::GitError;
+use crate::GitObject;
+
+// Object a revision names, in git's revision syntax:
+// - `<rev>:<path>` for an entry of a tree, `:<path>` or `:<n>:<path>` for
+//   one of the index, `:/<regex>` for the newest commit reachable from a
+//   ref whose message matches;
+// - otherwise a name (a full or abbreviated object id, a ref found in the
+//   DWIM order, `@` for HEAD, `<branch>@{upstream}` or `@{-<n>}`) followed
+//   by any number of `~<n>`, `^<n>`, `^{<type>}`, `^{}` or `^{/<regex>}`.
+pub fn resolve_revision(git_dir: &Path, object_store: &dyn ObjectStore, revision: &str) -> Result<String, GitError> {
+    if let Some(pattern) = revision.strip_prefix(":/") {
+        let mut tips: Vec<String> = list_refs(git_dir, "refs/")?.into_iter().map(|(_, sha1_hash)| sha1_hash).collect();
+        tips.extend(read_ref(git_dir, "HEAD")?);
+        return find_by_message(object_store, &tips, pattern)?
+            .ok_or_else(|| GitError::InvalidRef(format!("no commit message matches '{pattern}'")));
+    }
+    if let Some(path) = revision.strip_prefix(':') {
+        return resolve_index_path(git_dir, path);
+    }
+    if let Some((tree_ish, path)) = revision.split_once(':') {
+        let tree: String = resolve_tree_ish(git_dir, object_store, tree_ish)?;
+        return resolve_tree_path(object_store, &tree, path)?
+            .ok_or_else(|| GitError::InvalidRef(format!("path '{path}' does not exist in '{tree_ish}'")));
+    }
+
+    let name_end: usize = revision.find(['~', '^']).unwrap_or(revision.len());
+    let (name, mut suffixes): (&str, &str) = revision.split_at(name_end);
+    let mut sha1_hash: String = resolve_name(git_dir, object_store, name)?;
+
+    let unknown = || GitError::InvalidRef(format!("unknown revision '{revision}'"));
+    while let Some(operator) = suffixes.chars().next() {
+        if !matches!(operator, '~' | '^') {
+            return Err(unknown());
+        }
+        suffixes = &suffixes[operator.len_utf8()..];
+        if operator == '^' && suffixes.starts_with('{') {
+            let end: usize = suffixes.find('}').ok_or_else(unknown)?;
+            let peel: &str = &suffixes[1..end];
+            suffixes = &suffixes[end + 1..];
+            sha1_hash = match peel.strip_prefix('/') {
+                Some(pattern) => find_by_message(object_store, &[sha1_hash], pattern)?.ok_or_else(unknown)?,
+                None => peel_to_type(object_store, &sha1_hash, peel)?.ok_or_else(unknown)?,
+            };
+            continue;
+        }
+
+        let digits: usize = suffixes.chars().take_while(char::is_ascii_digit).count();
+        let count: usize = match digits {
+            0 => 1,
+            _ => suffixes[..digits].parse().map_err(|_| unknown())?,
+        };
+        suffixes = &suffixes[digits..];
+
+        let commit: String = peel_to_type(object_store, &sha1_hash, "commit")?.ok_or_else(unknown)?;
+        sha1_hash = match operator {
+            // `^0` is the commit itself, `^<n>` its n-th parent.
+            '^' if count == 0 => commit,
+            '^' => read_commit(object_store, &commit)?.parents.get(count - 1).cloned().ok_or_else(unknown)?,
+            _ => {
+                let mut ancestor: String = commit;
+                for _ in 0..count {
+                    ancestor = read_commit(object_store, &ancestor)?.parents.first().cloned().ok_or_else(unknown)?;
+                }
+                ancestor
+            }
+        };
+    }
+
+    Ok(sha1_hash)
+}
+
+// Tree a revision names, through tags and commits.
+pub fn resolve_tree_ish(git_dir: &Path, object_store: &dyn ObjectStore, revision: &str) -> Result<String, GitError> {
+    let sha1_hash: String = resolve_revision(git_dir, object_store, revision)?;
+    peel_to_type(object_store, &sha1_hash, "tree")?
+        .ok_or_else(|| GitError::InvalidRef(format!("'{revision}' is not a tree")))
+}
+
+// Full name of the ref a revision designates, following symbolic refs,
+// like `git rev-parse --symbolic-full-name`: "HEAD" when detached, `None`
+// for object ids and revisions with suffixes.
+pub fn resolve_ref_name(git_dir: &Path, revision: &str) -> Result<Option<String>, GitError> {
+    if revision.contains(['~', '^', ':']) {
+        return Ok(None);
+    }
+
+    let Some(mut name) = ref_name(git_dir, revision)? else {
+        return Ok(None);
+    };
+    while let Some(target) = read_symbolic_ref(git_dir, &name)? {
+        name = target;
+    }
+    Ok(Some(name))
+}
+
+fn resolve_name(git_dir: &Path, object_store: &dyn ObjectStore, name: &str) -> Result<String, GitError> {
+    if name.len() == 40 && name.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(name.to_ascii_lowercase());
+    }
+    if let Some(full_name) = ref_name(git_dir, name)? {
+        return read_ref(git_dir, &full_name)?.ok_or_else(|| GitError::InvalidRef(format!("unknown revision '{name}'")));
+    }
+    if let Some(sha1_hash) = find_abbreviated(object_store, name)? {
+        return Ok(sha1_hash);
+    }
+
+    // The previous branch may have been a detached commit.
+    if let Some(previous) = name.strip_prefix("@{-").and_then(|rest| rest.strip_suffix('}')) {
+        if let Some(checkout) = previous.parse().ok().and_then(|n| previous_checkout(git_dir, n)) {
+            return resolve_name(git_dir, object_store, &checkout);
+        }
+    }
+    Err(GitError::InvalidRef(format!("unknown revision '{name}'")))
+}
+
+// Full name of the ref `name` designates, before following symbolic refs.
+fn ref_name(git_dir: &Path, name: &str) -> Result<Option<String>, GitError> {
+    if name == "@" {
+        return Ok(Some("HEAD".to_string()));
+    }
+
+    if let Some((branch, selector)) = name.split_once("@{") {
+        let Some(selector) = selector.strip_suffix('}') else {
+            return Ok(None);
+        };
+
+        if let Some(previous) = selector.strip_prefix('-').filter(|_| branch.is_empty()) {
+            let previous: usize = previous.parse().map_err(|_| GitError::InvalidRef(format!("unknown revision '{name}'")))?;
+            let Some(checkout) = previous_checkout(git_dir, previous) else {
+                return Err(GitError::InvalidRef(format!("'{name}': only {previous} checkout(s) in the reflog")));
+            };
+            return ref_name(git_dir, &checkout);
+        }
+
+        if selector.eq_ignore_ascii_case("u") || selector.eq_ignore_ascii_case("upstream") {
+            let branch: String = match branch {
+                "" | "HEAD" => match read_symbolic_ref(git_dir, "HEAD")? {
+                    Some(target) => target.strip_prefix("refs/heads/").unwrap_or(&target).to_string(),
+                    None => return Err(GitError::InvalidRef("HEAD does not point to a branch".to_string())),
+                },
+                _ => branch.to_string(),
+            };
+            let config: Config = Config::load(&git_dir.join("config"))?;
+            return match branch_upstream(&config, &branch)? {
+                Some(upstream) => Ok(Some(upstream)),
+                None => Err(GitError::InvalidRef(format!("no upstream configured for branch '{branch}'"))),
+            };
+        }
+
+        return Err(GitError::InvalidRef(format!("unsupported reflog selector '{name}'")));
+    }
+
+    Ok(dwim_ref(git_dir, name)?.map(|(full_name, _)| full_name))
+}
+
+// Object whose id starts with `prefix`, at least `MIN_ABBREV` hex digits.
+// Prefixes shared by several objects are an error listing them.
+fn find_abbreviated(object_store: &dyn ObjectStore, prefix: &str) -> Result<Option<String>, GitError> {
+    if prefix.len() < MIN_ABBREV || prefix.len() > 40 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+
+    let prefix: String = prefix.to_ascii_lowercase();
+    let candidates: Vec<String> = object_store.find_prefix(&prefix)?;
+    match &candidates[..] {
+        [] => Ok(None),
+        [sha1_hash] => Ok(Some(sha1_hash.clone())),
+        _ => Err(GitError::AmbiguousObject(ambiguity_hints(object_store, &prefix, candidates)?)),
+    }
+}
+
+// git's explanation of an ambiguous prefix: the candidates, tags first
+// then commits, trees and blobs, with the date and subject of commits and
+// the date and name of tags.
+fn ambiguity_hints(object_store: &dyn ObjectStore, prefix: &str, candidates: Vec<String>) -> Result<String, GitError> {
+    let mut described: Vec<(usize, String, String)> = Vec::new();
+    for sha1_hash in candidates {
+        let (git_type, _): (String, usize) = object_store.read_header(&sha1_hash)?;
+        let (rank, details): (usize, String) = match read_git_object(object_store, &sha1_hash)? {
+            GitObject::Tag { content } => {
+                let date: String = content.tagger.as_ref().map(|tagger| tagger.format_short_date()).unwrap_or_default();
+                (0, format!(" {date} - {}", content.name))
+            }
+            GitObject::Commit { content } => (1, format!(" {} - {}", content.author.format_short_date(), subject(&content))),
+            GitObject::Tree { .. } => (2, String::new()),
+            GitObject::Blob { .. } => (3, String::new()),
+        };
+        let abbreviated: String = abbreviate(object_store, &sha1_hash, ABBREV)?;
+        described.push((rank, sha1_hash, format!("hint:   {abbreviated} {git_type}{details}")));
+    }
+    described.sort();
+
+    let mut hints: Vec<String> = vec![
+        format!("error: short object ID {prefix} is ambiguous"),
+        "hint: The candidates are:".to_string(),
+    ];
+    hints.extend(described.into_iter().map(|(_, _, hint)| hint));
+    Ok(hints.join("\n"))
+}
+
+// Branch (or detached commit) checked out `n` checkouts ago, from the
+// "checkout: moving from <old> to <new>" entries of HEAD's reflog.
+fn previous_checkout(git_dir: &Path, n: usize) -> Option<String> {
+    let reflog: String = fs::read_to_string(git_dir.join("logs/HEAD")).ok()?;
+    reflog
+        .lines()
+        .rev()
+        .filter_map(|line| line.split_once('\t')?.1.strip_prefix("checkout: moving from "))
+        .filter_map(|moved| moved.split_once(" to ").map(|(from, _)| from.to_string()))
+        .nth(n.checked_sub(1)?)
+}
+
+// Follows tags, and commits to their tree, until an object of `git_type`
+// is reached: "" peels tags only and "object" anything. `None` when the
+// object cannot be peeled that way.
+fn peel_to_type(object_store: &dyn ObjectStore, sha1_hash: &str, git_type: &str) -> Result<Option<String>, GitError> {
+    let mut sha1_hash: String = sha1_hash.to_string();
+    loop {
+        let git_object: GitObject = read_git_object(object_store, &sha1_hash)?;
+        if git_type.eq("object") || git_object.get_type().eq(git_type) {
+            return Ok(Some(sha1_hash));
+        }
+
+        sha1_hash = match git_object {
+            GitObject::Tag { content } => content.object,
+            GitObject::Commit { content } if git_type.eq("tree") => content.tree,
+            _ if git_type.is_empty() => return Ok(Some(sha1_hash)),
+            _ => return Ok(None),
+        };
+    }
+}
+
+fn read_commit(object_store: &dyn ObjectStore, sha1_hash: &str) -> Result<Commit, GitError> {
+    match read_git_object(object_store, sha1_hash)? {
+        GitObject::Commit { content } => Ok(*content),
+        _ => Err(GitError::InvalidCommit(format!("{sha1_hash} is not a commit"))),
+    }
+}
+
+// Newest commit reachable from `tips` with a message line matching
+// `pattern`.
+fn find_by_message(object_store: &dyn ObjectStore, tips: &[String], pattern: &str) -> Result<Option<String>, GitError> {
+    let mut walk: RevWalk = RevWalk::new(object_store);
+    for tip in tips {
+        if let Some(commit) = peel_to_type(object_store, tip, "commit")? {
+            walk.push(&commit)?;
+        }
+    }
+
+    while let Some((sha1_hash, commit)) = walk.next_commit()? {
+        if commit.message().split(|&b| b == b'\n').any(|line| regex::is_match(pattern.as_bytes(), line, false)) {
+            return Ok(Some(sha1_hash));
+        }
+    }
+    Ok(None)
+}
+
+// Entry of a tree at a slash separated path, the tree itself for "".
+fn resolve_tree_path(object_store: &dyn ObjectStore, tree: &str, path: &str) -> Result<Option<String>, GitError> {
+    let mut sha1_hash: String = tree.to_string();
+    for component in path.split('/').filter(|component| !component.is_empty()) {
+        let GitObject::Tree { content } = read_git_object(object_store, &sha1_hash)? else {
+            return Ok(None);
+        };
+        match content.into_iter().find(|tree_entry| tree_entry.name.eq(component)) {
+            Some(tree_entry) => sha1_hash = tree_entry.sha1_hash,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(sha1_hash))
+}
+
+// Blob staged at `path`, written `<path>` or `<stage>:<path>`.
+fn resolve_index_path(git_dir: &Path, path: &str) -> Result<String, GitError> {
+    let (stage, path): (u8, &str) = match path.split_once(':') {
+        Some((stage @ ("0" | "1" | "2" | "3"), path)) => (stage.parse().unwrap_or(0), path),
+        _ => (0, path),
+    };
+
+    let index: Index = Index::load(&git_dir.join("index"))?;
+    let entry: Option<&IndexEntry> = index.entries.iter().find(|entry| entry.path.eq(path) && entry.stage == stage);
+    match entry {
+        Some(entry) => Ok(entry.sha1_hash.clone()),
+        None if stage == 0 => Err(GitError::InvalidRef(format!("path '{path}' does not exist in the index"))),
+        None => Err(GitError::InvalidRef(format!("path '{path}' is not at stage {stage} in the index"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use crate::open_object_store_at;
+    use crate::test_support::git;
+    use crate::test_support::TempRepo;
+
+    #[test]
+    fn test_resolve_revision_matches_git() {
+        let repo: TempRepo = TempRepo::new("revparse");
+        fs::create_dir_all(repo.join("src")).unwrap();
+        git(&repo, &["init", "-q", "-b", "main"]);
+        fs::write(repo.join("src/lib.rs"), "one\n").unwrap();
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-q", "-m", "first"]);
+        git(&repo, &["tag", "-a", "v1", "-m", "version 1"]);
+        git(&repo, &["checkout", "-q", "-b", "side"]);
+        fs::write(repo.join("src/lib.rs"), "side\n").unwrap();
+        git(&repo, &["commit", "-q", "-am", "side work"]);
+        git(&repo, &["checkout", "-q", "main"]);
+        fs::write(repo.join("README"), "readme\n").unwrap();
+        git(&repo, &["add", "README"]);
+        git(&repo, &["commit", "-q", "-m", "second\n\nwith a body"]);
+        git(&repo, &["merge", "-q", "--no-ff", "side", "-m", "merge side"]);
+        git(&repo, &["config", "branch.main.remote", "."]);
+        git(&repo, &["config", "branch.main.merge", "refs/heads/side"]);
+
+        let git_dir: PathBuf = repo.join(".git");
+        let object_store: Box<dyn ObjectStore> = open_object_store_at(&git_dir.join("objects"));
+        let head: String = git(&repo, &["rev-parse", "HEAD"]);
+        for revision in [
+            "HEAD",
+            "@",
+            "main",
+            "heads/side",
+            "v1",
+            "v1^{}",
+            "v1^{tree}",
+            "HEAD^",
+            "HEAD^2",
+            "HEAD^0",
+            "HEAD~2",
+            "HEAD^2^~0",
+            "HEAD^{tree}",
+            "HEAD:src",
+            "HEAD:src/lib.rs",
+            "side:src/lib.rs",
+            ":README",
+            ":/side",
+            "HEAD^{/second}",
+            "@{upstream}",
+            "main@{u}",
+            "@{-1}",
+            &head[..7],
+        ] {
+            assert_eq!(
+                git(&repo, &["rev-parse", revision]),
+                resolve_revision(&git_dir, object_store.as_ref(), revision).unwrap(),
+                "{revision}"
+            );
+        }
+        for revision in ["HEAD^3", "HEAD~5", "HEAD:missing", "nothing", "side@{u}", "HEAD:README^{tree}", "HEAD~é", "HEAD~1x"] {
+            assert!(resolve_revision(&git_dir, object_store.as_ref(), revision).is_err(), "{revision}");
+        }
+
+        assert_eq!(Some("refs/heads/main".to_string()), resolve_ref_name(&git_dir, "HEAD").unwrap());
+        assert_eq!(Some("refs/heads/side".to_string()), resolve_ref_name(&git_dir, "@{u}").unwrap());
+        assert_eq!(None, resolve_ref_name(&git_dir, "HEAD~1").unwrap());
+    }
+}
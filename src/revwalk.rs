@@ -0,0 +1,365 @@
+use std::cmp::Ordering;
+
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+use crate::commit::Commit;
+use crate::odb::ObjectStore;
+use crate::read_git_object;
+use crate::GitError;
+use crate::GitObject;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RevSort {
+    // Newest committer date first, as `git log` walks by default.
+    #[default]
+    Date,
+    // Same, but never showing a parent before all its children
+    // (`--date-order`).
+    DateOrder,
+    // Children before parents, keeping lines of history together
+    // (`--topo-order`).
+    Topo,
+}
+
+// A commit waiting in the walk queue, newest first and in insertion order
+// for equal dates.
+#[derive(Debug, PartialEq, Eq)]
+struct Pending {
+    date: i64,
+    order: usize,
+    sha1_hash: String,
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.date.cmp(&other.date).then_with(|| other.order.cmp(&self.order))
+    }
+}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Walks commits reachable from the pushed tips but not from the hidden
+// ones, like `git rev-list <tips> ^<hidden>`. Without hidden commits and
+// in the default order commits stream out as the queue is walked;
+// otherwise the walk is done in full first, so that commits hidden
+// through an older path are known before anything is returned.
+pub struct RevWalk<'a> {
+    object_store: &'a dyn ObjectStore,
+    sort: RevSort,
+    first_parent: bool,
+    commits: HashMap<String, Commit>,
+    seen: HashSet<String>,
+    uninteresting: HashSet<String>,
+    queue: BinaryHeap<Pending>,
+    inserted: usize,
+    // Set once the walk is done in full.
+    prepared: Option<VecDeque<String>>,
+}
+
+impl<'a> RevWalk<'a> {
+    pub fn new(object_store: &'a dyn ObjectStore) -> Self {
+        Self {
+            object_store,
+            sort: RevSort::default(),
+            first_parent: false,
+            commits: HashMap::new(),
+            seen: HashSet::new(),
+            uninteresting: HashSet::new(),
+            queue: BinaryHeap::new(),
+            inserted: 0,
+            prepared: None,
+        }
+    }
+
+    pub fn set_sort(&mut self, sort: RevSort) {
+        self.sort = sort;
+    }
+
+    // Follow only the first parent of merges (`--first-parent`).
+    pub fn set_first_parent(&mut self, first_parent: bool) {
+        self.first_parent = first_parent;
+    }
+
+    // Walks from `sha1_hash`, a commit or a tag pointing to one.
+    pub fn push(&mut self, sha1_hash: &str) -> Result<(), GitError> {
+        let sha1_hash: String = self.peel_to_commit(sha1_hash)?;
+        self.insert(&sha1_hash)
+    }
+
+    // Leaves out `sha1_hash` and everything it reaches.
+    pub fn hide(&mut self, sha1_hash: &str) -> Result<(), GitError> {
+        let sha1_hash: String = self.peel_to_commit(sha1_hash)?;
+        self.mark_uninteresting(&sha1_hash);
+        self.insert(&sha1_hash)
+    }
+
+    fn peel_to_commit(&self, sha1_hash: &str) -> Result<String, GitError> {
+        let mut sha1_hash: String = sha1_hash.to_string();
+        loop {
+            match read_git_object(self.object_store, &sha1_hash)? {
+                GitObject::Commit { .. } => return Ok(sha1_hash),
+                GitObject::Tag { content } => sha1_hash = content.object,
+                _ => return Err(GitError::InvalidCommit(format!("{sha1_hash} is not a commit"))),
+            }
+        }
+    }
+
+    fn load(&mut self, sha1_hash: &str) -> Result<&Commit, GitError> {
+        if !self.commits.contains_key(sha1_hash) {
+            let commit: Commit = match read_git_object(self.object_store, sha1_hash)? {
+                GitObject::Commit { content } => *content,
+                _ => return Err(GitError::InvalidCommit(format!("{sha1_hash} is not a commit"))),
+            };
+            self.commits.insert(sha1_hash.to_string(), commit);
+        }
+        Ok(&self.commits[sha1_hash])
+    }
+
+    fn insert(&mut self, sha1_hash: &str) -> Result<(), GitError> {
+        if !self.seen.insert(sha1_hash.to_string()) {
+            return Ok(());
+        }
+        let date: i64 = self.load(sha1_hash)?.committer.timestamp;
+        self.queue.push(Pending { date, order: self.inserted, sha1_hash: sha1_hash.to_string() });
+        self.inserted += 1;
+        Ok(())
+    }
+
+    // Marks a commit uninteresting, with the ancestors already walked
+    // through it.
+    fn mark_uninteresting(&mut self, sha1_hash: &str) {
+        let mut pending: Vec<String> = vec![sha1_hash.to_string()];
+        while let Some(sha1_hash) = pending.pop() {
+            if !self.uninteresting.insert(sha1_hash.clone()) {
+                continue;
+            }
+            if let Some(commit) = self.commits.get(&sha1_hash) {
+                pending.extend(commit.parents.iter().filter(|parent| self.seen.contains(*parent)).cloned());
+            }
+        }
+    }
+
+    // Takes the newest commit of the queue and queues its parents:
+    // all of them for uninteresting commits, so that everything they
+    // reach gets hidden, only the first with `first_parent`.
+    fn step(&mut self) -> Result<Option<String>, GitError> {
+        let Some(Pending { sha1_hash, .. }) = self.queue.pop() else {
+            return Ok(None);
+        };
+
+        let uninteresting: bool = self.uninteresting.contains(&sha1_hash);
+        let parents: Vec<String> = self.commits[&sha1_hash].parents.clone();
+        let followed: usize = if self.first_parent && !uninteresting { 1 } else { parents.len() };
+        for parent in parents.iter().take(followed) {
+            if uninteresting {
+                self.mark_uninteresting(parent);
+            }
+            self.insert(parent)?;
+        }
+        Ok(Some(sha1_hash))
+    }
+
+    fn everything_uninteresting(&self) -> bool {
+        self.queue.iter().all(|pending| self.uninteresting.contains(&pending.sha1_hash))
+    }
+
+    fn prepare(&mut self) -> Result<VecDeque<String>, GitError> {
+        let mut list: Vec<String> = Vec::new();
+        while !self.everything_uninteresting() {
+            let Some(sha1_hash) = self.step()? else {
+                break;
+            };
+            list.push(sha1_hash);
+        }
+        list.retain(|sha1_hash| !self.uninteresting.contains(sha1_hash));
+
+        match self.sort {
+            RevSort::Date => Ok(list.into()),
+            RevSort::DateOrder | RevSort::Topo => Ok(self.sort_topologically(list).into()),
+        }
+    }
+
+    // git's topological sort: a commit is emitted once all its children
+    // in the list have been. Ready commits are taken newest first for
+    // `DateOrder`, last ready first for `Topo` so that a line of history
+    // is finished before going on with another.
+    fn sort_topologically(&self, list: Vec<String>) -> Vec<String> {
+        let mut indegree: HashMap<&str, usize> = list.iter().map(|sha1_hash| (sha1_hash.as_str(), 1)).collect();
+        for sha1_hash in &list {
+            for parent in &self.commits[sha1_hash].parents {
+                if let Some(count) = indegree.get_mut(parent.as_str()) {
+                    *count += 1;
+                }
+            }
+        }
+
+        let date = |sha1_hash: &str| self.commits[sha1_hash].committer.timestamp;
+        let mut ready: Vec<(&str, usize)> = Vec::new();
+        let mut inserted: usize = 0;
+        for sha1_hash in list.iter().filter(|sha1_hash| indegree[sha1_hash.as_str()] == 1) {
+            ready.push((sha1_hash, inserted));
+            inserted += 1;
+        }
+        if self.sort == RevSort::Topo {
+            ready.reverse();
+        }
+
+        let mut sorted: Vec<String> = Vec::with_capacity(list.len());
+        loop {
+            let next: Option<usize> = match self.sort {
+                RevSort::Topo => ready.len().checked_sub(1),
+                _ => (0..ready.len()).max_by(|&a, &b| {
+                    let (a, b): ((&str, usize), (&str, usize)) = (ready[a], ready[b]);
+                    date(a.0).cmp(&date(b.0)).then_with(|| b.1.cmp(&a.1))
+                }),
+            };
+            let Some(next) = next else {
+                break;
+            };
+            let (sha1_hash, _): (&str, usize) = ready.remove(next);
+
+            for parent in &self.commits[sha1_hash].parents {
+                let Some(count) = indegree.get_mut(parent.as_str()) else {
+                    continue;
+                };
+                if *count == 0 {
+                    continue;
+                }
+                *count -= 1;
+                if *count == 1 {
+                    ready.push((parent, inserted));
+                    inserted += 1;
+                }
+            }
+            indegree.insert(sha1_hash, 0);
+            sorted.push(sha1_hash.to_string());
+        }
+        sorted
+    }
+
+    // Next commit of the walk, `None` once it is over.
+    pub fn next_commit(&mut self) -> Result<Option<(String, Commit)>, GitError> {
+        let limited: bool = !self.uninteresting.is_empty() || self.sort != RevSort::Date;
+        if limited && self.prepared.is_none() {
+            self.prepared = Some(self.prepare()?);
+        }
+        let next: Option<String> = match &mut self.prepared {
+            Some(prepared) => prepared.pop_front(),
+            None => self.step()?,
+        };
+        Ok(next.map(|sha1_hash| {
+            let commit: Commit = self.commits[&sha1_hash].clone();
+            (sha1_hash, commit)
+        }))
+    }
+}
+
+// Best common ancestors of two commits: the commits both reach that are
+// not the parent of another such commit, like `git merge-base --all`.
+pub fn merge_bases(object_store: &dyn ObjectStore, a: &str, b: &str) -> Result<Vec<String>, GitError> {
+    let reachable = |tip: &str| -> Result<HashMap<String, Vec<String>>, GitError> {
+        let mut commits: HashMap<String, Vec<String>> = HashMap::new();
+        let mut pending: Vec<String> = vec![tip.to_string()];
+        while let Some(sha1_hash) = pending.pop() {
+            if commits.contains_key(&sha1_hash) {
+                continue;
+            }
+            let parents: Vec<String> = match read_git_object(object_store, &sha1_hash)? {
+                GitObject::Commit { content } => content.parents,
+                _ => return Err(GitError::InvalidCommit(format!("{sha1_hash} is not a commit"))),
+            };
+            pending.extend(parents.iter().cloned());
+            commits.insert(sha1_hash, parents);
+        }
+        Ok(commits)
+    };
+
+    let from_a: HashMap<String, Vec<String>> = reachable(a)?;
+    let from_b: HashMap<String, Vec<String>> = reachable(b)?;
+    let common: Vec<&String> = from_a.keys().filter(|sha1_hash| from_b.contains_key(*sha1_hash)).collect();
+    let parents_of_common: HashSet<&String> = common.iter().flat_map(|sha1_hash| &from_a[*sha1_hash]).collect();
+
+    let mut bases: Vec<String> = common
+        .into_iter()
+        .filter(|sha1_hash| !parents_of_common.contains(sha1_hash))
+        .cloned()
+        .collect();
+    bases.sort();
+    Ok(bases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use crate::open_object_store_at;
+    use crate::test_support::git_output;
+    use crate::test_support::TempRepo;
+
+    fn git(dir: &Path, args: &[&str], date: &str) -> String {
+        String::from_utf8(git_output(dir, args, Some(date))).unwrap()
+    }
+
+    fn walk(object_store: &dyn ObjectStore, tips: &[&str], hidden: &[&str], sort: RevSort, first_parent: bool) -> String {
+        let mut walk: RevWalk = RevWalk::new(object_store);
+        walk.set_sort(sort);
+        walk.set_first_parent(first_parent);
+        for tip in tips {
+            walk.push(tip).unwrap();
+        }
+        for hidden in hidden {
+            walk.hide(hidden).unwrap();
+        }
+        let mut listed: String = String::new();
+        while let Some((sha1_hash, _)) = walk.next_commit().unwrap() {
+            listed.push_str(&format!("{sha1_hash}\n"));
+        }
+        listed
+    }
+
+    #[test]
+    fn test_revwalk_matches_rev_list() {
+        let repo: TempRepo = TempRepo::new("revwalk");
+        git(&repo, &["init", "-q", "-b", "main"], "");
+        let commit = |message: &str, date: &str| {
+            git(&repo, &["commit", "-q", "--allow-empty", "-m", message], date);
+        };
+        commit("one", "1700000000 +0000");
+        commit("two", "1700000100 +0000");
+        git(&repo, &["checkout", "-q", "-b", "side"], "");
+        // Older than its parent, which only topological orders account for.
+        commit("side one", "1700000050 +0000");
+        commit("side two", "1700000300 +0000");
+        git(&repo, &["checkout", "-q", "main"], "");
+        commit("three", "1700000200 +0000");
+        git(&repo, &["merge", "-q", "--no-ff", "side", "-m", "merge"], "1700000400 +0000");
+        git(&repo, &["checkout", "-q", "side"], "");
+        commit("side three", "1700000500 +0000");
+        git(&repo, &["checkout", "-q", "main"], "");
+        commit("four", "1700000600 +0000");
+
+        let main: String = git(&repo, &["rev-parse", "main"], "").trim().to_string();
+        let side: String = git(&repo, &["rev-parse", "side"], "").trim().to_string();
+        let object_store: Box<dyn ObjectStore> = open_object_store_at(&repo.join(".git/objects"));
+        let object_store: &dyn ObjectStore = object_store.as_ref();
+
+        assert_eq!(git(&repo, &["rev-list", "main"], ""), walk(object_store, &[&main], &[], RevSort::Date, false));
+        assert_eq!(git(&repo, &["rev-list", "--date-order", "main"], ""), walk(object_store, &[&main], &[], RevSort::DateOrder, false));
+        assert_eq!(git(&repo, &["rev-list", "--topo-order", "main", "side"], ""), walk(object_store, &[&main, &side], &[], RevSort::Topo, false));
+        assert_eq!(git(&repo, &["rev-list", "--first-parent", "main"], ""), walk(object_store, &[&main], &[], RevSort::Date, true));
+        assert_eq!(git(&repo, &["rev-list", "main..side"], ""), walk(object_store, &[&side], &[&main], RevSort::Date, false));
+        assert_eq!(git(&repo, &["rev-list", "side..main"], ""), walk(object_store, &[&main], &[&side], RevSort::Date, false));
+
+        let merge_base: String = git(&repo, &["merge-base", "--all", "main", "side"], "");
+        assert_eq!(merge_base, format!("{}\n", merge_bases(object_store, &main, &side).unwrap().join("\n")));
+    }
+}
@@ -0,0 +1,275 @@
+use std::env;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::timezone;
+use crate::GitError;
+
+// Identity line used by commits (author, committer) and tags (tagger):
+// `Name <email> 1700000000 +0100`. Name and email are kept as bytes, in
+// whatever encoding the object uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: Vec<u8>,
+    pub email: Vec<u8>,
+    pub timestamp: i64,
+    pub timezone: String,
+}
+
+impl Signature {
+    // Only the single spaces around `<email>` are separators, so that the
+    // line is written back byte for byte.
+    pub fn from_line(line: &[u8]) -> Result<Self, GitError> {
+        let invalid = || GitError::InvalidSignature(String::from_utf8_lossy(line).to_string());
+
+        let Some(email_start): Option<usize> = line.iter().position(|&b| b == b'<') else {
+            return Err(invalid());
+        };
+        let Some(email_end): Option<usize> = line.iter().rposition(|&b| b == b'>') else {
+            return Err(invalid());
+        };
+        if email_end < email_start {
+            return Err(invalid());
+        }
+
+        let name: &[u8] = &line[..email_start];
+        let name: &[u8] = name.strip_suffix(b" ").unwrap_or(name);
+        let email: &[u8] = &line[email_start + 1..email_end];
+
+        let Ok(date): Result<&str, _> = std::str::from_utf8(&line[email_end + 1..]) else {
+            return Err(invalid());
+        };
+        let Some((timestamp, timezone)): Option<(&str, &str)> = date.strip_prefix(' ').and_then(|date| date.split_once(' ')) else {
+            return Err(invalid());
+        };
+        let Ok(timestamp): Result<i64, _> = timestamp.parse::<i64>() else {
+            return Err(invalid());
+        };
+
+        Ok(Self {
+            name: name.to_vec(),
+            email: email.to_vec(),
+            timestamp,
+            timezone: timezone.to_string(),
+        })
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(&self.name);
+        bytes.extend_from_slice(b" <");
+        bytes.extend_from_slice(&self.email);
+        bytes.extend_from_slice(format!("> {} {}", self.timestamp, self.timezone).as_bytes());
+        bytes
+    }
+
+    // Builds the identity from `GIT_<ROLE>_NAME`, `GIT_<ROLE>_EMAIL` and
+    // `GIT_<ROLE>_DATE`, `role` being "AUTHOR" or "COMMITTER".
+    pub fn from_env(role: &str) -> Result<Self, GitError> {
+        let name: String = match env::var(format!("GIT_{role}_NAME")) {
+            Ok(name) => name,
+            Err(_) => env::var("USER").unwrap_or_else(|_| "unknown".to_string()),
+        };
+
+        let email: String = match env::var(format!("GIT_{role}_EMAIL")) {
+            Ok(email) => email,
+            Err(_) => format!("{name}@localhost"),
+        };
+
+        let (timestamp, timezone): (i64, String) = match env::var(format!("GIT_{role}_DATE")) {
+            Ok(date) => parse_date(&date)?,
+            Err(_) => {
+                let timestamp: i64 = now_timestamp();
+                (timestamp, format_timezone(timezone::local_offset_minutes(timestamp)))
+            }
+        };
+
+        Ok(Self {
+            name: name.into_bytes(),
+            email: email.into_bytes(),
+            timestamp,
+            timezone,
+        })
+    }
+
+    // Offset from UTC in minutes, `+0130` gives 90.
+    pub fn timezone_offset_minutes(&self) -> i64 {
+        let (sign, digits): (i64, &str) = match self.timezone.strip_prefix('-') {
+            Some(digits) => (-1, digits),
+            None => (1, self.timezone.trim_start_matches('+')),
+        };
+
+        let Ok(value): Result<i64, _> = digits.parse::<i64>() else {
+            return 0;
+        };
+
+        sign * ((value / 100) * 60 + value % 100)
+    }
+
+    // Date in git's default format, in the signature's own timezone:
+    // `Tue Nov 14 20:43:20 2023 -0130`.
+    pub fn format_date(&self) -> String {
+        const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+        const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+        let local: i64 = self.timestamp + self.timezone_offset_minutes() * 60;
+        let (days, seconds): (i64, i64) = (local.div_euclid(86400), local.rem_euclid(86400));
+        let (year, month, day): (i64, i64, i64) = civil_from_days(days);
+        format!(
+            "{} {} {day} {:02}:{:02}:{:02} {year} {}",
+            WEEKDAYS[days.rem_euclid(7) as usize],
+            MONTHS[month as usize - 1],
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            self.timezone
+        )
+    }
+
+    // Day in the signature's own timezone, as `--date=short` shows it:
+    // `2023-11-14`.
+    pub fn format_short_date(&self) -> String {
+        let local: i64 = self.timestamp + self.timezone_offset_minutes() * 60;
+        let (year, month, day): (i64, i64, i64) = civil_from_days(local.div_euclid(86400));
+        format!("{year:04}-{month:02}-{day:02}")
+    }
+}
+
+fn now_timestamp() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(_) => 0,
+    }
+}
+
+// Accepts git's internal format (`1700000000 +0100`, optionally prefixed
+// with '@') and ISO 8601 (`2023-11-14T22:13:20+01:00`, `2023-11-14 22:13:20 +0100`).
+pub fn parse_date(date: &str) -> Result<(i64, String), GitError> {
+    let date: &str = date.trim();
+    let invalid = || GitError::InvalidDate(date.to_string());
+
+    let internal: &str = date.strip_prefix('@').unwrap_or(date);
+    let (timestamp, timezone): (&str, &str) = internal.split_once(' ').unwrap_or((internal, "+0000"));
+    if let Ok(timestamp) = timestamp.parse::<i64>() {
+        return Ok((timestamp, normalize_timezone(timezone).ok_or_else(invalid)?));
+    }
+
+    // Fields are sliced by byte position, which only ASCII allows.
+    if date.len() < 19 || !date.is_char_boundary(19) {
+        return Err(invalid());
+    }
+    let (datetime, timezone): (&str, &str) = date.split_at(19);
+    let bytes: &[u8] = datetime.as_bytes();
+    if !datetime.is_ascii() || bytes[4] != b'-' || bytes[7] != b'-' || !matches!(bytes[10], b'T' | b' ') {
+        return Err(invalid());
+    }
+
+    let field = |range: std::ops::Range<usize>| datetime[range].parse::<i64>().map_err(|_| invalid());
+    let days: i64 = days_from_civil(field(0..4)?, field(5..7)?, field(8..10)?);
+    let seconds: i64 = field(11..13)? * 3600 + field(14..16)? * 60 + field(17..19)?;
+
+    let timezone: String = normalize_timezone(timezone.trim()).ok_or_else(invalid)?;
+    let offset: Signature = Signature {
+        name: Vec::new(),
+        email: Vec::new(),
+        timestamp: 0,
+        timezone: timezone.clone(),
+    };
+
+    let timestamp: i64 = days * 86400 + seconds - offset.timezone_offset_minutes() * 60;
+    Ok((timestamp, timezone))
+}
+
+// Offset in minutes as `+hhmm`, 90 gives `+0130`.
+fn format_timezone(minutes: i64) -> String {
+    let sign: char = if minutes < 0 { '-' } else { '+' };
+    format!("{sign}{:02}{:02}", minutes.abs() / 60, minutes.abs() % 60)
+}
+
+// `Z`, `+01:00`, `+0100` and `` all map to git's `+hhmm` form.
+fn normalize_timezone(timezone: &str) -> Option<String> {
+    if timezone.is_empty() || timezone.eq("Z") {
+        return Some("+0000".to_string());
+    }
+
+    let (sign, digits): (char, String) = match timezone.split_at(1) {
+        ("+", rest) => ('+', rest.replace(':', "")),
+        ("-", rest) => ('-', rest.replace(':', "")),
+        _ => return None,
+    };
+
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(format!("{sign}{digits}"))
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year: i64 = if month <= 2 { year - 1 } else { year };
+    let era: i64 = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era: i64 = year - era * 400;
+    let month_index: i64 = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year: i64 = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era: i64 = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// Year, month and day of a count of days since 1970-01-01, the inverse of
+// `days_from_civil`.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days: i64 = days + 719468;
+    let era: i64 = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era: i64 = days - era * 146097;
+    let year_of_era: i64 = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year: i64 = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index: i64 = (5 * day_of_year + 2) / 153;
+    let day: i64 = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month: i64 = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year: i64 = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_round_trip() {
+        let line: &[u8] = b"A U Thor <author@example.com> 1700000000 -0130";
+        let signature: Signature = Signature::from_line(line).unwrap();
+
+        assert_eq!(b"A U Thor", &signature.name[..]);
+        assert_eq!(b"author@example.com", &signature.email[..]);
+        assert_eq!(1700000000, signature.timestamp);
+        assert_eq!(-90, signature.timezone_offset_minutes());
+        assert_eq!(line, &signature.as_bytes()[..]);
+        assert_eq!("Tue Nov 14 20:43:20 2023 -0130", signature.format_date());
+        assert_eq!("2023-11-14", signature.format_short_date());
+
+        // Latin-1 names and stray spaces are kept as written.
+        let line: &[u8] = b"Ren\xe9  <rene@example.com> 1700000000 +0000";
+        assert_eq!(line, &Signature::from_line(line).unwrap().as_bytes()[..]);
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let expected: (i64, String) = (1700000000, "+0100".to_string());
+
+        assert_eq!(expected, parse_date("1700000000 +0100").unwrap());
+        assert_eq!(expected, parse_date("@1700000000 +0100").unwrap());
+        assert_eq!(expected, parse_date("2023-11-14T23:13:20+01:00").unwrap());
+        assert_eq!(expected, parse_date("2023-11-14 23:13:20 +0100").unwrap());
+        assert!(parse_date("yesterday").is_err());
+        assert!(parse_date("2023-11-14 23:13:2é +0100").is_err());
+        assert!(parse_date("2023-11-14 23:1é:20 +0100").is_err());
+    }
+
+    #[test]
+    fn test_format_timezone() {
+        assert_eq!("+0000", format_timezone(0));
+        assert_eq!("+0530", format_timezone(330));
+        assert_eq!("-0130", format_timezone(-90));
+    }
+}
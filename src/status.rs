@@ -0,0 +1,732 @@
+use std::cmp::Reverse;
+
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::fetch::branch_upstream;
+use crate::fetch::shorten_ref;
+use crate::fetch::ABBREV;
+use crate::hash_worktree_blob;
+use crate::ignore::IgnoreRules;
+use crate::index::Index;
+use crate::index::IndexEntry;
+use crate::index::StatData;
+use crate::odb::ObjectStore;
+use crate::open_object_store_at;
+use crate::pathspec::Pathspec;
+use crate::read_git_object;
+use crate::refs::read_ref;
+use crate::refs::read_symbolic_ref;
+use crate::worktree::has_entries_below;
+use crate::worktree::index_mtime;
+use crate::worktree::is_stat_clean;
+use crate::worktree::list_files;
+use crate::worktree::WorktreeFile;
+use crate::worktree::GITLINK_MODE;
+use crate::EntryMode;
+use crate::GitError;
+use crate::GitObject;
+
+const ZERO_ID: &str = "0000000000000000000000000000000000000000";
+
+// Labels of the long format are padded to the longest one, "typechange:"
+// and "deleted by them:", plus a space.
+const CHANGE_LABEL_WIDTH: usize = 12;
+const UNMERGED_LABEL_WIDTH: usize = 17;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UntrackedFiles {
+    No,
+    // Directories holding only untracked files are shown as `dir/`.
+    #[default]
+    Normal,
+    All,
+}
+
+// A tracked path that changed between HEAD and the index, or between the
+// index and the worktree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusEntry {
+    pub path: String,
+    // `XY` of the short format, ' ' when unchanged.
+    pub staged: char,
+    pub unstaged: char,
+    // Mode and object id in HEAD and in the index, mode 0 and the zero id
+    // when missing.
+    pub head: (u32, String),
+    pub index: (u32, String),
+    pub worktree_mode: u32,
+    // Stages 1 to 3 of an unmerged path.
+    pub conflict: Option<[(u32, String); 3]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upstream {
+    // Like `origin/main`.
+    pub name: String,
+    // Commits ahead and behind, `None` when the upstream ref is gone.
+    pub ahead_behind: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    // Current branch, `None` when HEAD is detached.
+    pub branch: Option<String>,
+    // Commit HEAD points to, `None` on an unborn branch.
+    pub head: Option<String>,
+    pub upstream: Option<Upstream>,
+    // A merge waits to be concluded (MERGE_HEAD exists).
+    pub merging: bool,
+    // Sorted by path.
+    pub entries: Vec<StatusEntry>,
+    pub untracked: Vec<String>,
+}
+
+impl Status {
+    fn has_staged_changes(&self) -> bool {
+        self.entries.iter().any(|entry| entry.conflict.is_none() && entry.staged != ' ')
+    }
+}
+
+// Compares HEAD's tree with the index, and the index with the worktree.
+// Files whose stat data matches the index are not read; the others are
+// hashed, and the index is rewritten with fresh stat data for those that
+// turn out unchanged so the next run can skip them.
+pub fn status(git_dir: &Path, worktree: &Path, pathspec: &Pathspec, untracked: UntrackedFiles) -> Result<Status, GitError> {
+    let object_store: Box<dyn ObjectStore> = open_object_store_at(&git_dir.join("objects"));
+    let index_path: PathBuf = git_dir.join("index");
+    let mut index: Index = Index::load(&index_path)?;
+    let index_mtime: Option<(u32, u32)> = index_mtime(&index_path);
+
+    let branch: Option<String> = read_symbolic_ref(git_dir, "HEAD")?
+        .and_then(|target| target.strip_prefix("refs/heads/").map(str::to_string));
+    let head: Option<String> = read_ref(git_dir, "HEAD")?;
+    let upstream: Option<Upstream> = match &branch {
+        Some(branch) => read_upstream(git_dir, object_store.as_ref(), branch, head.as_deref())?,
+        None => None,
+    };
+
+    let head_entries: Vec<IndexEntry> = match &head {
+        Some(head) => match read_git_object(object_store.as_ref(), head)? {
+            GitObject::Commit { content } => Index::from_tree(object_store.as_ref(), &content.tree)?.entries,
+            _ => return Err(GitError::InvalidRef(format!("HEAD is not a commit: {head}"))),
+        },
+        None => Vec::new(),
+    };
+    let head_entries: HashMap<&str, &IndexEntry> = head_entries.iter().map(|entry| (entry.path.as_str(), entry)).collect();
+
+    let mut ignore: IgnoreRules = IgnoreRules::new(worktree, git_dir);
+    let files: Vec<WorktreeFile> = list_files(worktree, &index, Some(&mut ignore), pathspec)?;
+    let files_by_path: HashMap<&str, &WorktreeFile> = files.iter().map(|file| (file.path.as_str(), file)).collect();
+
+    let mut entries: Vec<StatusEntry> = Vec::new();
+    let mut refreshed: Vec<(usize, StatData)> = Vec::new();
+    let mut position: usize = 0;
+    while position < index.entries.len() {
+        let entry: &IndexEntry = &index.entries[position];
+        let path: &str = &entry.path;
+        let stages: usize = index.entries[position..].iter().take_while(|other| other.path.eq(path)).count();
+        let head_entry: Option<&IndexEntry> = head_entries.get(path).copied();
+        let file: Option<&WorktreeFile> = files_by_path.get(path).copied();
+
+        if !pathspec.matches(path) {
+            position += stages;
+            continue;
+        }
+
+        if entry.stage != 0 {
+            let mut conflict: [(u32, String); 3] = std::array::from_fn(|_| (0, ZERO_ID.to_string()));
+            for stage_entry in &index.entries[position..position + stages] {
+                conflict[stage_entry.stage as usize - 1] = (stage_entry.mode, stage_entry.sha1_hash.clone());
+            }
+            let (staged, unstaged): (char, char) = unmerged_status(&conflict);
+            entries.push(StatusEntry {
+                path: path.to_string(),
+                staged,
+                unstaged,
+                head: mode_and_id(head_entry),
+                index: (0, ZERO_ID.to_string()),
+                worktree_mode: file.map_or(0, |file| EntryMode::from_metadata(&file.metadata).as_index_mode()),
+                conflict: Some(conflict),
+            });
+            position += stages;
+            continue;
+        }
+
+        let staged: char = match head_entry {
+            _ if entry.intent_to_add => ' ',
+            None => 'A',
+            Some(head_entry) if is_type_change(head_entry.mode, entry.mode) => 'T',
+            Some(head_entry) if head_entry.mode != entry.mode || !head_entry.sha1_hash.eq(&entry.sha1_hash) => 'M',
+            Some(_) => ' ',
+        };
+
+        let (unstaged, worktree_mode): (char, u32) = match file {
+            _ if entry.mode == GITLINK_MODE => match worktree.join(path).is_dir() {
+                true => (' ', GITLINK_MODE),
+                false => ('D', 0),
+            },
+            None => ('D', 0),
+            Some(_) if entry.intent_to_add => ('A', entry.mode),
+            Some(file) if is_stat_clean(entry, &file.metadata, index_mtime) => (' ', entry.mode),
+            Some(file) => {
+                let (mode, sha1_hash): (EntryMode, String) = hash_worktree_blob(&worktree.join(path), &file.metadata)?;
+                let mode: u32 = mode.as_index_mode();
+                let stat: StatData = StatData::from_metadata(&file.metadata);
+                if is_type_change(entry.mode, mode) {
+                    ('T', mode)
+                } else if entry.mode != mode || !entry.sha1_hash.eq(&sha1_hash) {
+                    ('M', mode)
+                } else {
+                    if stat != entry.stat {
+                        refreshed.push((position, stat));
+                    }
+                    (' ', mode)
+                }
+            }
+        };
+
+        if staged != ' ' || unstaged != ' ' {
+            entries.push(StatusEntry {
+                path: path.to_string(),
+                staged,
+                unstaged,
+                head: mode_and_id(head_entry),
+                index: (entry.mode, entry.sha1_hash.clone()),
+                worktree_mode,
+                conflict: None,
+            });
+        }
+        position += 1;
+    }
+
+    // Paths HEAD has that the index lost.
+    for (path, head_entry) in &head_entries {
+        if !is_tracked(&index, path) && pathspec.matches(path) {
+            entries.push(StatusEntry {
+                path: path.to_string(),
+                staged: 'D',
+                unstaged: ' ',
+                head: mode_and_id(Some(head_entry)),
+                index: (0, ZERO_ID.to_string()),
+                worktree_mode: 0,
+                conflict: None,
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.path.as_bytes().cmp(b.path.as_bytes()));
+
+    let untracked: Vec<String> = match untracked {
+        UntrackedFiles::No => Vec::new(),
+        UntrackedFiles::Normal => collapse_untracked(&index, &files),
+        UntrackedFiles::All => files
+            .iter()
+            .filter(|file| !is_tracked(&index, &file.path))
+            .map(|file| file.path.clone())
+            .collect(),
+    };
+
+    // Like git, the refreshed index is written when possible, and status
+    // goes on without it otherwise.
+    if !refreshed.is_empty() {
+        for (position, stat) in refreshed {
+            index.entries[position].stat = stat;
+        }
+        let _ = index.write(&index_path);
+    }
+
+    let merging: bool = git_dir.join("MERGE_HEAD").is_file();
+    Ok(Status { branch, head, upstream, merging, entries, untracked })
+}
+
+// Upstream of `branch` and how far `head` is from it.
+pub fn read_upstream(
+    git_dir: &Path,
+    object_store: &dyn ObjectStore,
+    branch: &str,
+    head: Option<&str>,
+) -> Result<Option<Upstream>, GitError> {
+    let config: Config = Config::load(&git_dir.join("config"))?;
+    let Some(upstream_ref) = branch_upstream(&config, branch)? else {
+        return Ok(None);
+    };
+
+    let name: String = shorten_ref(&upstream_ref).to_string();
+    let ahead_behind: Option<(usize, usize)> = match (read_ref(git_dir, &upstream_ref)?, head) {
+        (Some(upstream), Some(head)) => Some(ahead_behind(object_store, head, &upstream)?),
+        (Some(_), None) => Some((0, 0)),
+        (None, _) => None,
+    };
+    Ok(Some(Upstream { name, ahead_behind }))
+}
+
+// Commits only `local` reaches, and commits only `upstream` reaches.
+// Both sides are painted down from the tips, newest first, and the walk
+// stops once everything left is reachable from both, as git does, rather
+// than going through all history.
+fn ahead_behind(object_store: &dyn ObjectStore, local: &str, upstream: &str) -> Result<(usize, usize), GitError> {
+    let mut walk: PaintedWalk = PaintedWalk { object_store, commits: HashMap::new(), queue: BinaryHeap::new(), inserted: 0 };
+    walk.paint(&peel_to_commit(object_store, local)?, LOCAL)?;
+    walk.paint(&peel_to_commit(object_store, upstream)?, UPSTREAM)?;
+
+    while walk.queue.iter().any(|(_, _, sha1_hash)| walk.commits[sha1_hash].sides != LOCAL | UPSTREAM) {
+        let Some((_, _, sha1_hash)) = walk.queue.pop() else {
+            break;
+        };
+        let painted: &Painted = &walk.commits[&sha1_hash];
+        let (sides, parents): (u8, Vec<String>) = (painted.sides, painted.parents.clone());
+        for parent in &parents {
+            walk.paint(parent, sides)?;
+        }
+    }
+
+    let count = |sides: u8| walk.commits.values().filter(|painted| painted.sides == sides).count();
+    Ok((count(LOCAL), count(UPSTREAM)))
+}
+
+const LOCAL: u8 = 1;
+const UPSTREAM: u8 = 2;
+
+struct Painted {
+    // `LOCAL` and `UPSTREAM` when reached from either tip.
+    sides: u8,
+    date: i64,
+    parents: Vec<String>,
+}
+
+struct PaintedWalk<'a> {
+    object_store: &'a dyn ObjectStore,
+    commits: HashMap<String, Painted>,
+    // Commits reached from new sides, newest first and in insertion order
+    // for equal dates, so that children go before their parents.
+    queue: BinaryHeap<(i64, Reverse<usize>, String)>,
+    inserted: usize,
+}
+
+impl PaintedWalk<'_> {
+    fn paint(&mut self, sha1_hash: &str, sides: u8) -> Result<(), GitError> {
+        let painted: &mut Painted = match self.commits.entry(sha1_hash.to_string()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let GitObject::Commit { content } = read_git_object(self.object_store, sha1_hash)? else {
+                    return Err(GitError::InvalidCommit(format!("{sha1_hash} is not a commit")));
+                };
+                entry.insert(Painted { sides: 0, date: content.committer.timestamp, parents: content.parents })
+            }
+        };
+        if painted.sides & sides == sides {
+            return Ok(());
+        }
+
+        painted.sides |= sides;
+        self.queue.push((painted.date, Reverse(self.inserted), sha1_hash.to_string()));
+        self.inserted += 1;
+        Ok(())
+    }
+}
+
+fn peel_to_commit(object_store: &dyn ObjectStore, sha1_hash: &str) -> Result<String, GitError> {
+    let mut sha1_hash: String = sha1_hash.to_string();
+    loop {
+        match read_git_object(object_store, &sha1_hash)? {
+            GitObject::Tag { content } => sha1_hash = content.object,
+            _ => return Ok(sha1_hash),
+        }
+    }
+}
+
+// Whether the index has `path` at any stage.
+fn is_tracked(index: &Index, path: &str) -> bool {
+    match index.position(path, 0) {
+        Ok(_) => true,
+        Err(position) => index.entries.get(position).is_some_and(|entry| entry.path.eq(path)),
+    }
+}
+
+fn mode_and_id(entry: Option<&IndexEntry>) -> (u32, String) {
+    match entry {
+        Some(entry) => (entry.mode, entry.sha1_hash.clone()),
+        None => (0, ZERO_ID.to_string()),
+    }
+}
+
+// Files, symbolic links and submodules are different types; the
+// executable bit only makes a modification.
+fn is_type_change(old_mode: u32, new_mode: u32) -> bool {
+    old_mode != 0 && new_mode != 0 && (old_mode & 0o170000) != (new_mode & 0o170000)
+}
+
+// `XY` of an unmerged path, from which stages exist: 1 is the common
+// ancestor, 2 ours and 3 theirs.
+fn unmerged_status(conflict: &[(u32, String); 3]) -> (char, char) {
+    match (conflict[0].0 != 0, conflict[1].0 != 0, conflict[2].0 != 0) {
+        (true, true, true) => ('U', 'U'),
+        (false, true, true) => ('A', 'A'),
+        (true, true, false) => ('U', 'D'),
+        (true, false, true) => ('D', 'U'),
+        (false, true, false) => ('A', 'U'),
+        (false, false, true) => ('U', 'A'),
+        _ => ('D', 'D'),
+    }
+}
+
+// Untracked files, with directories that hold no tracked file shown once
+// as `dir/`.
+fn collapse_untracked(index: &Index, files: &[WorktreeFile]) -> Vec<String> {
+    let mut untracked: Vec<String> = Vec::new();
+    for file in files {
+        let path: &str = &file.path;
+        if is_tracked(index, path) {
+            continue;
+        }
+
+        let directory: Option<&str> = path
+            .match_indices('/')
+            .map(|(slash, _)| &path[..slash])
+            .find(|directory| !has_entries_below(index, directory));
+        let shown: String = match directory {
+            Some(directory) => format!("{directory}/"),
+            None => path.to_string(),
+        };
+        if untracked.last() != Some(&shown) {
+            untracked.push(shown);
+        }
+    }
+    untracked
+}
+
+// The default format. `show_untracked` is false with `-uno`, which the
+// closing advice mentions.
+pub fn format_long(status: &Status, show_untracked: bool) -> String {
+    let mut out: String = String::new();
+    match &status.branch {
+        Some(branch) => out.push_str(&format!("On branch {branch}\n")),
+        None => {
+            let head: &str = status.head.as_deref().unwrap_or(ZERO_ID);
+            out.push_str(&format!("HEAD detached at {}\n", &head[..ABBREV]));
+        }
+    }
+
+    // Nothing to compare with the upstream before the first commit.
+    if let Some(upstream) = status.upstream.as_ref().filter(|_| status.head.is_some()) {
+        let name: &str = &upstream.name;
+        match upstream.ahead_behind {
+            None => {
+                out.push_str(&format!("Your branch is based on '{name}', but the upstream is gone.\n"));
+                out.push_str("  (use \"git branch --unset-upstream\" to fixup)\n");
+            }
+            Some((0, 0)) => out.push_str(&format!("Your branch is up to date with '{name}'.\n")),
+            Some((ahead, 0)) => {
+                out.push_str(&format!("Your branch is ahead of '{name}' by {ahead} {}.\n", commits(ahead)));
+                out.push_str("  (use \"git push\" to publish your local commits)\n");
+            }
+            Some((0, behind)) => {
+                out.push_str(&format!(
+                    "Your branch is behind '{name}' by {behind} {}, and can be fast-forwarded.\n",
+                    commits(behind)
+                ));
+                out.push_str("  (use \"git pull\" to update your local branch)\n");
+            }
+            Some((ahead, behind)) => {
+                out.push_str(&format!("Your branch and '{name}' have diverged,\n"));
+                out.push_str(&format!(
+                    "and have {ahead} and {behind} different commits each, respectively.\n"
+                ));
+                out.push_str("  (use \"git pull\" to merge the remote branch into yours)\n");
+            }
+        }
+        out.push('\n');
+    }
+
+    let unmerged: Vec<&StatusEntry> = status.entries.iter().filter(|entry| entry.conflict.is_some()).collect();
+    if status.merging && unmerged.is_empty() {
+        out.push_str("All conflicts fixed but you are still merging.\n");
+        out.push_str("  (use \"git commit\" to conclude merge)\n\n");
+    } else if status.merging {
+        out.push_str("You have unmerged paths.\n");
+        out.push_str("  (fix conflicts and run \"git commit\")\n");
+        out.push_str("  (use \"git merge --abort\" to abort the merge)\n\n");
+    }
+
+    if status.head.is_none() {
+        out.push_str("\nNo commits yet\n\n");
+    }
+
+    let staged: Vec<&StatusEntry> = status
+        .entries
+        .iter()
+        .filter(|entry| entry.conflict.is_none() && entry.staged != ' ')
+        .collect();
+    let unstaged: Vec<&StatusEntry> = status
+        .entries
+        .iter()
+        .filter(|entry| entry.conflict.is_none() && entry.unstaged != ' ')
+        .collect();
+
+    if !staged.is_empty() {
+        out.push_str("Changes to be committed:\n");
+        match status.head {
+            Some(_) => out.push_str("  (use \"git restore --staged <file>...\" to unstage)\n"),
+            None => out.push_str("  (use \"git rm --cached <file>...\" to unstage)\n"),
+        }
+        for entry in &staged {
+            push_change(&mut out, entry.staged, &entry.path);
+        }
+        out.push('\n');
+    }
+
+    if !unmerged.is_empty() {
+        out.push_str("Unmerged paths:\n");
+        // Conflicts where a side deleted the file may be resolved by
+        // removing it.
+        let both_deleted: Vec<bool> = unmerged.iter().map(|entry| entry.staged == 'D' && entry.unstaged == 'D').collect();
+        let deleted_by_one: bool = unmerged.iter().any(|entry| matches!((entry.staged, entry.unstaged), ('U', 'D') | ('D', 'U')));
+        if both_deleted.iter().all(|&deleted| deleted) {
+            out.push_str("  (use \"git rm <file>...\" to mark resolution)\n");
+        } else if deleted_by_one || both_deleted.contains(&true) {
+            out.push_str("  (use \"git add/rm <file>...\" as appropriate to mark resolution)\n");
+        } else {
+            out.push_str("  (use \"git add <file>...\" to mark resolution)\n");
+        }
+        for entry in &unmerged {
+            let label: &str = match (entry.staged, entry.unstaged) {
+                ('D', 'D') => "both deleted:",
+                ('A', 'U') => "added by us:",
+                ('U', 'D') => "deleted by them:",
+                ('U', 'A') => "added by them:",
+                ('D', 'U') => "deleted by us:",
+                ('A', 'A') => "both added:",
+                _ => "both modified:",
+            };
+            out.push_str(&format!("\t{label:<UNMERGED_LABEL_WIDTH$}{}\n", entry.path));
+        }
+        out.push('\n');
+    }
+
+    if !unstaged.is_empty() {
+        out.push_str("Changes not staged for commit:\n");
+        match unstaged.iter().any(|entry| entry.unstaged == 'D') {
+            true => out.push_str("  (use \"git add/rm <file>...\" to update what will be committed)\n"),
+            false => out.push_str("  (use \"git add <file>...\" to update what will be committed)\n"),
+        }
+        out.push_str("  (use \"git restore <file>...\" to discard changes in working directory)\n");
+        for entry in &unstaged {
+            push_change(&mut out, entry.unstaged, &entry.path);
+        }
+        out.push('\n');
+    }
+
+    if !status.untracked.is_empty() {
+        out.push_str("Untracked files:\n");
+        out.push_str("  (use \"git add <file>...\" to include in what will be committed)\n");
+        for path in &status.untracked {
+            out.push_str(&format!("\t{path}\n"));
+        }
+        out.push('\n');
+    }
+
+    let committable: bool = status.has_staged_changes();
+    if committable && !show_untracked {
+        out.push_str("Untracked files not listed (use -u option to show untracked files)\n");
+    }
+    if committable {
+        return out;
+    }
+    if !unstaged.is_empty() || !unmerged.is_empty() {
+        out.push_str("no changes added to commit (use \"git add\" and/or \"git commit -a\")\n");
+    } else if !status.untracked.is_empty() {
+        out.push_str("nothing added to commit but untracked files present (use \"git add\" to track)\n");
+    } else if status.head.is_none() {
+        out.push_str("nothing to commit (create/copy files and use \"git add\" to track)\n");
+    } else if !show_untracked {
+        out.push_str("nothing to commit (use -u to show untracked files)\n");
+    } else {
+        out.push_str("nothing to commit, working tree clean\n");
+    }
+    out
+}
+
+fn push_change(out: &mut String, change: char, path: &str) {
+    let label: &str = match change {
+        'A' => "new file:",
+        'D' => "deleted:",
+        'T' => "typechange:",
+        _ => "modified:",
+    };
+    out.push_str(&format!("\t{label:<CHANGE_LABEL_WIDTH$}{path}\n"));
+}
+
+fn commits(count: usize) -> &'static str {
+    match count {
+        1 => "commit",
+        _ => "commits",
+    }
+}
+
+// `--short` and `--porcelain=v1`: `XY path` lines, untracked files last
+// as `?? path`, and with `branch` a `## ` header line.
+pub fn format_short(status: &Status, branch: bool) -> String {
+    let mut out: String = String::new();
+    if branch {
+        out.push_str("## ");
+        match (&status.branch, &status.head) {
+            (Some(branch), None) => out.push_str(&format!("No commits yet on {branch}")),
+            (Some(branch), Some(_)) => out.push_str(branch),
+            (None, _) => out.push_str("HEAD (no branch)"),
+        }
+        if let Some(upstream) = &status.upstream {
+            out.push_str(&format!("...{}", upstream.name));
+            match upstream.ahead_behind {
+                None => out.push_str(" [gone]"),
+                Some((0, 0)) => {}
+                Some((ahead, 0)) => out.push_str(&format!(" [ahead {ahead}]")),
+                Some((0, behind)) => out.push_str(&format!(" [behind {behind}]")),
+                Some((ahead, behind)) => out.push_str(&format!(" [ahead {ahead}, behind {behind}]")),
+            }
+        }
+        out.push('\n');
+    }
+
+    for entry in &status.entries {
+        out.push_str(&format!("{}{} {}\n", entry.staged, entry.unstaged, entry.path));
+    }
+    for path in &status.untracked {
+        out.push_str(&format!("?? {path}\n"));
+    }
+    out
+}
+
+// `--porcelain=v2`: `1` lines for changed entries, `u` lines for unmerged
+// ones and `?` lines for untracked files, with modes and object ids.
+pub fn format_porcelain_v2(status: &Status, branch: bool) -> String {
+    let mut out: String = String::new();
+    if branch {
+        out.push_str(&format!("# branch.oid {}\n", status.head.as_deref().unwrap_or("(initial)")));
+        out.push_str(&format!("# branch.head {}\n", status.branch.as_deref().unwrap_or("(detached)")));
+        if let Some(upstream) = &status.upstream {
+            out.push_str(&format!("# branch.upstream {}\n", upstream.name));
+            if let Some((ahead, behind)) = upstream.ahead_behind {
+                out.push_str(&format!("# branch.ab +{ahead} -{behind}\n"));
+            }
+        }
+    }
+
+    for entry in &status.entries {
+        let xy: String = [entry.staged, entry.unstaged]
+            .iter()
+            .map(|&c| if c == ' ' { '.' } else { c })
+            .collect();
+        let submodule: &str = match entry.index.0 == GITLINK_MODE || entry.head.0 == GITLINK_MODE {
+            true => "S...",
+            false => "N...",
+        };
+
+        match &entry.conflict {
+            Some([(m1, h1), (m2, h2), (m3, h3)]) => out.push_str(&format!(
+                "u {xy} {submodule} {m1:06o} {m2:06o} {m3:06o} {:06o} {h1} {h2} {h3} {}\n",
+                entry.worktree_mode, entry.path
+            )),
+            None => out.push_str(&format!(
+                "1 {xy} {submodule} {:06o} {:06o} {:06o} {} {} {}\n",
+                entry.head.0, entry.index.0, entry.worktree_mode, entry.head.1, entry.index.1, entry.path
+            )),
+        }
+    }
+    for path in &status.untracked {
+        out.push_str(&format!("? {path}\n"));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::test_support::git;
+    use crate::test_support::TempRepo;
+
+    fn repo_status(repo: &Path, untracked: UntrackedFiles) -> Status {
+        status(&repo.join(".git"), repo, &Pathspec::default(), untracked).unwrap()
+    }
+
+    #[test]
+    fn test_status_matches_git() {
+        let dir: TempRepo = TempRepo::new("status");
+        let repo: PathBuf = dir.join("work");
+        git(&dir, &["init", "-q", "--bare", "upstream.git"]);
+        git(&dir, &["clone", "-q", "upstream.git", "work"]);
+
+        let status: Status = repo_status(&repo, UntrackedFiles::Normal);
+        assert_eq!(git(&repo, &["status"]), format_long(&status, true).trim_end());
+
+        fs::create_dir_all(repo.join("dir/sub")).unwrap();
+        fs::write(repo.join("a.txt"), "a\n").unwrap();
+        fs::write(repo.join("b.txt"), "b\n").unwrap();
+        fs::write(repo.join("dir/sub/c.txt"), "c\n").unwrap();
+        fs::write(repo.join("dir/d.txt"), "d\n").unwrap();
+        fs::write(repo.join("run.sh"), "run\n").unwrap();
+        std::os::unix::fs::symlink("a.txt", repo.join("link")).unwrap();
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-q", "-m", "first"]);
+        git(&repo, &["push", "-q", "origin", "HEAD"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "second"]);
+
+        let status: Status = repo_status(&repo, UntrackedFiles::Normal);
+        assert!(status.entries.is_empty());
+        assert_eq!(Some((1, 0)), status.upstream.as_ref().and_then(|upstream| upstream.ahead_behind));
+        assert_eq!(git(&repo, &["status"]), format_long(&status, true).trim_end());
+
+        fs::write(repo.join("a.txt"), "changed\n").unwrap();
+        git(&repo, &["rm", "-q", "--cached", "b.txt"]);
+        fs::set_permissions(repo.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_file(repo.join("dir/d.txt")).unwrap();
+        fs::remove_file(repo.join("link")).unwrap();
+        fs::write(repo.join("link"), "now a file\n").unwrap();
+        fs::write(repo.join("staged.txt"), "staged\n").unwrap();
+        git(&repo, &["add", "staged.txt"]);
+        fs::write(repo.join("staged.txt"), "staged and changed\n").unwrap();
+        fs::create_dir_all(repo.join("new/deeper")).unwrap();
+        fs::write(repo.join("new/deeper/file"), "untracked\n").unwrap();
+        fs::write(repo.join("dir/sub/e.txt"), "untracked\n").unwrap();
+        fs::write(repo.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(repo.join("debug.log"), "ignored\n").unwrap();
+
+        let status: Status = repo_status(&repo, UntrackedFiles::Normal);
+        assert_eq!(git(&repo, &["status"]), format_long(&status, true).trim_end());
+        assert_eq!(git(&repo, &["status", "--short", "--branch"]), format_short(&status, true).trim_end());
+        assert_eq!(git(&repo, &["status", "--porcelain=v2", "--branch"]), format_porcelain_v2(&status, true).trim_end());
+
+        let status: Status = repo_status(&repo, UntrackedFiles::All);
+        assert_eq!(git(&repo, &["status", "--porcelain", "--untracked-files=all"]), format_short(&status, false).trim_end());
+        let status: Status = repo_status(&repo, UntrackedFiles::No);
+        assert_eq!(git(&repo, &["status", "--untracked-files=no"]), format_long(&status, false).trim_end());
+    }
+
+    #[test]
+    fn test_status_refreshes_stat_data() {
+        let repo: TempRepo = TempRepo::new("status_refresh");
+        git(&repo, &["init", "-q"]);
+        fs::write(repo.join("file"), "content\n").unwrap();
+        git(&repo, &["add", "file"]);
+        git(&repo, &["commit", "-q", "-m", "first"]);
+
+        // Rewriting the same content only changes the stat data.
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        fs::write(repo.join("file"), "content\n").unwrap();
+        let before: StatData = Index::load(&repo.join(".git/index")).unwrap().entries[0].stat;
+        assert!(repo_status(&repo, UntrackedFiles::Normal).entries.is_empty());
+
+        let after: StatData = Index::load(&repo.join(".git/index")).unwrap().entries[0].stat;
+        assert_ne!(before, after);
+        assert_eq!(StatData::from_metadata(&fs::metadata(repo.join("file")).unwrap()), after);
+        assert_eq!("", git(&repo, &["diff-files", "--name-only"]));
+    }
+}
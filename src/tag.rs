@@ -0,0 +1,160 @@
+use crate::commit::parse_object_headers;
+use crate::commit::write_object_header;
+use crate::commit::ObjectHeaders;
+use crate::signature::Signature;
+use crate::GitError;
+
+const SIGNATURE_MARKERS: [&[u8]; 3] = [
+    b"-----BEGIN PGP SIGNATURE-----",
+    b"-----BEGIN PGP MESSAGE-----",
+    b"-----BEGIN SSH SIGNATURE-----",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub object: String,
+    pub object_type: String,
+    pub name: String,
+    // Very old tags have no tagger line.
+    pub tagger: Option<Signature>,
+    pub extra_headers: Vec<(String, Vec<u8>)>,
+    // `None` when not even the empty line before the message is there.
+    pub message: Option<Vec<u8>>,
+    // Signed tags append the signature to the message body.
+    pub signature: Option<Vec<u8>>,
+}
+
+impl Tag {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, GitError> {
+        let (headers, body): (ObjectHeaders, Option<Vec<u8>>) = parse_object_headers(bytes)?;
+
+        let mut object: Option<String> = None;
+        let mut object_type: Option<String> = None;
+        let mut name: Option<String> = None;
+        let mut tagger: Option<Signature> = None;
+        let mut extra_headers: Vec<(String, Vec<u8>)> = Vec::new();
+
+        let text = |value: Vec<u8>| String::from_utf8(value).map_err(|_| GitError::InvalidTag("bad header value".to_string()));
+
+        for (key, value) in headers {
+            match key.as_str() {
+                "object" if object.is_none() => object = Some(text(value)?),
+                "type" if object_type.is_none() => object_type = Some(text(value)?),
+                "tag" if name.is_none() => name = Some(text(value)?),
+                "tagger" if tagger.is_none() => tagger = Some(Signature::from_line(&value)?),
+                _ => extra_headers.push((key, value)),
+            }
+        }
+
+        let Some(object) = object else {
+            return Err(GitError::InvalidTag("missing object".to_string()));
+        };
+        let Some(object_type) = object_type else {
+            return Err(GitError::InvalidTag("missing type".to_string()));
+        };
+        let Some(name) = name else {
+            return Err(GitError::InvalidTag("missing tag name".to_string()));
+        };
+
+        let (message, signature): (Option<Vec<u8>>, Option<Vec<u8>>) = match body {
+            Some(body) => match signature_start(&body) {
+                Some(start) => (Some(body[..start].to_vec()), Some(body[start..].to_vec())),
+                None => (Some(body), None),
+            },
+            None => (None, None),
+        };
+
+        Ok(Self {
+            object,
+            object_type,
+            name,
+            tagger,
+            extra_headers,
+            message,
+            signature,
+        })
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+
+        write_object_header(&mut bytes, "object", self.object.as_bytes());
+        write_object_header(&mut bytes, "type", self.object_type.as_bytes());
+        write_object_header(&mut bytes, "tag", self.name.as_bytes());
+        if let Some(tagger) = &self.tagger {
+            write_object_header(&mut bytes, "tagger", &tagger.as_bytes());
+        }
+        for (key, value) in &self.extra_headers {
+            write_object_header(&mut bytes, key, value);
+        }
+
+        if let Some(message) = &self.message {
+            bytes.push(b'\n');
+            bytes.extend_from_slice(message);
+        }
+        if let Some(signature) = &self.signature {
+            bytes.extend_from_slice(signature);
+        }
+        bytes
+    }
+}
+
+fn signature_start(body: &[u8]) -> Option<usize> {
+    let mut line_start: usize = 0;
+    while line_start < body.len() {
+        let line: &[u8] = &body[line_start..];
+        if SIGNATURE_MARKERS.iter().any(|marker| line.starts_with(marker)) {
+            return Some(line_start);
+        }
+
+        match line.iter().position(|&b| b == b'\n') {
+            Some(pos) => line_start += pos + 1,
+            None => break,
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_tag_round_trip() {
+        let raw: &[u8] = b"object 0123456789012345678901234567890123456789\n\
+type commit\n\
+tag v1.0.0\n\
+tagger T Agger <t@example.com> 1700000000 +0000\n\
+\n\
+Release 1.0.0\n\
+-----BEGIN PGP SIGNATURE-----\n\
+\n\
+abcdef\n\
+-----END PGP SIGNATURE-----\n";
+
+        let tag: Tag = Tag::from_bytes(raw).unwrap();
+
+        assert_eq!("commit", tag.object_type);
+        assert_eq!("v1.0.0", tag.name);
+        assert_eq!(Some(b"Release 1.0.0\n".to_vec()), tag.message);
+        assert!(tag.signature.is_some());
+        assert_eq!(raw, &tag.as_bytes()[..]);
+    }
+
+    #[test]
+    fn test_tag_without_message_round_trip() {
+        let raw: &[u8] = b"object 0123456789012345678901234567890123456789\n\
+type commit\n\
+tag v1.0.0\n";
+
+        let tag: Tag = Tag::from_bytes(raw).unwrap();
+        assert_eq!(None, tag.message);
+        assert_eq!(raw, &tag.as_bytes()[..]);
+
+        // An empty message keeps its separating line.
+        let mut raw: Vec<u8> = raw.to_vec();
+        raw.push(b'\n');
+        assert_eq!(raw, Tag::from_bytes(&raw).unwrap().as_bytes());
+    }
+}
@@ -0,0 +1,76 @@
+use std::fs;
+
+use std::ops::Deref;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use std::process;
+use std::process::Command;
+
+// A directory in the system temp directory, named after the test and the
+// process, removed when dropped so that failing tests clean up too.
+pub struct TempRepo {
+    path: PathBuf,
+}
+
+impl TempRepo {
+    pub fn new(name: &str) -> Self {
+        let path: PathBuf = std::env::temp_dir().join(format!("{name}_{}", process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+}
+
+impl Deref for TempRepo {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempRepo {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempRepo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// Stdout of real git run in `dir`, which tests compare with. Commits are
+// made as a fixture identity, at `date` when given; the test fails if git
+// does.
+pub fn git_output(dir: &Path, args: &[&str], date: Option<&str>) -> Vec<u8> {
+    let mut command: Command = Command::new("git");
+    command
+        .args(["-c", "user.name=Fixture", "-c", "user.email=fixture@example.com"])
+        .args(args)
+        .current_dir(dir);
+    if let Some(date) = date {
+        command.env("GIT_AUTHOR_DATE", date).env("GIT_COMMITTER_DATE", date);
+    }
+
+    let output = command.output().unwrap();
+    assert!(output.status.success(), "git {args:?}: {}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+// Same, as text without its final newline.
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    String::from_utf8(git_output(dir, args, None)).unwrap().trim_end().to_string()
+}
+
+// Writes `content` to `name`, commits it with `content` as the message and
+// returns the new commit.
+pub fn commit_file(dir: &Path, name: &str, content: &str) -> String {
+    fs::write(dir.join(name), content).unwrap();
+    git(dir, &["add", name]);
+    git(dir, &["commit", "-q", "-m", content]);
+    git(dir, &["rev-parse", "HEAD"])
+}
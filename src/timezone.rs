@@ -0,0 +1,274 @@
+use std::env;
+use std::fs;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::signature::civil_from_days;
+use crate::signature::days_from_civil;
+
+const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+const LOCALTIME_PATH: &str = "/etc/localtime";
+
+// Offset from UTC in minutes of the local time at `timestamp`, found as
+// localtime(3) does: `TZ` names a zoneinfo file or holds a POSIX rule,
+// /etc/localtime is used when it is unset, and anything unreadable is UTC.
+pub fn local_offset_minutes(timestamp: i64) -> i64 {
+    let seconds: Option<i64> = match env::var("TZ") {
+        Ok(tz) => {
+            let tz: &str = tz.strip_prefix(':').unwrap_or(&tz);
+            let path: PathBuf = if tz.starts_with('/') { PathBuf::from(tz) } else { Path::new(ZONEINFO_DIR).join(tz) };
+            match fs::read(&path) {
+                Ok(bytes) => tzif_offset(&bytes, timestamp),
+                Err(_) => posix_offset(tz, timestamp),
+            }
+        }
+        Err(_) => fs::read(LOCALTIME_PATH).ok().and_then(|bytes| tzif_offset(&bytes, timestamp)),
+    };
+
+    seconds.unwrap_or(0) / 60
+}
+
+struct TzifBlock {
+    transitions: Vec<i64>,
+    type_indices: Vec<u8>,
+    offsets: Vec<i64>,
+    end: usize,
+}
+
+// A TZif file (tzfile(5)) has a block with 32-bit times, then from
+// version 2 the same block with 64-bit times and a POSIX rule for the
+// times after its last transition.
+fn tzif_offset(bytes: &[u8], timestamp: i64) -> Option<i64> {
+    let mut block: TzifBlock = parse_tzif_block(bytes, 4)?;
+    let mut footer: Option<&str> = None;
+    if *bytes.get(4)? != 0 {
+        let rest: &[u8] = bytes.get(block.end..)?;
+        block = parse_tzif_block(rest, 8)?;
+        footer = rest
+            .get(block.end..)
+            .and_then(|tail| tail.strip_prefix(b"\n"))
+            .and_then(|tail| tail.split(|&byte| byte == b'\n').next())
+            .and_then(|rule| std::str::from_utf8(rule).ok());
+    }
+
+    let passed: usize = block.transitions.iter().take_while(|&&transition| transition <= timestamp).count();
+    if passed == block.transitions.len() {
+        if let Some(offset) = footer.and_then(|rule| posix_offset(rule, timestamp)) {
+            return Some(offset);
+        }
+    }
+
+    let type_index: usize = match passed {
+        0 => 0,
+        passed => *block.type_indices.get(passed - 1)? as usize,
+    };
+    block.offsets.get(type_index).copied()
+}
+
+fn parse_tzif_block(bytes: &[u8], time_size: usize) -> Option<TzifBlock> {
+    if bytes.get(..4)? != b"TZif" {
+        return None;
+    }
+
+    let count = |index: usize| -> Option<usize> {
+        let field: &[u8] = bytes.get(20 + 4 * index..24 + 4 * index)?;
+        Some(u32::from_be_bytes(field.try_into().ok()?) as usize)
+    };
+    let (utc_count, std_count, leap_count) = (count(0)?, count(1)?, count(2)?);
+    let (time_count, type_count, char_count) = (count(3)?, count(4)?, count(5)?);
+
+    let mut position: usize = 44;
+    let mut transitions: Vec<i64> = Vec::with_capacity(time_count);
+    for _ in 0..time_count {
+        let field: &[u8] = bytes.get(position..position + time_size)?;
+        transitions.push(match time_size {
+            4 => i32::from_be_bytes(field.try_into().ok()?) as i64,
+            _ => i64::from_be_bytes(field.try_into().ok()?),
+        });
+        position += time_size;
+    }
+
+    let type_indices: Vec<u8> = bytes.get(position..position + time_count)?.to_vec();
+    position += time_count;
+
+    // Each local time type is a 32-bit offset, a DST flag and a name index.
+    let mut offsets: Vec<i64> = Vec::with_capacity(type_count);
+    for _ in 0..type_count {
+        let field: &[u8] = bytes.get(position..position + 4)?;
+        offsets.push(i32::from_be_bytes(field.try_into().ok()?) as i64);
+        position += 6;
+    }
+
+    let end: usize = position + char_count + leap_count * (time_size + 4) + std_count + utc_count;
+    Some(TzifBlock {
+        transitions,
+        type_indices,
+        offsets,
+        end,
+    })
+}
+
+// POSIX `TZ` rules (tzset(3)) such as `CET-1CEST,M3.5.0,M10.5.0/3` or
+// `<+0530>-5:30`, in seconds east of UTC. Offsets in the rule itself count
+// west of UTC.
+fn posix_offset(rule: &str, timestamp: i64) -> Option<i64> {
+    let mut rest: &str = rule;
+    skip_zone_name(&mut rest)?;
+    let standard: i64 = -parse_time(&mut rest)?;
+    if rest.is_empty() {
+        return Some(standard);
+    }
+
+    skip_zone_name(&mut rest)?;
+    let daylight: i64 = if rest.is_empty() || rest.starts_with(',') { standard + 3600 } else { -parse_time(&mut rest)? };
+    // Without rules the US ones apply, as in glibc.
+    let rules: &str = match rest.strip_prefix(',') {
+        Some(rules) => rules,
+        None if rest.is_empty() => "M3.2.0,M11.1.0",
+        None => return None,
+    };
+    let (start, end): (&str, &str) = rules.split_once(',')?;
+
+    // Daylight time starts at a standard local time and ends at a daylight
+    // one.
+    let (year, _, _): (i64, i64, i64) = civil_from_days((timestamp + standard).div_euclid(86400));
+    let start: i64 = transition_seconds(start, year)? - standard;
+    let end: i64 = transition_seconds(end, year)? - daylight;
+    let in_daylight: bool = if start < end {
+        start <= timestamp && timestamp < end
+    } else {
+        !(end <= timestamp && timestamp < start)
+    };
+
+    Some(if in_daylight { daylight } else { standard })
+}
+
+// Names are at least three letters, or anything between `<` and `>`.
+fn skip_zone_name(rest: &mut &str) -> Option<()> {
+    let end: usize = match rest.strip_prefix('<') {
+        Some(quoted) => quoted.find('>')? + 2,
+        None => rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len()),
+    };
+    if end < 3 {
+        return None;
+    }
+
+    *rest = &rest[end..];
+    Some(())
+}
+
+// `[+-]hh[:mm[:ss]]` in seconds.
+fn parse_time(rest: &mut &str) -> Option<i64> {
+    let end: usize = rest.find(|c: char| !(c.is_ascii_digit() || matches!(c, '+' | '-' | ':'))).unwrap_or(rest.len());
+    let (time, remainder): (&str, &str) = rest.split_at(end);
+    *rest = remainder;
+
+    let (sign, time): (i64, &str) = match time.strip_prefix('-') {
+        Some(time) => (-1, time),
+        None => (1, time.strip_prefix('+').unwrap_or(time)),
+    };
+
+    let mut seconds: i64 = 0;
+    for (field, unit) in time.split(':').zip([3600, 60, 1]) {
+        seconds += field.parse::<u32>().ok()? as i64 * unit;
+    }
+    Some(sign * seconds)
+}
+
+// `Jn` (1 to 365, never counting February 29th), `n` (0 to 365) or
+// `Mm.w.d` (day `d` of week `w` of month `m`, week 5 being the last),
+// with an optional `/time`, as seconds since the epoch in local time.
+fn transition_seconds(spec: &str, year: i64) -> Option<i64> {
+    let (date, time): (&str, i64) = match spec.split_once('/') {
+        Some((date, mut time)) => (date, parse_time(&mut time)?),
+        None => (spec, 7200),
+    };
+
+    let january_first: i64 = days_from_civil(year, 1, 1);
+    let day: i64 = if let Some(day) = date.strip_prefix('J') {
+        let day: i64 = day.parse::<i64>().ok()?;
+        let leap: bool = days_from_civil(year, 3, 1) - days_from_civil(year, 2, 1) == 29;
+        january_first + day - 1 + i64::from(leap && day >= 60)
+    } else if let Some(rule) = date.strip_prefix('M') {
+        let fields: Vec<i64> = rule.split('.').map(|field| field.parse::<i64>().ok()).collect::<Option<_>>()?;
+        let [month, week, weekday] = fields[..] else {
+            return None;
+        };
+        if !(1..=12).contains(&month) || !(1..=5).contains(&week) || !(0..=6).contains(&weekday) {
+            return None;
+        }
+
+        // 1970-01-01 was a Thursday, and Sunday is day 0.
+        let first: i64 = days_from_civil(year, month, 1);
+        let length: i64 = days_from_civil(year + month / 12, month % 12 + 1, 1) - first;
+        let mut day: i64 = first + (weekday - (first + 4)).rem_euclid(7) + (week - 1) * 7;
+        while day - first >= length {
+            day -= 7;
+        }
+        day
+    } else {
+        january_first + date.parse::<i64>().ok()?
+    };
+
+    Some(day * 86400 + time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mid November and mid July 2023.
+    const WINTER: i64 = 1700000000;
+    const SUMMER: i64 = 1689400000;
+
+    #[test]
+    fn test_posix_offset() {
+        assert_eq!(Some(0), posix_offset("UTC0", SUMMER));
+        assert_eq!(Some(19800), posix_offset("<+0530>-5:30", WINTER));
+
+        let europe: &str = "CET-1CEST,M3.5.0,M10.5.0/3";
+        assert_eq!(Some(3600), posix_offset(europe, WINTER));
+        assert_eq!(Some(7200), posix_offset(europe, SUMMER));
+
+        // US rules are implied, and the southern summer spans the new year.
+        assert_eq!(Some(-14400), posix_offset("EST5EDT", SUMMER));
+        let australia: &str = "AEST-10AEDT,M10.1.0,M4.1.0/3";
+        assert_eq!(Some(39600), posix_offset(australia, WINTER));
+        assert_eq!(Some(36000), posix_offset(australia, SUMMER));
+
+        assert_eq!(None, posix_offset("", WINTER));
+        assert_eq!(None, posix_offset("CET-1CEST,M13.5.0,M10.5.0", WINTER));
+    }
+
+    #[test]
+    fn test_tzif_offset() {
+        // One transition from UTC to +0100 in the middle of 2023, then a
+        // `+0200` rule once it has passed.
+        let block = |time: Vec<u8>| -> Vec<u8> {
+            let mut bytes: Vec<u8> = b"TZif2".to_vec();
+            bytes.extend([0; 15]);
+            for count in [0u32, 0, 0, 1, 2, 4] {
+                bytes.extend(count.to_be_bytes());
+            }
+            bytes.extend(time);
+            bytes.push(1);
+            bytes.extend([0, 0, 0, 0, 0, 0]);
+            bytes.extend([0, 0, 0x0e, 0x10, 0, 0]);
+            bytes.extend(b"UTC\0");
+            bytes
+        };
+        let transition: i64 = 1690000000;
+        let mut bytes: Vec<u8> = block((transition as i32).to_be_bytes().to_vec());
+        bytes.extend(block(transition.to_be_bytes().to_vec()));
+        bytes.extend(b"\n<+02>-2\n");
+
+        assert_eq!(Some(0), tzif_offset(&bytes, SUMMER));
+        assert_eq!(Some(7200), tzif_offset(&bytes, WINTER));
+
+        // Version 1 files stay on the last type.
+        bytes[4] = 0;
+        assert_eq!(Some(3600), tzif_offset(&bytes, WINTER));
+        assert_eq!(None, tzif_offset(b"TZif", WINTER));
+    }
+}
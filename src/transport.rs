@@ -0,0 +1,152 @@
+use std::io::Read;
+use std::io::Write;
+
+use std::path::PathBuf;
+
+use std::process::Child;
+use std::process::ChildStdin;
+use std::process::ChildStdout;
+use std::process::Command;
+use std::process::Stdio;
+
+use crate::http::HttpRemote;
+use crate::pkt_line::PktLineReader;
+use crate::pkt_line::FLUSH_PKT;
+use crate::protocol::read_ref_advertisement;
+use crate::protocol::RefAdvertisement;
+use crate::GitError;
+
+pub const UPLOAD_PACK: &str = "git-upload-pack";
+pub const RECEIVE_PACK: &str = "git-receive-pack";
+
+// How requests reach a remote service (upload-pack to fetch, receive-pack
+// to push). The protocol spoken on top is the same for every transport.
+pub trait Transport {
+    fn discover_refs(&mut self, service: &str) -> Result<RefAdvertisement, GitError>;
+
+    // Sends `body` to the service whose refs were discovered last and
+    // returns the response.
+    fn request(&mut self, body: &[u8]) -> Result<Box<dyn Read + '_>, GitError>;
+}
+
+// `http(s)://` URLs use smart HTTP, `file://` URLs and paths spawn the
+// service locally.
+pub fn open_transport(url: &str) -> Result<Box<dyn Transport>, GitError> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        return Ok(Box::new(HttpTransport {
+            remote: HttpRemote::new(url),
+            service: None,
+        }));
+    }
+
+    let Some(path) = local_path(url) else {
+        return Err(GitError::Transport(format!("unsupported URL '{url}'")));
+    };
+    if !path.is_dir() {
+        return Err(GitError::Transport(format!("repository '{url}' does not exist")));
+    }
+
+    Ok(Box::new(LocalTransport {
+        path,
+        child: None,
+        sent: false,
+    }))
+}
+
+// Repository path behind a `file://` URL or a plain path.
+pub fn local_path(url: &str) -> Option<PathBuf> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return Some(PathBuf::from(path));
+    }
+    if url.contains("://") {
+        return None;
+    }
+
+    Some(PathBuf::from(url))
+}
+
+struct HttpTransport {
+    remote: HttpRemote,
+    service: Option<String>,
+}
+
+impl Transport for HttpTransport {
+    fn discover_refs(&mut self, service: &str) -> Result<RefAdvertisement, GitError> {
+        let advertisement: RefAdvertisement = self.remote.discover_refs(service)?;
+        self.service = Some(service.to_string());
+        Ok(advertisement)
+    }
+
+    fn request(&mut self, body: &[u8]) -> Result<Box<dyn Read + '_>, GitError> {
+        let Some(service) = &self.service else {
+            return Err(GitError::Transport("request before ref discovery".to_string()));
+        };
+        Ok(self.remote.post(service, body)?)
+    }
+}
+
+// Runs `git-upload-pack <path>` (or receive-pack) and talks pkt-line over
+// its stdin and stdout, as git does for local clones with `--no-local`.
+struct LocalTransport {
+    path: PathBuf,
+    child: Option<(Child, ChildStdin, PktLineReader<ChildStdout>)>,
+    sent: bool,
+}
+
+impl Transport for LocalTransport {
+    fn discover_refs(&mut self, service: &str) -> Result<RefAdvertisement, GitError> {
+        self.close();
+
+        let spawned = Command::new(service)
+            .arg(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn();
+        let mut child: Child = match spawned {
+            Ok(child) => child,
+            Err(err) => return Err(GitError::Transport(format!("cannot run {service}: {err}"))),
+        };
+
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(GitError::Transport(format!("cannot talk to {service}")));
+        };
+        let mut reader = PktLineReader::new(stdout);
+        let advertisement: Result<RefAdvertisement, GitError> = read_ref_advertisement(&mut reader);
+
+        self.child = Some((child, stdin, reader));
+        self.sent = false;
+        advertisement
+    }
+
+    fn request(&mut self, body: &[u8]) -> Result<Box<dyn Read + '_>, GitError> {
+        let Some((_, stdin, reader)) = &mut self.child else {
+            return Err(GitError::Transport("request before ref discovery".to_string()));
+        };
+
+        if let Err(err) = stdin.write_all(body).and_then(|()| stdin.flush()) {
+            return Err(GitError::Transport(format!("writing to remote: {err}")));
+        }
+        self.sent = true;
+
+        Ok(Box::new(reader))
+    }
+}
+
+impl LocalTransport {
+    // A flush tells a service that was never sent a request to stop.
+    fn close(&mut self) {
+        if let Some((mut child, mut stdin, _)) = self.child.take() {
+            if !self.sent {
+                let _ = stdin.write_all(FLUSH_PKT);
+            }
+            drop(stdin);
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Drop for LocalTransport {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs;
+
+use std::path::Path;
+
+use crate::ignore::IgnoreRules;
+use crate::index::Index;
+use crate::index::IndexEntry;
+use crate::index::StatData;
+use crate::pathspec::Pathspec;
+use crate::EntryMode;
+use crate::GitError;
+
+pub const GITLINK_MODE: u32 = 0o160000;
+
+// A file or symbolic link of the worktree, by its path from the top.
+#[derive(Debug)]
+pub struct WorktreeFile {
+    pub path: String,
+    pub metadata: fs::Metadata,
+}
+
+// Files the pathspec selects, sorted like index entries. Untracked files
+// that are ignored are left out unless `ignore` is `None`, and so are
+// directories holding another repository.
+pub fn list_files(
+    worktree: &Path,
+    index: &Index,
+    mut ignore: Option<&mut IgnoreRules>,
+    pathspec: &Pathspec,
+) -> Result<Vec<WorktreeFile>, GitError> {
+    let mut files: Vec<WorktreeFile> = Vec::new();
+    collect_files(worktree, "", false, index, &mut ignore, pathspec, &mut files)?;
+    files.sort_by(|a, b| a.path.as_bytes().cmp(b.path.as_bytes()));
+    Ok(files)
+}
+
+// `ignored` is set below an ignored directory, where only tracked files
+// are kept.
+fn collect_files(
+    worktree: &Path,
+    directory: &str,
+    ignored: bool,
+    index: &Index,
+    ignore: &mut Option<&mut IgnoreRules>,
+    pathspec: &Pathspec,
+    files: &mut Vec<WorktreeFile>,
+) -> Result<(), GitError> {
+    let entries = match fs::read_dir(worktree.join(directory)) {
+        Ok(entries) => entries,
+        Err(err) => return Err(GitError::Checkout(format!("cannot read '{directory}': {err}"))),
+    };
+
+    for entry in entries.map_while(Result::ok) {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if name.eq(".git") {
+            continue;
+        }
+        let path: String = match directory {
+            "" => name,
+            _ => format!("{directory}/{name}"),
+        };
+        let Ok(metadata) = fs::symlink_metadata(entry.path()) else {
+            continue;
+        };
+
+        if metadata.is_dir() {
+            if !pathspec.may_match_directory(&path) || index.find(&path, 0).is_some_and(|e| e.mode == GITLINK_MODE) {
+                continue;
+            }
+            let tracked_below: bool = has_entries_below(index, &path);
+            if !tracked_below && entry.path().join(".git").exists() {
+                continue;
+            }
+            let ignored: bool = ignored || ignore.as_deref_mut().is_some_and(|ignore| ignore.matches(&path, true));
+            if ignored && !tracked_below {
+                continue;
+            }
+            collect_files(worktree, &path, ignored, index, ignore, pathspec, files)?;
+            continue;
+        }
+
+        if !pathspec.matches(&path) {
+            continue;
+        }
+        let tracked: bool = index.position(&path, 0).is_ok() || has_conflict_stages(index, &path);
+        if !tracked && (ignored || ignore.as_deref_mut().is_some_and(|ignore| ignore.matches(&path, false))) {
+            continue;
+        }
+        files.push(WorktreeFile { path, metadata });
+    }
+
+    Ok(())
+}
+
+// Whether the index tracks anything inside `directory`.
+pub fn has_entries_below(index: &Index, directory: &str) -> bool {
+    let prefix: String = format!("{directory}/");
+    let position: usize = index.position(&prefix, 0).unwrap_or_else(|position| position);
+    index.entries.get(position).is_some_and(|entry| entry.path.starts_with(&prefix))
+}
+
+pub fn has_conflict_stages(index: &Index, path: &str) -> bool {
+    (1..=3).any(|stage| index.position(path, stage).is_ok())
+}
+
+// Whether a file still looks like what `entry` recorded, judging by its
+// stat data alone. Files changed in the same instant the index was
+// written could have changed unnoticed ("racy git"), so they are never
+// clean.
+pub fn is_stat_clean(entry: &IndexEntry, metadata: &fs::Metadata, index_mtime: Option<(u32, u32)>) -> bool {
+    if EntryMode::from_metadata(metadata).as_index_mode() != entry.mode {
+        return false;
+    }
+    if index_mtime.is_some_and(|index_mtime| (entry.stat.mtime, entry.stat.mtime_nsec) >= index_mtime) {
+        return false;
+    }
+
+    let stat: StatData = StatData::from_metadata(metadata);
+    let recorded: &StatData = &entry.stat;
+    (stat.mtime, stat.mtime_nsec, stat.ctime, stat.ctime_nsec) == (recorded.mtime, recorded.mtime_nsec, recorded.ctime, recorded.ctime_nsec)
+        && (stat.ino, stat.uid, stat.gid, stat.size) == (recorded.ino, recorded.uid, recorded.gid, recorded.size)
+}
+
+// Modification time of the index file, to detect racily clean entries.
+pub fn index_mtime(index_path: &Path) -> Option<(u32, u32)> {
+    let stat: StatData = StatData::from_metadata(&fs::metadata(index_path).ok()?);
+    Some((stat.mtime, stat.mtime_nsec))
+}